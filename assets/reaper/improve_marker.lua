@@ -0,0 +1,25 @@
+-- Inserts a marker named after the chord or key ImproVe last detected, at the play position
+-- Load it in the REAPER action list, then pass its command ID to improve --reaper-action
+-- The names are read from the ImproVe section of the project extended state
+
+local _, name = reaper.GetProjExtState(0, "ImproVe", "marker")
+if name == "" then
+  return
+end
+
+-- The edit cursor when stopped
+local position = reaper.GetCursorPosition()
+if reaper.GetPlayState() & 1 == 1 then
+  position = reaper.GetPlayPosition()
+end
+
+-- Rename the marker already there rather than stacking another
+local _, markers, regions = reaper.CountProjectMarkers(0)
+for i = 0, markers + regions - 1 do
+  local _, region, start, finish, _, index = reaper.EnumProjectMarkers(i)
+  if not region and math.abs(start - position) < 0.001 then
+    reaper.SetProjectMarker(index, false, start, finish, name)
+    return
+  end
+end
+reaper.AddProjectMarker(0, false, position, 0, name, -1)
@@ -0,0 +1,243 @@
+// The small part of JSON the messages use, so the crate has no dependency
+
+// Standard
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // The members in order
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    // The member of an object
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_spaces(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_spaces();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}', found the end", expected)),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(format!("expected {}", word));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.chars.peek() {
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('"') => self.string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(_) => self.number(),
+            None => Err("unexpected end".to_owned()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_ascii_digit() || "+-.eE".contains(c) {
+                text.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        text.parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid number '{}'", text))
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.chars.next().and_then(|c| c.to_digit(16));
+            code = code * 16 + digit.ok_or_else(|| "invalid unicode escape".to_owned())?;
+        }
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('/') => text.push('/'),
+                    Some('b') => text.push('\u{8}'),
+                    Some('f') => text.push('\u{c}'),
+                    Some('n') => text.push('\n'),
+                    Some('r') => text.push('\r'),
+                    Some('t') => text.push('\t'),
+                    Some('u') => {
+                        let mut code = self.hex()?;
+                        // A surrogate pair
+                        if (0xd800..0xdc00).contains(&code) {
+                            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                                return Err("unpaired surrogate".to_owned());
+                            }
+                            code = 0x10000
+                                + ((code - 0xd800) << 10)
+                                + (self.hex()?.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        text.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    _ => return Err("invalid escape".to_owned()),
+                },
+                Some(c) => text.push(c),
+                None => return Err("unterminated string".to_owned()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_spaces();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_spaces();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err("expected ',' or ']'".to_owned()),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_spaces();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_spaces();
+            let key = self.string()?;
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_spaces();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::Object(members)),
+                _ => return Err("expected ',' or '}'".to_owned()),
+            }
+        }
+    }
+}
+
+// Parse a whole JSON text
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+    };
+    let value = parser.value()?;
+    parser.skip_spaces();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some(c) => Err(format!("unexpected '{}' after the value", c)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_is_parsed() {
+        let value = parse(r#"{"v":1, "chord":"Cm", "level":null, "scores":[0.5, -1e-3], "ok":true}"#).unwrap();
+        assert_eq!(value.get("v").and_then(Value::as_f64), Some(1f64));
+        assert_eq!(value.get("chord").and_then(Value::as_str), Some("Cm"));
+        assert_eq!(value.get("level"), Some(&Value::Null));
+        assert_eq!(
+            value.get("scores").and_then(Value::as_array),
+            Some(&[Value::Number(0.5), Value::Number(-1e-3)][..])
+        );
+        assert_eq!(value.get("ok"), Some(&Value::Bool(true)));
+        assert_eq!(value.get("missing"), None);
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        let value = parse(r#""a\"b\\c\n\u00e9\ud83c\udfb5""#).unwrap();
+        assert_eq!(value.as_str(), Some("a\"b\\c\né🎵"));
+    }
+
+    #[test]
+    fn empty_containers_are_parsed() {
+        assert_eq!(parse(" [ ] ").unwrap(), Value::Array(Vec::new()));
+        assert_eq!(parse("{ }").unwrap(), Value::Object(Vec::new()));
+    }
+
+    #[test]
+    fn malformed_texts_are_errors() {
+        for text in ["", "{", "[1,]", "{\"a\" 1}", "\"open", "nul", "1 2", "\"\\ud83c\"", "\"\\x\""].iter() {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+// The client of the ImproVe widget protocol, for the music education apps embedding its analysis
+// Reads the messages served by `improve --widget PORT`, or the lines written by `improve --json FILE`
+//
+//     let client = improve_client::Client::connect("127.0.0.1:9200")?;
+//     for message in client {
+//         if let improve_client::Message::Frame(frame) = message? {
+//             println!("{:?} {:?}", frame.chord, frame.onset);
+//         }
+//     }
+//
+// Every message is a line of JSON, with the schema version "v" and its "type"
+//   hello: sent first, describing the engine and the notes scored
+//   frame: an analysed frame, with the chord, the levels, the events and the score of each note
+// Fields may be added within a version, so unknown fields and message types are ignored
+// Removing or changing a field increments the version, which this crate then refuses
+
+// Standard
+use std::fmt;
+use std::io::{self, BufRead, BufReader};
+use std::net::{TcpStream, ToSocketAddrs};
+
+mod json;
+
+use json::Value;
+
+// The version of the message schema read
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // The line is not JSON
+    Syntax(String),
+    // The message follows another version of the schema
+    Version(u32),
+    // A field is missing or of the wrong type
+    Field(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Syntax(reason) => write!(f, "Malformed message: {}", reason),
+            Error::Version(v) => write!(
+                f,
+                "Schema version {} is not supported, expected {}",
+                v, SCHEMA_VERSION
+            ),
+            Error::Field(name) => write!(f, "Missing or invalid field: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hello {
+    pub engine: String,
+    pub engine_version: String,
+    // The note of the first score, in scientific pitch notation (ie. C0)
+    pub first_note: String,
+    // The number of scores in each frame, a semitone apart
+    pub notes: usize,
+}
+
+// The descriptors of the session so far
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    // The entropy of the pitch classes played, in bits
+    pub entropy: f32,
+    // The chord changes per minute
+    pub chord_rate: f32,
+    // The variance of the dissonance of the notes played
+    pub tension_variance: f32,
+}
+
+// The values sent as null, as the level of silence, are read as NaN
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    // The time of the frame in the session, in seconds
+    pub time: f32,
+    // The chord best matching the notes heard, ie. Cm7
+    pub chord: Option<String>,
+    // How stable the scores are compared to the previous frame (0 .. 1)
+    pub confidence: f32,
+    // The RMS level of the input, in decibels
+    pub level: f32,
+    // The short-term loudness of the input, in LUFS, once measured long enough
+    pub loudness: Option<f32>,
+    // How much audio was heard relative to the halflife (0 .. 1), the scores meaning little below 1
+    pub warmup: f32,
+    // The note started on this frame, in scientific pitch notation
+    pub onset: Option<String>,
+    // The annotations received since the previous frame
+    pub annotations: Vec<String>,
+    // The input problems since the previous frame, ie. clipping
+    pub events: Vec<String>,
+    // The practice prompt of the session
+    pub prompt: Option<String>,
+    pub metrics: Option<Metrics>,
+    // The dissonance score of each note from Hello::first_note, the lower the more consonant
+    pub scores: Vec<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Hello(Hello),
+    Frame(Frame),
+    // A message type added since this version of the crate, by its name
+    Other(String),
+}
+
+fn field<'a>(value: &'a Value, name: &'static str) -> Result<&'a Value, Error> {
+    value.get(name).ok_or(Error::Field(name))
+}
+
+fn number(value: &Value, name: &'static str) -> Result<f32, Error> {
+    match field(value, name)? {
+        Value::Null => Ok(f32::NAN),
+        Value::Number(n) => Ok(*n as f32),
+        _ => Err(Error::Field(name)),
+    }
+}
+
+fn optional_number(value: &Value, name: &'static str) -> Result<Option<f32>, Error> {
+    match value.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v.as_f64().map(|n| Some(n as f32)).ok_or(Error::Field(name)),
+    }
+}
+
+fn text(value: &Value, name: &'static str) -> Result<String, Error> {
+    field(value, name)?
+        .as_str()
+        .map(str::to_owned)
+        .ok_or(Error::Field(name))
+}
+
+fn optional_text(value: &Value, name: &'static str) -> Result<Option<String>, Error> {
+    match value.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v
+            .as_str()
+            .map(|s| Some(s.to_owned()))
+            .ok_or(Error::Field(name)),
+    }
+}
+
+fn texts(value: &Value, name: &'static str) -> Result<Vec<String>, Error> {
+    match value.get(name) {
+        None => Ok(Vec::new()),
+        Some(v) => v
+            .as_array()
+            .ok_or(Error::Field(name))?
+            .iter()
+            .map(|t| t.as_str().map(str::to_owned).ok_or(Error::Field(name)))
+            .collect(),
+    }
+}
+
+fn metrics(value: &Value) -> Result<Option<Metrics>, Error> {
+    match value.get("metrics") {
+        None | Some(Value::Null) => Ok(None),
+        Some(m) => Ok(Some(Metrics {
+            entropy: number(m, "entropy")?,
+            chord_rate: number(m, "chord_rate")?,
+            tension_variance: number(m, "tension_variance")?,
+        })),
+    }
+}
+
+fn scores(value: &Value) -> Result<Vec<f32>, Error> {
+    field(value, "scores")?
+        .as_array()
+        .ok_or(Error::Field("scores"))?
+        .iter()
+        .map(|s| match s {
+            Value::Null => Ok(f32::NAN),
+            Value::Number(n) => Ok(*n as f32),
+            _ => Err(Error::Field("scores")),
+        })
+        .collect()
+}
+
+// Read a message from its line
+pub fn parse(line: &str) -> Result<Message, Error> {
+    let value = json::parse(line).map_err(Error::Syntax)?;
+    let version = field(&value, "v")?.as_f64().ok_or(Error::Field("v"))? as u32;
+    if version != SCHEMA_VERSION {
+        return Err(Error::Version(version));
+    }
+    match text(&value, "type")?.as_str() {
+        "hello" => Ok(Message::Hello(Hello {
+            engine: text(&value, "engine")?,
+            engine_version: text(&value, "engine_version")?,
+            first_note: text(&value, "first_note")?,
+            notes: field(&value, "notes")?
+                .as_f64()
+                .ok_or(Error::Field("notes"))? as usize,
+        })),
+        "frame" => Ok(Message::Frame(Frame {
+            time: number(&value, "time")?,
+            chord: optional_text(&value, "chord")?,
+            confidence: number(&value, "confidence")?,
+            level: number(&value, "level")?,
+            loudness: optional_number(&value, "loudness")?,
+            warmup: number(&value, "warmup")?,
+            onset: optional_text(&value, "onset")?,
+            annotations: texts(&value, "annotations")?,
+            events: texts(&value, "events")?,
+            prompt: optional_text(&value, "prompt")?,
+            metrics: metrics(&value)?,
+            scores: scores(&value)?,
+        })),
+        other => Ok(Message::Other(other.to_owned())),
+    }
+}
+
+// Reads the messages of a stream, one per line
+pub struct Client<R> {
+    reader: R,
+    line: String,
+}
+
+impl Client<BufReader<TcpStream>> {
+    // Connect to an engine started with --widget
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Client<BufReader<TcpStream>>> {
+        Ok(Client::new(BufReader::new(TcpStream::connect(address)?)))
+    }
+}
+
+impl<R: BufRead> Client<R> {
+    // Read from any stream, ie. a file written with --json
+    pub fn new(reader: R) -> Client<R> {
+        Client {
+            reader,
+            line: String::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Client<R> {
+    type Item = Result<Message, Error>;
+
+    // The next message, None once the engine is gone
+    fn next(&mut self) -> Option<Result<Message, Error>> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) => return Some(parse(self.line.trim())),
+                Err(e) => return Some(Err(Error::Io(e))),
+            }
+        }
+    }
+}
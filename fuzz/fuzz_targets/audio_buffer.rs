@@ -0,0 +1,53 @@
+// Blocks of any length and samples of any value, ie. NaN, through every buffer option
+// The first bytes set the options, the others are blocks of a length byte followed by their floats
+#![no_main]
+
+use std::sync::mpsc::channel;
+
+use libfuzzer_sys::fuzz_target;
+
+use improve::audio_buffer::{AudioBuffer, BufferOptions};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 3 {
+        return;
+    }
+    let (flags, hop) = (data[1], data[2] as usize);
+    let options = BufferOptions {
+        resolution: 1 << (data[0] % 12),
+        discard: flags & 1 != 0,
+        hop: if flags & 2 != 0 { Some(hop) } else { None },
+        agc: if flags & 4 != 0 { Some(0.5) } else { None },
+        gate: if flags & 8 != 0 { Some(-40f32) } else { None },
+        gate_attack: 0.01,
+        gate_release: 0.1,
+        idle: if flags & 16 != 0 { Some(0.1) } else { None },
+    };
+
+    let (sender, receiver) = channel();
+    let mut rest = &data[3..];
+    while let Some((&length, tail)) = rest.split_first() {
+        let bytes = (length as usize * 4).min(tail.len() / 4 * 4);
+        let block = tail[..bytes]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect::<Vec<f32>>();
+        sender.send(block).unwrap();
+        rest = &tail[bytes..];
+    }
+    drop(sender);
+
+    let mut buffer = AudioBuffer::new(receiver, options);
+    if flags & 32 != 0 {
+        buffer.set_rate(44100);
+    }
+    if flags & 64 != 0 {
+        buffer.resample(48000, 44100);
+    }
+    while let Some(packet) = buffer.take() {
+        assert_eq!(packet.len(), options.resolution);
+        buffer.take_events();
+        buffer.loudness();
+        buffer.gain();
+    }
+});
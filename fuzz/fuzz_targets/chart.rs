@@ -0,0 +1,15 @@
+// Charts of any bytes, in every chord convention
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use improve::chart::Chart;
+use improve::chords::Symbols;
+use improve::tools;
+
+fuzz_target!(|data: &[u8]| {
+    let text = tools::decode_text(data);
+    for &symbols in [Symbols::Standard, Symbols::Jazz, Symbols::German].iter() {
+        let _ = Chart::parse(&text, symbols);
+    }
+});
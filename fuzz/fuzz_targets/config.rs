@@ -0,0 +1,15 @@
+// Configuration files of any bytes
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use improve::config::Config;
+use improve::tools;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(config) = Config::parse("fuzz.conf", &tools::decode_text(data)) {
+        for &section in ["layout", "controller", "keys", "calibration"].iter() {
+            config.section(section);
+        }
+    }
+});
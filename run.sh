@@ -1 +1 @@
-RUSTFLAGS="$RUSTFLAGS -C target-cpu=native -C panic=abort" RUST_BACKTRACE=1 cargo run --release -- -od -r 65536 -z1
\ No newline at end of file
+RUSTFLAGS="$RUSTFLAGS -C target-cpu=native -C panic=abort" RUST_BACKTRACE=1 cargo run --release -- -d --hop 50% -r 65536 -z1
\ No newline at end of file
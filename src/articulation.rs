@@ -0,0 +1,225 @@
+// Tracks the pitch of sustained notes, to analyse vibrato and bends
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::chords::Chord;
+use crate::frequency::Frequency;
+use crate::intonation::Intonation;
+use crate::notes::Note;
+
+// How far the pitch can move from the starting note and still be the same note, in cents
+const MAX_BEND: f32 = 250f32;
+// Peaks weaker than this fraction of the loudest note so far end the note
+const RELEASE_RATIO: f32 = 0.1;
+// The minimum deviation of the end of a note for it to count as a bend, in cents
+const MIN_BEND: f32 = 50f32;
+// The minimum number of vibrato cycles to report a vibrato
+const MIN_VIBRATO_CYCLES: f32 = 2f32;
+// The minimum depth of a vibrato, smaller oscillations are detection noise
+const MIN_VIBRATO_DEPTH: f32 = 10f32;
+// The maximum number of pitch points kept per note
+const MAX_TRAJECTORY: usize = 512;
+
+// The names of the intervals from a chord root
+const INTERVAL_NAMES: [&str; 12] = [
+    "root", "b2", "2", "b3", "3", "4", "b5", "5", "b6", "6", "b7", "7",
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct Vibrato {
+    // The number of oscillations per second
+    pub rate: f32,
+    // Half of the peak to peak deviation, in cents
+    pub depth: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bend {
+    // The note the pitch was bent to
+    pub target: Note,
+    // The remaining deviation from the target, in cents
+    pub cents: f32,
+}
+
+// The pitch analysis of a sustained note
+#[derive(Clone, Debug)]
+pub struct NoteDetail {
+    // The note the pitch started on
+    pub note: Note,
+    // How long the note has been held for, in seconds
+    pub duration: f32,
+    // The pitch over time, in cents from the starting note
+    pub trajectory: Vec<f32>,
+    // The time covered by the trajectory, in seconds
+    span: f32,
+    pub vibrato: Option<Vibrato>,
+    pub bend: Option<Bend>,
+}
+
+impl NoteDetail {
+    fn new(note: Note) -> NoteDetail {
+        NoteDetail {
+            note,
+            duration: 0f32,
+            trajectory: Vec::new(),
+            span: 0f32,
+            vibrato: None,
+            bend: None,
+        }
+    }
+
+    // Update the vibrato and bend from the trajectory
+    fn analyse(&mut self) {
+        let len = self.trajectory.len();
+        if len < 4 {
+            return;
+        }
+        // The end of the note tells where it was bent to
+        let tail = &self.trajectory[len - len / 4..];
+        let end = tail.iter().sum::<f32>() / tail.len() as f32;
+        self.bend = if end.abs() >= MIN_BEND {
+            let semitones = (end / 100f32).round() as i32;
+            Note::iter()
+                .nth((self.note as i32 + semitones).max(0) as usize)
+                .map(|target| Bend {
+                    target,
+                    cents: end - semitones as f32 * 100f32,
+                })
+        } else {
+            None
+        };
+
+        // The vibrato oscillates around the average pitch
+        let mean = self.trajectory.iter().sum::<f32>() / len as f32;
+        let crossings = self
+            .trajectory
+            .iter()
+            .tuple_windows()
+            .filter(|&(a, b)| (a - mean).signum() != (b - mean).signum())
+            .count();
+        let cycles = crossings as f32 / 2f32;
+        let (min, max) = self.trajectory.iter().cloned().minmax().into_option().unwrap();
+        let depth = (max - min) / 2f32;
+        self.vibrato = if cycles >= MIN_VIBRATO_CYCLES && depth >= MIN_VIBRATO_DEPTH && self.span > 0f32 {
+            Some(Vibrato {
+                rate: cycles / self.span,
+                depth,
+            })
+        } else {
+            None
+        };
+    }
+
+    // Human readable lines describing the note
+    pub fn describe(&self, chord: Option<Chord>, names: [&str; 12]) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{}{} held {:.1}s",
+            names[self.note.get_octave_index() as usize].trim(),
+            self.note as usize / 12,
+            self.duration
+        )];
+        if let Some(vibrato) = self.vibrato {
+            lines.push(format!(
+                "vibrato {:.1}Hz, {:.0} cents deep",
+                vibrato.rate, vibrato.depth
+            ));
+        }
+        if let Some(bend) = self.bend {
+            // Name the target from the chord root if there is one
+            let target = match chord {
+                Some(chord) => {
+                    let interval = (bend.target.get_octave_index() as usize + 12 - chord.root) % 12;
+                    format!("the {}", INTERVAL_NAMES[interval])
+                }
+                None => names[bend.target.get_octave_index() as usize].trim().to_owned(),
+            };
+            let direction = if bend.cents < 0f32 { "flat" } else { "sharp" };
+            lines.push(format!(
+                "bent to {}, {:.0} cents {}",
+                target,
+                bend.cents.abs(),
+                direction
+            ));
+        }
+        lines
+    }
+}
+
+// Follows the loudest pitch, starting a new note when it moves too far or fades
+pub struct ArticulationTracker {
+    current: Option<NoteDetail>,
+    // The loudest amplitude of the current note
+    peak_amplitude: f32,
+    // If the current note started on the last update
+    onset: bool,
+}
+
+impl ArticulationTracker {
+    pub fn new() -> ArticulationTracker {
+        ArticulationTracker {
+            current: None,
+            peak_amplitude: 0f32,
+            onset: false,
+        }
+    }
+
+    // The note started on the last update, if any
+    pub fn onset(&self) -> Option<Note> {
+        if self.onset {
+            self.current.as_ref().map(|detail| detail.note)
+        } else {
+            None
+        }
+    }
+
+    // Add the loudest peak of a frame, and return the current note
+    pub fn update(&mut self, peaks: &[Frequency], seconds: f32) -> Option<NoteDetail> {
+        let loudest = peaks
+            .iter()
+            .max_by(|a, b| a.intensity.partial_cmp(&b.intensity).unwrap())
+            .cloned();
+        self.onset = false;
+        let loudest = match loudest {
+            Some(f) => f,
+            None => {
+                self.current = None;
+                return None;
+            }
+        };
+        let amplitude = loudest.amplitude();
+
+        // Continue the current note if the pitch and level allow it
+        let continued = match self.current.as_mut() {
+            Some(detail) => {
+                let cents = Intonation::cents(loudest.value, detail.note);
+                if cents.abs() <= MAX_BEND && amplitude >= self.peak_amplitude * RELEASE_RATIO {
+                    detail.duration += seconds;
+                    if detail.trajectory.len() < MAX_TRAJECTORY {
+                        detail.trajectory.push(cents);
+                        detail.span += seconds;
+                    }
+                    detail.analyse();
+                    self.peak_amplitude = self.peak_amplitude.max(amplitude);
+                    true
+                } else {
+                    false
+                }
+            }
+            None => false,
+        };
+
+        // Otherwise start a new note
+        if !continued {
+            self.current = Note::from_freq(loudest.value).map(|note| {
+                let mut detail = NoteDetail::new(note);
+                detail.trajectory.push(Intonation::cents(loudest.value, note));
+                detail
+            });
+            self.peak_amplitude = amplitude;
+            self.onset = self.current.is_some();
+        }
+        self.current.clone()
+    }
+}
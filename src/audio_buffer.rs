@@ -1,61 +1,678 @@
 use std::collections::VecDeque;
 use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
 
-#[derive(Default)]
+use crate::echo::Canceller;
+use crate::loudness::LoudnessMeter;
+use crate::resample::Resampler;
+use crate::ring::{Consumer, Wait};
+use crate::wav::Recorder;
+
+// The sample value counted as clipped
+const CLIP_LEVEL: f32 = 0.999;
+// The largest sample value kept, beyond full scale some drivers emit garbage on overruns
+const MAX_SAMPLE: f32 = 1f32;
+// How late the capture can be before it counts as a dropout, in seconds
+const DROPOUT_TOLERANCE: f64 = 0.25;
+// The RMS level the automatic gain control brings the input to, in decibels
+const AGC_TARGET: f32 = -20f32;
+// The level under which the gain is held, so silence and noise are not raised, in decibels
+const AGC_FLOOR: f32 = -50f32;
+// The gain range of the automatic gain control, in decibels
+const AGC_MAX_GAIN: f32 = 24f32;
+const AGC_MIN_GAIN: f32 = -12f32;
+// The level under which the input counts as silent for the idle detection, in decibels
+const IDLE_LEVEL: f32 = -60f32;
+// How long the meter holds a peak before following lower ones, in seconds
+const PEAK_HOLD: f32 = 1.5;
+// How long a capture can write nothing before it counts as disconnected
+const STALL_TIMEOUT: Duration = Duration::from_secs(1);
+
+// The levels of the input captured since the previous frame, before the gain control, in decibels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Meter {
+    pub rms: f32,
+    // The highest sample, held for a moment so short peaks can be read
+    pub peak: f32,
+}
+
+impl Default for Meter {
+    fn default() -> Meter {
+        Meter {
+            rms: f32::NEG_INFINITY,
+            peak: f32::NEG_INFINITY,
+        }
+    }
+}
+
+// A problem with the input, logged in the session and flagged on the spectrogram
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    // The number of samples at full scale
+    Clip(usize),
+    // The number of samples discarded, the analysis falling behind
+    Overrun(usize),
+    // The audio missing from the capture, in seconds
+    Dropout(f32),
+    // The number of NaN or infinite samples replaced by silence
+    Invalid(usize),
+    // The seconds the capture wrote nothing, ie. a USB interface unplugged, replaced by silence
+    Disconnected(f32),
+}
+
+impl InputEvent {
+    pub fn describe(self) -> String {
+        match self {
+            InputEvent::Clip(samples) => format!("clipping ({} samples)", samples),
+            InputEvent::Overrun(samples) => format!("overrun ({} samples discarded)", samples),
+            InputEvent::Dropout(seconds) => format!("dropout ({:.0}ms missing)", seconds * 1000f32),
+            InputEvent::Invalid(samples) => format!("invalid samples ({} replaced)", samples),
+            InputEvent::Disconnected(seconds) => format!("disconnected ({:.0}s without audio)", seconds),
+        }
+    }
+
+    // Add an event of the same kind to this one, returning false for another kind
+    pub fn merge(&mut self, other: InputEvent) -> bool {
+        match (self, other) {
+            (InputEvent::Clip(a), InputEvent::Clip(b))
+            | (InputEvent::Overrun(a), InputEvent::Overrun(b))
+            | (InputEvent::Invalid(a), InputEvent::Invalid(b)) => {
+                *a += b;
+                true
+            }
+            (InputEvent::Dropout(a), InputEvent::Dropout(b))
+            | (InputEvent::Disconnected(a), InputEvent::Disconnected(b)) => {
+                *a += b;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// How the channels of the capture reach the analysis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelMode {
+    // Mixed down to one stream
+    Mix,
+    // Only one channel, from 0
+    Select(usize),
+    // Every channel analysed apart
+    Split,
+}
+
+impl ChannelMode {
+    // mix, split, or a channel number from 1
+    pub fn from_name(name: &str) -> Option<ChannelMode> {
+        match name {
+            "mix" => Some(ChannelMode::Mix),
+            "split" => Some(ChannelMode::Split),
+            _ => match name.parse::<usize>() {
+                Ok(channel) if channel >= 1 => Some(ChannelMode::Select(channel - 1)),
+                _ => None,
+            },
+        }
+    }
+}
+
+// Separate interleaved samples into the streams analysed, one unless they are split
+pub fn deinterleave(input: &[f32], channels: usize, mode: ChannelMode) -> Vec<Vec<f32>> {
+    let channels = channels.max(1);
+    let streams = if mode == ChannelMode::Split { channels } else { 1 };
+    (0..streams)
+        .map(|stream| stream_samples(input, channels, mode, stream).collect())
+        .collect()
+}
+
+// The samples of one of the streams analysed, without allocating for the capture callbacks
+pub fn stream_samples<'a>(
+    input: &'a [f32],
+    channels: usize,
+    mode: ChannelMode,
+    stream: usize,
+) -> impl Iterator<Item = f32> + 'a {
+    let channels = channels.max(1);
+    input.chunks_exact(channels).map(move |frame| match mode {
+        ChannelMode::Mix => frame.iter().sum::<f32>() / channels as f32,
+        ChannelMode::Select(channel) => frame[channel.min(channels - 1)],
+        ChannelMode::Split => frame[stream.min(channels - 1)],
+    })
+}
+
+// Where the samples come from, packets sent by the file and network sources, or the ring of a capture
+pub enum Input {
+    Packets(Receiver<Vec<f32>>),
+    Ring(Consumer),
+}
+
+impl From<Receiver<Vec<f32>>> for Input {
+    fn from(receiver: Receiver<Vec<f32>>) -> Input {
+        Input::Packets(receiver)
+    }
+}
+
+impl From<Consumer> for Input {
+    fn from(consumer: Consumer) -> Input {
+        Input::Ring(consumer)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
 pub struct BufferOptions {
     pub resolution: usize,
     pub discard: bool,
-    pub overlap: bool,
+    // The samples between the starts of two packets, None for packets end to end
+    pub hop: Option<usize>,
+    // The window of the automatic gain control in seconds, None without it
+    pub agc: Option<f32>,
+    // The level under which the noise gate closes in decibels, None without it
+    pub gate: Option<f32>,
+    // How long the gate takes to open and to close, in seconds
+    pub gate_attack: f32,
+    pub gate_release: f32,
+    // The silence in seconds after which the input is idle, None to always analyse
+    pub idle: Option<f32>,
 }
 
 pub struct AudioBuffer {
     options: BufferOptions,
     buffer: VecDeque<f32>,
-    receiver: Receiver<Vec<f32>>,
+    input: Input,
+    // The samples read from the ring, kept to be reused
+    scratch: Vec<f32>,
+    // When set, consecutive packets are only this many elements apart
+    hop: Option<usize>,
+    // Measures every sample received, including the discarded ones
+    loudness: Option<LoudnessMeter>,
+    // The input rate, 0 until known
+    rate: i32,
+    // When the first packet was received, and the samples received since
+    started: Option<Instant>,
+    received: u64,
+    // The audio already reported missing, in seconds
+    missing: f64,
+    // The problems since the last call to take_events
+    events: Vec<InputEvent>,
+    // The NaN or infinite samples replaced since the start
+    invalid: u64,
+    // The mean square of the input averaged over the AGC window, None until something is heard
+    mean_square: Option<f32>,
+    // The gain applied to the last packet taken
+    gain: f32,
+    // How open the noise gate is (0 .. 1)
+    gate: f32,
+    // How long the input has been silent, in seconds
+    quiet: f32,
+    // The sum of the squares and the count of the samples received since the last meter reading
+    meter_squares: f32,
+    meter_samples: usize,
+    // The peak held by the meter, and for how long, in seconds
+    peak: f32,
+    peak_age: f32,
+    // Writes the samples received to WAV files, when recording
+    recorder: Option<Recorder>,
+    // Subtracts the playback heard by the microphone, when suppressing the feedback
+    canceller: Option<Canceller>,
+    // Brings the input to the analysis rate, when the capture opened at another one
+    resampler: Option<Resampler>,
+    // The samples received behind the last packet taken
+    backlog: usize,
 }
 
 impl AudioBuffer {
-    pub fn new(receiver: Receiver<Vec<f32>>, options: BufferOptions) -> AudioBuffer {
+    pub fn new<I: Into<Input>>(input: I, options: BufferOptions) -> AudioBuffer {
         AudioBuffer {
             buffer: VecDeque::with_capacity(options.resolution),
-            receiver,
+            input: input.into(),
+            scratch: Vec::with_capacity(options.resolution),
+            hop: options.hop.map(|h| h.max(1).min(options.resolution)),
             options,
+            loudness: None,
+            rate: 0,
+            started: None,
+            received: 0,
+            missing: 0f64,
+            events: Vec::new(),
+            invalid: 0,
+            mean_square: None,
+            gain: 1f32,
+            gate: 1f32,
+            quiet: 0f32,
+            meter_squares: 0f32,
+            meter_samples: 0,
+            peak: 0f32,
+            peak_age: 0f32,
+            recorder: None,
+            canceller: None,
+            resampler: None,
+            backlog: 0,
+        }
+    }
+
+    // Record every sample received, including the discarded ones
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    // Remove the playback of the program from the samples analysed
+    pub fn suppress_feedback(&mut self, canceller: Canceller) {
+        self.canceller = Some(canceller);
+    }
+
+    // Resample the input from the capture rate to the analysis rate, the one set
+    pub fn resample(&mut self, capture_rate: i32, rate: i32) {
+        self.resampler = Some(Resampler::new(capture_rate, rate));
+    }
+
+    // Measure the loudness and the dropouts of the input, at its sample rate
+    pub fn set_rate(&mut self, rate: i32) {
+        self.rate = rate;
+        self.loudness = Some(LoudnessMeter::new(rate));
+    }
+
+    // The short-term loudness, in LUFS
+    pub fn loudness(&self) -> Option<f32> {
+        self.loudness.as_ref().and_then(LoudnessMeter::short_term)
+    }
+
+    // The input problems since the previous call
+    pub fn take_events(&mut self) -> Vec<InputEvent> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+
+    // The NaN or infinite samples replaced since the start
+    pub fn invalid_samples(&self) -> u64 {
+        self.invalid
+    }
+
+    // The gain applied to the last packet taken, in decibels, 0 without the gain control
+    pub fn gain(&self) -> f32 {
+        20f32 * self.gain.log10()
+    }
+
+    // Open the noise gate over the attack while the input is above the threshold, close it over the release
+    fn update_gate(&mut self, packet: &[f32]) {
+        let threshold = match self.options.gate {
+            Some(threshold) if self.rate > 0 && !packet.is_empty() => threshold,
+            _ => return,
+        };
+        let mean_square = packet.iter().map(|s| s * s).sum::<f32>() / packet.len() as f32;
+        let duration = packet.len() as f32 / self.rate as f32;
+        if 10f32 * mean_square.log10() >= threshold {
+            self.gate = (self.gate + duration / self.options.gate_attack.max(1e-3)).min(1f32);
+        } else {
+            self.gate = (self.gate - duration / self.options.gate_release.max(1e-3)).max(0f32);
+        }
+    }
+
+    // The RMS and peak levels since the previous call
+    pub fn meter(&mut self) -> Meter {
+        let rms = if self.meter_samples > 0 {
+            10f32 * (self.meter_squares / self.meter_samples as f32).log10()
+        } else {
+            f32::NEG_INFINITY
+        };
+        self.meter_squares = 0f32;
+        self.meter_samples = 0;
+        Meter {
+            rms,
+            peak: 20f32 * self.peak.log10(),
         }
     }
 
+    // Sum the squares for the RMS, and keep the peak until it is older than the hold
+    fn update_meter(&mut self, packet: &[f32]) {
+        self.meter_squares += packet.iter().map(|s| s * s).sum::<f32>();
+        self.meter_samples += packet.len();
+        let peak = packet.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+        if self.rate > 0 {
+            self.peak_age += packet.len() as f32 / self.rate as f32;
+        }
+        if peak >= self.peak || self.peak_age > PEAK_HOLD {
+            self.peak = peak;
+            self.peak_age = 0f32;
+        }
+    }
+
+    // Whether the input was silent long enough for the analysis to pause
+    pub fn is_idle(&self) -> bool {
+        self.options.idle.map_or(false, |idle| self.quiet >= idle)
+    }
+
+    // Count the silence, anything under the noise gate being silent
+    fn update_idle(&mut self, packet: &[f32]) {
+        if self.rate <= 0 || packet.is_empty() {
+            return;
+        }
+        let threshold = self.options.gate.map_or(IDLE_LEVEL, |g| g.max(IDLE_LEVEL));
+        let mean_square = packet.iter().map(|s| s * s).sum::<f32>() / packet.len() as f32;
+        if 10f32 * mean_square.log10() < threshold {
+            self.quiet += packet.len() as f32 / self.rate as f32;
+        } else {
+            self.quiet = 0f32;
+        }
+    }
+
+    // Average the level of the packets played, over the window of the gain control
+    fn measure_gain(&mut self, packet: &[f32]) {
+        let window = match self.options.agc {
+            Some(window) if self.rate > 0 && !packet.is_empty() => window * self.rate as f32,
+            _ => return,
+        };
+        let mean_square = packet.iter().map(|s| s * s).sum::<f32>() / packet.len() as f32;
+        if 10f32 * mean_square.log10() < AGC_FLOOR {
+            return;
+        }
+        let weight = 1f32 - (-(packet.len() as f32) / window).exp();
+        let average = self.mean_square.get_or_insert(mean_square);
+        *average += weight * (mean_square - *average);
+    }
+
+    fn push_event(&mut self, event: InputEvent) {
+        if !self.events.last_mut().map_or(false, |last| last.merge(event)) {
+            self.events.push(event);
+        }
+    }
+
+    fn receive(&mut self, packet: &mut [f32]) {
+        // A single NaN would poison the loudness and every score after it
+        let mut invalid = 0;
+        for sample in packet.iter_mut() {
+            if !sample.is_finite() {
+                *sample = 0f32;
+                invalid += 1;
+            } else {
+                *sample = sample.max(-MAX_SAMPLE).min(MAX_SAMPLE);
+            }
+        }
+        if invalid > 0 {
+            self.invalid += invalid as u64;
+            self.push_event(InputEvent::Invalid(invalid));
+        }
+        // A failing disk stops the recording, not the analysis
+        if let Some(Err(e)) = self.recorder.as_mut().map(|r| r.write(packet)) {
+            status!("{}, recording stopped", e);
+            self.recorder = None;
+        }
+        self.update_meter(packet);
+        let clipped = packet.iter().filter(|s| s.abs() >= CLIP_LEVEL).count();
+        if clipped > 0 {
+            self.push_event(InputEvent::Clip(clipped));
+        }
+        // The levels and the clipping are those of the capture, the rest is without the playback
+        if let Some(canceller) = self.canceller.as_mut() {
+            canceller.cancel(packet);
+        }
+        if let Some(loudness) = self.loudness.as_mut() {
+            loudness.feed(packet);
+        }
+        self.measure_gain(packet);
+        self.update_gate(packet);
+        self.update_idle(packet);
+        // A capture later than the audio already missing has dropped some more
+        if self.rate > 0 {
+            let started = *self.started.get_or_insert_with(Instant::now);
+            self.received += packet.len() as u64;
+            let late = started.elapsed().as_secs_f64() - self.received as f64 / self.rate as f64;
+            if late - self.missing > DROPOUT_TOLERANCE {
+                self.push_event(InputEvent::Dropout((late - self.missing) as f32));
+                self.missing = late;
+            }
+        }
+        self.buffer.extend(packet.iter());
+    }
+
+    // Bring the samples captured to the analysis rate, then receive them
+    fn feed(&mut self, packet: &mut Vec<f32>) {
+        match self.resampler.as_mut() {
+            Some(resampler) => {
+                let mut resampled = resampler.process(packet);
+                self.receive(&mut resampled);
+            }
+            None => self.receive(packet),
+        }
+    }
+
+    // Receive the samples waiting, or wait for some, returning false once the input is closed
+    fn read(&mut self, wait: bool) -> bool {
+        match &mut self.input {
+            Input::Packets(receiver) => {
+                let packet = if wait { receiver.recv().ok() } else { receiver.try_recv().ok() };
+                match packet {
+                    Some(mut packet) => {
+                        self.feed(&mut packet);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Input::Ring(consumer) => {
+                let mut scratch = std::mem::take(&mut self.scratch);
+                let dropped = if wait {
+                    match consumer.read_wait(&mut scratch, STALL_TIMEOUT) {
+                        Wait::Read(dropped) => Some(dropped),
+                        Wait::Closed => None,
+                        // Keep the analysis and the displays going on silence until the capture is back
+                        Wait::Timeout => {
+                            self.push_event(InputEvent::Disconnected(STALL_TIMEOUT.as_secs_f32()));
+                            let rate = self.resampler.as_ref().map_or(self.rate, Resampler::input_rate);
+                            scratch.resize((STALL_TIMEOUT.as_secs_f32() * rate.max(1) as f32) as usize, 0f32);
+                            Some(0)
+                        }
+                    }
+                } else {
+                    Some(consumer.read(&mut scratch))
+                };
+                // The samples that did not fit in the ring, the analysis falling behind
+                if let Some(dropped) = dropped.filter(|&d| d > 0) {
+                    self.push_event(InputEvent::Overrun(dropped));
+                }
+                let received = !scratch.is_empty();
+                if received {
+                    self.feed(&mut scratch);
+                }
+                scratch.clear();
+                self.scratch = scratch;
+                if wait {
+                    dropped.is_some()
+                } else {
+                    received
+                }
+            }
+        }
+    }
+
+    // Temporarily emit packets more often, reusing part of the previous packet
+    // None goes back to the hop of the options, which a longer one does not override
+    pub fn set_hop(&mut self, hop: Option<usize>) {
+        let hop = match (hop, self.options.hop) {
+            (Some(hop), Some(base)) => Some(hop.min(base)),
+            (hop, base) => hop.or(base),
+        };
+        self.hop = hop.map(|h| h.max(1).min(self.options.resolution));
+    }
+
+    // The samples received behind the last packet taken, and how long its newest sample waited, in seconds
+    pub fn backlog(&self) -> usize {
+        self.backlog
+    }
+
+    pub fn lag(&self) -> f32 {
+        match self.rate {
+            0 => 0f32,
+            rate => self.backlog as f32 / rate as f32,
+        }
+    }
+
+    pub fn resolution(&self) -> usize {
+        self.options.resolution
+    }
+
     // Return n elements, n being options.resolution
+    // If options.agc is set, normalize their level over its window
+    // If options.gate is set, silence them while the input is under its threshold
+    pub fn take(&mut self) -> Option<Vec<f32>> {
+        let mut packet = self.take_raw()?;
+        if let Some(mean_square) = self.mean_square {
+            let gain = AGC_TARGET - 10f32 * mean_square.log10();
+            self.gain = 10f32.powf(gain.max(AGC_MIN_GAIN).min(AGC_MAX_GAIN) / 20f32);
+        }
+        if self.gain != 1f32 || self.gate < 1f32 {
+            let gain = self.gain * self.gate;
+            for sample in packet.iter_mut() {
+                *sample *= gain;
+            }
+        }
+        Some(packet)
+    }
+
     // If options.discard is true, overwrite old elements
-    // If options.overlap is true, don't delete read elements
+    // If a hop is set, only delete that many elements, the next packet reusing the others
     // When receiver dies and data is exhausted, start returning None
-    pub fn take(&mut self) -> Option<Vec<f32>> {
+    fn take_raw(&mut self) -> Option<Vec<f32>> {
         // Set n as the previously received packet resolution
         let n = self.options.resolution;
         // Read all waiting packets
-        for packet in self.receiver.try_iter() {
-            self.buffer.extend(packet);
-        }
+        while self.read(false) {}
         // Make sure buffer contains at least n elements
         while self.buffer.len() < n {
-            let recv = self.receiver.recv().ok()?;
-            self.buffer.extend(recv);
+            if !self.read(true) {
+                return None;
+            }
         }
-        // If discard is on, discard surplus data
+        // If discard is on, discard surplus data, on purpose so no overrun
         if self.options.discard && self.buffer.len() > n {
-            self.buffer.drain(0..self.buffer.len() - n);
-        }
-        // If overlap is allowed, return n oldest elements, and only delete those over the limit
-        if self.options.overlap {
-            let ret = self.buffer.iter().cloned().take(n).collect();
-            // Calculate unneeded data for next batch
             let surplus = self.buffer.len() - n;
-            // Cap surplus at n to avoid ignoring data
-            let surplus = surplus.min(n);
-            // Delete surplus data
             self.buffer.drain(0..surplus);
-            Some(ret)
-        // If overlap is not allowed, remove them before returning
-        } else {
-            Some(self.buffer.drain(0..n).collect())
         }
+        self.backlog = self.buffer.len() - n;
+        // If a hop is set, return n oldest elements, and only delete the hop
+        if let Some(hop) = self.hop {
+            let ret = self.buffer.iter().cloned().take(n).collect();
+            self.buffer.drain(0..hop);
+            return Some(ret);
+        }
+        // Otherwise remove them before returning
+        Some(self.buffer.drain(0..n).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Sender};
+
+    const RATE: i32 = 8000;
+    // A tenth of a second
+    const PACKET: usize = 800;
+
+    fn open(options: BufferOptions) -> (Sender<Vec<f32>>, AudioBuffer) {
+        let (sender, receiver) = mpsc::channel();
+        let mut buffer = AudioBuffer::new(
+            receiver,
+            BufferOptions {
+                resolution: PACKET,
+                ..options
+            },
+        );
+        buffer.set_rate(RATE);
+        (sender, buffer)
+    }
+
+    // The packet taken after sending one of a constant level
+    fn level(sender: &Sender<Vec<f32>>, buffer: &mut AudioBuffer, level: f32) -> Vec<f32> {
+        sender.send(vec![level; PACKET]).unwrap();
+        buffer.take().unwrap()
+    }
+
+    #[test]
+    fn gain_brings_the_level_to_the_target() {
+        let (sender, mut buffer) = open(BufferOptions {
+            agc: Some(1f32),
+            ..BufferOptions::default()
+        });
+        // At -40 dB, raised by 20 dB
+        let packet = level(&sender, &mut buffer, 0.01);
+        assert!(packet.iter().all(|s| (s - 0.1).abs() < 1e-4));
+        assert!((buffer.gain() - 20f32).abs() < 1e-3);
+        // Under the floor, the gain is held
+        let packet = level(&sender, &mut buffer, 0.0001);
+        assert!(packet.iter().all(|s| (s - 0.001).abs() < 1e-6));
+
+        // At -48 dB, raised by no more than the largest gain
+        let (sender, mut buffer) = open(BufferOptions {
+            agc: Some(1f32),
+            ..BufferOptions::default()
+        });
+        level(&sender, &mut buffer, 0.004);
+        assert!((buffer.gain() - AGC_MAX_GAIN).abs() < 1e-3);
+
+        // Without the gain control, the samples are untouched
+        let (sender, mut buffer) = open(BufferOptions::default());
+        assert!(level(&sender, &mut buffer, 0.01).iter().all(|&s| s == 0.01));
+        assert_eq!(buffer.gain(), 0f32);
+    }
+
+    #[test]
+    fn gate_closes_over_the_release() {
+        let (sender, mut buffer) = open(BufferOptions {
+            gate: Some(-40f32),
+            gate_attack: 0.1,
+            gate_release: 0.2,
+            ..BufferOptions::default()
+        });
+        assert!(level(&sender, &mut buffer, 0.1).iter().all(|&s| s == 0.1));
+        // Half closed after a tenth of a second under the threshold, then closed
+        assert!(level(&sender, &mut buffer, 0.001).iter().all(|s| (s - 0.0005).abs() < 1e-6));
+        assert!(level(&sender, &mut buffer, 0.001).iter().all(|&s| s == 0f32));
+        // Open again after the attack
+        assert!(level(&sender, &mut buffer, 0.1).iter().all(|&s| s == 0.1));
+    }
+
+    #[test]
+    fn discarding_is_no_overrun() {
+        let (sender, mut buffer) = open(BufferOptions {
+            discard: true,
+            ..BufferOptions::default()
+        });
+        for &level in [0.1, 0.2, 0.3].iter() {
+            sender.send(vec![level; PACKET]).unwrap();
+        }
+        // Only the newest packet is analysed
+        assert!(buffer.take().unwrap().iter().all(|&s| s == 0.3));
+        assert!(!buffer
+            .take_events()
+            .iter()
+            .any(|e| matches!(e, InputEvent::Overrun(_))));
+    }
+
+    #[test]
+    fn silence_makes_the_input_idle() {
+        let (sender, mut buffer) = open(BufferOptions {
+            idle: Some(0.25),
+            ..BufferOptions::default()
+        });
+        for _ in 0..2 {
+            level(&sender, &mut buffer, 0f32);
+            assert!(!buffer.is_idle());
+        }
+        level(&sender, &mut buffer, 0f32);
+        assert!(buffer.is_idle());
+        level(&sender, &mut buffer, 0.1);
+        assert!(!buffer.is_idle());
+
+        // Under the noise gate counts as silent
+        let (sender, mut buffer) = open(BufferOptions {
+            idle: Some(0.05),
+            gate: Some(-40f32),
+            ..BufferOptions::default()
+        });
+        level(&sender, &mut buffer, 0.001);
+        assert!(buffer.is_idle());
+
+        // Without the option, the input is never idle
+        let (sender, mut buffer) = open(BufferOptions::default());
+        level(&sender, &mut buffer, 0f32);
+        assert!(!buffer.is_idle());
     }
 }
@@ -0,0 +1,109 @@
+// The calibration of the capture clock, measured against a reference tone
+// An interface whose clock runs off shifts every analysed frequency, the correction rescales them
+// [calibration]: rate = FACTOR, the actual rate of the device over its nominal one
+
+// Standard
+use std::time::{Duration, Instant};
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::audio_buffer::AudioBuffer;
+use crate::config::Config;
+use crate::error::Error;
+use crate::fourier::{Analyzer, FourierAnalyzer, ScoringOptions};
+use crate::spectrum;
+use crate::tools;
+
+// The largest clock error measured, beyond which the tone heard is not the reference
+const MAX_ERROR: f32 = 0.03;
+// The number of frames hearing the reference needed for a measure
+const MIN_FRAMES: usize = 10;
+
+// The correction of the config, if calibrated
+pub fn from_config(config: &Config) -> Result<Option<f32>, String> {
+    let mut correction = None;
+    for (number, line) in config.section("calibration") {
+        let mut parts = line.splitn(2, '=').map(str::trim);
+        match (parts.next(), parts.next().map(str::parse::<f32>)) {
+            (Some("rate"), Some(Ok(factor))) if (factor - 1f32).abs() <= MAX_ERROR => correction = Some(factor),
+            (Some("rate"), _) => {
+                return Err(config.error(
+                    number,
+                    &format!("the rate correction must be within {}% of 1", MAX_ERROR * 100f32),
+                ))
+            }
+            _ => return Err(config.error(number, "expected rate = FACTOR")),
+        }
+    }
+    Ok(correction)
+}
+
+// Listen to the reference tone for the given duration, returning the rate correction
+pub fn calibrate(
+    buffer: AudioBuffer,
+    options: ScoringOptions,
+    reference: f32,
+    duration: Duration,
+) -> Result<f32, Error> {
+    let mut buffer = buffer;
+    // Measure the uncorrected frequencies
+    let mut analyzer = FourierAnalyzer::new(ScoringOptions {
+        calibration: None,
+        ..options
+    });
+
+    status!("Listening for {} seconds, play the {} Hz reference", duration.as_secs(), reference);
+    let start = Instant::now();
+    let mut measures = Vec::new();
+    while start.elapsed() < duration {
+        let vec = match buffer.take() {
+            Some(vec) => vec,
+            None => break,
+        };
+        let fourier = analyzer.analyze(&vec[..], None);
+        // The reference being the loudest, its peak is the most prominent
+        if let Some(peak) = spectrum::pick_peaks(&fourier, 1).first() {
+            if (peak.value / reference - 1f32).abs() <= MAX_ERROR {
+                measures.push(peak.value);
+            }
+        }
+    }
+    if measures.len() < MIN_FRAMES {
+        return Err(Error::NotHeard(format!("The {} Hz reference was not heard long enough", reference)));
+    }
+
+    // The median, ignoring the frames of the tone dying out
+    let measures = measures.into_iter().sorted_by(|a, b| a.partial_cmp(b).unwrap()).collect_vec();
+    let measured = measures[measures.len() / 2];
+    let factor = reference / measured;
+    status!("Heard {:.3} Hz, {:+.1} cents off", measured, 1200f32 * (measured / reference).log2());
+    status!("Rate correction = {:.6}", factor);
+    Ok(factor)
+}
+
+// Write the correction to a config file, replacing its calibration section
+pub fn save(path: &str, factor: f32) -> Result<(), String> {
+    let text = if std::path::Path::new(path).exists() {
+        tools::read_text(path)?
+    } else {
+        String::new()
+    };
+    let mut lines = Vec::new();
+    let mut skipping = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            skipping = trimmed[1..trimmed.len() - 1].trim() == "calibration";
+        }
+        if !skipping {
+            lines.push(line.to_owned());
+        }
+    }
+    lines.push("[calibration]".to_owned());
+    lines.push(format!("rate = {:.6}", factor));
+    std::fs::write(path, lines.join("\n") + "\n").map_err(|e| format!("Cannot write {}: {}", path, e))?;
+    status!("Calibration saved to {}, applied with --config {}", path, path);
+    Ok(())
+}
@@ -0,0 +1,411 @@
+// Melodies read from a file, the cantus firmus of the counterpoint exercises
+// Reads standard MIDI files (.mid) and uncompressed MusicXML (.musicxml, .xml)
+// The melody is taken as monophonic: the highest of the notes starting together, cut by the next one
+
+// Standard
+use std::collections::HashMap;
+
+// Crate
+use crate::notes::Note;
+use crate::tools;
+
+// The MIDI number of C0
+const MIDI_C0: u8 = 12;
+// The MIDI channel of the drums, counted from 0
+const DRUM_CHANNEL: u8 = 9;
+// The shortest gap between two notes taken for a rest, in beats
+const MIN_REST: f32 = 0.25;
+
+// A note of the melody, or a rest
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub note: Option<Note>,
+    // In quarter notes
+    pub beats: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Melody {
+    pub title: Option<String>,
+    pub tempo: Option<f32>,
+    pub steps: Vec<Step>,
+}
+
+impl Melody {
+    // Read a MIDI or MusicXML melody, by its extension
+    pub fn load(path: &str) -> Result<Melody, String> {
+        let lower = path.to_lowercase();
+        let melody = if lower.ends_with(".mid") || lower.ends_with(".midi") {
+            let bytes = std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+            midi(&bytes).map_err(|e| format!("{}: {}", path, e))?
+        } else if lower.ends_with(".musicxml") || lower.ends_with(".xml") {
+            musicxml(&tools::read_text(path)?).map_err(|e| format!("{}: {}", path, e))?
+        } else {
+            return Err(format!(
+                "{}: not a MIDI (.mid) or MusicXML (.musicxml) file",
+                path
+            ));
+        };
+        if melody.steps.iter().all(|s| s.note.is_none()) {
+            return Err(format!("{}: no notes found", path));
+        }
+        Ok(melody)
+    }
+}
+
+// The notes starting at the given times, until the given ends, as a melody
+// The overlapping notes are cut, and the gaps left between them are rests
+fn monophonic(mut notes: Vec<(f32, f32, Note)>) -> Vec<Step> {
+    // The highest note first of those starting together
+    notes.sort_by(|a, b| {
+        a.0.partial_cmp(&b.0)
+            .unwrap()
+            .then((b.2 as usize).cmp(&(a.2 as usize)))
+    });
+    notes.dedup_by(|later, first| later.0 == first.0);
+    let mut steps = Vec::new();
+    let mut time = 0f32;
+    for (i, &(start, end, note)) in notes.iter().enumerate() {
+        if start - time >= MIN_REST {
+            steps.push(Step {
+                note: None,
+                beats: start - time,
+            });
+        }
+        let end = notes.get(i + 1).map_or(end, |next| end.min(next.0));
+        steps.push(Step {
+            note: Some(note),
+            beats: end - start,
+        });
+        time = end;
+    }
+    steps
+}
+
+// Reads the variable length quantities and the fixed size numbers of a MIDI file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self.bytes.get(self.position).ok_or("truncated MIDI file")?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position + count;
+        let taken = self
+            .bytes
+            .get(self.position..end)
+            .ok_or("truncated MIDI file")?;
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn number(&mut self, count: usize) -> Result<u32, String> {
+        Ok(self.take(count)?.iter().fold(0, |n, &b| n << 8 | b as u32))
+    }
+
+    fn quantity(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.byte()?;
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("invalid variable length quantity".to_owned())
+    }
+
+    fn done(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+}
+
+// The notes of every track of a standard MIDI file, the drums left out
+fn midi(bytes: &[u8]) -> Result<Melody, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(4)? != b"MThd" {
+        return Err("not a standard MIDI file".to_owned());
+    }
+    let length = reader.number(4)? as usize;
+    let header = reader.take(length)?;
+    if header.len() < 6 {
+        return Err("invalid MIDI header".to_owned());
+    }
+    let division = (header[4] as u32) << 8 | header[5] as u32;
+    if division & 0x8000 != 0 || division == 0 {
+        return Err("SMPTE timed MIDI files are not supported".to_owned());
+    }
+    let mut title = None;
+    let mut tempo = None;
+    let mut notes = Vec::new();
+    while !reader.done() {
+        let id = reader.take(4)?;
+        let length = reader.number(4)? as usize;
+        let chunk = reader.take(length)?;
+        if id != b"MTrk" {
+            continue;
+        }
+        let mut track = Reader {
+            bytes: chunk,
+            position: 0,
+        };
+        let mut ticks = 0u64;
+        let mut status = 0u8;
+        // The start of the notes held, by channel and key
+        let mut held: HashMap<(u8, u8), u64> = HashMap::new();
+        while !track.done() {
+            ticks += track.quantity()? as u64;
+            let mut byte = track.byte()?;
+            match byte {
+                0xFF => {
+                    let kind = track.byte()?;
+                    let length = track.quantity()? as usize;
+                    let data = track.take(length)?;
+                    match kind {
+                        0x03 if title.is_none() && !data.is_empty() => {
+                            title = Some(String::from_utf8_lossy(data).trim().to_owned())
+                        }
+                        0x51 if tempo.is_none() && data.len() == 3 => {
+                            let micros =
+                                (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+                            tempo = Some(60e6 / micros.max(1) as f32);
+                        }
+                        _ => (),
+                    }
+                    continue;
+                }
+                0xF0 | 0xF7 => {
+                    let length = track.quantity()? as usize;
+                    track.take(length)?;
+                    continue;
+                }
+                _ => (),
+            }
+            // The data bytes of the previous status, when running
+            let first = if byte < 0x80 {
+                let data = byte;
+                byte = status;
+                data
+            } else {
+                status = byte;
+                track.byte()?
+            };
+            let (kind, channel) = (byte & 0xF0, byte & 0x0F);
+            let second = match kind {
+                0xC0 | 0xD0 => 0,
+                0x80 | 0x90 | 0xA0 | 0xB0 | 0xE0 => track.byte()?,
+                _ => return Err("invalid MIDI event".to_owned()),
+            };
+            if channel == DRUM_CHANNEL {
+                continue;
+            }
+            match kind {
+                0x90 if second > 0 => {
+                    held.insert((channel, first), ticks);
+                }
+                0x80 | 0x90 => {
+                    let start = held.remove(&(channel, first));
+                    let note = first
+                        .checked_sub(MIDI_C0)
+                        .and_then(|i| Note::iter().nth(i as usize));
+                    if let (Some(start), Some(note)) = (start, note) {
+                        let beats = |ticks: u64| ticks as f32 / division as f32;
+                        notes.push((beats(start), beats(ticks), note));
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    Ok(Melody {
+        title: title.filter(|t| !t.is_empty()),
+        tempo,
+        steps: monophonic(notes),
+    })
+}
+
+// The contents of the elements of a name, with where they start, ie. <note> or <note default-x="10">
+fn elements<'a>(text: &'a str, name: &str) -> Vec<(usize, &'a str)> {
+    let close = format!("</{}>", name);
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(start) = text[from..].find(&format!("<{}", name)).map(|s| s + from) {
+        let after = start + name.len() + 1;
+        from = after;
+        let rest = &text[after..];
+        // Not an element with a longer name, ie. <notations>
+        if !rest.starts_with('>') && !rest.starts_with(' ') {
+            continue;
+        }
+        let open_end = match rest.find('>') {
+            Some(end) => after + end,
+            None => break,
+        };
+        // Empty elements have no content
+        if text[..open_end].ends_with('/') {
+            found.push((start, ""));
+            continue;
+        }
+        if let Some(end) = text[open_end..].find(&close) {
+            found.push((start, &text[open_end + 1..open_end + end]));
+            from = open_end + end + close.len();
+        }
+    }
+    found
+}
+
+// The text of the first element of a name
+fn element<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    elements(text, name)
+        .first()
+        .map(|(_, content)| content.trim())
+}
+
+// The notes of the first voice of the first part of a MusicXML score
+fn musicxml(text: &str) -> Result<Melody, String> {
+    if !text.contains("<score-partwise") {
+        return Err("not a partwise MusicXML score".to_owned());
+    }
+    let part = elements(text, "part")
+        .first()
+        .map(|(_, part)| *part)
+        .ok_or("no part found")?;
+    let title = element(text, "work-title").or_else(|| element(text, "movement-title"));
+    let tempo = text.find("<sound").and_then(|start| {
+        let sound = &text[start..start + text[start..].find('>')?];
+        let value = &sound[sound.find("tempo=\"")? + 7..];
+        value[..value.find('"')?].parse::<f32>().ok()
+    });
+    // The divisions of a quarter note change along the score, before the notes they apply to
+    let mut changes = elements(part, "divisions");
+    changes.reverse();
+    let mut divisions = 1f32;
+    let mut steps: Vec<Step> = Vec::new();
+    for (position, note) in elements(part, "note") {
+        while changes.last().map_or(false, |&(p, _)| p < position) {
+            let (_, change) = changes.pop().unwrap();
+            divisions = change
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| "invalid divisions")?
+                .max(1f32);
+        }
+        if note.contains("<chord") || note.contains("<grace") {
+            continue;
+        }
+        if element(note, "voice").map_or(false, |v| v != "1") {
+            continue;
+        }
+        let beats = element(note, "duration")
+            .and_then(|d| d.parse::<f32>().ok())
+            .ok_or("note without duration")?
+            / divisions;
+        let pitch = if note.contains("<rest") {
+            None
+        } else {
+            let step = element(note, "step").ok_or("note without step")?;
+            let octave = element(note, "octave").ok_or("note without octave")?;
+            let alter = element(note, "alter")
+                .and_then(|a| a.parse::<f32>().ok())
+                .unwrap_or(0f32);
+            let natural = Note::from_name(&format!("{}{}", step, octave)).ok_or("invalid pitch")?;
+            let index = natural as i32 + alter.round() as i32;
+            Some(
+                Note::iter()
+                    .nth(index.max(0) as usize)
+                    .ok_or("pitch out of range")?,
+            )
+        };
+        // The second note of a tie lengthens the first
+        let tied = note.contains("<tie type=\"stop\"");
+        match steps.last_mut() {
+            Some(last) if tied && last.note == pitch => last.beats += beats,
+            Some(last) if pitch.is_none() && last.note.is_none() => last.beats += beats,
+            _ => steps.push(Step { note: pitch, beats }),
+        }
+    }
+    Ok(Melody {
+        title: title.map(str::to_owned).filter(|t| !t.is_empty()),
+        tempo,
+        steps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A format 0 file at 96 ticks per quarter note, with a single track of the events
+    fn midi_file(events: &[u8]) -> Vec<u8> {
+        let mut bytes = b"MThd\x00\x00\x00\x06\x00\x00\x00\x01\x00\x60MTrk".to_vec();
+        bytes.extend_from_slice(&(events.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(events);
+        bytes
+    }
+
+    // C4 for a beat, a beat of rest, then E4 for a beat
+    const EVENTS: [u8; 20] = [
+        0x00, 0x90, 0x3C, 0x64, 0x60, 0x80, 0x3C, 0x40, 0x60, 0x90, 0x40, 0x64, 0x60, 0x80, 0x40,
+        0x40, 0x00, 0xFF, 0x2F, 0x00,
+    ];
+
+    #[test]
+    fn midi_melody_is_read() {
+        let melody = midi(&midi_file(&EVENTS)).unwrap();
+        let steps = melody.steps.iter().map(|s| (s.note, s.beats)).collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            vec![(Some(Note::C4), 1f32), (None, 1f32), (Some(Note::E4), 1f32)]
+        );
+    }
+
+    #[test]
+    fn truncated_midi_is_an_error() {
+        let bytes = midi_file(&EVENTS);
+        for length in [0, 3, 10, bytes.len() - 4].iter() {
+            assert!(midi(&bytes[..*length]).is_err(), "{} bytes", length);
+        }
+        assert!(midi(b"RIFF\x00\x00\x00\x06").is_err());
+    }
+
+    #[test]
+    fn musicxml_melody_is_read() {
+        let text = r#"<score-partwise><part id="P1"><measure number="1">
+            <attributes><divisions>2</divisions></attributes>
+            <note><pitch><step>D</step><alter>1</alter><octave>4</octave></pitch><duration>4</duration></note>
+            <note><rest/><duration>2</duration></note>
+            <note><pitch><step>D</step><alter>1</alter><octave>4</octave></pitch><duration>2</duration>
+                <tie type="stop"/></note>
+            </measure></part></score-partwise>"#;
+        let steps = musicxml(text).unwrap().steps;
+        assert_eq!(
+            steps,
+            vec![
+                Step {
+                    note: Some(Note::DSharp4),
+                    beats: 2f32
+                },
+                Step {
+                    note: None,
+                    beats: 1f32
+                },
+                Step {
+                    note: Some(Note::DSharp4),
+                    beats: 1f32
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn musicxml_without_score_is_an_error() {
+        assert!(musicxml("<score-timewise></score-timewise>").is_err());
+        assert!(musicxml("<score-partwise><part id=\"P1\"><note><rest/></note></part></score-partwise>").is_err());
+    }
+}
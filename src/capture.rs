@@ -0,0 +1,169 @@
+// The open capture, kept alive while the analysis runs
+// A capture writing nothing for a while is lost, ie. a USB interface unplugged
+// The SDL capture is then reopened until the device is back, the analysis going on over silence
+// The SDL audio being tied to the main thread, the displays check on the capture every frame
+
+// Standard
+use std::time::{Duration, Instant};
+
+// Tools
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired, AudioStatus};
+use sdl2::AudioSubsystem;
+
+// Crate
+use crate::audio_buffer::{self, ChannelMode};
+use crate::error::Error;
+#[cfg(feature = "cpal-backend")]
+use crate::cpal_input;
+#[cfg(feature = "jack-backend")]
+use crate::jack_input;
+use crate::ring::{self, Handle};
+use crate::timeline::Timeline;
+
+// How long a capture can write nothing before it is lost
+const STALL_TIMEOUT: Duration = Duration::from_secs(1);
+// How often a lost capture is reopened
+const RETRY_PERIOD: Duration = Duration::from_secs(2);
+
+#[allow(dead_code)]
+pub enum Capture {
+    Sdl(AudioDevice<Recorder>),
+    #[cfg(feature = "cpal-backend")]
+    Cpal(cpal_input::CpalInput),
+    #[cfg(feature = "jack-backend")]
+    Jack(jack_input::JackInput),
+}
+
+// Audio callback object, writes to a preallocated ring per stream analysed
+pub struct Recorder {
+    pub producers: Vec<ring::Producer>,
+    // The channels interleaved in the input, and how they are analysed
+    pub channels: usize,
+    pub mode: ChannelMode,
+    pub timeline: Timeline,
+}
+
+impl AudioCallback for Recorder {
+    type Channel = f32;
+
+    fn callback(&mut self, input: &mut [f32]) {
+        self.timeline.advance(input.len() / self.channels.max(1));
+        for (stream, producer) in self.producers.iter_mut().enumerate() {
+            producer.write(audio_buffer::stream_samples(input, self.channels, self.mode, stream));
+        }
+    }
+}
+
+// What reopens an SDL capture, at the rate the analysis runs at
+pub struct SdlCapture {
+    pub audio: AudioSubsystem,
+    pub driver: String,
+    // The full name of the device, the default one when None
+    pub device: Option<String>,
+    pub rate: i32,
+    pub channels: u8,
+    pub samples: Option<u16>,
+    pub mode: ChannelMode,
+    pub timeline: Timeline,
+    // The rings of the streams analysed
+    pub handles: Vec<Handle>,
+}
+
+impl SdlCapture {
+    // Open the device again, writing to the rings through the producers replacing the previous ones
+    fn open(&self, producers: Vec<ring::Producer>) -> Result<AudioDevice<Recorder>, Error> {
+        let spec = AudioSpecDesired {
+            freq: Some(self.rate),
+            channels: Some(self.channels),
+            samples: self.samples,
+        };
+        let device = self
+            .audio
+            .open_capture(self.device.as_deref(), &spec, |spec| Recorder {
+                producers,
+                channels: spec.channels as usize,
+                mode: self.mode,
+                timeline: self.timeline.clone(),
+            })
+            .map_err(|reason| Error::Capture {
+                driver: self.driver.clone(),
+                reason,
+            })?;
+        device.resume();
+        Ok(device)
+    }
+}
+
+pub struct Watchdog {
+    capture: Option<Capture>,
+    sdl: Option<SdlCapture>,
+    // The samples written when last checked, and when they last grew
+    written: usize,
+    heard: Instant,
+    // When the lost capture was last reopened, None while it writes
+    retried: Option<Instant>,
+}
+
+impl Watchdog {
+    // Nothing captured, ie. a recording or a mirrored display
+    pub fn none() -> Watchdog {
+        Watchdog::keep(None)
+    }
+
+    // Keep the capture alive, without reopening it
+    pub fn keep(capture: Option<Capture>) -> Watchdog {
+        Watchdog {
+            capture,
+            sdl: None,
+            written: 0,
+            heard: Instant::now(),
+            retried: None,
+        }
+    }
+
+    // Reopen the SDL capture whenever it is lost
+    pub fn sdl(device: AudioDevice<Recorder>, sdl: SdlCapture) -> Watchdog {
+        Watchdog {
+            capture: Some(Capture::Sdl(device)),
+            sdl: Some(sdl),
+            ..Watchdog::none()
+        }
+    }
+
+    // Reopen the capture once lost, returning the warning shown until it is back
+    pub fn check(&mut self) -> Option<String> {
+        let sdl = self.sdl.as_ref()?;
+        let written = sdl.handles.iter().map(Handle::written).sum();
+        if written != self.written {
+            self.written = written;
+            self.heard = Instant::now();
+            if self.retried.take().is_some() {
+                status!("Capture reopened");
+            }
+        }
+        let stopped = match self.capture.as_ref() {
+            Some(Capture::Sdl(device)) => device.status() == AudioStatus::Stopped,
+            _ => true,
+        };
+        if !stopped && self.heard.elapsed() <= STALL_TIMEOUT {
+            return None;
+        }
+        if self.retried.map_or(true, |r| r.elapsed() >= RETRY_PERIOD) {
+            if self.retried.is_none() {
+                status!("Capture lost, reopening");
+            }
+            self.retried = Some(Instant::now());
+            // The previous device is closed once its producers are replaced, leaving the rings open
+            let producers = sdl.handles.iter().map(Handle::producer).collect();
+            self.capture = None;
+            match sdl.open(producers) {
+                Ok(device) => {
+                    self.capture = Some(Capture::Sdl(device));
+                    self.heard = Instant::now();
+                }
+                Err(error) => status!("{}", error),
+            }
+        }
+        Some("Capture lost, reconnecting".to_owned())
+    }
+}
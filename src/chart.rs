@@ -0,0 +1,298 @@
+// Chord charts, followed at a fixed tempo to provide the harmonic context
+// Reads plain text (| C | Am F |), ChordPro and iReal Pro (irealbook://) charts
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::chords::{Chord, Symbols};
+use crate::tools;
+
+// The tempo used when neither the chart nor the options set one
+pub const DEFAULT_TEMPO: f32 = 120f32;
+
+// A line of lyrics, or a cue like a section name
+#[derive(Clone, Debug)]
+pub struct Lyric {
+    // The bar the line starts at
+    pub bar: usize,
+    pub text: String,
+    pub cue: bool,
+}
+
+// A chord chart, every bar split evenly between its chords
+#[derive(Clone, Debug)]
+pub struct Chart {
+    pub title: Option<String>,
+    pub tempo: Option<f32>,
+    pub beats_per_bar: usize,
+    // None being no chord
+    pub bars: Vec<Vec<Option<Chord>>>,
+    // The lyrics and cues of ChordPro charts, in order
+    pub lyrics: Vec<Lyric>,
+}
+
+// Parse a tempo, ignoring the ones no chart can be followed at
+fn parse_tempo(tempo: &str) -> Option<f32> {
+    tempo.parse::<f32>().ok().filter(|t| t.is_finite() && *t > 0f32)
+}
+
+// Parse a chord, or a no chord symbol
+fn parse_chord(symbol: &str, symbols: Symbols) -> Result<Option<Chord>, String> {
+    match symbol {
+        "N.C." | "NC" | "n" => Ok(None),
+        _ => Chord::parse(symbol, symbols)
+            .map(Some)
+            .ok_or_else(|| format!("Unknown chord '{}'", symbol)),
+    }
+}
+
+// Decode the percent encoded characters of an url
+fn decode(url: &str) -> String {
+    let bytes = url.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = url.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+impl Chart {
+    // Load a chart, the format being guessed from its content
+    // The plain text and ChordPro chords are written in the given convention, iReal Pro ones always standard
+    pub fn load(path: &str, symbols: Symbols) -> Result<Chart, String> {
+        let chart = Chart::parse(&tools::read_text(path)?, symbols)?;
+        if chart.bars.is_empty() {
+            return Err(format!("No chords found in {}", path));
+        }
+        Ok(chart)
+    }
+
+    // Parse the text of a chart, possibly without any chord
+    pub fn parse(text: &str, symbols: Symbols) -> Result<Chart, String> {
+        let trimmed = text.trim();
+        if trimmed.starts_with("irealb://") {
+            Err("Only the irealbook:// iReal Pro format is supported, not irealb://".to_owned())
+        } else if trimmed.starts_with("irealbook://") {
+            Chart::parse_ireal(trimmed)
+        } else if trimmed.contains('[') || trimmed.contains('{') {
+            Chart::parse_chordpro(trimmed, symbols)
+        } else {
+            Chart::parse_text(trimmed, symbols)
+        }
+    }
+
+    fn new() -> Chart {
+        Chart {
+            title: None,
+            tempo: None,
+            beats_per_bar: 4,
+            bars: Vec::new(),
+            lyrics: Vec::new(),
+        }
+    }
+
+    // Bars separated by '|', chords by spaces
+    fn parse_text(text: &str, symbols: Symbols) -> Result<Chart, String> {
+        let mut chart = Chart::new();
+        for line in text.lines() {
+            // The header of the written chord sheets
+            if line.starts_with("Tempo:") {
+                let mut words = line["Tempo:".len()..].split_whitespace();
+                chart.tempo = words.next().and_then(parse_tempo);
+                if let Some(Ok(beats)) = words.last().and_then(|t| t.split('/').next()).map(str::parse::<usize>) {
+                    chart.beats_per_bar = beats.max(1);
+                }
+                continue;
+            }
+            // Comments, like the annotations of the written chord sheets
+            if line.starts_with('#') {
+                continue;
+            }
+            for bar in line.split('|') {
+                let chords = bar.split_whitespace().map(|s| parse_chord(s, symbols)).collect::<Result<Vec<_>, _>>()?;
+                if !chords.is_empty() {
+                    chart.bars.push(chords);
+                }
+            }
+        }
+        Ok(chart)
+    }
+
+    // Chords in brackets, lines without bar lines having a chord per bar
+    // The text around the chords is kept as lyrics, the comments and sections as cues
+    fn parse_chordpro(text: &str, symbols: Symbols) -> Result<Chart, String> {
+        let mut chart = Chart::new();
+        for line in text.lines().map(str::trim) {
+            let bar = chart.bars.len();
+            let mut cue = |text: &str| {
+                chart.lyrics.push(Lyric {
+                    bar,
+                    text: text.to_owned(),
+                    cue: true,
+                })
+            };
+            // Directives
+            if line.starts_with('{') && line.ends_with('}') {
+                let directive = &line[1..line.len() - 1];
+                let mut parts = directive.splitn(2, ':');
+                let name = parts.next().unwrap().trim();
+                let value = parts.next().unwrap_or("").trim();
+                match name {
+                    "title" | "t" => chart.title = Some(value.to_owned()),
+                    "tempo" => chart.tempo = parse_tempo(value),
+                    "comment" | "c" | "comment_italic" | "ci" | "comment_box" | "cb" => cue(value),
+                    "start_of_chorus" | "soc" => cue(if value.is_empty() { "Chorus" } else { value }),
+                    "start_of_verse" | "sov" => cue(if value.is_empty() { "Verse" } else { value }),
+                    "start_of_bridge" | "sob" => cue(if value.is_empty() { "Bridge" } else { value }),
+                    "time" => {
+                        if let Some(Ok(beats)) = value.split('/').next().map(str::parse::<usize>) {
+                            chart.beats_per_bar = beats.max(1);
+                        }
+                    }
+                    _ => (),
+                }
+                continue;
+            }
+            // Comments
+            if line.starts_with('#') {
+                continue;
+            }
+            let chords_in = |segment: &str| {
+                segment
+                    .split('[')
+                    .skip(1)
+                    .filter_map(|s| s.split(']').next())
+                    .map(|s| parse_chord(s, symbols))
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            if line.contains('|') {
+                for bar in line.split('|') {
+                    let chords = chords_in(bar)?;
+                    if !chords.is_empty() {
+                        chart.bars.push(chords);
+                    }
+                }
+            } else {
+                for chord in chords_in(line)? {
+                    chart.bars.push(vec![chord]);
+                }
+            }
+            // The lyrics, without the chords and bar lines
+            let lyrics = line
+                .split('[')
+                .map(|s| s.splitn(2, ']').last().unwrap())
+                .collect::<String>()
+                .replace('|', " ");
+            let lyrics = lyrics.split_whitespace().join(" ");
+            if !lyrics.is_empty() {
+                chart.lyrics.push(Lyric {
+                    bar,
+                    text: lyrics,
+                    cue: false,
+                });
+            }
+        }
+        Ok(chart)
+    }
+
+    // irealbook://Title=Composer=Style=Key=n=Chords, possibly several songs
+    // Only the first song is read
+    fn parse_ireal(url: &str) -> Result<Chart, String> {
+        let url = decode(&url["irealbook://".len()..]);
+        let fields = url.split('=').collect_vec();
+        if fields.len() < 5 {
+            return Err("Malformed iReal Pro chart".to_owned());
+        }
+        let mut chart = Chart::new();
+        chart.title = Some(fields[0].to_owned());
+        let progression = fields[fields.len() - 1];
+
+        let mut bar: Vec<Option<Chord>> = Vec::new();
+        let mut previous: Vec<Option<Chord>> = Vec::new();
+        let mut chars = progression.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                // Bar lines, repeats and the final bar
+                '|' | '[' | ']' | '{' | '}' | 'Z' => {
+                    if !bar.is_empty() {
+                        previous = std::mem::replace(&mut bar, Vec::new());
+                        chart.bars.push(previous.clone());
+                    }
+                }
+                // Time signatures, ie. T44, T34
+                'T' => {
+                    let signature = chars.by_ref().take(2).collect::<String>();
+                    chart.beats_per_bar = match signature.as_str() {
+                        "12" => 12,
+                        _ => signature.get(..1).and_then(|b| b.parse::<usize>().ok()).unwrap_or(4),
+                    };
+                }
+                // Section marks and endings
+                '*' | 'N' => {
+                    chars.next();
+                }
+                // Comments and alternate chords
+                '<' => while chars.next().map_or(false, |c| c != '>') {},
+                '(' => while chars.next().map_or(false, |c| c != ')') {},
+                // Repeat the previous bar
+                'x' => bar = previous.clone(),
+                // No chord
+                'n' => bar.push(None),
+                // Repeat the previous chord
+                'p' => {
+                    if let Some(&chord) = bar.last() {
+                        bar.push(chord);
+                    }
+                }
+                'A'..='G' => {
+                    let mut symbol = c.to_string();
+                    while let Some(&next) = chars.peek() {
+                        if " ,|[]{}Z()<*".contains(next) {
+                            break;
+                        }
+                        symbol.push(next);
+                        chars.next();
+                    }
+                    bar.push(parse_chord(&symbol, Symbols::Standard)?);
+                }
+                // Spacing, sizes, segno, coda and fermatas
+                _ => (),
+            }
+        }
+        if !bar.is_empty() {
+            chart.bars.push(bar);
+        }
+        Ok(chart)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_order_mark_before_chordpro() {
+        let text = tools::decode_text(b"\xEF\xBB\xBF{title: Blues}\n[C7]Woke up [F7]this morning");
+        let chart = Chart::parse(&text, Symbols::Standard).unwrap();
+        assert_eq!(chart.title.as_deref(), Some("Blues"));
+        assert_eq!(chart.bars[0][0].map(|c| c.root), Some(0));
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_unknown_chord() {
+        let text = tools::decode_text(b"| C | \xFF G |");
+        assert!(Chart::parse(&text, Symbols::Standard).is_err());
+    }
+}
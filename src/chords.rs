@@ -0,0 +1,374 @@
+// Chord detection from pitch class profiles
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::display::Notation;
+use crate::notes::{Note, NOTE_COUNT};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Dominant7,
+    Major7,
+    Minor7,
+    Suspended4,
+}
+
+pub const QUALITIES: [Quality; 8] = [
+    Quality::Major,
+    Quality::Minor,
+    Quality::Diminished,
+    Quality::Augmented,
+    Quality::Dominant7,
+    Quality::Major7,
+    Quality::Minor7,
+    Quality::Suspended4,
+];
+
+impl Quality {
+    // The intervals of the chord from its root, in semitones
+    pub fn intervals(self) -> &'static [usize] {
+        match self {
+            Quality::Major => &[0, 4, 7],
+            Quality::Minor => &[0, 3, 7],
+            Quality::Diminished => &[0, 3, 6],
+            Quality::Augmented => &[0, 4, 8],
+            Quality::Dominant7 => &[0, 4, 7, 10],
+            Quality::Major7 => &[0, 4, 7, 11],
+            Quality::Minor7 => &[0, 3, 7, 10],
+            Quality::Suspended4 => &[0, 5, 7],
+        }
+    }
+
+    pub fn symbol(self, symbols: Symbols) -> &'static str {
+        match (symbols, self) {
+            (_, Quality::Major) => "",
+            (Symbols::Jazz, Quality::Minor) => "−",
+            (_, Quality::Minor) => "m",
+            (Symbols::Jazz, Quality::Diminished) => "°",
+            (_, Quality::Diminished) => "dim",
+            (Symbols::Jazz, Quality::Augmented) => "+",
+            (_, Quality::Augmented) => "aug",
+            (_, Quality::Dominant7) => "7",
+            (Symbols::Jazz, Quality::Major7) => "Δ7",
+            (_, Quality::Major7) => "maj7",
+            (Symbols::Jazz, Quality::Minor7) => "−7",
+            (_, Quality::Minor7) => "m7",
+            (_, Quality::Suspended4) => "sus4",
+        }
+    }
+}
+
+// The regional conventions of the chord symbols
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Symbols {
+    // Cmaj7, Cm7, Cdim, Caug
+    Standard,
+    // CΔ7, C−7, C°, C+
+    Jazz,
+    // The standard symbols, B natural written H and B flat B
+    German,
+}
+
+impl Symbols {
+    pub fn from_name(name: &str) -> Option<Symbols> {
+        match name {
+            "standard" => Some(Symbols::Standard),
+            "jazz" => Some(Symbols::Jazz),
+            "german" => Some(Symbols::German),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Symbols::Standard => "standard",
+            Symbols::Jazz => "jazz",
+            Symbols::German => "german",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Chord {
+    // The pitch class of the root, 0 being C
+    pub root: usize,
+    pub quality: Quality,
+}
+
+// The quality of chord symbol suffixes, the longest ones first
+// Extensions fall back to the closest supported quality, the sixth and added note chords having none
+const SUFFIXES: [(&str, Quality); 50] = [
+    ("maj13", Quality::Major7),
+    ("maj11", Quality::Major7),
+    ("7sus4", Quality::Suspended4),
+    ("9sus4", Quality::Suspended4),
+    ("m7b5", Quality::Diminished),
+    ("maj7", Quality::Major7),
+    ("maj9", Quality::Major7),
+    ("min7", Quality::Minor7),
+    ("min9", Quality::Minor7),
+    ("sus4", Quality::Suspended4),
+    ("7sus", Quality::Suspended4),
+    ("9sus", Quality::Suspended4),
+    ("−7", Quality::Minor7),
+    ("−9", Quality::Minor7),
+    ("Δ7", Quality::Major7),
+    ("Δ9", Quality::Major7),
+    ("ø7", Quality::Diminished),
+    ("ma7", Quality::Major7),
+    ("dim", Quality::Diminished),
+    ("aug", Quality::Augmented),
+    ("sus", Quality::Suspended4),
+    ("min", Quality::Minor),
+    ("maj", Quality::Major),
+    ("m11", Quality::Minor7),
+    ("m13", Quality::Minor7),
+    ("-11", Quality::Minor7),
+    ("M7", Quality::Major7),
+    ("M9", Quality::Major7),
+    ("^7", Quality::Major7),
+    ("^9", Quality::Major7),
+    ("m7", Quality::Minor7),
+    ("m9", Quality::Minor7),
+    ("-7", Quality::Minor7),
+    ("-9", Quality::Minor7),
+    ("h7", Quality::Diminished),
+    ("o7", Quality::Diminished),
+    ("13", Quality::Dominant7),
+    ("11", Quality::Dominant7),
+    ("^", Quality::Major7),
+    ("h", Quality::Diminished),
+    ("o", Quality::Diminished),
+    ("−", Quality::Minor),
+    ("Δ", Quality::Major7),
+    ("°", Quality::Diminished),
+    ("ø", Quality::Diminished),
+    ("+", Quality::Augmented),
+    ("m", Quality::Minor),
+    ("-", Quality::Minor),
+    ("7", Quality::Dominant7),
+    ("9", Quality::Dominant7),
+];
+
+// The separators between the tensions of a chord
+const TENSION_SEPARATORS: [char; 3] = ['(', ')', ','];
+
+// If only the tensions of a seventh chord are left after its suffix (ie. b9, #11, (13), alt)
+fn is_tensions(rest: &str) -> bool {
+    let mut rest = rest.trim_start_matches(TENSION_SEPARATORS);
+    while !rest.is_empty() {
+        rest = match rest.strip_prefix("alt") {
+            Some(rest) => rest,
+            None => {
+                let degree = rest.trim_start_matches(['b', '#']);
+                match ["13", "11", "9", "5"].iter().find_map(|d| degree.strip_prefix(d)) {
+                    Some(rest) => rest,
+                    None => return false,
+                }
+            }
+        };
+        rest = rest.trim_start_matches(TENSION_SEPARATORS);
+    }
+    true
+}
+
+// The minimum share of the heard intensity the chord tones must explain
+const MIN_MATCH: f32 = 0.6;
+
+impl Chord {
+    // The pitch classes of the chord
+    pub fn pitch_classes(self) -> Vec<usize> {
+        self.quality
+            .intervals()
+            .iter()
+            .map(|i| (self.root + i) % 12)
+            .collect_vec()
+    }
+
+    // The chord voiced upwards from the root, starting in the octave of the given note
+    pub fn voicing(self, octave: Note) -> Vec<Note> {
+        let base = (octave as usize / 12) * 12 + self.root;
+        self.quality
+            .intervals()
+            .iter()
+            .map(|i| base + i)
+            .filter(|&i| i < NOTE_COUNT)
+            .filter_map(|i| Note::iter().nth(i))
+            .collect_vec()
+    }
+
+    // Parse a chord symbol (ie. C, F#m7, Bbmaj7, D-7, G7/B, E−7, FΔ7), ignoring the bass note
+    // H is B natural, and B is B flat in German
+    pub fn parse(name: &str, symbols: Symbols) -> Option<Chord> {
+        let name = name.trim();
+        let mut chars = name.chars();
+        let base = match (chars.next()?.to_ascii_uppercase(), symbols) {
+            ('C', _) => 0,
+            ('D', _) => 2,
+            ('E', _) => 4,
+            ('F', _) => 5,
+            ('G', _) => 7,
+            ('A', _) => 9,
+            ('B', Symbols::German) => 10,
+            ('B', _) | ('H', _) => 11,
+            _ => return None,
+        };
+        let rest = chars.as_str();
+        let (alteration, rest) = if rest.starts_with('#') {
+            (1, &rest[1..])
+        } else if rest.starts_with('b') {
+            (11, &rest[1..])
+        } else {
+            (0, rest)
+        };
+        // The suffixes not understood are refused rather than read as another chord
+        let suffix = rest.split('/').next().unwrap();
+        let quality = match SUFFIXES.iter().find(|(symbol, _)| suffix.starts_with(symbol)) {
+            Some(&(symbol, quality)) => {
+                let rest = &suffix[symbol.len()..];
+                let seventh = symbol.ends_with(|c: char| c.is_ascii_digit());
+                if rest.is_empty() || (seventh && is_tensions(rest)) {
+                    quality
+                } else {
+                    return None;
+                }
+            }
+            None if suffix.is_empty() => Quality::Major,
+            None => return None,
+        };
+        Some(Chord {
+            root: (base + alteration) % 12,
+            quality,
+        })
+    }
+
+    // The same chord, moved by a number of semitones
+    pub fn transpose(self, semitones: i32) -> Chord {
+        Chord {
+            root: (self.root as i32 + semitones).rem_euclid(12) as usize,
+            quality: self.quality,
+        }
+    }
+
+    pub fn name(self, notation: Notation) -> String {
+        format!(
+            "{}{}",
+            notation.root_name(self.root),
+            self.quality.symbol(notation.symbols)
+        )
+    }
+
+    // Find the chord best matching a pitch class profile
+    pub fn detect(chroma: &[f32; 12]) -> Option<Chord> {
+        let total: f32 = chroma.iter().sum();
+        if total <= 0f32 {
+            return None;
+        }
+        let (chord, score) = (0..12)
+            .cartesian_product(QUALITIES.iter())
+            .map(|(root, &quality)| {
+                let chord = Chord { root, quality };
+                let tones = chord.pitch_classes();
+                // Reward the chord tones, penalize larger chords slightly
+                let matched: f32 = tones.iter().map(|&p| chroma[p]).sum();
+                let score = matched / total - 0.02 * tones.len() as f32;
+                (chord, score)
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())?;
+        if score + 0.02 * chord.pitch_classes().len() as f32 >= MIN_MATCH {
+            Some(chord)
+        } else {
+            None
+        }
+    }
+}
+
+// Sum the values of every note per pitch class
+pub fn chroma(values: &[f32; NOTE_COUNT]) -> [f32; 12] {
+    let mut chroma = [0f32; 12];
+    for (i, v) in values.iter().enumerate() {
+        chroma[i % 12] += v;
+    }
+    chroma
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Language;
+
+    #[test]
+    fn triad_is_detected() {
+        let mut chroma = [0f32; 12];
+        for &p in [0, 4, 7].iter() {
+            chroma[p] = 1f32;
+        }
+        assert_eq!(
+            Chord::detect(&chroma),
+            Some(Chord {
+                root: 0,
+                quality: Quality::Major
+            })
+        );
+        assert_eq!(Chord::detect(&[0f32; 12]), None);
+    }
+
+    #[test]
+    fn suffixes_are_parsed() {
+        let parse = |name| Chord::parse(name, Symbols::Standard).map(|c| (c.root, c.quality));
+        assert_eq!(parse("E−7"), Some((4, Quality::Minor7)));
+        assert_eq!(parse("FΔ7"), Some((5, Quality::Major7)));
+        assert_eq!(parse("Bbmaj7"), Some((10, Quality::Major7)));
+        assert_eq!(parse("F#m7b5"), Some((6, Quality::Diminished)));
+        assert_eq!(parse("G7/B"), Some((7, Quality::Dominant7)));
+        assert_eq!(parse("D-"), Some((2, Quality::Minor)));
+        assert_eq!(parse("X7"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn extensions_keep_their_seventh() {
+        let parse = |name| Chord::parse(name, Symbols::Standard).map(|c| c.quality);
+        assert_eq!(parse("C9"), Some(Quality::Dominant7));
+        assert_eq!(parse("Cmaj9"), Some(Quality::Major7));
+        assert_eq!(parse("Cm11"), Some(Quality::Minor7));
+        assert_eq!(parse("C7b9"), Some(Quality::Dominant7));
+        assert_eq!(parse("C13(#11)"), Some(Quality::Dominant7));
+        assert_eq!(parse("Cmaj7#11"), Some(Quality::Major7));
+        assert_eq!(parse("C7alt"), Some(Quality::Dominant7));
+        assert_eq!(parse("C7sus4"), Some(Quality::Suspended4));
+        assert_eq!(parse("Cmaj"), Some(Quality::Major));
+        // Without a supported quality
+        for name in ["C6", "Cm6", "C69", "Csus2", "Cadd9", "C5", "Cmb9", "C7x"].iter() {
+            assert_eq!(parse(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn german_b_is_b_flat() {
+        assert_eq!(Chord::parse("B", Symbols::German).unwrap().root, 10);
+        assert_eq!(Chord::parse("H7", Symbols::German).unwrap().root, 11);
+        assert_eq!(Chord::parse("B", Symbols::Standard).unwrap().root, 11);
+        assert_eq!(Chord::parse("H", Symbols::Standard).unwrap().root, 11);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for &symbols in [Symbols::Standard, Symbols::Jazz, Symbols::German].iter() {
+            let notation = Notation {
+                language: Language::English,
+                symbols,
+            };
+            for (root, &quality) in (0..12).cartesian_product(QUALITIES.iter()) {
+                let chord = Chord { root, quality };
+                assert_eq!(Chord::parse(&chord.name(notation), symbols), Some(chord));
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+// Recognizes the instrument heard from a few spectral features, to suggest the matching input type
+// Each new input, at the start or after the input was idle, is listened to for a few seconds
+//   register: the octave of the fundamental, from A4
+//   brightness: the octaves from the fundamental to the spectral centroid
+//   decay: how fast held notes fade, in decibels per second, plucked and struck notes fading
+//   vibrato: the pitch movement of held notes, in cents per frame
+//   flatness: the noisiness of the spectrum, ie. breath
+// The averages are matched to the nearest prototype, the features scaled by their spread
+
+// Standard
+use std::time::Instant;
+
+// Crate
+use crate::fourier::InputType;
+use crate::frequency::Frequency;
+use crate::spectrum;
+
+// How long an input is listened to before it is classified, in seconds of frames with notes
+const LISTEN_SECONDS: f32 = 5f32;
+// The peaks weaker than this fraction of the strongest are not taken for the fundamental
+const FUNDAMENTAL_FLOOR: f32 = 0.1;
+// The pitch change, in cents, from which a frame starts a new note
+const NEW_NOTE_CENTS: f32 = 50f32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instrument {
+    Voice,
+    Guitar,
+    Piano,
+    Bass,
+    Wind,
+    Bowed,
+}
+
+impl Instrument {
+    pub fn name(self) -> &'static str {
+        match self {
+            Instrument::Voice => "voice",
+            Instrument::Guitar => "guitar",
+            Instrument::Piano => "piano",
+            Instrument::Bass => "bass",
+            Instrument::Wind => "wind instrument",
+            Instrument::Bowed => "bowed string",
+        }
+    }
+
+    // The input type analysing it best
+    pub fn input_type(self) -> InputType {
+        match self {
+            Instrument::Voice => InputType::Voice,
+            _ => InputType::Instrument,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Classify {
+    // Only show the instrument recognized, and the input type suggested
+    Suggest,
+    // Switch to the input type suggested
+    Auto,
+}
+
+impl Classify {
+    pub fn from_name(name: &str) -> Option<Classify> {
+        match name {
+            "suggest" => Some(Classify::Suggest),
+            "auto" => Some(Classify::Auto),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Classify::Suggest => "suggest",
+            Classify::Auto => "auto",
+        }
+    }
+}
+
+// register, brightness, decay, vibrato, flatness
+type Features = [f32; 5];
+
+// The typical features of each instrument
+const PROTOTYPES: [(Instrument, Features); 6] = [
+    (Instrument::Voice, [-1.0, 2.0, 0.0, 15.0, 0.2]),
+    (Instrument::Guitar, [-1.2, 1.5, -15.0, 2.0, 0.1]),
+    (Instrument::Piano, [-0.7, 1.3, -10.0, 1.0, 0.05]),
+    (Instrument::Bass, [-2.6, 1.5, -10.0, 2.0, 0.1]),
+    (Instrument::Wind, [-0.1, 1.5, 0.0, 6.0, 0.25]),
+    (Instrument::Bowed, [-0.6, 2.5, 0.0, 10.0, 0.15]),
+];
+// The spread of each feature, the differences being measured in spreads
+const SPREADS: Features = [1.0, 0.7, 8.0, 5.0, 0.15];
+
+// The nearest prototype
+fn nearest(features: &Features) -> Instrument {
+    let distance = |prototype: &Features| -> f32 {
+        prototype
+            .iter()
+            .zip(features.iter())
+            .zip(SPREADS.iter())
+            .map(|((p, f), s)| ((p - f) / s).powi(2))
+            .sum()
+    };
+    PROTOTYPES
+        .iter()
+        .min_by(|a, b| distance(&a.1).partial_cmp(&distance(&b.1)).unwrap())
+        .unwrap()
+        .0
+}
+
+pub struct Classifier {
+    // The sums of the features of the frames listened to, and their counts
+    sums: Features,
+    counts: [usize; 5],
+    // The seconds of frames with notes listened to
+    heard: f32,
+    // The fundamental and level of the previous frame with notes, and when it was analysed
+    previous: Option<(f32, f32, Instant)>,
+    // The instrument recognized, once listened to long enough
+    instrument: Option<Instrument>,
+}
+
+impl Classifier {
+    pub fn new() -> Classifier {
+        Classifier {
+            sums: [0f32; 5],
+            counts: [0; 5],
+            heard: 0f32,
+            previous: None,
+            instrument: None,
+        }
+    }
+
+    // Listen to a new input
+    pub fn restart(&mut self) {
+        *self = Classifier::new();
+    }
+
+    pub fn instrument(&self) -> Option<Instrument> {
+        self.instrument
+    }
+
+    fn add(&mut self, feature: usize, value: f32) {
+        if value.is_finite() {
+            self.sums[feature] += value;
+            self.counts[feature] += 1;
+        }
+    }
+
+    // Listen to a frame, at the level in decibels, returning the instrument once recognized
+    pub fn listen(
+        &mut self,
+        spectrum: &[Frequency],
+        peaks: &[Frequency],
+        level: f32,
+    ) -> Option<Instrument> {
+        if self.instrument.is_some() {
+            return None;
+        }
+        let strongest = peaks.iter().fold(0f32, |s, p| s.max(p.intensity));
+        let fundamental = match peaks
+            .iter()
+            .filter(|p| p.intensity >= strongest * FUNDAMENTAL_FLOOR && p.value > 0f32)
+            .map(|p| p.value)
+            .fold(None, |low: Option<f32>, f| {
+                Some(low.map_or(f, |l| l.min(f)))
+            }) {
+            Some(fundamental) => fundamental,
+            None => {
+                self.previous = None;
+                return None;
+            }
+        };
+        let now = Instant::now();
+        let total: f32 = spectrum.iter().map(|f| f.amplitude()).sum();
+        let centroid = spectrum
+            .iter()
+            .map(|f| f.value * f.amplitude())
+            .sum::<f32>()
+            / total;
+        self.add(0, (fundamental / 440f32).log2());
+        self.add(1, (centroid / fundamental).log2());
+        self.add(4, spectrum::flatness(spectrum));
+        // The decay and the vibrato of the note held since the previous frame
+        if let Some((previous, previous_level, time)) = self.previous {
+            let cents = 1200f32 * (fundamental / previous).log2().abs();
+            let seconds = (now - time).as_secs_f32();
+            if cents < NEW_NOTE_CENTS && seconds > 0f32 {
+                self.heard += seconds;
+                self.add(2, (level - previous_level) / seconds);
+                self.add(3, cents);
+            }
+        }
+        self.previous = Some((fundamental, level, now));
+        if self.heard < LISTEN_SECONDS {
+            return None;
+        }
+        let mut features = [0f32; 5];
+        for (feature, (sum, &count)) in features
+            .iter_mut()
+            .zip(self.sums.iter().zip(self.counts.iter()))
+        {
+            *feature = sum / count.max(1) as f32;
+        }
+        self.instrument = Some(nearest(&features));
+        self.instrument
+    }
+}
@@ -0,0 +1,143 @@
+// Compares two saved sessions, to see the progress between them
+// Reports the keys used, how consonant the notes played were, and each pitch class side by side
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::display::Notation;
+use crate::keys;
+use crate::session::SavedSession;
+
+// The dissonance bands of the consonance distribution
+const BANDS: usize = 5;
+// The width of the bars, for a whole share of the notes
+const BAR_WIDTH: f32 = 40f32;
+
+fn duration(seconds: f32) -> String {
+    format!("{}:{:02}", seconds as u32 / 60, seconds as u32 % 60)
+}
+
+fn bar(share: f32) -> String {
+    "#".repeat((share * BAR_WIDTH).round() as usize)
+}
+
+// The share of the notes in each dissonance band
+fn distribution(session: &SavedSession) -> [f32; BANDS] {
+    let mut shares = [0f32; BANDS];
+    for onset in session.onsets.iter() {
+        let band = ((onset.score.max(0f32) * BANDS as f32) as usize).min(BANDS - 1);
+        shares[band] += 1f32 / session.onsets.len() as f32;
+    }
+    shares
+}
+
+// The share of the notes, and their average dissonance, of every pitch class
+fn pitch_classes(session: &SavedSession) -> [(f32, Option<f32>); 12] {
+    let mut classes = [(0f32, None); 12];
+    let by_class = session
+        .onsets
+        .iter()
+        .map(|o| (o.note.get_octave_index() as usize, o.score))
+        .into_group_map();
+    for (class, scores) in by_class {
+        classes[class] = (
+            scores.len() as f32 / session.onsets.len() as f32,
+            Some(scores.iter().sum::<f32>() / scores.len() as f32),
+        );
+    }
+    classes
+}
+
+// The likeliest key, weighing each pitch class by the notes played
+fn key(session: &SavedSession, notation: Notation) -> String {
+    let mut profile = [0f32; 12];
+    for onset in session.onsets.iter() {
+        profile[onset.note.get_octave_index() as usize] += 1f32;
+    }
+    match keys::candidates(&profile).first() {
+        Some(&(key, correlation)) if !session.onsets.is_empty() => {
+            format!("{} ({:.2})", key.name(notation), correlation)
+        }
+        _ => "none".to_owned(),
+    }
+}
+
+// Human readable lines comparing the sessions, the first before the second
+pub fn compare(
+    a_name: &str,
+    a: &SavedSession,
+    b_name: &str,
+    b: &SavedSession,
+    notation: Notation,
+) -> Result<Vec<String>, String> {
+    for (name, session) in [(a_name, a), (b_name, b)].iter() {
+        if session.onsets.is_empty() {
+            return Err(format!("No notes were played in {}", name));
+        }
+    }
+    let mut lines = vec![
+        format!("--- {} ({}, {} notes)", a_name, duration(a.duration), a.onsets.len()),
+        format!("+++ {} ({}, {} notes)", b_name, duration(b.duration), b.onsets.len()),
+        format!("Key: {} -> {}", key(a, notation), key(b, notation)),
+    ];
+
+    let (ma, mb) = (a.metrics().unwrap(), b.metrics().unwrap());
+    lines.push(format!(
+        "Pitch class entropy: {:.2} -> {:.2} bits ({:+.2})",
+        ma.entropy,
+        mb.entropy,
+        mb.entropy - ma.entropy
+    ));
+    lines.push(format!(
+        "Chord changes per minute: {:.1} -> {:.1} ({:+.1})",
+        ma.chord_rate,
+        mb.chord_rate,
+        mb.chord_rate - ma.chord_rate
+    ));
+    lines.push(format!(
+        "Tension variance: {:.3} -> {:.3} ({:+.3})",
+        ma.tension_variance,
+        mb.tension_variance,
+        mb.tension_variance - ma.tension_variance
+    ));
+
+    // The consonance distributions, one bar of each session per band
+    lines.push("Dissonance of the notes played, share of the notes:".to_owned());
+    for (band, (sa, sb)) in distribution(a).iter().zip(distribution(b).iter()).enumerate() {
+        let range = format!(
+            "{:.1}-{:.1}",
+            band as f32 / BANDS as f32,
+            (band + 1) as f32 / BANDS as f32
+        );
+        lines.push(format!("  {} - {:>3.0}% {}", range, sa * 100f32, bar(*sa)));
+        lines.push(format!("  {} + {:>3.0}% {}", range, sb * 100f32, bar(*sb)));
+    }
+
+    // The pitch classes overlaid, with the change of their average dissonance
+    lines.push("Pitch classes, share of the notes and average dissonance:".to_owned());
+    let names = notation.get_names();
+    for (class, ((sa, da), (sb, db))) in pitch_classes(a).iter().zip(pitch_classes(b).iter()).enumerate() {
+        if *sa == 0f32 && *sb == 0f32 {
+            continue;
+        }
+        let dissonance = |d: &Option<f32>| d.map_or("  - ".to_owned(), |d| format!("{:.2}", d));
+        let change = match (da, db) {
+            (Some(da), Some(db)) if db < da => "more consonant",
+            (Some(da), Some(db)) if db > da => "more dissonant",
+            (Some(_), Some(_)) => "",
+            (Some(_), None) => "dropped",
+            _ => "new",
+        };
+        lines.push(format!(
+            "  {} {:>3.0}% {} -> {:>3.0}% {} {}",
+            names[class],
+            sa * 100f32,
+            dissonance(da),
+            sb * 100f32,
+            dissonance(db),
+            change
+        ));
+    }
+    Ok(lines.into_iter().map(|l| l.trim_end().to_owned()).collect_vec())
+}
@@ -0,0 +1,97 @@
+// The configuration file, made of sections of lines
+// Sections start with a [name] header, lines starting with '#' are comments
+
+// The sections understood, with the module reading them
+// layout: the windows and their panels, see layout.rs
+// controller: the game controller mapping, see controller.rs
+// keys: the keyboard bindings, see keymap.rs
+// calibration: the correction of the capture clock, see calibration.rs
+
+// Crate
+use crate::tools;
+
+const SECTIONS: [&str; 4] = ["layout", "controller", "keys", "calibration"];
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    path: String,
+    // Every section, with its lines and their line numbers
+    sections: Vec<(String, Vec<(usize, String)>)>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, String> {
+        Config::parse(path, &tools::read_text(path)?)
+    }
+
+    // Parse the text of a configuration, the path only naming it in the errors
+    pub fn parse(path: &str, text: &str) -> Result<Config, String> {
+        let mut config = Config {
+            path: path.to_owned(),
+            sections: Vec::new(),
+        };
+        for (number, line) in text.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim();
+                if !SECTIONS.contains(&name) {
+                    return Err(config.error(
+                        number,
+                        &format!("unknown section '{}', expected {}", name, SECTIONS.join(", ")),
+                    ));
+                }
+                config.sections.push((name.to_owned(), Vec::new()));
+                continue;
+            }
+            match config.sections.last_mut() {
+                Some((_, lines)) => lines.push((number, line.to_owned())),
+                None => return Err(config.error(number, "expected a [section] first")),
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // If the section is given, even without any line
+    pub fn has_section(&self, name: &str) -> bool {
+        self.sections.iter().any(|(section, _)| section == name)
+    }
+
+    // The lines of a section, with their line numbers
+    pub fn section(&self, name: &str) -> Vec<(usize, &str)> {
+        self.sections
+            .iter()
+            .filter(|(section, _)| section == name)
+            .flat_map(|(_, lines)| lines.iter().map(|(number, line)| (*number, line.as_str())))
+            .collect()
+    }
+
+    // An error message pointing to a line
+    pub fn error(&self, number: usize, message: &str) -> String {
+        format!("{}:{}: {}", self.path, number + 1, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_order_mark_before_the_first_section() {
+        let text = tools::decode_text(b"\xEF\xBB\xBF[keys]\nspace pause");
+        let config = Config::parse("test.conf", &text).unwrap();
+        assert_eq!(config.section("keys"), vec![(1, "space pause")]);
+    }
+
+    #[test]
+    fn invalid_utf8_is_kept_in_the_line() {
+        let text = tools::decode_text(b"[keys]\n\xFF pause");
+        let config = Config::parse("test.conf", &text).unwrap();
+        assert_eq!(config.section("keys"), vec![(1, "\u{fffd} pause")]);
+    }
+}
@@ -0,0 +1,60 @@
+// An external harmonic context, replacing the analysed audio for scoring
+// Set by sources that know the harmony exactly, like MIDI input or a chord chart
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::frequency::Frequency;
+use crate::notes::Note;
+
+// The number of harmonics of each synthesized context note
+const CONTEXT_HARMONICS: usize = 8;
+
+#[derive(Clone)]
+pub struct Context {
+    notes: Arc<Mutex<Vec<Note>>>,
+    // The chords set since the start, to tell the chord changes of a chart from the notes played
+    chords: Arc<AtomicUsize>,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context {
+            notes: Arc::new(Mutex::new(Vec::new())),
+            chords: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn set_notes(&self, notes: Vec<Note>) {
+        *self.notes.lock().unwrap() = notes;
+    }
+
+    // Set the notes of the next chord of a chart
+    pub fn set_chord(&self, notes: Vec<Note>) {
+        self.set_notes(notes);
+        self.chords.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn chord_changes(&self) -> usize {
+        self.chords.load(Ordering::Relaxed)
+    }
+
+    pub fn notes(&self) -> Vec<Note> {
+        self.notes.lock().unwrap().clone()
+    }
+
+    // A synthetic spectrum of the context notes, each with decaying harmonics
+    pub fn spectrum(&self) -> Vec<Frequency> {
+        let mut spectrum = Vec::new();
+        for note in self.notes() {
+            for h in 1..=CONTEXT_HARMONICS {
+                spectrum.push(Frequency {
+                    intensity: 1f32 / h as f32,
+                    value: note.freq() * h as f32,
+                });
+            }
+        }
+        spectrum.sort_by(|a, b| a.value.partial_cmp(&b.value).unwrap());
+        spectrum
+    }
+}
@@ -0,0 +1,548 @@
+// Figured bass exercises, the continuo followed at the tempo like a chart
+// The bass and its figures are the harmonic context, the left hand playing the bass and the right
+// hand realizing the figures, each figure judged once its beats are over
+//   the bass: the note written, in the left hand
+//   the realization: every tone of the figures in the right hand, the fifth of the seventh chords
+//   being optional, and no tone foreign to them
+//   the voice leading: no parallel fifths or octaves between the bass and the right hand
+// A plain text file, bars separated by '|', a bass and its figures per chord, ie.
+//   Title: Sarabande
+//   Key: D minor
+//   Tempo: 72
+//   Time: 3/4
+//   | D A:6 Bb:6/5 | C#:6 D | G:6/4 A:7/# | D |
+// The figures are diatonic in the key, a sharp (or a plus), a flat or a natural (n) making sharp, flat
+// or natural the one it is written with, a lone one the third, and the figures left out are implied,
+// ie. 6 being 6/3 and 6/5 being 6/5/3
+
+// Standard
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::chords::{Chord, Quality, Symbols};
+use crate::context::Context;
+use crate::display::Notation;
+use crate::keys::{Key, Mode};
+use crate::notes::Note;
+use crate::scores::Hands;
+use crate::timeline::Timeline;
+use crate::tools;
+
+// The lowest key of the right hand, when the keyboard is not split otherwise
+pub const DEFAULT_SPLIT: Note = Note::C4;
+// The octaves the bass and the realization are set in, as the harmonic context
+const BASS_OCTAVE: usize = 2;
+const REALIZATION_OCTAVE: usize = 4;
+// The share of the frames of a figure a note must be heard over to count as played
+const PLAYED_SHARE: f32 = 0.3;
+// The figures shown at once by the displays
+const PAGE_FIGURES: usize = 12;
+
+// A bass note and its figures, lasting a beat or more
+#[derive(Clone, Debug, PartialEq)]
+pub struct Figure {
+    // The pitch class of the bass, 0 being C
+    pub bass: usize,
+    // As written, ie. 6/5, empty for a root position triad
+    pub figures: String,
+    // The pitch classes above the bass, as realized in the key
+    pub tones: Vec<usize>,
+    // At the tempo
+    pub beats: f32,
+}
+
+impl Figure {
+    // ie. Bb6/5
+    pub fn name(&self, notation: Notation) -> String {
+        format!("{}{}", notation.root_name(self.bass), self.figures)
+    }
+
+    // The tones a realization must have, the fifth of the seventh chords being left out at times
+    fn required(&self) -> Vec<usize> {
+        let fifth = (self.bass + 7) % 12;
+        self.tones
+            .iter()
+            .cloned()
+            .filter(|&t| !(self.tones.len() > 2 && t == fifth))
+            .collect_vec()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FiguredBass {
+    pub title: Option<String>,
+    pub tempo: Option<f32>,
+    pub key: Key,
+    pub figures: Vec<Figure>,
+}
+
+impl FiguredBass {
+    // Read a figured bass, the bass notes written in the given convention
+    pub fn load(path: &str, symbols: Symbols) -> Result<FiguredBass, String> {
+        let text = tools::read_text(path)?;
+        let mut bass = FiguredBass {
+            title: None,
+            tempo: None,
+            key: Key {
+                root: 0,
+                mode: Mode::Major,
+            },
+            figures: Vec::new(),
+        };
+        let mut beats_per_bar = 4;
+        let mut bars: Vec<Vec<(usize, bool, String)>> = Vec::new();
+        for (number, line) in text.lines().map(str::trim).enumerate() {
+            let error = |message: &str| format!("{}:{}: {}", path, number + 1, message);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, ':').map(str::trim);
+            let value = parts.nth(1).unwrap_or("");
+            if line.starts_with("Title:") {
+                bass.title = Some(value.to_owned()).filter(|t| !t.is_empty());
+            } else if line.starts_with("Key:") {
+                bass.key = Key::parse(value, symbols)
+                    .ok_or_else(|| error("the key is not a key (ie. D minor, F major)"))?;
+            } else if line.starts_with("Tempo:") {
+                bass.tempo = Some(
+                    value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|t| t.is_finite() && *t > 0f32)
+                        .ok_or_else(|| error("the tempo is not a positive float"))?,
+                );
+            } else if line.starts_with("Time:") {
+                beats_per_bar = value
+                    .split('/')
+                    .next()
+                    .and_then(|b| b.trim().parse::<usize>().ok())
+                    .filter(|&b| b > 0)
+                    .ok_or_else(|| error("the time is not a time signature (ie. 3/4)"))?;
+            } else {
+                for bar in line.split('|') {
+                    let chords = bar
+                        .split_whitespace()
+                        .map(|token| {
+                            let mut parts = token.splitn(2, ':');
+                            let note = parts.next().unwrap();
+                            let figures = parts.next().unwrap_or("");
+                            // A bass note, without any chord symbol
+                            let root = Chord::parse(note, symbols)
+                                .filter(|c| {
+                                    c.quality == Quality::Major && note.chars().count() <= 2
+                                })
+                                .ok_or_else(|| error(&format!("unknown bass note '{}'", note)))?;
+                            Ok((root.root, note[1..].starts_with('b'), figures.to_owned()))
+                        })
+                        .collect::<Result<Vec<_>, String>>()?;
+                    if !chords.is_empty() {
+                        bars.push(chords);
+                    }
+                }
+            }
+        }
+        for bar in bars {
+            let beats = beats_per_bar as f32 / bar.len() as f32;
+            for (note, flat, figures) in bar {
+                let tones = realize(note, flat, &figures, bass.key)
+                    .ok_or_else(|| format!("{}: unknown figures '{}'", path, figures))?;
+                bass.figures.push(Figure {
+                    bass: note,
+                    figures,
+                    tones,
+                    beats,
+                });
+            }
+        }
+        if bass.figures.is_empty() {
+            return Err(format!("No figures found in {}", path));
+        }
+        Ok(bass)
+    }
+}
+
+// The pitch classes above a bass, its figures counted along the scale of the key
+// A bass outside the scale is the degree it alters, the one under it when sharp, above it when flat
+fn realize(bass: usize, flat: bool, figures: &str, key: Key) -> Option<Vec<usize>> {
+    let mut written = Vec::new();
+    if !figures.is_empty() {
+        for figure in figures.split('/') {
+            let accidental = |c: char| c == '#' || c == '+' || c == 'b' || c == 'n';
+            let digits = figure.trim_matches(accidental);
+            // A lone accidental alters the third
+            let interval = match digits {
+                "" => 3,
+                digits => digits.parse::<usize>().ok().filter(|&i| i >= 2 && i <= 9)?,
+            };
+            written.push((interval, figure.chars().find(|&c| accidental(c))));
+        }
+    }
+    let numbers = written
+        .iter()
+        .map(|&(i, _)| i)
+        .sorted()
+        .rev()
+        .dedup()
+        .collect_vec();
+    let implied: Vec<usize> = match numbers.as_slice() {
+        [] | [3] | [5] | [5, 3] | [8] | [8, 5, 3] => vec![5, 3],
+        [6] | [6, 3] => vec![6, 3],
+        [7] | [7, 3] | [7, 5] | [7, 5, 3] => vec![7, 5, 3],
+        [6, 5] | [6, 5, 3] => vec![6, 5, 3],
+        [4, 3] | [6, 4, 3] => vec![6, 4, 3],
+        [2] | [4, 2] | [6, 4, 2] => vec![6, 4, 2],
+        [4] | [5, 4] => vec![5, 4],
+        [9] | [9, 3] | [9, 5, 3] => vec![9, 5, 3],
+        numbers => numbers.to_vec(),
+    };
+    let scale = key.scale();
+    // The black keys of the scale are flats in the keys with flats, sharps otherwise
+    let relative_major = match key.mode {
+        Mode::Major => key.root,
+        Mode::Minor => (key.root + 3) % 12,
+    };
+    let flats = [5, 10, 3, 8, 1].contains(&relative_major);
+    let degree = scale.iter().position(|&p| p == bass).or_else(|| {
+        let natural = if flat {
+            (bass + 1) % 12
+        } else {
+            (bass + 11) % 12
+        };
+        scale.iter().position(|&p| p == natural)
+    })?;
+    Some(
+        implied
+            .iter()
+            .map(|&interval| {
+                let pitch = scale[(degree + interval - 1) % 7];
+                let black = [1, 3, 6, 8, 10].contains(&pitch);
+                let accidental = written
+                    .iter()
+                    .find(|&&(i, _)| i == interval)
+                    .and_then(|&(_, a)| a);
+                let alteration = match accidental {
+                    Some('#') | Some('+') if flats || !black => 1,
+                    Some('b') if !flats || !black => 11,
+                    Some('n') if black && flats => 1,
+                    Some('n') if black => 11,
+                    _ => 0,
+                };
+                (pitch + alteration) % 12
+            })
+            .dedup()
+            .collect_vec(),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    NoBass,
+    WrongBass,
+    NoRealization,
+    MissingTone,
+    ForeignTone,
+    ParallelFifths,
+    ParallelOctaves,
+}
+
+impl Fault {
+    pub fn name(self) -> &'static str {
+        match self {
+            Fault::NoBass => "no bass",
+            Fault::WrongBass => "wrong bass",
+            Fault::NoRealization => "nothing over the bass",
+            Fault::MissingTone => "figure not realized",
+            Fault::ForeignTone => "tone foreign to the figures",
+            Fault::ParallelFifths => "parallel fifths",
+            Fault::ParallelOctaves => "parallel octaves",
+        }
+    }
+}
+
+// A figure as played
+#[derive(Clone, Debug, PartialEq)]
+pub struct Judgement {
+    // The figure, counted from 0
+    pub figure: usize,
+    // The bass heard the longest, None when nothing was
+    pub bass: Option<Note>,
+    // The notes of the right hand heard long enough, lowest first
+    pub played: Vec<Note>,
+    pub faults: Vec<Fault>,
+}
+
+// How far the exercise is, for the displays
+#[derive(Clone, Debug)]
+pub struct Progress {
+    pub figures: Arc<Vec<Figure>>,
+    // The pass through the bass, and the figure being played
+    pub pass: usize,
+    pub figure: usize,
+    // The figures judged during this pass
+    pub judgements: Vec<Judgement>,
+}
+
+impl Progress {
+    // The figures shown, the page of the one being played
+    pub fn page(&self) -> Range<usize> {
+        let start = self.figure / PAGE_FIGURES * PAGE_FIGURES;
+        start..(start + PAGE_FIGURES).min(self.figures.len())
+    }
+
+    // ie. 9 of 11 figures without faults
+    pub fn describe(&self) -> String {
+        let good = self
+            .judgements
+            .iter()
+            .filter(|j| j.faults.is_empty())
+            .count();
+        format!(
+            "{} of {} figures without faults",
+            good,
+            self.judgements.len()
+        )
+    }
+
+    // The pass so far, then every fault with the figures it was made on
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Continuo pass {}: {}",
+            self.pass + 1,
+            self.describe()
+        )];
+        let faults = self
+            .judgements
+            .iter()
+            .flat_map(|j| j.faults.iter().map(move |&f| (f, j.figure + 1)));
+        for (fault, figures) in &faults
+            .sorted_by_key(|&(f, _)| f as usize)
+            .group_by(|&(f, _)| f)
+        {
+            lines.push(format!(
+                "  {}: figures {}",
+                fault.name(),
+                figures.map(|(_, s)| s).dedup().join(", ")
+            ));
+        }
+        lines
+    }
+
+    // The faults of the last figure judged with some, ie. figure 4: wrong bass
+    pub fn last_faults(&self) -> Option<String> {
+        let judgement = self
+            .judgements
+            .iter()
+            .rev()
+            .find(|j| !j.faults.is_empty())?;
+        Some(format!(
+            "figure {}: {}",
+            judgement.figure + 1,
+            judgement.faults.iter().map(|f| f.name()).join(", ")
+        ))
+    }
+}
+
+struct State {
+    // The pass and the figure being played, as set by the follower
+    position: (usize, usize),
+    // The position the notes heard are counted for, the frames each bass and each note of the right
+    // hand was heard on, and the frames counted
+    listened: (usize, usize),
+    basses: Vec<(Note, usize)>,
+    heard: Vec<(Note, usize)>,
+    frames: usize,
+    judgements: Vec<Judgement>,
+}
+
+#[derive(Clone)]
+pub struct Continuo {
+    figures: Arc<Vec<Figure>>,
+    // In beats per minute
+    tempo: f32,
+    state: Arc<Mutex<State>>,
+    // The clock of the follower, the captured audio ahead by its latency
+    timeline: Timeline,
+}
+
+impl Continuo {
+    pub fn new(bass: FiguredBass, tempo: f32, timeline: Timeline) -> Continuo {
+        Continuo {
+            figures: Arc::new(bass.figures),
+            tempo,
+            state: Arc::new(Mutex::new(State {
+                position: (0, 0),
+                listened: (0, 0),
+                basses: Vec::new(),
+                heard: Vec::new(),
+                frames: 0,
+                judgements: Vec::new(),
+            })),
+            timeline,
+        }
+    }
+
+    // Follow the bass in a loop, setting it and its figures as the harmonic context
+    pub fn follow(&self, context: Context) {
+        let continuo = self.clone();
+        std::thread::spawn(move || {
+            let note = |pitch: usize, octave: usize| Note::iter().nth(octave * 12 + pitch);
+            // The figures are timed from the start, not to drift from the audio
+            let mut time = continuo.timeline.now();
+            for pass in 0.. {
+                for (index, figure) in continuo.figures.iter().enumerate() {
+                    let notes = std::iter::once(note(figure.bass, BASS_OCTAVE))
+                        .chain(figure.tones.iter().map(|&t| note(t, REALIZATION_OCTAVE)))
+                        .flatten()
+                        .collect_vec();
+                    context.set_chord(notes);
+                    continuo.state.lock().unwrap().position = (pass, index);
+                    time += figure.beats as f64 * 60f64 / continuo.tempo as f64;
+                    continuo.timeline.wait_until(time, || true);
+                }
+            }
+        });
+    }
+
+    // Count the notes of both hands heard on a frame, judging the previous figure once the follower
+    // moved on
+    pub fn listen(&self, hands: Option<&Hands>) {
+        let mut state = self.state.lock().unwrap();
+        if state.position != state.listened {
+            let (pass, figure) = state.listened;
+            let least = (state.frames as f32 * PLAYED_SHARE).ceil().max(1f32) as usize;
+            let bass = state
+                .basses
+                .iter()
+                .max_by_key(|&&(_, frames)| frames)
+                .filter(|&&(_, frames)| frames >= least)
+                .map(|&(note, _)| note);
+            let played = state
+                .heard
+                .iter()
+                .filter(|&&(_, frames)| frames >= least)
+                .map(|&(note, _)| note)
+                .sorted_by_key(|&n| n as usize)
+                .collect_vec();
+            let judgement = self.judge(&state.judgements, figure, bass, played);
+            state.judgements.push(judgement);
+            // Every pass is judged afresh
+            if state.position.0 != pass {
+                for line in self.progress_of(&state, pass).report() {
+                    status!("{}", line);
+                }
+                state.judgements.clear();
+            }
+            state.listened = state.position;
+            state.basses.clear();
+            state.heard.clear();
+            state.frames = 0;
+        }
+        state.frames += 1;
+        let hands = match hands {
+            Some(hands) => hands,
+            None => return,
+        };
+        let count = |counted: &mut Vec<(Note, usize)>, note: Note| match counted
+            .iter_mut()
+            .find(|(n, _)| *n == note)
+        {
+            Some((_, frames)) => *frames += 1,
+            None => counted.push((note, 1)),
+        };
+        if let Some(&bass) = hands.left.first() {
+            count(&mut state.basses, bass);
+        }
+        for &note in hands.right.iter() {
+            count(&mut state.heard, note);
+        }
+    }
+
+    // The faults of a figure as played, the judgements so far being those of the pass
+    fn judge(
+        &self,
+        judgements: &[Judgement],
+        figure: usize,
+        bass: Option<Note>,
+        played: Vec<Note>,
+    ) -> Judgement {
+        let written = &self.figures[figure];
+        let mut faults = Vec::new();
+        match bass {
+            None => faults.push(Fault::NoBass),
+            Some(bass) if bass.get_octave_index() as usize != written.bass => {
+                faults.push(Fault::WrongBass)
+            }
+            _ => (),
+        }
+        let classes = played
+            .iter()
+            .map(|&n| n.get_octave_index() as usize)
+            .collect_vec();
+        if played.is_empty() {
+            faults.push(Fault::NoRealization);
+        } else {
+            if written.required().iter().any(|t| !classes.contains(t)) {
+                faults.push(Fault::MissingTone);
+            }
+            if classes
+                .iter()
+                .any(|c| *c != written.bass && !written.tones.contains(c))
+            {
+                faults.push(Fault::ForeignTone);
+            }
+        }
+        // From the figure just before, a voice of the right hand moving with the bass into the same
+        // perfect consonance
+        let previous = judgements.last().filter(|j| j.figure + 1 == figure);
+        if let (Some(previous), Some(bass)) = (previous, bass) {
+            if let Some(before) = previous.bass.filter(|&b| b != bass) {
+                let (b1, b2) = (before as i32, bass as i32);
+                for &u1 in previous.played.iter() {
+                    for &u2 in played.iter() {
+                        let (u1, u2) = (u1 as i32, u2 as i32);
+                        let class = (u2 - b2).rem_euclid(12);
+                        let same_voice = (u2 - u1).abs() <= 7;
+                        if same_voice
+                            && class == (u1 - b1).rem_euclid(12)
+                            && (u2 - u1).signum() == (b2 - b1).signum()
+                        {
+                            let fault = match class {
+                                7 => Fault::ParallelFifths,
+                                0 => Fault::ParallelOctaves,
+                                _ => continue,
+                            };
+                            if !faults.contains(&fault) {
+                                faults.push(fault);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Judgement {
+            figure,
+            bass,
+            played,
+            faults,
+        }
+    }
+
+    fn progress_of(&self, state: &State, pass: usize) -> Progress {
+        let (_, figure) = state.position;
+        Progress {
+            figures: self.figures.clone(),
+            pass,
+            figure,
+            judgements: state.judgements.clone(),
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        let state = self.state.lock().unwrap();
+        self.progress_of(&state, state.position.0)
+    }
+}
@@ -0,0 +1,122 @@
+// Runtime commands, sent from the displays to the analysis, and the actions triggering them
+
+// Standard
+use std::sync::mpsc::{Receiver, Sender};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    // Forget the smoothed scores, starting over from the current audio
+    Reset,
+    // Switch to the next musical style
+    NextStyle,
+    // Change the time for the perceived dissonance to drop by half
+    SetHalflife(f32),
+    // Mark the timeline with a text, from an external tool
+    Annotate(String),
+    // Undo or redo the last change of the options, or undo them all back to the launch options
+    Undo,
+    Redo,
+    Revert,
+}
+
+// Forward the commands to every analysis, when the channels are analysed apart
+pub fn fan_out(receiver: Receiver<Command>, senders: Vec<Sender<Command>>) {
+    std::thread::spawn(move || {
+        for command in receiver.iter() {
+            for sender in senders.iter() {
+                sender.send(command.clone()).ok();
+            }
+        }
+    });
+}
+
+// Everything the keyboard, the controls panel or a game controller can do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Quit,
+    // Play the detected chord, to hear what the scores are based on
+    PlayChord,
+    // Switch between the note graph and the intonation heatmap
+    Intonation,
+    // Switch between the note graph and the held note details
+    Detail,
+    NextSong,
+    PreviousSong,
+    NextChord,
+    Reset,
+    // Keep showing the current scores
+    Freeze,
+    NextStyle,
+    // Switch between the frame, session and absolute color scaling
+    Scaling,
+    // Show the key bindings and the current options
+    Help,
+    // Undo or redo the changes of the options, or go back to the options at launch
+    Undo,
+    Redo,
+    Revert,
+}
+
+pub const ACTIONS: [Action; 15] = [
+    Action::Quit,
+    Action::PlayChord,
+    Action::Intonation,
+    Action::Detail,
+    Action::NextSong,
+    Action::PreviousSong,
+    Action::NextChord,
+    Action::Reset,
+    Action::Freeze,
+    Action::NextStyle,
+    Action::Scaling,
+    Action::Help,
+    Action::Undo,
+    Action::Redo,
+    Action::Revert,
+];
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::PlayChord => "play-chord",
+            Action::Intonation => "intonation",
+            Action::Detail => "detail",
+            Action::NextSong => "next-song",
+            Action::PreviousSong => "previous-song",
+            Action::NextChord => "next-chord",
+            Action::Reset => "reset",
+            Action::Freeze => "freeze",
+            Action::NextStyle => "style",
+            Action::Scaling => "scaling",
+            Action::Help => "help",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Revert => "revert",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::PlayChord => "Play the detected chord",
+            Action::Intonation => "Show the intonation heatmap",
+            Action::Detail => "Show the held note details",
+            Action::NextSong => "Next song",
+            Action::PreviousSong => "Previous song",
+            Action::NextChord => "Next chord of the chart",
+            Action::Reset => "Reset the scores",
+            Action::Freeze => "Freeze the display",
+            Action::NextStyle => "Next musical style",
+            Action::Scaling => "Next color scaling",
+            Action::Help => "Show this help",
+            Action::Undo => "Undo the last option change",
+            Action::Redo => "Redo the option change undone",
+            Action::Revert => "Revert to the launch options",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().cloned().find(|action| action.name() == name)
+    }
+}
@@ -0,0 +1,133 @@
+// Game controller mapping, for footswitches and knobs when the hands are busy
+// Read from the controller section of the config, one mapping per line:
+//   a = next-chord           a button triggers an action
+//   rightx = halflife 0.1 4  an axis sets the halflife, from its minimum to its maximum
+
+// Sdl
+use sdl2::controller::{Axis, Button};
+
+// Crate
+use crate::config::Config;
+use crate::control::{Action, Command};
+
+// The default halflife range of an axis, in seconds
+const HALFLIFE_RANGE: (f32, f32) = (0.05, 2.0);
+// The smallest relative halflife change sent, to not flood the analysis
+const HALFLIFE_STEP: f32 = 0.02;
+
+#[derive(Clone, Debug)]
+pub struct ControllerMap {
+    buttons: Vec<(Button, Action)>,
+    // The axes setting the halflife, with its range
+    axes: Vec<(Axis, (f32, f32))>,
+    // The last halflife sent
+    halflife: Option<f32>,
+}
+
+impl Default for ControllerMap {
+    // Footswitch style, paging through chords and songs
+    fn default() -> ControllerMap {
+        ControllerMap {
+            buttons: vec![
+                (Button::A, Action::NextChord),
+                (Button::B, Action::Freeze),
+                (Button::X, Action::Reset),
+                (Button::Y, Action::NextStyle),
+                (Button::DPadRight, Action::NextSong),
+                (Button::DPadLeft, Action::PreviousSong),
+            ],
+            axes: Vec::new(),
+            halflife: None,
+        }
+    }
+}
+
+impl ControllerMap {
+    // The mapping of the config, or the default one if it has none
+    pub fn from_config(config: &Config) -> Result<ControllerMap, String> {
+        let lines = config.section("controller");
+        if lines.is_empty() {
+            return Ok(ControllerMap::default());
+        }
+        let mut map = ControllerMap {
+            buttons: Vec::new(),
+            axes: Vec::new(),
+            halflife: None,
+        };
+        for (number, line) in lines {
+            let mut parts = line.splitn(2, '=');
+            let input = parts.next().unwrap().trim();
+            let mut target = parts.next().unwrap_or("").split_whitespace();
+            let name = target.next().unwrap_or("");
+            if let Some(button) = Button::from_string(input) {
+                let action = Action::from_name(name)
+                    .ok_or_else(|| config.error(number, &format!("unknown action '{}'", name)))?;
+                map.buttons.push((button, action));
+            } else if let Some(axis) = Axis::from_string(input) {
+                if name != "halflife" {
+                    return Err(config.error(number, "axes can only set the halflife"));
+                }
+                let bounds = target.map(str::parse::<f32>).collect::<Result<Vec<f32>, _>>();
+                let range = match bounds.as_ref().map(Vec::as_slice) {
+                    Ok([]) => HALFLIFE_RANGE,
+                    Ok(&[min, max]) if min > 0f32 && min < max => (min, max),
+                    _ => return Err(config.error(number, "expected a halflife range (ie. halflife 0.1 4)")),
+                };
+                map.axes.push((axis, range));
+            } else {
+                return Err(config.error(number, &format!("unknown controller input '{}'", input)));
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn action(&self, button: Button) -> Option<Action> {
+        self.buttons.iter().find(|(b, _)| *b == button).map(|&(_, action)| action)
+    }
+
+    // The command of an axis position, the halflife growing exponentially along the axis
+    pub fn command(&mut self, axis: Axis, value: i16) -> Option<Command> {
+        let &(_, (min, max)) = self.axes.iter().find(|(a, _)| *a == axis)?;
+        let position = (value as f32 - i16::min_value() as f32) / (u16::max_value() as f32);
+        let halflife = min * (max / min).powf(position);
+        match self.halflife {
+            Some(last) if (halflife / last - 1f32).abs() < HALFLIFE_STEP => None,
+            _ => {
+                self.halflife = Some(halflife);
+                Some(Command::SetHalflife(halflife))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(text: &str) -> Result<ControllerMap, String> {
+        ControllerMap::from_config(&Config::parse("test.conf", text)?)
+    }
+
+    #[test]
+    fn mapping_replaces_the_default() {
+        let mut map = map("[controller]\nx = help\nrightx = halflife 0.5 2\n").unwrap();
+        assert_eq!(map.action(Button::X), Some(Action::Help));
+        assert_eq!(map.action(Button::A), None);
+        // From the minimum to the maximum, exponentially
+        assert_eq!(map.command(Axis::RightX, i16::min_value()), Some(Command::SetHalflife(0.5)));
+        assert_eq!(map.command(Axis::RightX, i16::min_value() + 1), None);
+        assert_eq!(map.command(Axis::RightX, i16::max_value()), Some(Command::SetHalflife(2f32)));
+        assert_eq!(map.command(Axis::LeftX, 0), None);
+
+        let map = ControllerMap::from_config(&Config::default()).unwrap();
+        assert_eq!(map.action(Button::A), Some(Action::NextChord));
+    }
+
+    #[test]
+    fn malformed_mappings_are_refused() {
+        assert!(map("[controller]\nz = help\n").unwrap_err().contains("unknown controller input 'z'"));
+        assert!(map("[controller]\na = dance\n").unwrap_err().contains("unknown action 'dance'"));
+        assert_eq!(map("[controller]\nleftx = help\n").unwrap_err(), "test.conf:2: axes can only set the halflife");
+        assert!(map("[controller]\nleftx = halflife 2 1\n").is_err());
+    }
+}
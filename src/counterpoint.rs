@@ -0,0 +1,384 @@
+// Counterpoint exercises against a cantus firmus, followed at the tempo like a chart
+// The cantus is the harmonic context, the notes played being scored against it, and each note played
+// is judged by the rules of species counterpoint
+//   first species: a note against every note of the cantus, each interval a consonance
+//   second species: two notes against each, the second a dissonance only when passing by step
+//   third species: four notes against each, the weak ones dissonant only when passing or neighbouring
+// Perfect consonances in parallel or reached by similar motion, leaps over an octave or by a tritone
+// and unisons are faults, the first interval being a perfect consonance and the last an octave
+
+// Standard
+use std::sync::{Arc, Mutex};
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::cantus::{Melody, Step};
+use crate::context::Context;
+use crate::notes::Note;
+use crate::timeline::Timeline;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Species {
+    First,
+    Second,
+    Third,
+}
+
+impl Species {
+    pub fn from_name(name: &str) -> Option<Species> {
+        match name {
+            "first" => Some(Species::First),
+            "second" => Some(Species::Second),
+            "third" => Some(Species::Third),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Species::First => "first",
+            Species::Second => "second",
+            Species::Third => "third",
+        }
+    }
+
+    // The notes played against each note of the cantus
+    pub fn notes(self) -> usize {
+        match self {
+            Species::First => 1,
+            Species::Second => 2,
+            Species::Third => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    Silent,
+    Dissonance,
+    UnresolvedDissonance,
+    ParallelFifths,
+    ParallelOctaves,
+    DirectFifth,
+    DirectOctave,
+    Unison,
+    LargeLeap,
+    TritoneLeap,
+    ImperfectStart,
+    ImperfectEnd,
+}
+
+impl Fault {
+    pub fn name(self) -> &'static str {
+        match self {
+            Fault::Silent => "no note",
+            Fault::Dissonance => "dissonance",
+            Fault::UnresolvedDissonance => "dissonance not passing by step",
+            Fault::ParallelFifths => "parallel fifths",
+            Fault::ParallelOctaves => "parallel octaves",
+            Fault::DirectFifth => "direct fifth",
+            Fault::DirectOctave => "direct octave",
+            Fault::Unison => "unison",
+            Fault::LargeLeap => "leap over an octave",
+            Fault::TritoneLeap => "tritone leap",
+            Fault::ImperfectStart => "not starting on a perfect consonance",
+            Fault::ImperfectEnd => "not ending on an octave",
+        }
+    }
+}
+
+// A note played against the cantus
+#[derive(Clone, Debug, PartialEq)]
+pub struct Judgement {
+    // The note of the cantus, counted from 0, and the beat of the species against it
+    pub step: usize,
+    pub beat: usize,
+    pub cantus: Note,
+    // The note heard the longest during the beat, None when nothing was
+    pub played: Option<Note>,
+    pub faults: Vec<Fault>,
+}
+
+// How far the exercise is, for the displays
+#[derive(Clone, Debug)]
+pub struct Progress {
+    pub steps: Arc<Vec<Step>>,
+    pub species: Species,
+    // The pass through the cantus, and the note of the cantus being played
+    pub pass: usize,
+    pub step: usize,
+    // The notes judged during this pass
+    pub judgements: Vec<Judgement>,
+}
+
+impl Progress {
+    // ie. 9 of 11 notes without faults
+    pub fn describe(&self) -> String {
+        let good = self
+            .judgements
+            .iter()
+            .filter(|j| j.faults.is_empty())
+            .count();
+        format!("{} of {} notes without faults", good, self.judgements.len())
+    }
+
+    // The pass so far, then every fault with the notes it was made on
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Counterpoint pass {}: {}",
+            self.pass + 1,
+            self.describe()
+        )];
+        let faults = self
+            .judgements
+            .iter()
+            .flat_map(|j| j.faults.iter().map(move |&f| (f, j.step + 1)));
+        for (fault, steps) in &faults
+            .sorted_by_key(|&(f, _)| f as usize)
+            .group_by(|&(f, _)| f)
+        {
+            lines.push(format!(
+                "  {}: notes {}",
+                fault.name(),
+                steps.map(|(_, s)| s).dedup().join(", ")
+            ));
+        }
+        lines
+    }
+
+    // The faults of the last note judged with some, ie. note 4: parallel fifths
+    pub fn last_faults(&self) -> Option<String> {
+        let judgement = self
+            .judgements
+            .iter()
+            .rev()
+            .find(|j| !j.faults.is_empty())?;
+        Some(format!(
+            "note {}: {}",
+            judgement.step + 1,
+            judgement.faults.iter().map(|f| f.name()).join(", ")
+        ))
+    }
+}
+
+struct State {
+    // The pass, the note of the cantus and the beat being played, as set by the follower
+    position: (usize, usize, usize),
+    // The position the notes heard are counted for, and the frames each note was heard on
+    listened: (usize, usize, usize),
+    heard: Vec<(Note, usize)>,
+    silent: usize,
+    judgements: Vec<Judgement>,
+}
+
+#[derive(Clone)]
+pub struct Counterpoint {
+    steps: Arc<Vec<Step>>,
+    // In beats per minute
+    tempo: f32,
+    species: Species,
+    state: Arc<Mutex<State>>,
+    // The clock of the follower, the captured audio ahead by its latency
+    timeline: Timeline,
+}
+
+impl Counterpoint {
+    pub fn new(melody: Melody, tempo: f32, species: Species, timeline: Timeline) -> Counterpoint {
+        Counterpoint {
+            steps: Arc::new(melody.steps),
+            tempo,
+            species,
+            state: Arc::new(Mutex::new(State {
+                position: (0, 0, 0),
+                listened: (0, 0, 0),
+                heard: Vec::new(),
+                silent: 0,
+                judgements: Vec::new(),
+            })),
+            timeline,
+        }
+    }
+
+    // Follow the cantus in a loop, setting its notes as the harmonic context
+    pub fn follow(&self, context: Context) {
+        let counterpoint = self.clone();
+        std::thread::spawn(move || {
+            let beats = counterpoint.species.notes();
+            // The notes are timed from the start, not to drift from the audio
+            let mut time = counterpoint.timeline.now();
+            for pass in 0.. {
+                for (index, step) in counterpoint.steps.iter().enumerate() {
+                    context.set_chord(step.note.into_iter().collect());
+                    let duration =
+                        step.beats as f64 * 60f64 / counterpoint.tempo as f64 / beats as f64;
+                    for beat in 0..beats {
+                        counterpoint.state.lock().unwrap().position = (pass, index, beat);
+                        time += duration;
+                        counterpoint.timeline.wait_until(time, || true);
+                    }
+                }
+            }
+        });
+    }
+
+    // Count the note heard on a frame, judging the previous beat once the follower moved on
+    pub fn listen(&self, played: Option<Note>) {
+        let mut state = self.state.lock().unwrap();
+        if state.position != state.listened {
+            let (pass, step, beat) = state.listened;
+            let played = state
+                .heard
+                .iter()
+                .max_by_key(|&&(_, frames)| frames)
+                .filter(|&&(_, frames)| frames >= state.silent)
+                .map(|&(note, _)| note);
+            if let Some(cantus) = self.steps[step].note {
+                let judgement = self.judge(&mut state.judgements, step, beat, cantus, played);
+                state.judgements.push(judgement);
+            }
+            // Every pass is judged afresh
+            if state.position.0 != pass {
+                for line in self.progress_of(&state, pass).report() {
+                    status!("{}", line);
+                }
+                state.judgements.clear();
+            }
+            state.listened = state.position;
+            state.heard.clear();
+            state.silent = 0;
+        }
+        match played {
+            Some(note) => match state.heard.iter_mut().find(|(n, _)| *n == note) {
+                Some((_, frames)) => *frames += 1,
+                None => state.heard.push((note, 1)),
+            },
+            None => state.silent += 1,
+        }
+    }
+
+    // The faults of a note played against the cantus, the judgements so far being those of the pass
+    // A weak dissonance not left by step is a fault of the previous note
+    fn judge(
+        &self,
+        judgements: &mut [Judgement],
+        step: usize,
+        beat: usize,
+        cantus: Note,
+        played: Option<Note>,
+    ) -> Judgement {
+        let beats = self.species.notes();
+        let mut faults = Vec::new();
+        let played_note = match played {
+            Some(note) => note,
+            None => {
+                faults.push(Fault::Silent);
+                return Judgement {
+                    step,
+                    beat,
+                    cantus,
+                    played,
+                    faults,
+                };
+            }
+        };
+        let (p, c) = (played_note as i32, cantus as i32);
+        let class = (p - c).abs() % 12;
+        let strong = beat == 0;
+        // The note played on the beat just before, if any
+        let previous = judgements
+            .last_mut()
+            .filter(|j| j.step * beats + j.beat + 1 == step * beats + beat);
+        if let Some(previous) = previous {
+            if let Some(before) = previous.played {
+                let leap = (p - before as i32).abs();
+                let previous_class = (before as i32 - previous.cantus as i32).abs() % 12;
+                if !consonant(previous_class) && previous.beat != 0 && leap > 2 {
+                    previous.faults.push(Fault::UnresolvedDissonance);
+                }
+                if !consonant(class) && !strong && leap > 2 {
+                    faults.push(Fault::UnresolvedDissonance);
+                }
+                if leap > 12 {
+                    faults.push(Fault::LargeLeap);
+                } else if leap == 6 {
+                    faults.push(Fault::TritoneLeap);
+                }
+            }
+        }
+        if !consonant(class) && strong {
+            faults.push(Fault::Dissonance);
+        }
+        if strong {
+            // From the previous downbeat, the voices moving the same way into a perfect consonance
+            let downbeat = judgements
+                .iter()
+                .rev()
+                .find(|j| j.beat == 0 && j.step + 1 == step);
+            if let Some((before, cantus_before)) =
+                downbeat.and_then(|j| j.played.map(|p| (p as i32, j.cantus as i32)))
+            {
+                let similar = (p - before).signum() == (c - cantus_before).signum() && p != before;
+                let previous_class = (before - cantus_before).abs() % 12;
+                if similar && (class == 0 || class == 7) {
+                    // The upper voice leaping into it
+                    let upper_leap = (if p >= c {
+                        p - before
+                    } else {
+                        c - cantus_before
+                    })
+                    .abs()
+                        > 2;
+                    faults.extend(match (class == previous_class, class) {
+                        (true, 7) => Some(Fault::ParallelFifths),
+                        (true, _) => Some(Fault::ParallelOctaves),
+                        (false, 7) if upper_leap => Some(Fault::DirectFifth),
+                        (false, _) if upper_leap => Some(Fault::DirectOctave),
+                        _ => None,
+                    });
+                }
+            }
+            let first = self.steps.iter().position(|s| s.note.is_some());
+            let last = self.steps.iter().rposition(|s| s.note.is_some());
+            if Some(step) == first && class != 0 && class != 7 {
+                faults.push(Fault::ImperfectStart);
+            } else if Some(step) == last && class != 0 {
+                faults.push(Fault::ImperfectEnd);
+            } else if p == c && Some(step) != first && Some(step) != last {
+                faults.push(Fault::Unison);
+            }
+        }
+        Judgement {
+            step,
+            beat,
+            cantus,
+            played,
+            faults,
+        }
+    }
+
+    fn progress_of(&self, state: &State, pass: usize) -> Progress {
+        let (_, step, _) = state.position;
+        Progress {
+            steps: self.steps.clone(),
+            species: self.species,
+            pass,
+            step,
+            judgements: state.judgements.clone(),
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        let state = self.state.lock().unwrap();
+        self.progress_of(&state, state.position.0)
+    }
+}
+
+// The consonances above the lower voice, the fourth being a dissonance in two voices
+fn consonant(class: i32) -> bool {
+    match class {
+        0 | 3 | 4 | 7 | 8 | 9 => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,128 @@
+// Capture through cpal, an alternative to SDL on the setups where its capture fails
+// The channels are mixed down and written to a ring like the SDL recorder does
+
+// Tools
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, Device, Sample, SampleFormat, SampleRate, Stream, StreamConfig};
+
+// Crate
+use crate::error::Error;
+use crate::ring::{self, Consumer, Producer};
+use crate::timeline::Timeline;
+
+// The open capture stream, capturing until dropped
+pub struct CpalInput {
+    _stream: Stream,
+    pub name: String,
+    pub rate: i32,
+    // The samples per callback, when fixed
+    pub samples: Option<u32>,
+}
+
+fn capture_error(reason: impl ToString) -> Error {
+    Error::Capture {
+        driver: "cpal".to_owned(),
+        reason: reason.to_string(),
+    }
+}
+
+// Build a stream mixing down samples of any format
+fn build<T: Sample>(
+    device: &Device,
+    config: &StreamConfig,
+    producer: Producer,
+    timeline: Timeline,
+) -> Result<Stream, Error> {
+    let mut producer = producer;
+    let channels = config.channels.max(1) as usize;
+    device
+        .build_input_stream(
+            config,
+            move |input: &[T], _: &cpal::InputCallbackInfo| {
+                timeline.advance(input.len() / channels);
+                producer.write(
+                    input.chunks_exact(channels).map(|frame| {
+                        frame.iter().map(Sample::to_f32).sum::<f32>() / channels as f32
+                    }),
+                );
+            },
+            |e| eprintln!("Capture error: {}", e),
+        )
+        .map_err(capture_error)
+}
+
+// Open the default input device, or the first one whose name contains the given one
+// The samples are read from the ring returned
+pub fn open(
+    device: Option<&str>,
+    rate: u32,
+    samples: Option<u16>,
+    timeline: Timeline,
+) -> Result<(CpalInput, Consumer), Error> {
+    let host = cpal::default_host();
+    let device = match device {
+        Some(wanted) => {
+            let devices = host
+                .input_devices()
+                .map_err(capture_error)?
+                .collect::<Vec<Device>>();
+            let names = devices
+                .iter()
+                .map(|d| d.name().unwrap_or_default())
+                .collect::<Vec<String>>();
+            // By index, or part of the name
+            let index = match wanted.parse::<usize>() {
+                Ok(i) if i < names.len() => Some(i),
+                _ => names.iter().position(|n| n.contains(wanted)),
+            };
+            match index {
+                Some(i) => devices.into_iter().nth(i).unwrap(),
+                None => {
+                    return Err(Error::Device {
+                        device: wanted.to_owned(),
+                        available: names,
+                    })
+                }
+            }
+        }
+        None => host.default_input_device().ok_or_else(|| Error::Device {
+            device: "default".to_owned(),
+            available: Vec::new(),
+        })?,
+    };
+    let name = device.name().unwrap_or_default();
+
+    // The asked rate if the device supports it, its default config otherwise
+    let supported = device
+        .supported_input_configs()
+        .map_err(capture_error)?
+        .find(|c| c.min_sample_rate().0 <= rate && c.max_sample_rate().0 >= rate)
+        .map(|c| c.with_sample_rate(SampleRate(rate)));
+    let supported = match supported {
+        Some(supported) => supported,
+        None => device.default_input_config().map_err(capture_error)?,
+    };
+    let format = supported.sample_format();
+    let mut config: StreamConfig = supported.into();
+    if let Some(samples) = samples {
+        config.buffer_size = BufferSize::Fixed(samples as u32);
+    }
+
+    let (producer, consumer) = ring::for_rate(config.sample_rate.0 as i32);
+    let stream = match format {
+        SampleFormat::F32 => build::<f32>(&device, &config, producer, timeline)?,
+        SampleFormat::I16 => build::<i16>(&device, &config, producer, timeline)?,
+        SampleFormat::U16 => build::<u16>(&device, &config, producer, timeline)?,
+    };
+    stream.play().map_err(capture_error)?;
+    let input = CpalInput {
+        _stream: stream,
+        name,
+        rate: config.sample_rate.0 as i32,
+        samples: match config.buffer_size {
+            BufferSize::Fixed(samples) => Some(samples),
+            BufferSize::Default => None,
+        },
+    };
+    Ok((input, consumer))
+}
@@ -0,0 +1,134 @@
+// The capture devices of the audio subsystem, which the SDL bindings only list for playback
+
+// Standard
+use std::ffi::CStr;
+use std::process::Command;
+
+// SDL2
+use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+// Crate
+use crate::error::Error;
+
+// The usual delay added by Bluetooth sources, in seconds
+pub const BLUETOOTH_LATENCY: f32 = 0.2;
+// The PulseAudio name of the monitor of the default output, PipeWire understanding it too
+pub const DEFAULT_MONITOR: &str = "@DEFAULT_MONITOR@";
+// The parts of the names of Bluetooth devices
+const BLUETOOTH_NAMES: [&str; 6] = ["bluetooth", "bluez", "hands-free", "headset", "airpods", "a2dp"];
+
+// The names of the capture devices, by index
+pub fn capture_names(_audio: &AudioSubsystem) -> Vec<String> {
+    // The subsystem being initialized, the device list is available
+    let count = unsafe { sdl2::sys::SDL_GetNumAudioDevices(1) };
+    (0..count.max(0))
+        .filter_map(|i| {
+            let name = unsafe { sdl2::sys::SDL_GetAudioDeviceName(i, 1) };
+            if name.is_null() {
+                None
+            } else {
+                Some(unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned())
+            }
+        })
+        .collect()
+}
+
+// The name given by its index, or the first containing the part given, whatever the case
+fn find(query: &str, names: &[String]) -> Option<String> {
+    match query.parse::<usize>() {
+        Ok(i) if i < names.len() => Some(names[i].clone()),
+        _ => {
+            let query = query.to_lowercase();
+            names.iter().find(|n| n.to_lowercase().contains(&query)).cloned()
+        }
+    }
+}
+
+// Find a capture device from its index or part of its name
+pub fn find_capture(device: &str, audio: &AudioSubsystem) -> Result<String, Error> {
+    let names = capture_names(audio);
+    find(device, &names).ok_or_else(|| Error::Device {
+        device: device.to_owned(),
+        available: names,
+    })
+}
+
+// A capture callback ignoring the audio, to open devices for their specs
+struct Probe;
+
+impl AudioCallback for Probe {
+    type Channel = f32;
+
+    fn callback(&mut self, _input: &mut [f32]) {}
+}
+
+// A line per capture device, with the spec it opens with when asked for the analysis one
+pub fn list(audio: &AudioSubsystem, desired: &AudioSpecDesired) -> Vec<String> {
+    let names = capture_names(audio);
+    if names.is_empty() {
+        return vec!["No capture device found".to_owned()];
+    }
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let spec = match audio.open_capture(name.as_str(), desired, |_| Probe) {
+                Ok(device) => {
+                    let spec = device.spec();
+                    format!(
+                        "{} Hz, {} channel(s), {:?}, {} samples",
+                        spec.freq, spec.channels, spec.format, spec.samples
+                    )
+                }
+                Err(e) => format!("cannot be opened: {}", e),
+            };
+            let bluetooth = if is_bluetooth(name) { " (Bluetooth)" } else { "" };
+            format!("{}: {}{} = {}", i, name, bluetooth, spec)
+        })
+        .collect()
+}
+
+// The monitor sources of the sound server, capturing what the outputs play
+// SDL leaves them out of its capture devices, they are listed with pactl
+pub fn monitor_sources() -> Vec<String> {
+    let output = match Command::new("pactl").args(&["list", "short", "sources"]).output() {
+        Ok(output) if output.status.success() => output.stdout,
+        _ => return Vec::new(),
+    };
+    String::from_utf8_lossy(&output)
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .filter(|name| name.ends_with(".monitor"))
+        .map(str::to_owned)
+        .collect()
+}
+
+// Find a monitor source from its index or part of its name
+pub fn find_monitor(monitor: &str) -> Result<String, Error> {
+    let names = monitor_sources();
+    find(monitor, &names).ok_or_else(|| Error::Device {
+        device: monitor.to_owned(),
+        available: names,
+    })
+}
+
+// If the device looks like a Bluetooth source, from its name
+pub fn is_bluetooth(name: &str) -> bool {
+    let name = name.to_lowercase();
+    BLUETOOTH_NAMES.iter().any(|part| name.contains(part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devices_are_found_whatever_the_case() {
+        let names = vec!["Built-in Microphone".to_owned(), "USB Audio CODEC".to_owned()];
+        assert_eq!(find("usb audio", &names).as_deref(), Some("USB Audio CODEC"));
+        assert_eq!(find("MICROPHONE", &names).as_deref(), Some("Built-in Microphone"));
+        assert_eq!(find("1", &names).as_deref(), Some("USB Audio CODEC"));
+        assert_eq!(find("webcam", &names), None);
+    }
+}
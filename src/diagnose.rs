@@ -0,0 +1,122 @@
+// Looks for the usual causes of a capture failure, to guide the user past a bare SDL error
+
+// Standard
+#[cfg(target_os = "linux")]
+use std::io::ErrorKind;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+// The findings on the audio setup, a line each
+pub fn capture() -> Vec<String> {
+    let drivers = sdl2::audio::drivers().collect::<Vec<&str>>();
+    let mut lines = vec![format!("SDL audio drivers = {}", drivers.join(", "))];
+    if let Ok(requested) = std::env::var("SDL_AUDIODRIVER") {
+        if !drivers.contains(&requested.as_str()) {
+            lines.push(format!(
+                "SDL_AUDIODRIVER={} is not one of them, unset it or pick one above with --audio-driver",
+                requested
+            ));
+        }
+    }
+    lines.extend(platform(&drivers));
+    lines
+}
+
+// Whether the sound devices can be opened, None when there are none
+#[cfg(target_os = "linux")]
+fn alsa_access() -> Option<Result<(), ErrorKind>> {
+    let control = std::fs::read_dir("/dev/snd")
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with("control"))
+        })?;
+    Some(std::fs::File::open(control).map(|_| ()).map_err(|e| e.kind()))
+}
+
+// Whether the user is listed in the audio group
+#[cfg(target_os = "linux")]
+fn in_audio_group() -> bool {
+    let user = std::env::var("USER").unwrap_or_default();
+    std::fs::read_to_string("/etc/group")
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.starts_with("audio:"))
+        .flat_map(|line| line.rsplit(':').next().unwrap_or("").split(','))
+        .any(|member| member == user)
+}
+
+#[cfg(target_os = "linux")]
+fn platform(drivers: &[&str]) -> Vec<String> {
+    let mut lines = Vec::new();
+    // The sound servers, found from their sockets
+    let runtime = std::env::var("XDG_RUNTIME_DIR").unwrap_or_default();
+    let pipewire = Path::new(&runtime).join("pipewire-0").exists();
+    let pulse = Path::new(&runtime).join("pulse").join("native").exists();
+    match (pipewire, pulse) {
+        (_, true) if drivers.contains(&"pulseaudio") => lines.push(
+            "A PulseAudio server runs (maybe PipeWire's), try --audio-driver pulseaudio".to_owned(),
+        ),
+        (true, false) if drivers.contains(&"pipewire") => {
+            lines.push("PipeWire runs, try --audio-driver pipewire".to_owned())
+        }
+        (true, false) => lines.push(
+            "PipeWire runs without its PulseAudio server, which this SDL needs: install pipewire-pulse"
+                .to_owned(),
+        ),
+        (_, true) => lines.push(
+            "A PulseAudio server runs, but this SDL was built without its driver".to_owned(),
+        ),
+        (false, false) => {
+            lines.push("No sound server found, the ALSA devices are used directly".to_owned())
+        }
+    }
+    // The sound cards, and the rights on them
+    let cards = std::fs::read_to_string("/proc/asound/cards").unwrap_or_default();
+    if cards.trim().is_empty() || cards.contains("no soundcards") {
+        lines.push("No sound card is known to ALSA, check the interface is plugged in and powered".to_owned());
+    }
+    match alsa_access() {
+        Some(Err(ErrorKind::PermissionDenied)) if !in_audio_group() => lines.push(
+            "The sound devices are not accessible: sudo usermod -aG audio $USER, then log in again"
+                .to_owned(),
+        ),
+        Some(Err(ErrorKind::PermissionDenied)) => lines.push(
+            "The sound devices are not accessible, though you are in the audio group: log in again"
+                .to_owned(),
+        ),
+        Some(_) => (),
+        None if !pipewire && !pulse => {
+            lines.push("No device in /dev/snd, the sound driver may not be loaded".to_owned())
+        }
+        None => (),
+    }
+    lines
+}
+
+#[cfg(target_os = "windows")]
+fn platform(_drivers: &[&str]) -> Vec<String> {
+    // Neither cause can be checked without the Windows APIs
+    vec![
+        "Another program may hold the input in exclusive mode: in the Sound control panel, Recording, \
+         Properties, Advanced, untick 'Allow applications to take exclusive control'"
+            .to_owned(),
+        "The microphone privacy settings may block desktop apps: Settings, Privacy, Microphone".to_owned(),
+    ]
+}
+
+#[cfg(target_os = "macos")]
+fn platform(_drivers: &[&str]) -> Vec<String> {
+    vec![
+        "The terminal may not be allowed the microphone: System Settings, Privacy & Security, Microphone"
+            .to_owned(),
+    ]
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+fn platform(_drivers: &[&str]) -> Vec<String> {
+    Vec::new()
+}
@@ -1,11 +1,39 @@
-use crate::notes::Note;
+use std::ops::Range;
+use std::time::Instant;
 
-#[derive(Clone, Copy, Debug)]
-pub enum Notation {
+use crate::chords::{Chord, Symbols};
+use crate::control::Action;
+use crate::fourier::ScoringOptions;
+use crate::keymap::Keymap;
+use crate::keys::Key;
+use crate::notes::{Note, NOTE_COUNT};
+use crate::scores::Scores;
+use crate::tools::Normalizable;
+use crate::voicings::Fingering;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
     English,
     Romance,
 }
 
+// The names of the notes, and how the chords are written
+#[derive(Clone, Copy, Debug)]
+pub struct Notation {
+    pub language: Language,
+    pub symbols: Symbols,
+}
+
+impl Default for Notation {
+    // As the sessions are saved
+    fn default() -> Notation {
+        Notation {
+            language: Language::English,
+            symbols: Symbols::Standard,
+        }
+    }
+}
+
 const NOTE_NAMES_ENGLISH: [&str; 12] = [
     " C ", " C#", " D ", " D#", " E ", " F ", " F#", " G ", " G#", " A ", " A#", " B ",
 ];
@@ -19,16 +47,321 @@ impl Notation {
         self.get_names()[index]
     }
     pub fn get_names(self) -> [&'static str; 12] {
-        match self {
-            Notation::English => NOTE_NAMES_ENGLISH,
-            Notation::Romance => NOTE_NAMES_ROMANCE,
+        match self.language {
+            Language::English => NOTE_NAMES_ENGLISH,
+            Language::Romance => NOTE_NAMES_ROMANCE,
+        }
+    }
+
+    // The root of a chord or a key, B natural being H and B flat B in German
+    pub fn root_name(self, pitch: usize) -> &'static str {
+        match (self.language, self.symbols, pitch % 12) {
+            (Language::English, Symbols::German, 10) => "B",
+            (Language::English, Symbols::German, 11) => "H",
+            (_, _, pitch) => self.get_names()[pitch].trim(),
         }
     }
 }
 
+// The default redraws per second of remote terminals
+pub const REMOTE_REFRESH: f32 = 4f32;
+
 #[derive(Clone, Copy, Debug)]
 pub struct DisplayOptions {
     pub notation: Notation,
     pub clear_term: bool,
-    pub instrument: (),
+    // The highest number of terminal redraws per second
+    pub refresh: f32,
+    // Only 16 colors and few escape sequences, for terminals over SSH
+    pub remote: bool,
+    // The instrument the voicings are suggested for, None without suggestions
+    pub instrument: Option<Fingering>,
+    // Whether the latency and the queues are shown
+    pub latency: bool,
+    // The minimum score confidence (0 .. 1) for the suggestions to be updated
+    pub confidence: f32,
+    // When the suggestions are updated, and the tempo and bar counting the beats without a chart
+    pub cadence: Cadence,
+    pub tempo: f32,
+    pub beats_per_bar: usize,
+    // The halflife of the scores shown, in seconds, 0 showing them as analyzed
+    pub smoothing: f32,
+    pub scaling: Scaling,
+    // The note names drawn over the scores
+    pub labels: Labels,
+    // The size of the note names, relative to the fretboard cells (0 .. 1)
+    pub label_size: f32,
+    // The streams analysed, more than one when the channels are split
+    pub channels: usize,
+}
+
+impl DisplayOptions {
+    // A line per option, for the help displays
+    pub fn describe(&self) -> Vec<String> {
+        vec![
+            format!("Notation    {:?}, {} chords", self.notation.language, self.notation.symbols.name()),
+            format!("Confidence  {:.2}", self.confidence),
+            format!("Cadence     {}", self.cadence.name()),
+            format!("Smoothing   {:.2}s", self.smoothing),
+            format!("Scaling     {}", self.scaling.name()),
+            format!("Labels      {}", self.labels.describe()),
+        ]
+    }
+}
+
+// Smooths the scores shown over time, leaving the analysis untouched
+pub struct Smoother {
+    halflife: f32,
+    // The scores, values and dissonance last shown, and when
+    previous: Option<([f32; NOTE_COUNT], [f32; NOTE_COUNT], [f32; NOTE_COUNT], Instant)>,
+}
+
+impl Smoother {
+    pub fn new(halflife: f32) -> Smoother {
+        Smoother {
+            halflife,
+            previous: None,
+        }
+    }
+
+    pub fn smooth(&mut self, scores: &mut Scores) {
+        if self.halflife <= 0f32 {
+            return;
+        }
+        if let Some((note_scores, note_values, dissonance, time)) = self.previous.as_ref() {
+            let factor = 0.5f32.powf(time.elapsed().as_secs_f32() / self.halflife);
+            for i in 0..NOTE_COUNT {
+                scores.note_scores[i] = note_scores[i] * factor + scores.note_scores[i] * (1f32 - factor);
+                scores.note_values[i] = note_values[i] * factor + scores.note_values[i] * (1f32 - factor);
+                scores.dissonance[i] = dissonance[i] * factor + scores.dissonance[i] * (1f32 - factor);
+            }
+        }
+        self.previous = Some((scores.note_scores, scores.note_values, scores.dissonance, Instant::now()));
+    }
+}
+
+// When the suggestions shown are replaced by the latest stable scores
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cadence {
+    Continuous,
+    Beat,
+    Bar,
+    Chord,
+}
+
+impl Cadence {
+    pub fn from_name(name: &str) -> Option<Cadence> {
+        match name {
+            "continuous" => Some(Cadence::Continuous),
+            "beat" => Some(Cadence::Beat),
+            "bar" => Some(Cadence::Bar),
+            "chord" => Some(Cadence::Chord),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Cadence::Continuous => "continuous",
+            Cadence::Beat => "beat",
+            Cadence::Bar => "bar",
+            Cadence::Chord => "chord",
+        }
+    }
+}
+
+// Holds the suggestions between the beats, bars or chords, the analysis going on underneath
+// The beats are those of the chart when one is followed, else counted at the tempo from the start
+pub struct Refresher {
+    cadence: Cadence,
+    // In seconds
+    beat_duration: f32,
+    beats_per_bar: usize,
+    start: Instant,
+    // The bar and beat, or the chord, of the last refresh
+    refreshed: Option<(usize, usize)>,
+    chord: Option<Option<Chord>>,
+}
+
+impl Refresher {
+    pub fn new(options: &DisplayOptions) -> Refresher {
+        Refresher {
+            cadence: options.cadence,
+            beat_duration: 60f32 / options.tempo,
+            beats_per_bar: options.beats_per_bar.max(1),
+            start: Instant::now(),
+            refreshed: None,
+            chord: None,
+        }
+    }
+
+    // Whether the stable scores replace the suggestions, from the bar and beat of the chart if any
+    // A refresh missed on unstable scores happens on the next stable ones
+    pub fn due(&mut self, beat: Option<(usize, usize)>, chord: Option<Chord>, stable: bool) -> bool {
+        if !stable {
+            return false;
+        }
+        let (bar, beat) = beat.unwrap_or_else(|| {
+            let beats = (self.start.elapsed().as_secs_f32() / self.beat_duration) as usize;
+            (beats / self.beats_per_bar + 1, beats % self.beats_per_bar + 1)
+        });
+        let due = match self.cadence {
+            Cadence::Continuous => true,
+            Cadence::Beat => self.refreshed != Some((bar, beat)),
+            Cadence::Bar => self.refreshed.map(|(b, _)| b) != Some(bar),
+            Cadence::Chord => self.chord != Some(chord),
+        };
+        if due {
+            self.refreshed = Some((bar, beat));
+            self.chord = Some(chord);
+        }
+        due
+    }
+}
+
+// The dissonance per unit of heard intensity shown as fully dissonant, with the absolute scaling
+// Roughly a semitone against a single note
+const ABSOLUTE_DISSONANCE: f32 = 0.5;
+
+// How the scores are mapped to colors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scaling {
+    // From the best to the worst note of the frame, to spot the best note now
+    Frame,
+    // From no dissonance to the worst note heard during the session
+    Session,
+    // On a fixed dissonance scale, to judge the overall consonance
+    Absolute,
+}
+
+impl Default for Scaling {
+    fn default() -> Scaling {
+        Scaling::Frame
+    }
+}
+
+impl Scaling {
+    pub fn from_name(name: &str) -> Option<Scaling> {
+        match name {
+            "frame" => Some(Scaling::Frame),
+            "session" => Some(Scaling::Session),
+            "absolute" => Some(Scaling::Absolute),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scaling::Frame => "frame",
+            Scaling::Session => "session",
+            Scaling::Absolute => "absolute",
+        }
+    }
+
+    pub fn next(self) -> Scaling {
+        match self {
+            Scaling::Frame => Scaling::Session,
+            Scaling::Session => Scaling::Absolute,
+            Scaling::Absolute => Scaling::Frame,
+        }
+    }
+}
+
+// Maps the scores to the 0 (consonant) .. 1 (dissonant) range of the colors
+pub struct Scaler {
+    pub scaling: Scaling,
+    // The highest dissonance of the session
+    max: f32,
+}
+
+impl Scaler {
+    pub fn new(scaling: Scaling) -> Scaler {
+        Scaler { scaling, max: 0f32 }
+    }
+
+    // Keep track of the session range, every frame whatever the scaling
+    pub fn update(&mut self, scores: &Scores) {
+        let max = scores.dissonance.iter().cloned().filter(|d| d.is_finite()).fold(0f32, f32::max);
+        self.max = self.max.max(max);
+    }
+
+    // The scaled scores of a range of notes
+    pub fn scale(&self, scores: &Scores, notes: Range<usize>) -> Vec<f32> {
+        match self.scaling {
+            Scaling::Frame => {
+                let mut scaled = scores.note_scores[notes].to_owned();
+                scaled.normalize();
+                scaled
+            }
+            Scaling::Session => scores.dissonance[notes]
+                .iter()
+                .map(|&d| if self.max > 0f32 { d / self.max } else { 0f32 })
+                .collect(),
+            Scaling::Absolute => scores.dissonance[notes]
+                .iter()
+                .map(|&d| (d / ABSOLUTE_DISSONANCE).min(1f32))
+                .collect(),
+        }
+    }
+}
+
+// The help of a display: its key bindings, then the current options
+pub fn help(
+    keymap: &Keymap,
+    supported: &[Action],
+    scoring: &ScoringOptions,
+    options: &DisplayOptions,
+) -> Vec<String> {
+    let mut lines = vec!["Keys".to_owned()];
+    lines.extend(keymap.help(supported));
+    lines.push(String::new());
+    lines.push("Options".to_owned());
+    lines.extend(scoring.describe());
+    lines.extend(options.describe());
+    lines
+}
+
+// Which notes get their name drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Labels {
+    All,
+    // The most consonant notes, for readability at small sizes
+    Best(usize),
+    // The notes of the song key, or of the key heard so far
+    Key,
+    None,
+}
+
+impl Labels {
+    pub fn describe(self) -> String {
+        match self {
+            Labels::All => "all".to_owned(),
+            Labels::Best(count) => format!("best {}", count),
+            Labels::Key => "key".to_owned(),
+            Labels::None => "none".to_owned(),
+        }
+    }
+
+    // If every note of a range gets its name drawn, from its scaled score and the first note of the range
+    pub fn shown(self, scores: &[f32], first: usize, key: Option<Key>) -> Vec<bool> {
+        match self {
+            Labels::All => vec![true; scores.len()],
+            Labels::None => vec![false; scores.len()],
+            Labels::Best(count) => {
+                let mut order = (0..scores.len()).collect::<Vec<usize>>();
+                order.sort_by(|&a, &b| scores[a].partial_cmp(&scores[b]).unwrap_or(std::cmp::Ordering::Equal));
+                let mut shown = vec![false; scores.len()];
+                for &i in order.iter().take(count) {
+                    shown[i] = true;
+                }
+                shown
+            }
+            Labels::Key => match key {
+                Some(key) => (first..first + scores.len())
+                    .map(|note| key.scale().contains(&(note % 12)))
+                    .collect(),
+                None => vec![true; scores.len()],
+            },
+        }
+    }
 }
@@ -1,7 +1,10 @@
 // The SDL display loop
 
 // Standard
-use std::sync::mpsc::Receiver;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Instant;
 
 // Tools
 use itertools::Itertools;
@@ -12,20 +15,44 @@ use palette::Srgb;
 // Sdl
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::audio::AudioDevice;
 use sdl2::pixels::Color;
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Point;
 use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
 use sdl2::render::Canvas;
 use sdl2::render::Texture;
+use sdl2::ttf::Font;
+use sdl2::event::WindowEvent;
+use sdl2::video::FullscreenType;
 use sdl2::video::Window;
 use sdl2::video::WindowPos;
+use sdl2::AudioSubsystem;
 use sdl2::Sdl;
 
 // Crate
-use crate::display::DisplayOptions;
+use crate::capture::Watchdog;
+use crate::chords::Chord;
+use crate::control::{Action, Command, ACTIONS};
+use crate::controller::ControllerMap;
+use crate::display::{self, DisplayOptions, Refresher, Scaler, Smoother};
+use crate::echo::Tap;
+use crate::error::Error;
+use crate::fourier::{History, ScoringOptions};
+use crate::keymap::{Key, Keymap};
+use crate::keys::KeyTracker;
+use crate::latency::{Monitor, Report};
+use crate::layout::{Layout, Panel};
 use crate::notes::Note::*;
+use crate::notes::{Note, NOTE_COUNT};
+use crate::saved::{Placement, Saved};
 use crate::scores::Scores;
+use crate::setlist::Setlist;
+use crate::synth::{self, Synth};
 use crate::tools::*;
+use crate::voicings::{self, Voicing};
 
 // Guitar constants
 
@@ -54,6 +81,102 @@ const LAST_NOTE: usize = STRINGS[STRING_COUNT - 1] + FRET_COUNT as usize;
 // Font asset
 const FONT_NAME: &str = "assets/UbuntuMono-R.ttf";
 
+// The deviation in cents shown with the most intense color
+const INTONATION_RANGE: f32 = 50.0;
+
+// What the graph window shows
+#[derive(Clone, Copy, PartialEq)]
+enum GraphMode {
+    // The scores and values of every note
+    Notes,
+    // The average deviation of every played note
+    Intonation,
+    // The pitch trajectory, vibrato and bend of the held note
+    Detail,
+}
+
+impl GraphMode {
+    fn from_name(name: &str) -> Option<GraphMode> {
+        match name {
+            "notes" => Some(GraphMode::Notes),
+            "intonation" => Some(GraphMode::Intonation),
+            "detail" => Some(GraphMode::Detail),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            GraphMode::Notes => "notes",
+            GraphMode::Intonation => "intonation",
+            GraphMode::Detail => "detail",
+        }
+    }
+}
+
+// The buttons of the controls panel, from left to right
+const BUTTONS: [Action; 4] = [Action::Reset, Action::Freeze, Action::NextChord, Action::NextStyle];
+
+// The label of a controls panel button
+fn button_label(action: Action, frozen: bool) -> &'static str {
+    match action {
+        Action::Freeze if frozen => "Unfreeze",
+        Action::Freeze => "Freeze",
+        Action::NextChord => "Next chord",
+        Action::NextStyle => "Style",
+        _ => "Reset",
+    }
+}
+
+// Get the key of a keycode
+fn key(keycode: Keycode) -> Option<Key> {
+    match keycode {
+        Keycode::Escape => Some(Key::Escape),
+        Keycode::PageUp => Some(Key::PageUp),
+        Keycode::PageDown => Some(Key::PageDown),
+        Keycode::F1 => Some(Key::F(1)),
+        Keycode::F2 => Some(Key::F(2)),
+        Keycode::F3 => Some(Key::F(3)),
+        Keycode::F4 => Some(Key::F(4)),
+        Keycode::F5 => Some(Key::F(5)),
+        Keycode::F6 => Some(Key::F(6)),
+        Keycode::F7 => Some(Key::F(7)),
+        Keycode::F8 => Some(Key::F(8)),
+        Keycode::F9 => Some(Key::F(9)),
+        Keycode::F10 => Some(Key::F(10)),
+        Keycode::F11 => Some(Key::F(11)),
+        Keycode::F12 => Some(Key::F(12)),
+        // Printable keycodes are their lowercase character
+        _ => match keycode as i32 {
+            code @ 32..=126 => Some(Key::Char(code as u8 as char)),
+            _ => None,
+        },
+    }
+}
+
+// The display state changed by the actions
+struct State {
+    // The content of the graph panels
+    graph_mode: GraphMode,
+    // The scores shown instead of the new ones while frozen
+    frozen: Option<Scores>,
+    // Kept alive while the chord plays
+    playback: Option<AudioDevice<Synth>>,
+    // Copies the playback for the feedback suppression
+    tap: Option<Tap>,
+    // If the help is shown
+    help: bool,
+    // The scoring options, kept up to date with the commands sent
+    scoring: ScoringOptions,
+    // Their changes, undone like in the analysis
+    history: History,
+    // The color scaling of the scores
+    scaler: Scaler,
+}
+
+// How long the detected chord is played for
+const PAD_SECONDS: f32 = 3.0;
+
 // Board graph window dimensions
 const BOARD_HEIGHT: u32 = (STRING_COUNT as u32 + 1) * STRING_HEIGHT;
 const BOARD_WIDTH: u32 = (FRET_COUNT) * FRET_WIDTH + FRET_LINE;
@@ -62,61 +185,214 @@ const BOARD_WIDTH: u32 = (FRET_COUNT) * FRET_WIDTH + FRET_LINE;
 const FOURIER_HEIGHT: u32 = 200;
 const FOURIER_WIDTH: u32 = 1024;
 
+// Note grid cell dimensions
+const NOTE_CELL_WIDTH: u32 = FRET_WIDTH * 2;
+const NOTE_CELL_HEIGHT: u32 = STRING_HEIGHT * 2;
+
+// Chord banner dimensions
+const BANNER_HEIGHT: u32 = 48;
+const BANNER_FONT_HEIGHT: u16 = 40;
+
+// Controls panel dimensions, large enough to tap
+const BUTTON_WIDTH: u32 = FOURIER_WIDTH / BUTTONS.len() as u32;
+const BUTTON_HEIGHT: u32 = 96;
+
+// Stats panel dimensions, for four lines
+const STATS_HEIGHT: u32 = STRING_HEIGHT * 6 + 8;
+
+// Lyrics panel dimensions, the line sung after the previous one, then the coming ones
+const LYRICS_LINES: usize = 5;
+const LYRICS_HEIGHT: u32 = STRING_HEIGHT * LYRICS_LINES as u32;
+
+// Loudness panel dimensions, a meter per line after its value
+const LOUDNESS_HEIGHT: u32 = STRING_HEIGHT * 3 + 8;
+const METER_LABEL_WIDTH: u32 = 200;
+// The lowest level shown by the meters, in decibels or LUFS
+const METER_FLOOR: f32 = -60.0;
+// The level from which the input is close to clipping, in decibels
+const CLIP_WARNING: f32 = -3.0;
+// The EBU R128 target loudness, marked on the meter, and the loudness shown as too loud
+const TARGET_LOUDNESS: f32 = -23.0;
+const LOUD_WARNING: f32 = -14.0;
+
+// Voicings panel dimensions, a column per voicing, its name and notes over its diagram
+const VOICING_WIDTH: u32 = FOURIER_WIDTH / 3;
+const DIAGRAM_TOP: u32 = STRING_HEIGHT * 2 + 8;
+const VOICINGS_HEIGHT: u32 = DIAGRAM_TOP + DIAGRAM_STRING * STRING_COUNT as u32 + 8;
+// The chord diagrams, four frets from the lowest one pressed
+const DIAGRAM_FRETS: usize = 4;
+const DIAGRAM_FRET: u32 = 60;
+const DIAGRAM_STRING: u32 = 14;
+// The keyboards, three octaves from the one of the lowest note
+const KEYBOARD_OCTAVES: usize = 3;
+
+// Counterpoint panel dimensions, the cantus and the notes played, the faults and the pass so far
+const COUNTERPOINT_HEIGHT: u32 = STRING_HEIGHT * 4 + 8;
+const COUNTERPOINT_LABEL_WIDTH: u32 = 64;
+// Continuo panel dimensions, the figures, the bass and the realization played, the faults and the
+// pass so far
+const CONTINUO_HEIGHT: u32 = STRING_HEIGHT * 5 + 8;
+
+// The frequency range of the spectrogram, and its dynamic range in decibels
+const SPECTROGRAM_MIN_HZ: f32 = 50.0;
+const SPECTROGRAM_MAX_HZ: f32 = 5000.0;
+const SPECTROGRAM_RANGE: f32 = 60.0;
+
 // The display loop, receives data from the fourier thread
 // Hard to abstract further because of rust-sdl safety guards
 pub fn display(
     sdl: Sdl,
     receiver: Receiver<Scores>,
+    commands: Sender<Command>,
     options: DisplayOptions,
-) -> Result<(), String> {
-    // Open windows
+    scoring: ScoringOptions,
+    keymap: Keymap,
+    setlist: Option<Setlist>,
+    layout: Layout,
+    controller_map: ControllerMap,
+    watchdog: &mut Watchdog,
+    tap: Option<Tap>,
+    monitor: Monitor,
+    saved: Option<Saved>,
+) -> Result<(), Error> {
+    let mut monitor = monitor;
+    // Open windows, sized from their panels
 
-    let video_subsystem = sdl.video().unwrap();
+    let video_subsystem = sdl.video().map_err(|reason| Error::Sdl {
+        subsystem: "video",
+        reason,
+    })?;
 
-    let mut window = video_subsystem
-        .window("ImproVe Fourier", FOURIER_WIDTH, FOURIER_HEIGHT)
-        .position_centered()
-        .build()
-        .unwrap();
-    let pos = window.position();
-    window.set_position(
-        WindowPos::Centered,
-        WindowPos::Positioned(pos.1 - BOARD_HEIGHT as i32 - 100),
-    );
-
-    let mut canvas_fourier = window.into_canvas().build().unwrap();
-
-    let window = video_subsystem
-        .window("ImproVe Fretboard", BOARD_WIDTH, BOARD_HEIGHT)
-        .position_centered()
-        .build()
-        .unwrap();
+    // Where the windows were left last time
+    let placements = layout
+        .windows
+        .iter()
+        .enumerate()
+        .map(|(i, _)| saved.as_ref().and_then(|saved| saved.windows.get(i).cloned().flatten()))
+        .collect_vec();
+    let mut windows = Vec::new();
+    for (window_layout, placement) in layout.windows.iter().zip(placements.iter()) {
+        let rows = &window_layout.rows;
+        // Place the panels of every row left to right, and the rows top to bottom
+        let mut panels = Vec::new();
+        let (mut width, mut height) = (0, 0);
+        for row in rows.iter() {
+            let (mut x, mut row_height) = (0, 0);
+            for &panel in row.iter() {
+                let (w, h) = panel_size(panel, options);
+                panels.push((panel, Rect::new(x as i32, height as i32, w, h)));
+                x += w;
+                row_height = row_height.max(h);
+            }
+            width = width.max(x);
+            height += row_height;
+        }
+        let title = format!("ImproVe {}", rows[0][0].title());
+        // Resizable, the panels being scaled to the window
+        let mut builder = video_subsystem.window(&title, width, height);
+        builder.position_centered();
+        if !window_layout.fullscreen {
+            builder.resizable();
+        }
+        let mut window = builder
+            .build()
+            .map_err(|e| Error::Display {
+                what: format!("window {}", title),
+                reason: e.to_string(),
+            })?;
+        // Center the window on its monitor
+        if let Some(display) = window_layout.display {
+            let bounds = video_subsystem.display_bounds(display).map_err(|_| {
+                format!(
+                    "Display {} not found, {} available",
+                    display,
+                    video_subsystem.num_video_displays().unwrap_or(0)
+                )
+            })?;
+            window.set_position(
+                WindowPos::Positioned(bounds.x() + (bounds.width() as i32 - width as i32) / 2),
+                WindowPos::Positioned(bounds.y() + (bounds.height() as i32 - height as i32) / 2),
+            );
+        }
+        if let (Some(placement), false) = (placement, window_layout.fullscreen) {
+            window.set_position(WindowPos::Positioned(placement.x), WindowPos::Positioned(placement.y));
+            window.set_size(placement.width, placement.height).map_err(|e| e.to_string())?;
+        }
+        if window_layout.fullscreen {
+            window.set_fullscreen(FullscreenType::Desktop)?;
+        }
+        windows.push((window, panels, title));
+    }
 
-    let mut canvas_board = window.into_canvas().build().unwrap();
+    // Stack the windows without a monitor above the last one
+    let mut offset = 0;
+    for (((window, _, _), window_layout), placement) in
+        windows.iter_mut().zip(layout.windows.iter()).zip(placements.iter()).rev()
+    {
+        if window_layout.display.is_some() || window_layout.fullscreen || placement.is_some() {
+            continue;
+        }
+        let pos = window.position();
+        window.set_position(WindowPos::Centered, WindowPos::Positioned(pos.1 - offset));
+        offset += window.size().1 as i32 + 100;
+    }
 
-    canvas_board.present();
-    canvas_fourier.present();
+    let mut canvases = Vec::new();
+    for ((window, panels, title), window_layout) in windows.into_iter().zip(layout.windows.iter()) {
+        let mut canvas = window.into_canvas().build().map_err(|e| Error::Display {
+            what: format!("renderer of {}", title),
+            reason: e.to_string(),
+        })?;
+        if !window_layout.fullscreen {
+            let (width, height) = panels.iter().fold((0, 0), |(w, h), (_, rect)| {
+                (w.max(rect.right() as u32), h.max(rect.bottom() as u32))
+            });
+            canvas.set_logical_size(width, height).map_err(|e| e.to_string())?;
+        }
+        canvases.push((canvas, panels, title));
+    }
+    for (canvas, _, _) in canvases.iter_mut() {
+        canvas.present();
+    }
 
     // Build text textures, for use in the loop
 
     // Init the front
-    let ttf_context = sdl2::ttf::init().unwrap();
-    let texture_creator = canvas_board.texture_creator();
-    let font = ttf_context.load_font(FONT_NAME, FONT_HEIGHT).unwrap();
+    let ttf_context = sdl2::ttf::init().map_err(|e| Error::Sdl {
+        subsystem: "fonts",
+        reason: e.to_string(),
+    })?;
+    let load_font = |size| {
+        ttf_context.load_font(FONT_NAME, size).map_err(|reason| Error::Display {
+            what: format!("font {}", FONT_NAME),
+            reason,
+        })
+    };
+    let font = load_font(FONT_HEIGHT)?;
+    let banner_font = load_font(BANNER_FONT_HEIGHT)?;
+    let label_height = (FONT_HEIGHT as f32 * options.label_size).round() as u16;
+    let label_font = load_font(label_height.max(1))?;
+    // Textures belong to the window they were built for
+    let texture_creators = canvases.iter().map(|(canvas, _, _)| canvas.texture_creator()).collect_vec();
 
     // Build a texture for every note names
-    let textures = options
-        .notation
-        .get_names()
+    let textures = texture_creators
         .iter()
-        .map(|name| {
-            let surface = font
-                .render(name)
-                .blended(Color::RGBA(30, 30, 30, 255))
-                .unwrap();
-            texture_creator
-                .create_texture_from_surface(&surface)
-                .unwrap()
+        .map(|texture_creator| {
+            options
+                .notation
+                .get_names()
+                .iter()
+                .map(|name| {
+                    let surface = label_font
+                        .render(name)
+                        .blended(Color::RGBA(30, 30, 30, 255))
+                        .unwrap();
+                    texture_creator
+                        .create_texture_from_surface(&surface)
+                        .unwrap()
+                })
+                .collect_vec()
         })
         .collect_vec();
 
@@ -130,48 +406,590 @@ pub fn display(
         .blended(Color::RGB(255, 255, 255))
         .unwrap();
 
-    let texture_header = texture_creator
-        .create_texture_from_surface(&surface_header)
-        .unwrap();
+    let textures_header = texture_creators
+        .iter()
+        .map(|texture_creator| {
+            texture_creator
+                .create_texture_from_surface(&surface_header)
+                .unwrap()
+        })
+        .collect_vec();
+
+    // The spectrogram pixels, scrolled left every frame
+    let has_spectrogram = layout
+        .windows
+        .iter()
+        .flat_map(|window| window.rows.iter().flatten())
+        .any(|&panel| panel == Panel::Spectrogram);
+    let mut spectrogram = vec![0u8; (FOURIER_WIDTH * FOURIER_HEIGHT * 3) as usize];
+    // The number of dark columns added since the last lit one, the spectrogram is still once it is all dark
+    let mut dark_columns = 0;
+    // The annotations shown on the spectrogram, with how many frames ago they were received
+    let mut markers: Vec<(usize, String, Color)> = Vec::new();
+    let mut textures_spectrogram = texture_creators
+        .iter()
+        .map(|texture_creator| {
+            texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, FOURIER_WIDTH, FOURIER_HEIGHT)
+                .unwrap()
+        })
+        .collect_vec();
 
     // Build the event pump, to kill everything elegantly
     let mut events = sdl.event_pump().unwrap();
 
+    // The output device, to play the detected chord
+    let audio = sdl.audio().map_err(|reason| Error::Sdl {
+        subsystem: "audio",
+        reason,
+    })?;
+    let mut state = State {
+        graph_mode: saved
+            .as_ref()
+            .and_then(|saved| saved.graph.as_ref())
+            .and_then(|graph| GraphMode::from_name(graph))
+            .unwrap_or(GraphMode::Notes),
+        frozen: None,
+        playback: None,
+        tap,
+        help: false,
+        scoring,
+        history: History::new(scoring),
+        scaler: Scaler::new(options.scaling),
+    };
+    // The help is shown over the tallest window
+    let help_window = canvases
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (canvas, _, _))| canvas.window().size().1)
+        .map(|(i, _)| i)
+        .unwrap();
+    // The game controllers, kept open to receive their events
+    let controller_subsystem = sdl.game_controller().map_err(|reason| Error::Sdl {
+        subsystem: "game controllers",
+        reason,
+    })?;
+    let mut controller_map = controller_map;
+    let mut controllers = Vec::new();
+    // The chord, song and capture warning shown in the window titles
+    let mut shown_chord = None;
+    let mut shown_song = None;
+    let mut shown_warning = None;
+    let mut song_text = None;
+    // The scores shown, smoothed apart from the analysis
+    let mut smoother = Smoother::new(options.smoothing);
+    // The last stable scores, shown on the fretboard
+    let mut board: Option<Scores> = None;
+    let mut refresher = Refresher::new(&options);
+    // The key heard so far, for the labels of the notes in key
+    let mut key_tracker = KeyTracker::new();
+    // The ranges of the other channels, scaled apart
+    let mut channel_scalers = (1..options.channels).map(|_| Scaler::new(options.scaling)).collect_vec();
+
+    // Every panel is drawn to its own texture, and redrawn once what it shows changes
+    let mut panel_textures = Vec::new();
+    for (texture_creator, (_, panels, _)) in texture_creators.iter().zip(canvases.iter()) {
+        let mut textures = Vec::new();
+        for &(_, rect) in panels.iter() {
+            textures.push(
+                texture_creator
+                    .create_texture_target(None, rect.width(), rect.height())
+                    .map_err(|e| e.to_string())?,
+            );
+        }
+        panel_textures.push(textures);
+    }
+    // The hash of what every panel shows, and how many times it was drawn
+    let mut signatures = canvases.iter().map(|(_, panels, _)| vec![None; panels.len()]).collect_vec();
+    let mut redraws = canvases.iter().map(|(_, panels, _)| vec![0; panels.len()]).collect_vec();
+    // The windows to present even if none of their panels changed, once uncovered
+    let mut exposed = vec![true; canvases.len()];
+    // The help lines last shown
+    let mut shown_help = None;
+    // When the display started and the frames received, for the redraw rates and the scrolling
+    let start = Instant::now();
+    let mut frame = 0usize;
+
     // Iterate on scores
-    for scores in receiver.into_iter() {
-        // Draw the fourier frequency graph
-        draw_graph(&mut canvas_fourier, &scores);
+    'frames: for mut scores in receiver.into_iter() {
+        frame += 1;
+        monitor.receive(scores.latency);
+        let warning = watchdog.check();
+        smoother.smooth(&mut scores);
+        state.scaler.update(&scores);
+        let heard_key = key_tracker.update(&scores.note_values);
+        // The labels follow the song key, or the key heard so far
+        let label_key = setlist.as_ref().and_then(Setlist::key).or(heard_key);
+        let beat = setlist.as_ref().map(Setlist::beat);
+        let annotations = scores.annotations.clone();
+        let input_events = scores.input_events.clone();
+        let scores = state.frozen.clone().unwrap_or(scores);
+        // The pitch class scores of the other channels
+        let channel_scores = scores
+            .channels
+            .iter()
+            .zip(channel_scalers.iter_mut())
+            .map(|(channel, scaler)| {
+                scaler.scaling = state.scaler.scaling;
+                channel.as_ref().map(|channel| {
+                    scaler.update(channel);
+                    scaler.scale(channel, FIRST_NOTE..LAST_NOTE)
+                })
+            })
+            .collect_vec();
+        // Keep the fretboard until the scores are stable again, and until the next refresh
+        let chord = setlist.as_ref().and_then(Setlist::chord).or(scores.chord);
+        if refresher.due(beat, chord, scores.confidence >= options.confidence) {
+            board = Some(scores.clone());
+        }
+        // The voicings of the chord of the chart, or of the chord heard, over the stable scores
+        let voicings = match (options.instrument, board.as_ref()) {
+            (Some(fingering), Some(board)) => setlist
+                .as_ref()
+                .and_then(Setlist::chord)
+                .or(board.chord)
+                .map(|chord| (chord, voicings::suggest(chord, &board.note_scores, fingering))),
+            _ => None,
+        };
+        // The frets of the best guitar voicing, marked on the fretboard
+        let marked = voicings
+            .as_ref()
+            .and_then(|(_, v)| v.first())
+            .map_or(Vec::new(), |v| {
+                v.frets
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(string, fret)| fret.map(|f| STRINGS[string] + f + string * NOTE_COUNT))
+                    .collect_vec()
+            });
+        if has_spectrogram {
+            dark_columns = if scroll_spectrogram(&mut spectrogram, &scores) {
+                0
+            } else {
+                dark_columns + 1
+            };
+            // The markers scroll along, until they leave the spectrogram
+            for marker in markers.iter_mut() {
+                marker.0 += 1;
+            }
+            markers.retain(|&(age, _, _)| age < FOURIER_WIDTH as usize);
+            markers.extend(annotations.into_iter().map(|text| (0, text, Color::RGB(255, 255, 255))));
+            // The input problems are flagged in red
+            markers.extend(
+                input_events
+                    .into_iter()
+                    .map(|event| (0, event.describe(), Color::RGB(255, 0, 0))),
+            );
+        }
 
-        // Draw the fretboard graph
-        draw_board(&mut canvas_board, &scores, &textures, &texture_header);
+        // Show the current song and the detected chord
+        let song = setlist.as_ref().map(|s| (s.current(), s.shift()));
+        let title = if scores.chord != shown_chord || song != shown_song || warning != shown_warning {
+            shown_chord = scores.chord;
+            shown_song = song;
+            shown_warning = warning;
+            song_text = match (&setlist, song) {
+                (Some(setlist), Some((song, _))) => Some(setlist.describe(song, options.notation)),
+                _ => None,
+            };
+            let mut title = String::new();
+            if let Some(warning) = shown_warning.as_ref() {
+                title += &format!(" - {}", warning);
+            }
+            if let Some(text) = song_text.as_ref() {
+                title += &format!(" - {}", text);
+            }
+            if let Some(chord) = shown_chord {
+                title += &format!(" - {}", chord.name(options.notation));
+            }
+            Some(title)
+        } else {
+            None
+        };
+
+        // Redraw the panels showing something new, then present the windows they belong to
+        for (i, (canvas, panels, name)) in canvases.iter_mut().enumerate() {
+            if let Some(title) = title.as_ref() {
+                canvas
+                    .window_mut()
+                    .set_title(&format!("{}{}", name, title))
+                    .map_err(|e| e.to_string())?;
+            }
+            let mut changed = std::mem::replace(&mut exposed[i], false);
+            for (j, &(panel, _)) in panels.iter().enumerate() {
+                let mut hasher = DefaultHasher::new();
+                match panel {
+                    Panel::Graph => {
+                        (state.graph_mode as u8).hash(&mut hasher);
+                        match state.graph_mode {
+                            GraphMode::Notes => {
+                                for values in [scores.note_scores, scores.note_values].iter() {
+                                    let mut values = values.to_vec();
+                                    values.normalize();
+                                    quantize(&mut hasher, &values, FOURIER_HEIGHT as f32);
+                                }
+                            }
+                            GraphMode::Intonation => {
+                                for deviation in scores.intonation.iter() {
+                                    deviation.map(|d| d.round() as i32).hash(&mut hasher);
+                                }
+                            }
+                            GraphMode::Detail => format!("{:?}", scores.detail).hash(&mut hasher),
+                        }
+                    }
+                    Panel::Fretboard | Panel::Notes => {
+                        if let Some(board) = board.as_ref() {
+                            let note_scores = state.scaler.scale(board, FIRST_NOTE..LAST_NOTE);
+                            quantize(&mut hasher, &note_scores, 255f32);
+                            if panel == Panel::Fretboard {
+                                let mut note_values = board.note_values[FIRST_NOTE..LAST_NOTE].to_owned();
+                                note_values.normalize();
+                                quantize(&mut hasher, &note_values, 255f32);
+                                options.labels.shown(&note_scores, FIRST_NOTE, label_key).hash(&mut hasher);
+                                marked.hash(&mut hasher);
+                            }
+                        }
+                    }
+                    // Scrolling until every column is dark
+                    Panel::Spectrogram => {
+                        if dark_columns < FOURIER_WIDTH as usize || !markers.is_empty() {
+                            frame.hash(&mut hasher);
+                        }
+                    }
+                    Panel::Chord => scores.chord.map(|c| c.name(options.notation)).hash(&mut hasher),
+                    Panel::Stats => stats_lines(&scores, song_text.as_ref(), beat, monitor.report(), options).hash(&mut hasher),
+                    Panel::Controls => state.frozen.is_some().hash(&mut hasher),
+                    Panel::Lyrics => {
+                        if let Some(setlist) = setlist.as_ref() {
+                            let (bar, progress) = setlist.position();
+                            (setlist.current(), bar, (progress * STRING_HEIGHT as f32) as u32).hash(&mut hasher);
+                        }
+                    }
+                    // By half decibels
+                    Panel::Loudness => {
+                        let rms = scores.meter.rms.max(METER_FLOOR);
+                        let peak = scores.meter.peak.max(METER_FLOOR);
+                        ((rms * 2f32) as i32, (peak * 2f32) as i32, scores.loudness.map(|l| (l * 2f32) as i32))
+                            .hash(&mut hasher)
+                    }
+                    Panel::Attribution => {
+                        if let Some(rows) = attribution_rows(&scores) {
+                            quantize(&mut hasher, &rows[0], 255f32);
+                            quantize(&mut hasher, &rows[1], 255f32);
+                        }
+                        attribution_labels(&scores, options).hash(&mut hasher);
+                    }
+                    Panel::Voicings => format!("{:?}", voicings).hash(&mut hasher),
+                    Panel::Counterpoint => format!("{:?}", scores.counterpoint).hash(&mut hasher),
+                    Panel::Continuo => format!("{:?}", scores.continuo).hash(&mut hasher),
+                    Panel::Channels => {
+                        for channel in channel_scores.iter() {
+                            channel.is_some().hash(&mut hasher);
+                            if let Some(channel) = channel {
+                                quantize(&mut hasher, channel, 255f32);
+                            }
+                        }
+                    }
+                }
+                let signature = Some(hasher.finish());
+                if signatures[i][j] == signature {
+                    continue;
+                }
+                signatures[i][j] = signature;
+                changed = true;
+                redraws[i][j] += 1;
+
+                let mut drawn = Ok(());
+                let texture_spectrogram = &mut textures_spectrogram[i];
+                canvas
+                    .with_texture_canvas(&mut panel_textures[i][j], |canvas| match panel {
+                        Panel::Graph => draw_graph(canvas, &scores, state.graph_mode, &font, options),
+                        Panel::Fretboard => {
+                            if let Some(board) = board.as_ref() {
+                                let note_scores = state.scaler.scale(board, FIRST_NOTE..LAST_NOTE);
+                                let shown = options.labels.shown(&note_scores, FIRST_NOTE, label_key);
+                                draw_board(
+                                    canvas,
+                                    &note_scores,
+                                    &board.note_values,
+                                    &shown,
+                                    &marked,
+                                    &textures[i],
+                                    &textures_header[i],
+                                );
+                            }
+                        }
+                        Panel::Notes => {
+                            if let Some(board) = board.as_ref() {
+                                let note_scores = state.scaler.scale(board, FIRST_NOTE..LAST_NOTE);
+                                draw_note_grid(canvas, &note_scores, &textures[i], 0);
+                            }
+                        }
+                        Panel::Spectrogram => {
+                            drawn = texture_spectrogram
+                                .update(None, &spectrogram, FOURIER_WIDTH as usize * 3)
+                                .map_err(|e| e.to_string())
+                                .and_then(|_| canvas.copy(texture_spectrogram, None, None));
+                            draw_markers(canvas, &markers, &font);
+                        }
+                        Panel::Chord => draw_chord(canvas, &scores, &banner_font, options),
+                        Panel::Stats => draw_stats(canvas, &stats_lines(&scores, song_text.as_ref(), beat, monitor.report(), options), &font),
+                        Panel::Controls => draw_controls(canvas, state.frozen.is_some(), &banner_font),
+                        Panel::Lyrics => draw_lyrics(canvas, setlist.as_ref(), &font),
+                        Panel::Loudness => draw_loudness(canvas, &scores, &font),
+                        Panel::Channels => draw_channels(canvas, &channel_scores, &textures[i], &font),
+                        Panel::Attribution => draw_attribution(canvas, &scores, &textures[i], &font, options),
+                        Panel::Voicings => draw_voicings(canvas, voicings.as_ref(), &font, options),
+                        Panel::Counterpoint => draw_counterpoint(canvas, &scores, &font, options),
+                        Panel::Continuo => draw_continuo(canvas, &scores, &font, options),
+                    })
+                    .map_err(|e| e.to_string())?;
+                drawn?;
+            }
+
+            // The help changes with the options
+            let help = if state.help && i == help_window {
+                let options = DisplayOptions {
+                    scaling: state.scaler.scaling,
+                    ..options
+                };
+                Some(display::help(&keymap, &ACTIONS, &state.scoring, &options))
+            } else {
+                None
+            };
+            if i == help_window {
+                changed |= help != shown_help;
+                shown_help = help.clone();
+            }
+            if !changed {
+                continue;
+            }
+            for (j, &(_, rect)) in panels.iter().enumerate() {
+                canvas.copy(&panel_textures[i][j], None, Some(rect))?;
+            }
+            if let Some(lines) = help.as_ref() {
+                draw_help(canvas, lines, &font);
+            }
+            canvas.present();
+        }
+        monitor.drawn();
 
         for event in events.poll_iter() {
-            match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
+            let action = match event {
+                Event::Quit { .. } => Some(Action::Quit),
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => key(keycode).and_then(|key| keymap.action(key)),
+                // Taps on the controls, touches also come as mouse clicks
+                Event::MouseButtonDown {
+                    window_id,
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => canvases
+                    .iter()
+                    .filter(|(canvas, _, _)| canvas.window().id() == window_id)
+                    .flat_map(|(_, panels, _)| panels.iter())
+                    .filter(|&&(panel, rect)| panel == Panel::Controls && rect.contains_point((x, y)))
+                    .map(|&(_, rect)| BUTTONS[((x - rect.x()) as u32 / BUTTON_WIDTH) as usize])
+                    .next(),
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match controller_subsystem.open(which) {
+                        Ok(controller) => {
+                            status!("Game controller = {}", controller.name());
+                            controllers.push(controller);
+                        }
+                        Err(e) => eprintln!("Cannot open game controller {}: {}", which, e),
+                    }
+                    None
+                }
+                Event::ControllerButtonDown { button, .. } => controller_map.action(button),
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Exposed,
                     ..
                 } => {
-                    return Ok(());
+                    for (i, (canvas, _, _)) in canvases.iter().enumerate() {
+                        exposed[i] |= canvas.window().id() == window_id;
+                    }
+                    None
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    if let Some(command) = controller_map.command(axis, value) {
+                        send(&mut state, &commands, command);
+                    }
+                    None
+                }
+                _ => None,
+            };
+            if let Some(action) = action {
+                if perform(action, &mut state, &scores, &audio, &commands, setlist.as_ref())? {
+                    break 'frames;
                 }
-                _ => {}
             }
         }
     }
 
+    // Report how often every panel was redrawn
+    let seconds = start.elapsed().as_secs_f32().max(1f32);
+    for (i, (_, panels, _)) in canvases.iter().enumerate() {
+        let rates = panels
+            .iter()
+            .zip(redraws[i].iter())
+            .map(|(&(panel, _), &count)| format!("{} {:.1}", panel.title(), count as f32 / seconds))
+            .join(", ");
+        status!("Redraws per second = {}", rates);
+    }
+
+    // Save where the windows were left and the toggles, for the next launch
+    if let Some(mut saved) = saved {
+        saved.windows = canvases
+            .iter()
+            .zip(layout.windows.iter())
+            .map(|((canvas, _, _), window_layout)| {
+                let window = canvas.window();
+                let ((x, y), (width, height)) = (window.position(), window.size());
+                Some(Placement { x, y, width, height }).filter(|_| !window_layout.fullscreen)
+            })
+            .collect_vec();
+        saved.graph = Some(state.graph_mode.name().to_owned());
+        saved.scaling = Some(state.scaler.scaling);
+        saved.style = Some(state.scoring.style);
+        saved.halflife = Some(state.scoring.halflife);
+        saved.save();
+    }
     Ok(())
 }
 
-// Display the fretboard graph
+// Send a command to the analysis, keeping track of the options it changes
+fn send(state: &mut State, commands: &Sender<Command>, command: Command) {
+    state.history.apply(&mut state.scoring, &command);
+    commands.send(command).ok();
+}
+
+// Apply an action, returns true to quit
+fn perform(
+    action: Action,
+    state: &mut State,
+    scores: &Scores,
+    audio: &AudioSubsystem,
+    commands: &Sender<Command>,
+    setlist: Option<&Setlist>,
+) -> Result<bool, Error> {
+    match action {
+        Action::Quit => return Ok(true),
+        Action::PlayChord => {
+            if let Some(chord) = scores.chord {
+                let frequencies = chord.voicing(C3).iter().map(|n| n.freq()).collect_vec();
+                state.playback = Some(synth::start(audio, state.tap.as_ref(), |rate| {
+                    Synth::pad(frequencies, rate, PAD_SECONDS)
+                })?);
+            }
+        }
+        Action::Intonation => {
+            state.graph_mode = match state.graph_mode {
+                GraphMode::Intonation => GraphMode::Notes,
+                _ => GraphMode::Intonation,
+            };
+        }
+        Action::Detail => {
+            state.graph_mode = match state.graph_mode {
+                GraphMode::Detail => GraphMode::Notes,
+                _ => GraphMode::Detail,
+            };
+        }
+        Action::NextSong => {
+            if let Some(setlist) = setlist {
+                setlist.next();
+            }
+        }
+        Action::PreviousSong => {
+            if let Some(setlist) = setlist {
+                setlist.previous();
+            }
+        }
+        Action::NextChord => {
+            if let Some(setlist) = setlist {
+                setlist.next_chord();
+            }
+        }
+        Action::Reset => {
+            state.frozen = None;
+            send(state, commands, Command::Reset);
+        }
+        Action::Freeze => {
+            state.frozen = match state.frozen {
+                Some(_) => None,
+                None => Some(scores.clone()),
+            };
+        }
+        Action::NextStyle => send(state, commands, Command::NextStyle),
+        Action::Undo => send(state, commands, Command::Undo),
+        Action::Redo => send(state, commands, Command::Redo),
+        Action::Revert => send(state, commands, Command::Revert),
+        Action::Scaling => state.scaler.scaling = state.scaler.scaling.next(),
+        Action::Help => state.help = !state.help,
+    }
+    Ok(false)
+}
+
+// The size of a panel, in pixels
+fn panel_size(panel: Panel, options: DisplayOptions) -> (u32, u32) {
+    let channels = options.channels;
+    match panel {
+        Panel::Graph | Panel::Spectrogram => (FOURIER_WIDTH, FOURIER_HEIGHT),
+        Panel::Fretboard => (BOARD_WIDTH, BOARD_HEIGHT),
+        Panel::Notes => (NOTE_CELL_WIDTH * 12, NOTE_CELL_HEIGHT),
+        Panel::Chord => (FOURIER_WIDTH, BANNER_HEIGHT),
+        // A line more for the latency
+        Panel::Stats => (FOURIER_WIDTH, STATS_HEIGHT + if options.latency { STRING_HEIGHT } else { 0 }),
+        Panel::Controls => (BUTTON_WIDTH * BUTTONS.len() as u32, BUTTON_HEIGHT),
+        Panel::Lyrics => (FOURIER_WIDTH, LYRICS_HEIGHT),
+        Panel::Loudness => (FOURIER_WIDTH, LOUDNESS_HEIGHT),
+        Panel::Channels => (NOTE_CELL_WIDTH * 12, NOTE_CELL_HEIGHT * (channels.max(2) - 1) as u32),
+        Panel::Attribution => (NOTE_CELL_WIDTH * 12, NOTE_CELL_HEIGHT * 2),
+        Panel::Voicings => (FOURIER_WIDTH, VOICINGS_HEIGHT),
+        Panel::Counterpoint => (FOURIER_WIDTH, COUNTERPOINT_HEIGHT),
+        Panel::Continuo => (FOURIER_WIDTH, CONTINUO_HEIGHT),
+    }
+}
+
+// The score colors, from consonant to dissonant
+fn score_gradient() -> Gradient<Hsv> {
+    let gradient_a = Hsv::new(120.0, 1.0, 1.0);
+    let gradient_b = Hsv::new(0.0, 1.0, 1.0);
+    Gradient::new(vec![gradient_a, gradient_b])
+}
+
+// Write a line of text, at the given position
+fn draw_text(canvas: &mut Canvas<Window>, font: &Font, text: &str, pnt: Point, color: Color) {
+    if text.is_empty() {
+        return;
+    }
+    let texture_creator = canvas.texture_creator();
+    let surface = font.render(text).blended(color).unwrap();
+    let texture = texture_creator
+        .create_texture_from_surface(&surface)
+        .unwrap();
+    let rect = Rect::new(pnt.x, pnt.y, surface.width(), surface.height());
+    canvas.copy(&texture, None, Some(rect)).unwrap();
+}
+
+// Display the fretboard graph, from the scaled scores of the fretboard notes and which are named
 fn draw_board(
     canvas: &mut Canvas<Window>,
-    scores: &Scores,
+    note_scores: &[f32],
+    note_values: &[f32; NOTE_COUNT],
+    shown: &[bool],
+    marked: &[usize],
     texture_notes: &[Texture],
     texture_header: &Texture,
 ) {
-    // Clear canvas
+    // Clear panel
     canvas.set_draw_color(Color::RGB(30, 30, 30));
-    canvas.clear();
+    canvas.fill_rect(None).unwrap();
 
     // Display Header
     canvas
@@ -182,23 +1000,17 @@ fn draw_board(
         )
         .unwrap();
 
-    let mut note_scores = scores.note_scores[FIRST_NOTE..LAST_NOTE].to_owned();
-    note_scores.normalize();
-    let mut note_values = scores.note_values[FIRST_NOTE..LAST_NOTE].to_owned();
+    let mut note_values = note_values[FIRST_NOTE..LAST_NOTE].to_owned();
     note_values.normalize();
 
-    let gradient_score = {
-        let gradient_a = Hsv::new(120.0, 1.0, 1.0);
-        let gradient_b = Hsv::new(0.0, 1.0, 1.0);
-        Gradient::new(vec![gradient_a, gradient_b])
-    };
+    let gradient_score = score_gradient();
     // The canvas position
     let mut pnt = Point::new(0, 0);
     // Skip first line
     pnt = pnt.offset(0, STRING_HEIGHT as i32);
 
     // For every guitar strings
-    for &j in STRINGS.iter().rev() {
+    for (string, &j) in STRINGS.iter().enumerate().rev() {
         // For every note on that string
         for i in j..j + FRET_COUNT as usize {
             // Write the name with the appropriate color
@@ -214,8 +1026,15 @@ fn draw_board(
             // Draw tesxt and color to canvas
             canvas.set_draw_color(Color::from(color));
             canvas.fill_rect(rect).unwrap();
-            let rect = Rect::new(pnt.x, pnt.y - 1, FRET_WIDTH, STRING_HEIGHT);
-            canvas.copy(texture, None, Some(rect)).unwrap();
+            if shown[i - FIRST_NOTE] {
+                let rect = Rect::new(pnt.x, pnt.y - 1, FRET_WIDTH, STRING_HEIGHT);
+                canvas.copy(texture, None, Some(label_rect(texture, rect))).unwrap();
+            }
+            // Outline the frets of the voicing suggested
+            if marked.contains(&(i + string * NOTE_COUNT)) {
+                canvas.set_draw_color(Color::RGB(255, 255, 255));
+                canvas.draw_rect(rect).unwrap();
+            }
             
             // Underline notes being played (depending on value)
             
@@ -238,18 +1057,616 @@ fn draw_board(
         }
         pnt = Point::new(0, pnt.y() + STRING_HEIGHT as i32);
     }
-    canvas.present();
 }
 
-fn draw_graph(canvas: &mut Canvas<Window>, scores: &Scores) {
+fn draw_graph(
+    canvas: &mut Canvas<Window>,
+    scores: &Scores,
+    mode: GraphMode,
+    font: &Font,
+    options: DisplayOptions,
+) {
     // Clear graph
     canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
+    canvas.fill_rect(None).unwrap();
+
+    match mode {
+        GraphMode::Notes => draw_notes(canvas, scores),
+        GraphMode::Intonation => draw_intonation(canvas, scores),
+        GraphMode::Detail => draw_detail(canvas, scores, font, options),
+    }
+}
+
+// The rectangle of a note name centered in its cell, keeping the size it was rendered at
+fn label_rect(texture: &Texture, cell: Rect) -> Rect {
+    let query = texture.query();
+    let (width, height) = (query.width.min(cell.width()), query.height.min(cell.height()));
+    Rect::new(
+        cell.x() + (cell.width() - width) as i32 / 2,
+        cell.y() + (cell.height() - height) as i32 / 2,
+        width,
+        height,
+    )
+}
+
+// Display the score of every pitch class, averaged over the scaled scores of the fretboard notes
+fn draw_note_grid(canvas: &mut Canvas<Window>, note_scores: &[f32], texture_notes: &[Texture], y: i32) {
+    let gradient_score = score_gradient();
+    for class in 0..12 {
+        let scores = note_scores
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (i + FIRST_NOTE) % 12 == class)
+            .map(|(_, &score)| score)
+            .collect_vec();
+        let score = scores.iter().sum::<f32>() / scores.len() as f32;
+        let color: (u8, u8, u8) = Srgb::from(gradient_score.get(score))
+            .into_format()
+            .into_components();
+        let x = class as i32 * NOTE_CELL_WIDTH as i32;
+        canvas.set_draw_color(Color::from(color));
+        canvas
+            .fill_rect(Rect::new(x, y, NOTE_CELL_WIDTH - 1, NOTE_CELL_HEIGHT))
+            .unwrap();
+        // Center the name in the cell
+        let rect = Rect::new(
+            x + (NOTE_CELL_WIDTH - FRET_WIDTH) as i32 / 2,
+            y + (NOTE_CELL_HEIGHT - STRING_HEIGHT) as i32 / 2,
+            FRET_WIDTH,
+            STRING_HEIGHT,
+        );
+        canvas
+            .copy(&texture_notes[class], None, Some(label_rect(&texture_notes[class], rect)))
+            .unwrap();
+    }
+}
+
+// Display a row of pitch classes per other channel, numbered from 2
+fn draw_channels(canvas: &mut Canvas<Window>, channels: &[Option<Vec<f32>>], texture_notes: &[Texture], font: &Font) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    for (i, channel) in channels.iter().enumerate() {
+        let y = i as i32 * NOTE_CELL_HEIGHT as i32;
+        if let Some(note_scores) = channel {
+            draw_note_grid(canvas, note_scores, texture_notes, y);
+        }
+        draw_text(canvas, font, &format!("{}", i + 2), Point::new(4, y + 2), Color::RGB(255, 255, 255));
+    }
+}
+
+// The dissonance with the band and with the player over the fretboard notes, on a common scale
+fn attribution_rows(scores: &Scores) -> Option<[Vec<f32>; 2]> {
+    let attribution = scores.attribution.as_ref()?;
+    let band = &attribution.band[FIRST_NOTE..LAST_NOTE];
+    let own = &attribution.own[FIRST_NOTE..LAST_NOTE];
+    let max = band.iter().chain(own.iter()).cloned().fold(0f32, f32::max);
+    let scale = |values: &[f32]| {
+        values
+            .iter()
+            .map(|v| if max > 0f32 { v / max } else { 0f32 })
+            .collect_vec()
+    };
+    Some([scale(band), scale(own)])
+}
+
+// Display the voicings suggested side by side, the best on the left
+fn draw_voicings(
+    canvas: &mut Canvas<Window>,
+    voicings: Option<&(Chord, Vec<Voicing>)>,
+    font: &Font,
+    options: DisplayOptions,
+) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    let (chord, voicings) = match voicings {
+        Some((chord, voicings)) => (chord, voicings),
+        None => return,
+    };
+    let names = options.notation.get_names();
+    let white = Color::RGB(255, 255, 255);
+    let pressed = Color::RGB(255, 200, 0);
+    for (column, voicing) in voicings.iter().enumerate() {
+        let left = column as i32 * VOICING_WIDTH as i32 + 8;
+        let title = format!("{} {}", chord.name(options.notation), voicing.style.name());
+        draw_text(canvas, font, &title, Point::new(left, 4), white);
+        draw_text(canvas, font, &voicing.describe(names), Point::new(left, 4 + STRING_HEIGHT as i32), white);
+        let top = DIAGRAM_TOP as i32;
+        if voicing.frets.is_empty() {
+            // A keyboard, the white keys then the black ones over them
+            let first = voicing.notes[0] as usize / 12 * 12;
+            let whites = (0..KEYBOARD_OCTAVES * 12).filter(|k| ![1, 3, 6, 8, 10].contains(&(k % 12))).collect_vec();
+            let key_width = (VOICING_WIDTH - 16) / whites.len() as u32;
+            let height = DIAGRAM_STRING * STRING_COUNT as u32;
+            let held = |key: usize| voicing.notes.iter().any(|&n| n as usize == first + key);
+            for (w, &key) in whites.iter().enumerate() {
+                let rect = Rect::new(left + (w as u32 * key_width) as i32, top, key_width - 1, height);
+                canvas.set_draw_color(if held(key) { pressed } else { white });
+                canvas.fill_rect(rect).unwrap();
+            }
+            for key in (0..KEYBOARD_OCTAVES * 12).filter(|k| [1, 3, 6, 8, 10].contains(&(k % 12))) {
+                let w = whites.iter().filter(|&&white| white < key).count() as u32;
+                let x = left + (w * key_width - key_width / 3) as i32;
+                let rect = Rect::new(x, top, key_width * 2 / 3, height * 3 / 5);
+                canvas.set_draw_color(if held(key) { pressed } else { Color::RGB(0, 0, 0) });
+                canvas.fill_rect(rect).unwrap();
+            }
+        } else {
+            // A chord diagram, the high string on top like the fretboard
+            let lowest = voicing.frets.iter().flatten().filter(|&&f| f > 0).min().cloned().unwrap_or(1);
+            let base = if voicing.frets.iter().flatten().all(|&f| f <= DIAGRAM_FRETS) { 1 } else { lowest };
+            let label_width = 32;
+            draw_text(canvas, font, &base.to_string(), Point::new(left, top + 2), white);
+            canvas.set_draw_color(Color::RGB(120, 120, 120));
+            for string in 0..STRING_COUNT {
+                let y = top + (string as u32 * DIAGRAM_STRING + DIAGRAM_STRING / 2) as i32;
+                let width = DIAGRAM_FRET * DIAGRAM_FRETS as u32;
+                canvas
+                    .draw_line(Point::new(left + label_width, y), Point::new(left + label_width + width as i32, y))
+                    .unwrap();
+            }
+            for fret in 0..=DIAGRAM_FRETS {
+                let x = left + label_width + (fret as u32 * DIAGRAM_FRET) as i32;
+                canvas
+                    .draw_line(Point::new(x, top), Point::new(x, top + (DIAGRAM_STRING * STRING_COUNT as u32) as i32))
+                    .unwrap();
+            }
+            for (string, fret) in voicing.frets.iter().enumerate() {
+                let y = top + ((STRING_COUNT - 1 - string) as u32 * DIAGRAM_STRING) as i32;
+                let (x, text) = match fret {
+                    Some(0) => (left + label_width - 14, "o".to_owned()),
+                    Some(f) => {
+                        let position = (f - base) as u32 * DIAGRAM_FRET + DIAGRAM_FRET / 2;
+                        (left + label_width + position as i32 - 6, "*".to_owned())
+                    }
+                    None => (left + label_width - 14, "x".to_owned()),
+                };
+                let color = if fret.map_or(false, |f| f > 0) { pressed } else { white };
+                draw_text(canvas, font, &text, Point::new(x, y - 2), color);
+            }
+        }
+    }
+}
+
+// Display the cantus firmus a column per note, the current one in yellow, and under it the notes
+// played against it, in green without faults and in red with some
+fn draw_counterpoint(canvas: &mut Canvas<Window>, scores: &Scores, font: &Font, options: DisplayOptions) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    let progress = match scores.counterpoint.as_ref() {
+        Some(progress) => progress,
+        None => return,
+    };
+    let names = options.notation.get_names();
+    let white = Color::RGB(255, 255, 255);
+    let row = |i: i32| 4 + i * STRING_HEIGHT as i32;
+    draw_text(canvas, font, "Cantus", Point::new(4, row(0)), white);
+    draw_text(canvas, font, "Played", Point::new(4, row(1)), white);
+    let width = (FOURIER_WIDTH - COUNTERPOINT_LABEL_WIDTH) / progress.steps.len().max(1) as u32;
+    let beat_width = width / progress.species.notes() as u32;
+    for (i, step) in progress.steps.iter().enumerate() {
+        let x = (COUNTERPOINT_LABEL_WIDTH + i as u32 * width) as i32;
+        let color = if i == progress.step { Color::RGB(255, 255, 0) } else { Color::RGB(160, 160, 160) };
+        let name = step.note.map_or("-", |n| names[n.get_octave_index() as usize]);
+        draw_text(canvas, font, name, Point::new(x, row(0)), color);
+    }
+    for judgement in progress.judgements.iter() {
+        let x = COUNTERPOINT_LABEL_WIDTH + judgement.step as u32 * width + judgement.beat as u32 * beat_width;
+        let color = if judgement.faults.is_empty() { Color::RGB(0, 255, 0) } else { Color::RGB(255, 0, 0) };
+        let name = judgement.played.map_or("-", |n| names[n.get_octave_index() as usize]);
+        draw_text(canvas, font, name, Point::new(x as i32, row(1)), color);
+    }
+    if let Some(faults) = progress.last_faults() {
+        draw_text(canvas, font, &faults, Point::new(4, row(2)), Color::RGB(255, 0, 0));
+    }
+    let summary = format!("Pass {}, {} species, {}", progress.pass + 1, progress.species.name(), progress.describe());
+    draw_text(canvas, font, &summary, Point::new(4, row(3)), white);
+}
+
+// Display a page of the figured bass a column per figure, the current one in yellow, and under it
+// the bass and the realization played, in green without faults and in red with some
+fn draw_continuo(canvas: &mut Canvas<Window>, scores: &Scores, font: &Font, options: DisplayOptions) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    let progress = match scores.continuo.as_ref() {
+        Some(progress) => progress,
+        None => return,
+    };
+    let names = options.notation.get_names();
+    let white = Color::RGB(255, 255, 255);
+    let row = |i: i32| 4 + i * STRING_HEIGHT as i32;
+    draw_text(canvas, font, "Figures", Point::new(4, row(0)), white);
+    draw_text(canvas, font, "Bass", Point::new(4, row(1)), white);
+    draw_text(canvas, font, "Right", Point::new(4, row(2)), white);
+    let page = progress.page();
+    let width = (FOURIER_WIDTH - COUNTERPOINT_LABEL_WIDTH) / page.len().max(1) as u32;
+    let x = |figure: usize| (COUNTERPOINT_LABEL_WIDTH + (figure - page.start) as u32 * width) as i32;
+    for i in page.clone() {
+        let color = if i == progress.figure { Color::RGB(255, 255, 0) } else { Color::RGB(160, 160, 160) };
+        draw_text(canvas, font, &progress.figures[i].name(options.notation), Point::new(x(i), row(0)), color);
+    }
+    for judgement in progress.judgements.iter().filter(|j| page.contains(&j.figure)) {
+        let color = if judgement.faults.is_empty() { Color::RGB(0, 255, 0) } else { Color::RGB(255, 0, 0) };
+        let bass = judgement.bass.map_or("-", |n| names[n.get_octave_index() as usize]);
+        let played = judgement.played.iter().map(|n| names[n.get_octave_index() as usize]).join(" ");
+        draw_text(canvas, font, bass, Point::new(x(judgement.figure), row(1)), color);
+        draw_text(canvas, font, if played.is_empty() { "-" } else { &played }, Point::new(x(judgement.figure), row(2)), color);
+    }
+    if let Some(faults) = progress.last_faults() {
+        draw_text(canvas, font, &faults, Point::new(4, row(3)), Color::RGB(255, 0, 0));
+    }
+    let summary = format!("Pass {}, {}", progress.pass + 1, progress.describe());
+    draw_text(canvas, font, &summary, Point::new(4, row(4)), white);
+}
+
+// The names of the rows, the hands and the notes they hold when the keyboard is split
+fn attribution_labels(scores: &Scores, options: DisplayOptions) -> [String; 2] {
+    let hands = match scores.hands.as_ref() {
+        Some(hands) => hands,
+        None => return ["Band".to_owned(), "You".to_owned()],
+    };
+    let names = options.notation.get_names();
+    let held = |notes: &[Note]| {
+        notes
+            .iter()
+            .map(|&n| format!("{}{}", names[n.get_octave_index() as usize], n as usize / 12))
+            .join(" ")
+    };
+    [
+        format!("Left {}", held(&hands.left)),
+        format!("Right {}", held(&hands.right)),
+    ]
+}
+
+// Display the pitch classes clashing with the band, then with the notes just played
+fn draw_attribution(
+    canvas: &mut Canvas<Window>,
+    scores: &Scores,
+    texture_notes: &[Texture],
+    font: &Font,
+    options: DisplayOptions,
+) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    let rows = attribution_rows(scores);
+    for (i, name) in attribution_labels(scores, options).iter().enumerate() {
+        let y = i as i32 * NOTE_CELL_HEIGHT as i32;
+        if let Some(rows) = rows.as_ref() {
+            draw_note_grid(canvas, &rows[i], texture_notes, y);
+        }
+        draw_text(canvas, font, name, Point::new(4, y + 2), Color::RGB(255, 255, 255));
+    }
+}
+
+// Add the latest spectrum to the right of the spectrogram, on a log frequency scale
+// Returns false when the new column is dark
+fn scroll_spectrogram(pixels: &mut [u8], scores: &Scores) -> bool {
+    let (width, height) = (FOURIER_WIDTH as usize, FOURIER_HEIGHT as usize);
+    let (low, high) = (SPECTROGRAM_MIN_HZ.ln(), SPECTROGRAM_MAX_HZ.ln());
+    // The loudest frequency of every row
+    let mut column = vec![0f32; height];
+    for f in scores
+        .fourier
+        .iter()
+        .filter(|f| f.value >= SPECTROGRAM_MIN_HZ && f.value <= SPECTROGRAM_MAX_HZ)
+    {
+        let row: usize = f.value.ln().map_interval_rev(low..=high, 0..=height - 1);
+        column[row] = column[row].max(f.intensity);
+    }
+    let max = column.iter().cloned().fold(0f32, f32::max);
+    for (y, &intensity) in column.iter().enumerate() {
+        let line = &mut pixels[y * width * 3..(y + 1) * width * 3];
+        line.copy_within(3.., 0);
+        // Relative to the loudest frequency, in decibels
+        let level = if max > 0f32 {
+            (1f32 + 10f32 * (intensity / max).log10() / SPECTROGRAM_RANGE).max(0f32)
+        } else {
+            0f32
+        };
+        let color: (u8, u8, u8) = Srgb::from(Hsv::new(240.0 * (1.0 - level), 1.0, level))
+            .into_format()
+            .into_components();
+        line[(width - 1) * 3..].copy_from_slice(&[color.0, color.1, color.2]);
+    }
+    max > 0f32
+}
+
+// Hash values at the precision they are drawn with, so that invisible changes are not redrawn
+fn quantize<H: Hasher>(hasher: &mut H, values: &[f32], steps: f32) {
+    for value in values {
+        ((value * steps) as i32).hash(hasher);
+    }
+}
+
+// Draw the annotations over the spectrogram, as a line where they were received
+fn draw_markers(canvas: &mut Canvas<Window>, markers: &[(usize, String, Color)], font: &Font) {
+    for &(age, ref text, color) in markers {
+        let x = FOURIER_WIDTH as i32 - 1 - age as i32;
+        canvas.set_draw_color(color);
+        canvas
+            .draw_line(Point::new(x, 0), Point::new(x, FOURIER_HEIGHT as i32))
+            .unwrap();
+        draw_text(canvas, font, text, Point::new(x + 4, 4), color);
+    }
+}
+
+// Display the detected chord, in large
+fn draw_chord(canvas: &mut Canvas<Window>, scores: &Scores, font: &Font, options: DisplayOptions) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    if let Some(chord) = scores.chord {
+        let name = chord.name(options.notation);
+        let (width, height) = font.size_of(&name).unwrap();
+        let pnt = Point::new(
+            (FOURIER_WIDTH as i32 - width as i32) / 2,
+            (BANNER_HEIGHT as i32 - height as i32) / 2,
+        );
+        draw_text(canvas, font, &name, pnt, Color::RGB(255, 255, 0));
+    }
+}
 
-    draw_notes(canvas, scores);
+// Display the buttons of the common actions
+fn draw_controls(canvas: &mut Canvas<Window>, frozen: bool, font: &Font) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    for (i, &button) in BUTTONS.iter().enumerate() {
+        let x = i as i32 * BUTTON_WIDTH as i32;
+        let color = if button == Action::Freeze && frozen {
+            Color::RGB(30, 90, 160)
+        } else {
+            Color::RGB(60, 60, 60)
+        };
+        canvas.set_draw_color(color);
+        canvas
+            .fill_rect(Rect::new(x + 4, 4, BUTTON_WIDTH - 8, BUTTON_HEIGHT - 8))
+            .unwrap();
+        let label = button_label(button, frozen);
+        let (width, height) = font.size_of(label).unwrap();
+        let pnt = Point::new(
+            x + (BUTTON_WIDTH as i32 - width as i32) / 2,
+            (BUTTON_HEIGHT as i32 - height as i32) / 2,
+        );
+        draw_text(canvas, font, label, pnt, Color::RGB(255, 255, 255));
+    }
+}
+
+// Display the help over a window, in as many columns as needed
+fn draw_help(canvas: &mut Canvas<Window>, lines: &[String], font: &Font) {
+    // The panels are drawn at their own size, scaled to the window unless fullscreen
+    let (width, height) = match canvas.logical_size() {
+        (0, 0) => canvas.window().size(),
+        size => size,
+    };
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 220));
+    canvas.fill_rect(Rect::new(0, 0, width, height)).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+    let rows = ((height as usize).saturating_sub(8) / STRING_HEIGHT as usize).max(1);
+    let mut x = 8;
+    for column in lines.chunks(rows) {
+        for (i, line) in column.iter().enumerate() {
+            let pnt = Point::new(x, 4 + i as i32 * STRING_HEIGHT as i32);
+            draw_text(canvas, font, line, pnt, Color::RGB(255, 255, 255));
+        }
+        let column_width = column
+            .iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| font.size_of(line).unwrap().0)
+            .max()
+            .unwrap_or(0);
+        x += column_width as i32 + 24;
+    }
+}
+
+// Display the confidence, chord, song and held note
+// The bar and beat of the followed chart are shown by the chord
+fn stats_lines(
+    scores: &Scores,
+    song: Option<&String>,
+    beat: Option<(usize, usize)>,
+    latency: Option<Report>,
+    options: DisplayOptions,
+) -> Vec<String> {
+    let names = options.notation.get_names();
+    let mut lines = vec![
+        if scores.idle {
+            format!("Confidence {:.2} (idle, waiting for the input)", scores.confidence)
+        } else if scores.warmup < 1f32 {
+            format!("Confidence {:.2} (warming up {:.0}%)", scores.confidence, scores.warmup * 100f32)
+        } else {
+            format!("Confidence {:.2}", scores.confidence)
+        },
+        format!(
+            "Chord      {}{}",
+            scores.chord.map_or("-".to_owned(), |c| c.name(options.notation)),
+            beat.map_or(String::new(), |(bar, beat)| format!(" (bar {} beat {})", bar, beat))
+        ),
+        format!("Song       {}", song.map_or("-", |s| s.as_str())),
+        format!(
+            "Held       {}",
+            scores
+                .detail
+                .as_ref()
+                .map_or("-".to_owned(), |d| d.describe(scores.chord, names).join(", "))
+        ),
+        format!(
+            "Input      {}{}",
+            match scores.invalid_samples {
+                0 => "clean".to_owned(),
+                n => format!("{} invalid samples replaced", n),
+            },
+            scores.instrument.map_or(String::new(), |i| format!(", sounds like a {}", i.name()))
+        ),
+        format!("Prompt     {}", scores.prompt.as_deref().unwrap_or("-")),
+    ];
+    if options.latency {
+        lines.push(format!("Latency    {}", latency.map_or("-".to_owned(), |r| r.describe())));
+    }
+    lines
+}
 
-    // Flush
-    canvas.present();
+// Display the RMS and peak levels of the input and its short-term loudness, as horizontal bars
+fn draw_loudness(canvas: &mut Canvas<Window>, scores: &Scores, font: &Font) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    let shown = |level: f32| if level > METER_FLOOR { Some(level) } else { None };
+    let meters = [
+        ("RMS", shown(scores.meter.rms), "dB", CLIP_WARNING),
+        ("Peak", shown(scores.meter.peak), "dB", CLIP_WARNING),
+        ("Short-term", scores.loudness, "LUFS", LOUD_WARNING),
+    ];
+    let width = FOURIER_WIDTH - METER_LABEL_WIDTH - 8;
+    // The position of a value on the meters
+    let x = |value: f32| {
+        let fraction = ((value - METER_FLOOR) / -METER_FLOOR).max(0f32).min(1f32);
+        METER_LABEL_WIDTH as i32 + (fraction * width as f32) as i32
+    };
+    for (i, &(name, value, unit, warning)) in meters.iter().enumerate() {
+        let y = 4 + i as i32 * STRING_HEIGHT as i32;
+        let text = match value {
+            Some(value) => format!("{:<10} {:>6.1} {}", name, value, unit),
+            None => format!("{:<10}      -", name),
+        };
+        draw_text(canvas, font, &text, Point::new(4, y), Color::RGB(255, 255, 255));
+        if let Some(value) = value {
+            let color = if value > warning {
+                Color::RGB(255, 60, 0)
+            } else {
+                Color::RGB(0, 200, 0)
+            };
+            canvas.set_draw_color(color);
+            let length = (x(value) - METER_LABEL_WIDTH as i32).max(1) as u32;
+            canvas
+                .fill_rect(Rect::new(METER_LABEL_WIDTH as i32, y + 2, length, STRING_HEIGHT - 4))
+                .unwrap();
+        }
+    }
+    // Mark the target loudness
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    let y = 4 + 2 * STRING_HEIGHT as i32;
+    canvas
+        .draw_line(
+            Point::new(x(TARGET_LOUDNESS), y),
+            Point::new(x(TARGET_LOUDNESS), y + STRING_HEIGHT as i32),
+        )
+        .unwrap();
+}
+
+fn draw_stats(canvas: &mut Canvas<Window>, lines: &[String], font: &Font) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    for (i, line) in lines.iter().enumerate() {
+        let pnt = Point::new(4, 4 + i as i32 * STRING_HEIGHT as i32);
+        draw_text(canvas, font, line, pnt, Color::RGB(255, 255, 255));
+    }
+}
+
+// Display the lyrics of the current song, scrolling up as the chart is followed
+fn draw_lyrics(canvas: &mut Canvas<Window>, setlist: Option<&Setlist>, font: &Font) {
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(None).unwrap();
+    let setlist = match setlist {
+        Some(setlist) => setlist,
+        None => return,
+    };
+    let lyrics = &setlist.song(setlist.current()).chart.lyrics;
+    let (bar, progress) = setlist.position();
+    let position = bar as f32 + progress;
+    // The line being sung, and how far the next one is, as a fractional line
+    let current = lyrics.iter().rposition(|l| l.bar as f32 <= position);
+    let from = current.map_or(0f32, |i| lyrics[i].bar as f32);
+    let scroll = match lyrics.get(current.map_or(0, |i| i + 1)) {
+        Some(next) if next.bar as f32 > from => {
+            current.map_or(-1f32, |i| i as f32) + (position - from) / (next.bar as f32 - from)
+        }
+        _ => current.map_or(-1f32, |i| i as f32),
+    };
+    for (i, lyric) in lyrics.iter().enumerate() {
+        let y = (1f32 + i as f32 - scroll) * STRING_HEIGHT as f32;
+        if y < -(STRING_HEIGHT as f32) || y > LYRICS_HEIGHT as f32 {
+            continue;
+        }
+        let color = if lyric.cue {
+            Color::RGB(0, 192, 255)
+        } else if Some(i) == current {
+            Color::RGB(255, 255, 0)
+        } else {
+            Color::RGB(160, 160, 160)
+        };
+        draw_text(canvas, font, &lyric.text, Point::new(4, y as i32), color);
+    }
+}
+
+// Display the intonation heatmap of the fretboard notes
+// Flat notes are blue, sharp notes are red, unplayed notes are black
+fn draw_intonation(canvas: &mut Canvas<Window>, scores: &Scores) {
+    let count = (LAST_NOTE - FIRST_NOTE) as u32;
+    for (i, deviation) in scores.intonation[FIRST_NOTE..LAST_NOTE].iter().enumerate() {
+        let deviation = match deviation {
+            Some(d) => d.max(-INTONATION_RANGE).min(INTONATION_RANGE),
+            None => continue,
+        };
+        // White when in tune, saturating towards the range limit
+        let hue = if deviation < 0.0 { 240.0 } else { 0.0 };
+        let saturation = deviation.abs() / INTONATION_RANGE;
+        let color: (u8, u8, u8) = Srgb::from(Hsv::new(hue, saturation, 1.0))
+            .into_format()
+            .into_components();
+        canvas.set_draw_color(Color::from(color));
+        let x = (i as u32).map_interval(0..=count, 0..=FOURIER_WIDTH as i32);
+        let next = (i as u32 + 1).map_interval(0..=count, 0..=FOURIER_WIDTH as i32);
+        let rect = Rect::new(x, 0, (next - x).max(1) as u32, FOURIER_HEIGHT);
+        canvas.fill_rect(rect).unwrap();
+    }
+
+    // Mark the octaves
+    canvas.set_draw_color(Color::RGB(80, 80, 80));
+    for i in (FIRST_NOTE..LAST_NOTE).filter(|i| i % 12 == 0) {
+        let x = ((i - FIRST_NOTE) as u32).map_interval(0..=count, 0..=FOURIER_WIDTH as i32);
+        canvas
+            .draw_line(Point::new(x, 0), Point::new(x, FOURIER_HEIGHT as i32))
+            .unwrap();
+    }
+}
+
+// Display the pitch trajectory of the held note, and describe it
+fn draw_detail(canvas: &mut Canvas<Window>, scores: &Scores, font: &Font, options: DisplayOptions) {
+    let detail = match &scores.detail {
+        Some(detail) => detail,
+        None => return,
+    };
+
+    // Draw the trajectory, one semitone above and below the starting note
+    canvas.set_draw_color(Color::RGB(80, 80, 80));
+    let middle = FOURIER_HEIGHT as i32 / 2;
+    canvas
+        .draw_line(Point::new(0, middle), Point::new(FOURIER_WIDTH as i32, middle))
+        .unwrap();
+    if detail.trajectory.len() > 1 {
+        let last = detail.trajectory.len() as u32 - 1;
+        let points = detail
+            .trajectory
+            .iter()
+            .enumerate()
+            .map(|(i, cents)| {
+                Point::new(
+                    (i as u32).map_interval(0..=last, 0..=FOURIER_WIDTH as i32 - 1),
+                    cents
+                        .max(-100f32)
+                        .min(100f32)
+                        .map_interval_rev(-100f32..=100f32, 0..=FOURIER_HEIGHT as i32 - 1),
+                )
+            })
+            .collect_vec();
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.draw_lines(points.as_slice()).unwrap();
+    }
+
+    // Write the description
+    let names = options.notation.get_names();
+    for (i, line) in detail.describe(scores.chord, names).iter().enumerate() {
+        let pnt = Point::new(4, 4 + i as i32 * STRING_HEIGHT as i32);
+        draw_text(canvas, font, line, pnt, Color::RGB(255, 255, 0));
+    }
 }
 
 // Display the fourier graph
@@ -1,75 +1,575 @@
 // The terminal display loop
 
-use crate::display::DisplayOptions;
+use crate::audio_buffer::Meter;
+use crate::capture::Watchdog;
+use crate::classifier::Classify;
+use crate::control::{Action, Command};
+use crate::display::{self, DisplayOptions, Notation, Refresher, Scaler, Smoother};
+use crate::error::Error;
+use crate::fourier::{History, ScoringOptions};
+use crate::keymap::{Key, Keymap};
+use crate::keys::KeyTracker;
+use crate::latency::Monitor;
 use crate::notes::Note::*;
 use crate::notes::{Note, NOTE_COUNT};
 use crate::scores::Scores;
+use crate::setlist::Setlist;
+use crate::voicings;
 
 use std::io;
 use std::io::BufWriter;
 use std::io::Write;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use termion::input::TermRead;
 
 // Number of notes in line
 const GUITAR_STRING_LENGTH: usize = 44;
 // Every string defined by their note (E2 to E4)
 const GUITAR_STRINGS: [Note; 6] = [E2, A2, D3, G3, B3, E4];
 
-// Clear terminal and display guitar
-fn guitar(scores: &[f32; NOTE_COUNT], options: DisplayOptions) {
-    // Create buffer to avoid flicker
-    let mut buffer = BufWriter::new(io::stdout());
-
-    // Add the clear screen message to the buffer
-    if options.clear_term {
-        write!(
-            &mut buffer,
-            "{}",
-            termion::cursor::Up(GUITAR_STRINGS.len() as u16 + 1)
-        )
-        .unwrap();
+// The actions of the terminal display, which has no graphs nor sound
+const SUPPORTED: [Action; 11] = [
+    Action::Help,
+    Action::Reset,
+    Action::Freeze,
+    Action::NextStyle,
+    Action::Undo,
+    Action::Redo,
+    Action::Revert,
+    Action::Scaling,
+    Action::NextChord,
+    Action::NextSong,
+    Action::PreviousSong,
+];
+
+// Get the key of a terminal key
+fn key(key: termion::event::Key) -> Option<Key> {
+    match key {
+        termion::event::Key::Char('\n') => None,
+        termion::event::Key::Char(c) => Some(Key::Char(c.to_ascii_lowercase())),
+        termion::event::Key::Esc => Some(Key::Escape),
+        termion::event::Key::PageUp => Some(Key::PageUp),
+        termion::event::Key::PageDown => Some(Key::PageDown),
+        termion::event::Key::F(n) => Some(Key::F(n)),
+        _ => None,
     }
+}
+
+// How long an input problem stays shown
+const PROBLEM_SHOWN: Duration = Duration::from_secs(3);
+
+// The 16 color backgrounds of remote terminals, from the best to the worst score
+const REMOTE_COLORS: [&str; 5] = ["42", "102", "103", "43", "41"];
+
+// The pitch classes listed as clashing with the band or the player
+const CLASHES_SHOWN: usize = 3;
+
+// The level meter, its number of columns and its range in decibels
+const METER_LENGTH: usize = 30;
+const METER_FLOOR: f32 = -60f32;
+// The peak level from which the input is close to clipping, in decibels
+const CLIP_WARNING: f32 = -3f32;
+
+// A part of a line, written at once
+#[derive(Clone, PartialEq)]
+struct Cell {
+    // The attributes of the text, the terminal default when None
+    color: Option<String>,
+    text: String,
+    // The number of columns it takes
+    width: usize,
+}
 
+impl Cell {
+    fn plain(text: &str) -> Cell {
+        Cell {
+            color: None,
+            text: text.to_owned(),
+            width: text.chars().count(),
+        }
+    }
+}
+
+// The attributes of a score, a gradient or one of a few colors on remote terminals
+fn color(score: f32, remote: bool) -> String {
+    if remote {
+        let index = (score * REMOTE_COLORS.len() as f32) as usize;
+        return format!("30;{}", REMOTE_COLORS[index.min(REMOTE_COLORS.len() - 1)]);
+    }
+    let gradient = (score * 255f32) as u8;
+    format!(
+        "30;48;2;{red};{green};{blue}",
+        red = gradient,
+        green = (255 - gradient),
+        blue = gradient / 4
+    )
+}
+
+// The guitar cells, the fret count then a line per string
+fn guitar(scores: &[f32], shown: &[bool], options: DisplayOptions) -> Vec<Vec<Cell>> {
     // Display the fret count
-    write!(&mut buffer, " 0 |").unwrap();
+    let mut header = " 0 |".to_owned();
     for i in 1..GUITAR_STRING_LENGTH {
-        write!(&mut buffer, "{:^3}", i).unwrap();
+        header += &format!("{:^3}", i);
     }
-    writeln!(&mut buffer).unwrap();
+    let mut lines = vec![vec![Cell::plain(&header)]];
 
     // For every guitar strings
     for &string in GUITAR_STRINGS.iter().rev() {
+        let mut line = Vec::new();
         // For every note on that string
         for note in string.iter_from().take(GUITAR_STRING_LENGTH) {
             // Get note name and calculated score
-            let name = options.notation.get_name(note);
+            let name = if shown[note as usize] {
+                options.notation.get_name(note)
+            } else {
+                "   "
+            };
             let score = scores[note as usize];
             let score = score.max(0f32).min(1f32);
             // Write the name with the appropriate color
-            let gradient = (score * 255f32) as u8;
-            write!(
-                &mut buffer,
-                "\x1b[30;48;2;{red};{green};{blue}m{name}",
-                red = gradient,
-                green = (255 - gradient),
-                blue = gradient / 4,
-                name = name
-            )
-            .unwrap();
+            line.push(Cell {
+                color: Some(color(score, options.remote)),
+                text: name.to_owned(),
+                width: 3,
+            });
             // Add the bar to differentiate the zero 'fret' from the rest
             if string == note {
-                write!(&mut buffer, "\x1b[0;0m|").unwrap();
+                line.push(Cell::plain("|"));
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+// The level meter, the RMS as a bar and the peak as a mark, red when close to clipping
+fn meter(meter: Meter) -> Vec<Cell> {
+    let column = |level: f32| {
+        let fraction = ((level - METER_FLOOR) / -METER_FLOOR).max(0f32).min(1f32);
+        (fraction * METER_LENGTH as f32).round() as usize
+    };
+    let rms = column(meter.rms);
+    let peak = column(meter.peak).min(METER_LENGTH);
+    let bar = (0..METER_LENGTH)
+        .map(|i| if i + 1 == peak { '|' } else if i < rms { '#' } else { '-' })
+        .collect::<String>();
+    let color = if meter.peak > CLIP_WARNING { "31" } else { "32" };
+    let shown = |level: f32| if level > METER_FLOOR { format!("{:.1}", level) } else { "-".to_owned() };
+    vec![
+        Cell::plain("Level ["),
+        Cell {
+            color: Some(color.to_owned()),
+            text: bar,
+            width: METER_LENGTH,
+        },
+        Cell::plain(&format!(
+            "] RMS {} dB, peak {} dB",
+            shown(meter.rms),
+            shown(meter.peak)
+        )),
+    ]
+}
+
+// The pitch classes clashing the most, from the dissonance of every note
+fn clashes(dissonance: &[f32], notation: Notation) -> String {
+    let mut classes = [0f32; 12];
+    for (i, value) in dissonance.iter().enumerate() {
+        classes[i % 12] += value;
+    }
+    let mut order = (0..12).filter(|&c| classes[c] > 0f32).collect::<Vec<usize>>();
+    order.sort_by(|&a, &b| classes[b].partial_cmp(&classes[a]).unwrap());
+    if order.is_empty() {
+        return "-".to_owned();
+    }
+    order
+        .iter()
+        .take(CLASHES_SHOWN)
+        .map(|&c| notation.get_names()[c])
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// The notes held by a hand, with their octave
+fn held(notes: &[Note], notation: Notation) -> String {
+    if notes.is_empty() {
+        return "-".to_owned();
+    }
+    notes
+        .iter()
+        .map(|&n| format!("{}{}", notation.get_names()[n.get_octave_index() as usize], n as usize / 12))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Write cells, only changing the attributes between cells of different colors
+fn write_cells(buffer: &mut impl Write, cells: &[Cell]) {
+    let mut current = None;
+    for cell in cells {
+        if cell.color != current {
+            match cell.color.as_ref() {
+                Some(color) => write!(buffer, "\x1b[{}m", color).unwrap(),
+                None => write!(buffer, "\x1b[0m").unwrap(),
             }
+            current = cell.color.clone();
         }
-        writeln!(&mut buffer, "\x1b[0;0m").unwrap();
+        write!(buffer, "{}", cell.text).unwrap();
     }
-    buffer.flush().unwrap();
+    if current.is_some() {
+        write!(buffer, "\x1b[0m").unwrap();
+    }
+}
+
+// What the terminal shows, to only rewrite the cells that changed
+struct Screen {
+    lines: Vec<Vec<Cell>>,
 }
 
-// Simply feeds the scores into the guitar display
-pub fn display(receiver: Receiver<Scores>, options: DisplayOptions) -> Result<(), String> {
-    for scores in receiver.into_iter() {
-        guitar(&scores.note_scores, options);
+impl Screen {
+    // Write the lines, over the previous ones when clearing
+    fn draw(&mut self, lines: Vec<Vec<Cell>>, clear: bool) {
+        // Create buffer to avoid flicker
+        let mut buffer = BufWriter::new(io::stdout());
+        let height = self.lines.len();
+        if !clear || lines.len() != height {
+            // Add the clear screen message to the buffer
+            if clear && height > 0 {
+                write!(
+                    &mut buffer,
+                    "{}{}",
+                    termion::cursor::Up(height as u16),
+                    termion::clear::AfterCursor
+                )
+                .unwrap();
+            }
+            for line in lines.iter() {
+                write_cells(&mut buffer, line);
+                writeln!(&mut buffer).unwrap();
+            }
+        } else {
+            for (row, (new, old)) in lines.iter().zip(self.lines.iter()).enumerate() {
+                let first = match new.iter().zip(old.iter()).position(|(a, b)| a != b) {
+                    Some(first) => first,
+                    None if new.len() != old.len() => new.len().min(old.len()),
+                    None => continue,
+                };
+                // Rewrite from the first to the last changed cell, or to the end of a resized line
+                let last = if new.len() == old.len() {
+                    new.iter()
+                        .zip(old.iter())
+                        .rposition(|(a, b)| a != b)
+                        .unwrap()
+                } else {
+                    new.len().saturating_sub(1)
+                };
+                let column: usize = new[..first].iter().map(|c| c.width).sum();
+                let distance = (height - row) as u16;
+                write!(&mut buffer, "{}\r", termion::cursor::Up(distance)).unwrap();
+                if column > 0 {
+                    write!(&mut buffer, "{}", termion::cursor::Right(column as u16)).unwrap();
+                }
+                write_cells(
+                    &mut buffer,
+                    &new[first.min(new.len())..(last + 1).min(new.len())],
+                );
+                if new.len() != old.len() {
+                    write!(&mut buffer, "{}", termion::clear::UntilNewline).unwrap();
+                }
+                write!(&mut buffer, "{}\r", termion::cursor::Down(distance)).unwrap();
+            }
+        }
+        buffer.flush().unwrap();
+        self.lines = lines;
+    }
+}
+
+// Feeds the scores into the guitar display, skipping the unstable ones
+// Keys are read once Enter is pressed, the terminal staying in line mode
+pub fn display(
+    receiver: Receiver<Scores>,
+    commands: Sender<Command>,
+    options: DisplayOptions,
+    scoring: ScoringOptions,
+    keymap: Keymap,
+    setlist: Option<Setlist>,
+    watchdog: &mut Watchdog,
+    monitor: Monitor,
+) -> Result<(), Error> {
+    let mut monitor = monitor;
+    let mut scoring = scoring;
+    let mut history = History::new(scoring);
+    // Read the keys in the background
+    let (key_sender, key_receiver) = channel();
+    std::thread::spawn(move || {
+        for key in io::stdin().keys().filter_map(Result::ok).filter_map(key) {
+            if key_sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut help = false;
+    // The last annotation received
+    let mut annotation = None;
+    // The last input problem, and when it happened
+    let mut problem: Option<(String, Instant)> = None;
+    let mut frozen: Option<Scores> = None;
+    // The scores shown, smoothed apart from the analysis
+    let mut smoother = Smoother::new(options.smoothing);
+    let mut scaler = Scaler::new(options.scaling);
+    // The scores of the suggestions shown, kept until the next refresh
+    let mut refresher = Refresher::new(&options);
+    let mut suggested: Option<Scores> = None;
+    // The ranges of the other channels, scaled apart
+    let mut channel_scalers = (1..options.channels)
+        .map(|_| Scaler::new(options.scaling))
+        .collect::<Vec<Scaler>>();
+    // The labels follow the song key, or the key heard so far
+    let mut key_tracker = KeyTracker::new();
+    // The lines shown, rewritten at most at the refresh rate
+    let mut screen = Screen { lines: Vec::new() };
+    let interval = Duration::from_secs_f32(1f32 / options.refresh);
+    while let Ok(mut scores) = receiver.recv() {
+        monitor.receive(scores.latency);
+        // Skip to the latest scores, the terminal being slower than the analysis
+        let mut annotations = std::mem::replace(&mut scores.annotations, Vec::new());
+        let mut input_events = std::mem::replace(&mut scores.input_events, Vec::new());
+        for newer in receiver.try_iter() {
+            monitor.receive(newer.latency);
+            annotations.extend(newer.annotations.iter().cloned());
+            input_events.extend(newer.input_events.iter().cloned());
+            scores = newer;
+        }
+        if let Some(event) = input_events.last() {
+            problem = Some((event.describe(), Instant::now()));
+        }
+        if problem
+            .as_ref()
+            .map_or(false, |(_, time)| time.elapsed() > PROBLEM_SHOWN)
+        {
+            problem = None;
+        }
+        let frame_start = Instant::now();
+        let warning = watchdog.check();
+        smoother.smooth(&mut scores);
+        scaler.update(&scores);
+        let heard_key = key_tracker.update(&scores.note_values);
+        if let Some(text) = annotations.last() {
+            annotation = Some(text.clone());
+        }
+        for key in key_receiver.try_iter() {
+            let action = match keymap.action(key) {
+                Some(action) if SUPPORTED.contains(&action) => action,
+                _ => continue,
+            };
+            let command = match action {
+                Action::Help => {
+                    help = !help;
+                    None
+                }
+                Action::Reset => {
+                    frozen = None;
+                    Some(Command::Reset)
+                }
+                Action::Freeze => {
+                    frozen = match frozen {
+                        Some(_) => None,
+                        None => Some(scores.clone()),
+                    };
+                    None
+                }
+                Action::NextStyle => Some(Command::NextStyle),
+                Action::Undo => Some(Command::Undo),
+                Action::Redo => Some(Command::Redo),
+                Action::Revert => Some(Command::Revert),
+                Action::Scaling => {
+                    scaler.scaling = scaler.scaling.next();
+                    None
+                }
+                _ => {
+                    if let Some(setlist) = setlist.as_ref() {
+                        match action {
+                            Action::NextChord => setlist.next_chord(),
+                            Action::NextSong => setlist.next(),
+                            _ => setlist.previous(),
+                        }
+                    }
+                    None
+                }
+            };
+            if let Some(command) = command {
+                history.apply(&mut scoring, &command);
+                commands.send(command).ok();
+            }
+        }
+        let scores = frozen.clone().unwrap_or(scores);
+        let beat = setlist.as_ref().map(Setlist::beat);
+        let chord = setlist.as_ref().and_then(Setlist::chord).or(scores.chord);
+        if refresher.due(beat, chord, scores.confidence >= options.confidence) {
+            suggested = Some(scores.clone());
+        }
+        if scores.confidence >= options.confidence {
+            let shown = suggested.as_ref().unwrap_or(&scores);
+            let mut text = Vec::new();
+            if let Some(warning) = warning.as_ref() {
+                text.push(format!("! {}", warning));
+            }
+            if let Some(prompt) = scores.prompt.as_ref() {
+                text.push(format!("Prompt: {}", prompt));
+            }
+            if scores.idle {
+                text.push("Idle, waiting for the input".to_owned());
+            } else if scores.warmup < 1f32 {
+                text.push(format!("Warming up {:.0}%", scores.warmup * 100f32));
+            }
+            if let Some(instrument) = scores.instrument {
+                let input_type = instrument.input_type();
+                if scoring.classify == Some(Classify::Suggest) && input_type != scoring.input_type {
+                    text.push(format!(
+                        "Sounds like a {}, try --input-type {}",
+                        instrument.name(),
+                        input_type.name()
+                    ));
+                } else {
+                    text.push(format!("Sounds like a {}", instrument.name()));
+                }
+            }
+            if let Some(progress) = scores.counterpoint.as_ref() {
+                let names = options.notation.get_names();
+                let name = |note: Option<Note>| note.map_or("-", |n| names[n.get_octave_index() as usize]);
+                let cantus = progress.steps.iter().enumerate().map(|(i, step)| {
+                    if i == progress.step {
+                        format!("[{}]", name(step.note))
+                    } else {
+                        name(step.note).to_owned()
+                    }
+                });
+                text.push(format!("Cantus: {}", cantus.collect::<Vec<_>>().join(" ")));
+                let played = progress.judgements.iter().map(|j| {
+                    if j.faults.is_empty() {
+                        name(j.played).to_owned()
+                    } else {
+                        format!("{}!", name(j.played))
+                    }
+                });
+                text.push(format!("Played: {}", played.collect::<Vec<_>>().join(" ")));
+                if let Some(faults) = progress.last_faults() {
+                    text.push(format!("Faults at {}", faults));
+                }
+                text.push(format!("Pass {}: {}", progress.pass + 1, progress.describe()));
+            }
+            if let Some(progress) = scores.continuo.as_ref() {
+                let names = options.notation.get_names();
+                let name = |note: &Note| names[note.get_octave_index() as usize];
+                let figures = progress.page().map(|i| {
+                    let figure = progress.figures[i].name(options.notation);
+                    if i == progress.figure {
+                        format!("[{}]", figure)
+                    } else {
+                        figure
+                    }
+                });
+                text.push(format!("Figures: {}", figures.collect::<Vec<_>>().join(" ")));
+                let page = progress.page();
+                let played = progress.judgements.iter().filter(|j| page.contains(&j.figure)).map(|j| {
+                    let played = format!(
+                        "{}/{}",
+                        j.bass.as_ref().map_or("-", name),
+                        j.played.iter().map(name).collect::<Vec<_>>().join(",")
+                    );
+                    if j.faults.is_empty() {
+                        played
+                    } else {
+                        format!("{}!", played)
+                    }
+                });
+                text.push(format!("Played: {}", played.collect::<Vec<_>>().join(" ")));
+                if let Some(faults) = progress.last_faults() {
+                    text.push(format!("Faults at {}", faults));
+                }
+                text.push(format!("Pass {}: {}", progress.pass + 1, progress.describe()));
+            }
+            if let Some(hands) = scores.hands.as_ref() {
+                text.push(format!("Left hand: {}", held(&hands.left, options.notation)));
+                text.push(format!("Right hand: {}", held(&hands.right, options.notation)));
+            }
+            if let Some(attribution) = scores.attribution.as_ref() {
+                let (band, own) = match scores.hands {
+                    Some(_) => ("the left hand", "the right hand"),
+                    None => ("the band", "your notes"),
+                };
+                text.push(format!("Clashing with {}: {}", band, clashes(&attribution.band, options.notation)));
+                text.push(format!("Clashing with {}: {}", own, clashes(&attribution.own, options.notation)));
+            }
+            if let Some(fingering) = options.instrument {
+                let chord = setlist.as_ref().and_then(Setlist::chord).or(shown.chord);
+                if let Some(chord) = chord {
+                    text.push(format!("Voicings for {}:", chord.name(options.notation)));
+                    for voicing in voicings::suggest(chord, &shown.note_scores, fingering) {
+                        let names = options.notation.get_names();
+                        text.push(format!("  {}  {}", voicing.style.name(), voicing.describe(names)));
+                    }
+                }
+            }
+            if let Some((bar, beat)) = beat {
+                text.push(format!("Bar {} beat {}", bar, beat));
+            }
+            if options.latency {
+                let report = monitor.report().map_or("-".to_owned(), |r| r.describe());
+                text.push(format!("Latency {}", report));
+            }
+            if let Some(annotation) = annotation.as_ref() {
+                text.push(format!("> {}", annotation));
+            }
+            if let Some((problem, _)) = problem.as_ref() {
+                text.push(format!("! Input {}", problem));
+            }
+            if scores.invalid_samples > 0 {
+                text.push(format!(
+                    "Invalid samples replaced {}",
+                    scores.invalid_samples
+                ));
+            }
+            if help {
+                let options = DisplayOptions {
+                    scaling: scaler.scaling,
+                    ..options
+                };
+                text.extend(display::help(&keymap, &SUPPORTED, &scoring, &options));
+                text.push("(press Enter after a key)".to_owned());
+            }
+            let note_scores = scaler.scale(shown, 0..NOTE_COUNT);
+            let label_key = setlist.as_ref().and_then(Setlist::key).or(heard_key);
+            let labeled = options.labels.shown(&note_scores, 0, label_key);
+            let mut lines = guitar(&note_scores, &labeled, options);
+            // The other channels below, numbered from 2
+            for (i, (channel, channel_scaler)) in scores
+                .channels
+                .iter()
+                .zip(channel_scalers.iter_mut())
+                .enumerate()
+            {
+                if let Some(channel) = channel {
+                    channel_scaler.scaling = scaler.scaling;
+                    channel_scaler.update(channel);
+                    let note_scores = channel_scaler.scale(channel, 0..NOTE_COUNT);
+                    let shown = options.labels.shown(&note_scores, 0, label_key);
+                    lines.push(vec![Cell::plain(&format!("Channel {}", i + 2))]);
+                    lines.extend(guitar(&note_scores, &shown, options));
+                }
+            }
+            lines.push(meter(scores.meter));
+            lines.extend(text.iter().map(|line| vec![Cell::plain(line)]));
+            screen.draw(lines, options.clear_term);
+            monitor.drawn();
+        }
+        std::thread::sleep(
+            interval
+                .checked_sub(frame_start.elapsed())
+                .unwrap_or_default(),
+        );
     }
     Ok(())
 }
@@ -0,0 +1,108 @@
+// Removes the playback of ImproVe itself from the capture, ie. the chords played back on a laptop
+// The playback is copied to a ring at the capture rate, and an NLMS adaptive filter learns the path
+// from the speakers to the microphone, its estimate of the echo being subtracted from the capture
+// The filter spans the delay and the reverberation of that path, and is idle without playback
+
+// Standard
+use std::collections::VecDeque;
+
+// Crate
+use crate::ring::{self, Consumer, Handle, Producer};
+
+// The adaptation step of the filter, normalized by the playback energy (0 .. 2)
+const STEP: f32 = 0.1;
+// The regularization of the step on quiet playback
+const REGULARIZATION: f32 = 1e-3;
+// The playback energy over the filter under which nothing is played
+const SILENT_ENERGY: f32 = 1e-9;
+
+// Copies the playback, every device played through writing to the same ring
+#[derive(Clone)]
+pub struct Tap {
+    handle: Handle,
+    // The capture rate, the playback opening at it so the samples match
+    pub rate: i32,
+}
+
+impl Tap {
+    // A producer for the next playback, the previous one falling silent
+    pub fn producer(&self) -> Producer {
+        self.handle.producer()
+    }
+}
+
+pub struct Canceller {
+    reference: Consumer,
+    // The playback read ahead of the capture matching it
+    pending: VecDeque<f32>,
+    scratch: Vec<f32>,
+    // The last playback samples, twice so the latest ones are contiguous, newest first
+    history: Vec<f32>,
+    position: usize,
+    // The energy of the playback samples in the filter
+    energy: f32,
+    // The estimated response of the path from the speakers to the microphone
+    weights: Vec<f32>,
+}
+
+// The tap of the playback, and the canceller removing it from the capture at the given rate
+// The filter spans the given seconds
+pub fn canceller(rate: i32, seconds: f32) -> (Tap, Canceller) {
+    let (producer, reference) = ring::for_rate(rate);
+    let taps = ((seconds * rate as f32) as usize).max(1);
+    (
+        Tap {
+            handle: producer.handle(),
+            rate,
+        },
+        Canceller {
+            reference,
+            pending: VecDeque::new(),
+            scratch: Vec::new(),
+            history: vec![0f32; 2 * taps],
+            position: 0,
+            energy: 0f32,
+            weights: vec![0f32; taps],
+        },
+    )
+}
+
+impl Canceller {
+    // Subtract the estimated echo of the playback from the captured samples
+    pub fn cancel(&mut self, packet: &mut [f32]) {
+        let taps = self.weights.len();
+        self.reference.read(&mut self.scratch);
+        self.pending.extend(self.scratch.drain(..));
+        // The playback running ahead of the capture by more than the filter is of no use
+        while self.pending.len() > taps + packet.len() {
+            self.pending.pop_front();
+        }
+        for sample in packet.iter_mut() {
+            let reference = self.pending.pop_front().unwrap_or(0f32);
+            self.position = if self.position == 0 {
+                taps - 1
+            } else {
+                self.position - 1
+            };
+            let oldest = self.history[self.position + taps];
+            self.history[self.position] = reference;
+            self.history[self.position + taps] = reference;
+            self.energy = (self.energy + reference * reference - oldest * oldest).max(0f32);
+            if self.energy < SILENT_ENERGY {
+                continue;
+            }
+            let window = &self.history[self.position..self.position + taps];
+            let echo: f32 = self.weights.iter().zip(window).map(|(w, h)| w * h).sum();
+            let error = *sample - echo;
+            let step = STEP * error / (self.energy + REGULARIZATION);
+            for (weight, h) in self.weights.iter_mut().zip(window) {
+                *weight += step * h;
+            }
+            *sample = error;
+        }
+        // Start over rather than diverge
+        if !self.weights.iter().all(|w| w.is_finite()) {
+            self.weights.iter_mut().for_each(|w| *w = 0f32);
+        }
+    }
+}
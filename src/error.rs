@@ -0,0 +1,158 @@
+// The errors reported to the user, with what failed and what to try
+
+// Standard
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    // SDL or one of its subsystems could not start
+    Sdl { subsystem: &'static str, reason: String },
+    // The capture device asked for does not exist
+    Device { device: String, available: Vec<String> },
+    // The capture device could not be opened
+    Capture { driver: String, reason: String },
+    // The capture device runs at a rate under the lowest one of the analysis
+    UnsupportedRate { driver: String, rate: i32, min: i32 },
+    // A window, texture or font of the SDL display could not be created
+    Display { what: String, reason: String },
+    // The chords or tones could not be played back
+    Playback { reason: String },
+    // What was listened for was not heard, already described
+    NotHeard(String),
+    // A recording could not be read
+    Recording { path: String, reason: String },
+    // The recording of the input could not be written
+    Recorder { path: String, reason: String },
+    // A server of the control API or of the remote displays could not listen
+    Listen { service: &'static str, address: String, reason: String },
+    // An option cannot be used as given
+    Option { option: &'static str, reason: String },
+    // Any other failure, already described
+    Other(String),
+}
+
+impl Error {
+    // Whether the audio capture failed, and is worth diagnosing
+    pub fn is_capture(&self) -> bool {
+        match self {
+            Error::Sdl { subsystem: "audio", .. } | Error::Capture { .. } => true,
+            Error::Device { available, .. } => available.is_empty(),
+            _ => false,
+        }
+    }
+
+    // What the user can try, for the common failures
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Error::Sdl { subsystem: "video", .. } => Some(
+                "Without a graphical session, use --terminal or --remote-term instead".to_owned(),
+            ),
+            Error::Sdl { subsystem: "audio", .. } | Error::Playback { .. } => Some(
+                "Pick another driver with --audio-driver, e.g. pulseaudio, pipewire or alsa".to_owned(),
+            ),
+            Error::Capture { driver, reason } => Some(if reason.to_lowercase().contains("format") {
+                format!(
+                    "The {} driver refused mono float samples, try another one with --audio-driver",
+                    driver
+                )
+            } else {
+                format!(
+                    "Check that an input is connected, unmuted and not held by another program, \
+                     or try another driver than {} with --audio-driver",
+                    driver
+                )
+            }),
+            Error::Device { available, .. } if available.is_empty() => Some(
+                "No capture device was found, check the interface is plugged in and powered".to_owned(),
+            ),
+            Error::Device { .. } => Some("Give the index or part of the name of a device above".to_owned()),
+            Error::UnsupportedRate { min, .. } => {
+                Some(format!("Set the input to {} Hz or more in the system sound settings", min))
+            }
+            Error::Display { what, .. } if what.starts_with("font") => Some(
+                "Run ImproVe from its own directory, the font is read from assets/".to_owned(),
+            ),
+            Error::NotHeard(_) => Some(
+                "Check that the input is connected and loud enough, the live display showing its level".to_owned(),
+            ),
+            Error::Recording { reason, .. } if reason.contains("FLAC") => {
+                Some("Convert it to WAV first, ie. with flac -d".to_owned())
+            }
+            Error::Recording { reason, .. } if reason.contains("unsupported") => Some(
+                "Convert it to 16 bits PCM first, ie. with sox FILE -b 16 converted.wav".to_owned(),
+            ),
+            Error::Recorder { .. } => {
+                Some("Check that the disk is not full and that the directory can be written to".to_owned())
+            }
+            Error::Listen { .. } => Some("Another program may use the port, give another one".to_owned()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Sdl { subsystem, reason } => write!(f, "Cannot start the SDL {}: {}", subsystem, reason),
+            Error::Device { device, available } => write!(
+                f,
+                "Capture device '{}' not found, available: {}",
+                device,
+                available
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| format!("{}: {}", i, name))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Error::Capture { driver, reason } => {
+                write!(f, "Cannot open the capture device with the {} driver: {}", driver, reason)
+            }
+            Error::UnsupportedRate { driver, rate, .. } => write!(
+                f,
+                "The {} capture device runs at {} Hz, too low to hear the highest notes",
+                driver, rate
+            ),
+            Error::Display { what, reason } => write!(f, "Cannot create the {}: {}", what, reason),
+            Error::Playback { reason } => write!(f, "Cannot open the playback device: {}", reason),
+            Error::NotHeard(reason) => write!(f, "{}", reason),
+            Error::Recording { path, reason } => write!(f, "Cannot read the recording {}: {}", path, reason),
+            Error::Recorder { path, reason } => write!(f, "Cannot write the recording {}: {}", path, reason),
+            Error::Listen {
+                service,
+                address,
+                reason,
+            } => write!(f, "Cannot listen for the {} on {}: {}", service, address, reason),
+            Error::Option { option, reason } => write!(f, "Option --{}: {}", option, reason),
+            Error::Other(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// The modules describing their failures in a string
+impl From<String> for Error {
+    fn from(reason: String) -> Error {
+        Error::Other(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_hint_names_the_lowest_rate() {
+        let error = Error::UnsupportedRate {
+            driver: "alsa".to_owned(),
+            rate: 8000,
+            min: 16000,
+        };
+        assert!(error.to_string().contains("8000 Hz"));
+        assert_eq!(
+            error.hint().unwrap(),
+            "Set the input to 16000 Hz or more in the system sound settings"
+        );
+    }
+}
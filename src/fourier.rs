@@ -1,5 +1,7 @@
 // Standard
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::vec;
 
 // Tools
@@ -7,13 +9,194 @@ use itertools::Itertools;
 
 // Math
 use rustfft::num_complex::Complex;
-use rustfft::FFTplanner;
+use rustfft::{FFTplanner, FFT};
 
 //Crate
 use crate::audio_buffer::AudioBuffer;
+use crate::classifier::{Classifier, Classify};
+use crate::context::Context;
+use crate::continuo::Continuo;
+use crate::counterpoint::Counterpoint;
 use crate::frequency::Frequency;
-use crate::scores::{ScoreCalculator, Scores};
+use crate::latency::Latency;
+use crate::control::Command;
+use crate::notes::Note;
+use crate::output::Output;
+use crate::pitch::{VoiceTracker, Yin};
+use crate::scores::{Aggregation, Fold, ScoreCalculator, Scores, Style};
+use crate::session::{Onset, Session};
+use crate::spectrum;
+use crate::timeline::Timeline;
 
+// The option changes kept to be undone
+const HISTORY_LENGTH: usize = 100;
+
+// The runtime changes of the options, to undo and redo them or revert to the options at launch
+// Reverting is a change like the others, undone to get back the options before it
+#[derive(Clone, Debug)]
+pub struct History {
+    launch: ScoringOptions,
+    // The options before every change, and after every change undone, the latest last
+    undone: Vec<ScoringOptions>,
+    redone: Vec<ScoringOptions>,
+}
+
+impl History {
+    pub fn new(launch: ScoringOptions) -> History {
+        History {
+            launch,
+            undone: Vec::new(),
+            redone: Vec::new(),
+        }
+    }
+
+    // Apply a command to the options, keeping track of the changes
+    pub fn apply(&mut self, options: &mut ScoringOptions, command: &Command) {
+        let before = *options;
+        match command {
+            Command::Undo => {
+                if let Some(previous) = self.undone.pop() {
+                    self.redone.push(before);
+                    *options = previous;
+                }
+            }
+            Command::Redo => {
+                if let Some(next) = self.redone.pop() {
+                    self.undone.push(before);
+                    *options = next;
+                }
+            }
+            Command::Revert => {
+                self.record(before);
+                *options = self.launch;
+            }
+            Command::NextStyle | Command::SetHalflife(_) => {
+                self.record(before);
+                options.apply(command);
+            }
+            Command::Reset | Command::Annotate(_) => (),
+        }
+    }
+
+    fn record(&mut self, before: ScoringOptions) {
+        if self.undone.len() == HISTORY_LENGTH {
+            self.undone.remove(0);
+        }
+        self.undone.push(before);
+        self.redone.clear();
+    }
+}
+
+// The available spectral analysis backends
+#[derive(Clone, Copy, Debug)]
+pub enum Transform {
+    // A single fourier transform over the whole resolution
+    Fourier,
+    // Shorter transforms for higher registers, for faster response on transients
+    VariableQ,
+    // A bin per third of a semitone, each note over as many of its periods
+    ConstantQ,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::Fourier
+    }
+}
+
+// The window applied to the packets before the transforms
+// Without one, the leakage of a low note smears over the neighbouring semitones
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    // Wide peaks, but the most accurate levels between the bins
+    FlatTop,
+}
+
+impl Default for Window {
+    fn default() -> Window {
+        Window::Hann
+    }
+}
+
+impl Window {
+    pub fn from_name(name: &str) -> Option<Window> {
+        match name {
+            "rectangular" => Some(Window::Rectangular),
+            "hann" => Some(Window::Hann),
+            "hamming" => Some(Window::Hamming),
+            "blackman-harris" => Some(Window::BlackmanHarris),
+            "flat-top" => Some(Window::FlatTop),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Window::Rectangular => "rectangular",
+            Window::Hann => "hann",
+            Window::Hamming => "hamming",
+            Window::BlackmanHarris => "blackman-harris",
+            Window::FlatTop => "flat-top",
+        }
+    }
+
+    // The coefficients of the cosines summed, alternating in sign
+    fn terms(self) -> &'static [f32] {
+        match self {
+            Window::Rectangular => &[1f32],
+            Window::Hann => &[0.5, 0.5],
+            Window::Hamming => &[0.54, 0.46],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Window::FlatTop => &[0.215_578_95, 0.416_631_58, 0.277_263_16, 0.083_578_95, 0.006_947_37],
+        }
+    }
+
+    // The window over a packet, scaled by its mean so the levels of the notes are kept
+    pub fn coefficients(self, len: usize) -> Vec<f32> {
+        let terms = self.terms();
+        (0..len)
+            .map(|i| {
+                let phase = 2f32 * std::f32::consts::PI * i as f32 / len as f32;
+                let sum: f32 = terms
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1f32 } else { -1f32 };
+                        sign * a * (k as f32 * phase).cos()
+                    })
+                    .sum();
+                sum / terms[0]
+            })
+            .collect_vec()
+    }
+}
+
+// The kind of sound being analysed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputType {
+    Instrument,
+    // Strong formants can be mistaken for fundamentals
+    Voice,
+}
+
+impl Default for InputType {
+    fn default() -> InputType {
+        InputType::Instrument
+    }
+}
+
+impl InputType {
+    pub fn name(self) -> &'static str {
+        match self {
+            InputType::Instrument => "instrument",
+            InputType::Voice => "voice",
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct ScoringOptions {
@@ -21,61 +204,883 @@ pub struct ScoringOptions {
     pub frequency: i32,
     // If the data should be padded for more precision
     pub zpadding: u32,
+    // The spectral analysis backend
+    pub transform: Transform,
+    // The window applied before the transforms
+    pub window: Window,
+    // The kind of sound being analysed
+    pub input_type: InputType,
+    // If the single pitch of a melodic line is detected instead of the spectrum
+    pub mono: bool,
+    // Whether the instrument of a new input is recognized, and its input type switched to
+    pub classify: Option<Classify>,
     // The time for the perceived dissonance to drop by half
     pub halflife: f32,
+    // The shorter halflife used right after a harmonic change
+    pub change_halflife: f32,
+    // The pitch class profile distance (0 .. 1) detected as a harmonic change
+    pub change_threshold: f32,
+    // The frequency under which heard components count as bass
+    pub bass_split: f32,
+    // The musical style, setting the bass decay unless overridden
+    pub style: Style,
+    // The bass decay time, relative to the halflife
+    pub bass_decay: f32,
+    // The frequency above which heard components are ignored for scoring
+    pub crossover: Option<f32>,
+    // The lowest key of the right hand, the left hand accompanying it
+    pub split: Option<Note>,
+    // How the scores are shared between octaves
+    pub fold: Fold,
+    // The maximum number of spectral peaks used for scoring
+    pub max_peaks: usize,
+    // The spectral flux ratio over which the analysis rate is boosted
+    pub boost: Option<f32>,
+    // If drum-dominated frames should be ignored
+    pub reject_drums: bool,
+    // The number of spectra the median filter is applied over
+    pub median: usize,
+    // The harmonics of the harmonic product spectrum suppressing the overtones, None without
+    pub hps: Option<usize>,
+    // The silence, in seconds, after which a new session starts
+    pub split_silence: Option<f32>,
+    // The actual rate of the device clock over the nominal one, when calibrated
+    pub calibration: Option<f32>,
+    // How the frames are combined over time
+    pub aggregation: Aggregation,
+    // The part of the dissonance kept at the chord changes of a chart, all of it when None
+    pub chord_reset: Option<f32>,
 }
 
-// Receives audio input, start FFT on most recent data and send results
-pub fn fourier_thread(buffer: AudioBuffer, sender: Sender<Scores>, options:ScoringOptions) {
+impl ScoringOptions {
+    // Apply a runtime command changing the options
+    pub fn apply(&mut self, command: &Command) {
+        match *command {
+            Command::Reset => (),
+            Command::NextStyle => {
+                self.style = self.style.next();
+                self.bass_decay = self.style.bass_decay();
+            }
+            Command::SetHalflife(halflife) => self.halflife = halflife,
+            Command::Annotate(_) | Command::Undo | Command::Redo | Command::Revert => (),
+        }
+    }
+
+    // A line per option, for the help displays
+    pub fn describe(&self) -> Vec<String> {
+        vec![
+            format!("Transform   {:?}", self.transform),
+            format!("Window      {}", self.window.name()),
+            format!("Input       {:?}", self.input_type),
+            format!("Mono        {}", if self.mono { "YIN pitch" } else { "off" }),
+            format!(
+                "Classify    {}",
+                self.classify.map_or("none", Classify::name)
+            ),
+            format!("Halflife    {:.2}s", self.halflife),
+            format!("Aggregation {}", self.aggregation.describe()),
+            format!(
+                "Chord reset {}",
+                self.chord_reset.map_or("none".to_owned(), |k| format!("{:.0}% kept", k * 100f32))
+            ),
+            format!("Style       {}", self.style.name()),
+            format!("Bass decay  {:.2}", self.bass_decay),
+            format!(
+                "Crossover   {}",
+                self.crossover.map_or("none".to_owned(), |c| format!("{:.0}Hz", c))
+            ),
+            format!("Split       {}", self.split.map_or("none".to_owned(), Note::name)),
+            format!("Fold        {:?}", self.fold),
+            format!("Max peaks   {}", self.max_peaks),
+            format!("Median      {}", self.median),
+            format!("HPS         {}", self.hps.map_or("none".to_owned(), |h| format!("{} harmonics", h))),
+        ]
+    }
+}
+
+// How long the analysis rate stays boosted after a large spectral change
+const BOOST_DURATION: Duration = Duration::from_secs(1);
+// The fraction of the resolution between two boosted analyses
+const BOOST_HOP: usize = 4;
+
+// Analyses more often right after large spectral changes, to catch new harmonies quickly
+struct Scheduler {
+    threshold: f32,
+    previous: Vec<f32>,
+    boosted_until: Option<Instant>,
+}
+
+impl Scheduler {
+    fn new(threshold: f32) -> Scheduler {
+        Scheduler {
+            threshold,
+            previous: Vec::new(),
+            boosted_until: None,
+        }
+    }
+
+    // The positive change in amplitude since the last spectrum, relative to its total amplitude
+    fn flux(&mut self, spectrum: &[Frequency]) -> f32 {
+        let amplitudes = spectrum.iter().map(|f| f.amplitude()).collect_vec();
+        let flux = if self.previous.len() == amplitudes.len() {
+            let rise: f32 = amplitudes
+                .iter()
+                .zip(self.previous.iter())
+                .map(|(a, b)| (a - b).max(0f32))
+                .sum();
+            let total: f32 = amplitudes.iter().sum();
+            if total > 0f32 {
+                rise / total
+            } else {
+                0f32
+            }
+        } else {
+            0f32
+        };
+        self.previous = amplitudes;
+        flux
+    }
+
+    // Update the buffer hop depending on the latest spectrum
+    fn schedule(&mut self, spectrum: &[Frequency], buffer: &mut AudioBuffer) {
+        let now = Instant::now();
+        if self.flux(spectrum) > self.threshold {
+            if self.boosted_until.is_none() {
+                buffer.set_hop(Some(buffer.resolution() / BOOST_HOP));
+            }
+            self.boosted_until = Some(now + BOOST_DURATION);
+        } else if self.boosted_until.map_or(false, |t| now > t) {
+            buffer.set_hop(None);
+            self.boosted_until = None;
+        }
+    }
+}
+
+// Turns audio packets into spectra
+// The mask, when given, is a spectrum previously returned by the same analyzer
+pub trait Analyzer {
+    fn analyze(&mut self, vec: &[f32], mask: Option<&[Frequency]>) -> Vec<Frequency>;
+}
+
+// The transform of a packet length, planned once with its window and buffers and reused every frame
+// Even lengths pack the real samples by pairs into a complex transform of half the length, halving
+// the work, the spectrum being untangled from it afterwards
+struct Plan {
+    // The samples windowed, and the length once padded
+    samples: usize,
+    len: usize,
+    window: Vec<f32>,
+    fft: Arc<dyn FFT<f32>>,
+    input: Vec<Complex<f32>>,
+    output: Vec<Complex<f32>>,
+    // The rotation of every bin kept, from the odd samples to the even ones, empty for odd lengths
+    twiddles: Vec<Complex<f32>>,
+    spectrum: Vec<Complex<f32>>,
+}
+
+impl Plan {
+    fn new(planner: &mut FFTplanner<f32>, samples: usize, len: usize, window: Window) -> Plan {
+        let (packed, twiddles) = if len % 2 == 0 {
+            let twiddles = (0..len / 2)
+                .map(|k| {
+                    let phase = -2f32 * std::f32::consts::PI * k as f32 / len as f32;
+                    Complex::new(phase.cos(), phase.sin())
+                })
+                .collect_vec();
+            (len / 2, twiddles)
+        } else {
+            (len, Vec::new())
+        };
+        Plan {
+            samples,
+            len,
+            window: window.coefficients(samples),
+            fft: planner.plan_fft(packed),
+            input: vec![Complex::default(); packed],
+            output: vec![Complex::default(); packed],
+            twiddles,
+            spectrum: Vec::with_capacity(len / 2),
+        }
+    }
+
+    // The bins of the windowed then padded samples up to half the sampling rate, 0hz first
+    fn process(&mut self, vec: &[f32]) -> &[Complex<f32>] {
+        // The transform scrambles its input, which is filled again every time
+        for c in self.input.iter_mut() {
+            *c = Complex::default();
+        }
+        let windowed = vec.iter().zip(self.window.iter()).map(|(&s, w)| s * w);
+        if self.twiddles.is_empty() {
+            for (c, s) in self.input.iter_mut().zip(windowed) {
+                c.re = s;
+            }
+        } else {
+            for (i, s) in windowed.enumerate() {
+                if i % 2 == 0 {
+                    self.input[i / 2].re = s;
+                } else {
+                    self.input[i / 2].im = s;
+                }
+            }
+        }
+        self.fft.process(&mut self.input, &mut self.output);
+        self.spectrum.clear();
+        if self.twiddles.is_empty() {
+            self.spectrum.extend_from_slice(&self.output[..self.len / 2]);
+        } else {
+            // The transforms of the even and odd samples, from the packed one and its mirror
+            let half = self.output.len();
+            for k in 0..half {
+                let packed = self.output[k];
+                let mirror = self.output[(half - k) % half].conj();
+                let even = (packed + mirror) * 0.5;
+                let odd = (packed - mirror) * Complex::new(0f32, -0.5);
+                self.spectrum.push(even + self.twiddles[k] * odd);
+            }
+        }
+        &self.spectrum
+    }
+}
+
+// The plans of the packet lengths analysed, kept across frames
+struct Plans {
     // The FFT pool, allows for optimized yet flexible data sizes
-    let mut planner = FFTplanner::<f32>::new(false);
+    planner: FFTplanner<f32>,
+    plans: Vec<Plan>,
+    window: Window,
+}
+
+impl Plans {
+    fn new(window: Window) -> Plans {
+        Plans {
+            planner: FFTplanner::new(false),
+            plans: Vec::new(),
+            window,
+        }
+    }
+
+    fn get(&mut self, samples: usize, len: usize) -> &mut Plan {
+        match self.plans.iter().position(|p| p.samples == samples && p.len == len) {
+            Some(i) => &mut self.plans[i],
+            None => {
+                let plan = Plan::new(&mut self.planner, samples, len, self.window);
+                self.plans.push(plan);
+                self.plans.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+// A single FFT over the whole packet
+pub struct FourierAnalyzer {
+    plans: Plans,
+    options: ScoringOptions,
+}
+
+impl FourierAnalyzer {
+    pub fn new(options: ScoringOptions) -> FourierAnalyzer {
+        FourierAnalyzer {
+            plans: Plans::new(options.window),
+            options,
+        }
+    }
+}
+
+impl Analyzer for FourierAnalyzer {
+    fn analyze(&mut self, vec: &[f32], mask: Option<&[Frequency]>) -> Vec<Frequency> {
+        fourier_analysis(vec, &mut self.plans, mask, self.options)
+    }
+}
+
+// The number of octave bands of the variable-Q analysis
+const VQ_BANDS: usize = 4;
+// The upper frequency of the lowest band, each band above is an octave higher
+const VQ_FIRST_LIMIT: f32 = 500f32;
+
+// Analyses each register over a different length of the most recent data
+// The low register keeps the full resolution for frequency precision
+// Each band above uses half the data of the previous one, for time precision
+pub struct VariableQAnalyzer {
+    // A plan for every band
+    plans: Plans,
+    options: ScoringOptions,
+}
+
+impl VariableQAnalyzer {
+    pub fn new(options: ScoringOptions) -> VariableQAnalyzer {
+        VariableQAnalyzer {
+            plans: Plans::new(options.window),
+            options,
+        }
+    }
+}
+
+impl Analyzer for VariableQAnalyzer {
+    fn analyze(&mut self, vec: &[f32], mask: Option<&[Frequency]>) -> Vec<Frequency> {
+        let mut spectrum = Vec::new();
+        for band in 0..VQ_BANDS {
+            // Get the most recent data of the band
+            let len = vec.len() >> band;
+            if len < 32 {
+                break;
+            }
+            // Get the frequency range of the band
+            let low = if band == 0 {
+                0f32
+            } else {
+                VQ_FIRST_LIMIT * 2f32.powi(band as i32 - 1)
+            };
+            let high = if band == VQ_BANDS - 1 {
+                std::f32::INFINITY
+            } else {
+                VQ_FIRST_LIMIT * 2f32.powi(band as i32)
+            };
+            // Shorter transforms produce smaller values, compensate
+            let gain = ((vec.len() / len) as f32).powi(2);
+            let fourier = fourier_analysis(&vec[vec.len() - len..], &mut self.plans, None, self.options);
+            spectrum.extend(
+                fourier
+                    .into_iter()
+                    .filter(|f| f.value >= low && f.value < high)
+                    .map(|f| Frequency {
+                        intensity: f.intensity * gain,
+                        value: f.value,
+                    }),
+            );
+        }
+        // Noise masking
+        if let Some(mask) = mask {
+            for (f, m) in spectrum.iter_mut().zip(mask.iter()) {
+                f.intensity = (f.intensity - m.intensity).max(0f32);
+            }
+        }
+        spectrum
+    }
+}
+
+// The bins of the constant-Q analysis per semitone, centered on the notes
+const CQ_BINS_PER_NOTE: usize = 3;
+// The highest frequency analysed, relative to the Nyquist frequency
+const CQ_NYQUIST_MARGIN: f32 = 0.9;
+
+// A bin of the constant-Q analysis
+struct QBin {
+    // The frequency reported, the calibration applied
+    value: f32,
+    // 2cos(w) of the Goertzel recurrence, at the frequency analysed
+    coefficient: f64,
+    // The window of the bin among those of the analyzer, and the gain matching its length to the packet
+    window: usize,
+    gain: f32,
+}
+
+// Analyses every note over a window lasting the same number of its periods, straight into note bins
+// The low register keeps windows as long as the packet for frequency precision, the shorter windows
+// of the high register respond faster on transients
+// Each bin is a single term of a DFT over the most recent data, computed with the Goertzel recurrence
+pub struct ConstantQAnalyzer {
+    options: ScoringOptions,
+    // The packet length the bins were laid out for
+    len: usize,
+    bins: Vec<QBin>,
+    // The windows of every length the bins use
+    windows: Vec<Vec<f32>>,
+}
+
+impl ConstantQAnalyzer {
+    pub fn new(options: ScoringOptions) -> ConstantQAnalyzer {
+        ConstantQAnalyzer {
+            options,
+            len: 0,
+            bins: Vec::new(),
+            windows: Vec::new(),
+        }
+    }
+
+    // Lay the bins out from the lowest note up to the Nyquist frequency, for packets of a length
+    fn layout(&mut self, len: usize) {
+        let rate = self.options.frequency as f32;
+        let calibration = self.options.calibration.unwrap_or(1f32);
+        // The periods of a note in a semitone wide bin
+        let quality = 1f32 / (2f32.powf(1f32 / 12f32) - 1f32);
+        self.len = len;
+        self.bins.clear();
+        self.windows.clear();
+        for note in Note::iter() {
+            for i in 0..CQ_BINS_PER_NOTE {
+                // In semitones around the note
+                let offset = (i as f32 - (CQ_BINS_PER_NOTE - 1) as f32 / 2f32) / CQ_BINS_PER_NOTE as f32;
+                let value = note.freq() * 2f32.powf(offset / 12f32);
+                let analysed = value / calibration;
+                if analysed >= rate / 2f32 * CQ_NYQUIST_MARGIN {
+                    return;
+                }
+                let samples = ((quality * rate / analysed).round() as usize).max(1).min(len);
+                let window = match self.windows.iter().position(|w| w.len() == samples) {
+                    Some(window) => window,
+                    None => {
+                        self.windows.push(self.options.window.coefficients(samples));
+                        self.windows.len() - 1
+                    }
+                };
+                let phase = 2f64 * std::f64::consts::PI * analysed as f64 / rate as f64;
+                self.bins.push(QBin {
+                    value,
+                    coefficient: 2f64 * phase.cos(),
+                    window,
+                    // Shorter windows produce smaller values, compensate
+                    gain: (len as f32 / samples as f32).powi(2),
+                });
+            }
+        }
+    }
+}
+
+impl Analyzer for ConstantQAnalyzer {
+    fn analyze(&mut self, vec: &[f32], mask: Option<&[Frequency]>) -> Vec<Frequency> {
+        if vec.len() != self.len {
+            self.layout(vec.len());
+        }
+        let mut spectrum = self
+            .bins
+            .iter()
+            .map(|bin| {
+                let window = &self.windows[bin.window];
+                let recent = &vec[vec.len() - window.len()..];
+                // In double precision, the recurrence of the low notes being ill-conditioned
+                let (mut s1, mut s2) = (0f64, 0f64);
+                for (&x, &w) in recent.iter().zip(window.iter()) {
+                    let s = (x * w) as f64 + bin.coefficient * s1 - s2;
+                    s2 = s1;
+                    s1 = s;
+                }
+                let power = (s1 * s1 + s2 * s2 - bin.coefficient * s1 * s2).max(0f64) as f32;
+                Frequency {
+                    intensity: power * bin.gain * a_weigh_frequency(bin.value),
+                    value: bin.value,
+                }
+            })
+            .collect_vec();
+        // Noise masking
+        if let Some(mask) = mask {
+            for (f, m) in spectrum.iter_mut().zip(mask.iter()) {
+                f.intensity = (f.intensity - m.intensity).max(0f32);
+            }
+        }
+        spectrum
+    }
+}
+
+// The width of the formant envelope removed from voice input, in hertz
+const VOICE_ENVELOPE_WIDTH: f32 = 300f32;
+// The spectral flatness over which voice input is considered breath noise
+const VOICE_BREATH_FLATNESS: f32 = 0.5;
+
+// Receives audio input, start FFT on most recent data and send results
+pub fn fourier_thread(
+    buffer: AudioBuffer,
+    sender: Sender<Scores>,
+    options: ScoringOptions,
+    sessions: Arc<Mutex<Vec<Session>>>,
+    context: Option<Context>,
+    counterpoint: Option<Counterpoint>,
+    continuo: Option<Continuo>,
+    outputs: Vec<Box<dyn Output>>,
+    commands: Receiver<Command>,
+) {
+    let mut outputs = outputs;
+    let mut options = options;
+    let mut history = History::new(options);
+    // The spectral analysis backend
+    let mut analyzer: Box<dyn Analyzer> = match options.transform {
+        Transform::Fourier => Box::new(FourierAnalyzer::new(options)),
+        Transform::VariableQ => Box::new(VariableQAnalyzer::new(options)),
+        Transform::ConstantQ => Box::new(ConstantQAnalyzer::new(options)),
+    };
     // The audio buffer, to get uniformly-sized audio packets
     let mut buffer = buffer;
 
-    println!("Gathering noise profile and buffering instrument");
+    status!("Gathering noise profile and buffering instrument");
     // Get the first first few seconds of recording
-    let vec = buffer.take().unwrap();
+    // A recording shorter than the resolution ends the analysis there
+    let vec = match buffer.take() {
+        Some(vec) => vec,
+        None => return,
+    };
     // Extract frequencies to serve as mask
-    let fourier = fourier_analysis(&vec[..], &mut planner, None, options);
+    let fourier = analyzer.analyze(&vec[..], None);
     let mask = Some(fourier.as_slice());
-    // Create a dissonance calculator from the frequencies
-    let mut calculator = ScoreCalculator::new(fourier.as_slice());
+    // Create a dissonance calculator
+    let mut calculator = ScoreCalculator::new(options);
+    // Create the temporal smoothing filter
+    let mut median = spectrum::MedianFilter::new(options.median);
+    // Create the analysis rate scheduler
+    let mut scheduler = options.boost.map(Scheduler::new);
+    // Create the drum classifier
+    let mut percussion = spectrum::PercussionDetector::new();
+    // Create the fundamental confirmation stage
+    let mut voice = match options.input_type {
+        InputType::Voice => Some(VoiceTracker::new()),
+        InputType::Instrument => None,
+    };
+    // Create the instrument classifier
+    let mut classifier = options.classify.map(|_| Classifier::new());
+    // Create the pitch detector of the melodic lines, replacing the spectral analysis
+    let mut yin = if options.mono {
+        Some(Yin::new(options.frequency as f32 * options.calibration.unwrap_or(1f32)))
+    } else {
+        None
+    };
+
+    // The annotations waiting for the next scores
+    let mut annotations = Vec::new();
+    // When the input went silent, in seconds into the current session
+    let mut silence_start = None;
+    // The last scores, shown again while the input is idle
+    let mut last: Option<Scores> = None;
+    let mut idle = false;
+    // The chords of the chart set so far
+    let mut chord_changes = context.as_ref().map_or(0, Context::chord_changes);
 
     // Start analysis loop
-    println!("Starting analysis");
+    status!("Starting analysis");
     // While audio buffer can still output data
     while let Some(vec) = buffer.take() {
-        // Apply fft and extract frequencies
-        let fourier = fourier_analysis(&vec[..], &mut planner, mask, options);
+        let taken = Instant::now();
+        // Apply the commands from the displays and the control API
+        if apply_commands(&commands, &mut history, &mut options, &mut calculator, &mut annotations) {
+            median = spectrum::MedianFilter::new(options.median);
+        }
+        // The level captured, before the gain control
+        let input_level = level(&vec) - buffer.gain();
+        // Start a new session once playing resumes after a long silence
+        if let Some(gap) = options.split_silence {
+            split_session(&sessions, input_level < SILENCE_LEVEL, gap, &mut silence_start);
+        }
+        // Pause the analysis while the input stays silent, the displays keeping the last scores
+        if buffer.is_idle() {
+            if !idle {
+                status!("Input idle, analysis paused");
+                idle = true;
+            }
+            if let Some(last) = last.as_ref() {
+                let mut scores = last.clone();
+                scores.idle = true;
+                scores.fourier = Vec::new();
+                scores.onset = None;
+                scores.level = input_level;
+                scores.loudness = buffer.loudness();
+                scores.meter = buffer.meter();
+                scores.input_events = buffer.take_events();
+                // Nothing played against the cantus meanwhile
+                judge(&mut scores, counterpoint.as_ref(), continuo.as_ref(), None);
+                stamp(&mut scores, &buffer, taken);
+                if sender.send(scores).is_err() {
+                    return;
+                }
+            }
+            continue;
+        } else if idle {
+            status!("Input resumed, analysis restarted");
+            calculator.resume();
+            // Maybe someone else playing
+            if let Some(classifier) = classifier.as_mut() {
+                classifier.restart();
+            }
+            idle = false;
+        }
+        // Get the spectrum and its most prominent peaks
+        let (fourier, peaks) = match yin.as_mut() {
+            // The single pitch detected, scored against as it is, nothing heard under the silence level
+            Some(yin) => {
+                let pitch = if input_level < SILENCE_LEVEL { Vec::new() } else { yin.spectrum(&vec) };
+                (pitch.clone(), pitch)
+            }
+            None => {
+                // Apply fft and extract frequencies
+                let fourier = analyzer.analyze(&vec[..], mask);
+                // Listen harder after large changes
+                if let Some(scheduler) = scheduler.as_mut() {
+                    scheduler.schedule(&fourier, &mut buffer);
+                }
+                // Keep drum hits out of the harmonic context
+                if options.reject_drums && percussion.is_percussive(&fourier) {
+                    continue;
+                }
+                // Reject short glitches
+                let fourier = median.filter(fourier);
+                // Keep the fundamentals, not their overtones
+                let fourier = match options.hps {
+                    Some(harmonics) => spectrum::harmonic_product(&fourier, harmonics),
+                    None => fourier,
+                };
+                // Keep only the most prominent peaks
+                let peaks = match voice.as_mut() {
+                    // Confirm the fundamental, suppressing formants and breath noise
+                    Some(voice) => {
+                        if spectrum::flatness(&fourier) > VOICE_BREATH_FLATNESS {
+                            Vec::new()
+                        } else {
+                            let whitened = spectrum::whiten(&fourier, VOICE_ENVELOPE_WIDTH);
+                            let peaks = spectrum::pick_peaks(&whitened, options.max_peaks);
+                            voice.confirm(&whitened, peaks)
+                        }
+                    }
+                    None => spectrum::pick_peaks(&fourier, options.max_peaks),
+                };
+                // Recognize the instrument of a new input, the next frames analysed as it suits it
+                if let Some(instrument) = classifier.as_mut().and_then(|c| c.listen(&fourier, &peaks, input_level)) {
+                    let input_type = instrument.input_type();
+                    if input_type == options.input_type {
+                        status!("Instrument = {}", instrument.name());
+                    } else if options.classify == Some(Classify::Auto) {
+                        status!("Instrument = {}, switching to the {} input type", instrument.name(), input_type.name());
+                        options.input_type = input_type;
+                        voice = match input_type {
+                            InputType::Voice => Some(VoiceTracker::new()),
+                            InputType::Instrument => None,
+                        };
+                    } else {
+                        status!("Instrument = {}, try --input-type {}", instrument.name(), input_type.name());
+                    }
+                }
+                (fourier, peaks)
+            }
+        };
+        // Start the next chord of the chart mostly afresh
+        reset_at_chord_change(&mut calculator, options, context.as_ref(), &mut chord_changes);
         // Calculate dissonance of each note
-        let scores = calculator.calculate(fourier, options.halflife);
-        // Send
+        let mut scores = calculator.calculate(fourier, &peaks, context.as_ref().map(Context::spectrum));
+        scores.instrument = classifier.as_ref().and_then(Classifier::instrument);
+        // Judge the note held against the cantus
+        let note = scores.detail.as_ref().map(|d| d.note);
+        judge(&mut scores, counterpoint.as_ref(), continuo.as_ref(), note);
+        scores.level = input_level;
+        scores.loudness = buffer.loudness();
+        scores.meter = buffer.meter();
+        scores.input_events = buffer.take_events();
+        scores.invalid_samples = buffer.invalid_samples();
+        // Publish, then send to the display
+        publish(&mut scores, &mut annotations, &sessions, &mut outputs);
+        last = Some(scores.clone());
+        stamp(&mut scores, &buffer, taken);
         sender.send(scores).ok();
     }
 }
 
+// Start a new session once playing resumes after a silence longer than the gap, in seconds
+fn split_session(sessions: &Mutex<Vec<Session>>, silent: bool, gap: f32, silence_start: &mut Option<f32>) {
+    let mut sessions = sessions.lock().unwrap();
+    let now = sessions.last().unwrap().duration();
+    if silent {
+        silence_start.get_or_insert(now);
+    } else if let Some(start) = silence_start.take() {
+        if now - start >= gap && !sessions.last().unwrap().is_empty() {
+            let next = sessions.last().unwrap().next();
+            sessions.push(next);
+            status!("Session {} started after {:.0}s of silence", sessions.len(), now - start);
+            if let Some(prompt) = sessions.last().unwrap().prompt() {
+                status!("Prompt = {}", prompt);
+            }
+        }
+    }
+}
+
+// Record the dynamics of the notes played, and the chords
+fn record(scores: &mut Scores, sessions: &Mutex<Vec<Session>>) {
+    let mut sessions = sessions.lock().unwrap();
+    let session = sessions.last_mut().unwrap();
+    scores.prompt = session.prompt().map(str::to_owned);
+    if let Some(note) = scores.onset {
+        session.add_onset(Onset {
+            note,
+            level: scores.level,
+            score: scores.note_scores[note as usize],
+        });
+    }
+    session.add_chord(scores.chord);
+    for text in scores.annotations.iter() {
+        session.add_annotation(text);
+    }
+    for &event in scores.input_events.iter() {
+        session.add_input_event(event);
+    }
+    scores.metrics = session.metrics();
+}
+
+// The period of the analysis of the notes played on MIDI
+const MIDI_PERIOD: Duration = Duration::from_millis(20);
+
+// Score the notes held on a MIDI keyboard instead of the captured audio
+// Their synthetic spectrum replaces the analysed one, without any pitch detection error
+pub fn midi_thread(
+    played: Context,
+    timeline: Timeline,
+    sender: Sender<Scores>,
+    options: ScoringOptions,
+    sessions: Arc<Mutex<Vec<Session>>>,
+    context: Option<Context>,
+    counterpoint: Option<Counterpoint>,
+    continuo: Option<Continuo>,
+    outputs: Vec<Box<dyn Output>>,
+    commands: Receiver<Command>,
+) {
+    let mut outputs = outputs;
+    let mut options = options;
+    let mut history = History::new(options);
+    let mut calculator = ScoreCalculator::new(options);
+    let mut annotations = Vec::new();
+    let mut silence_start = None;
+    let mut chord_changes = context.as_ref().map_or(0, Context::chord_changes);
+    // The session clock, advanced at the analysis rate as no audio is counted
+    timeline.set_rate(options.frequency);
+    let mut last = Instant::now();
+
+    status!("Starting analysis of the MIDI notes");
+    loop {
+        std::thread::sleep(MIDI_PERIOD);
+        let elapsed = last.elapsed();
+        last += elapsed;
+        timeline.advance((elapsed.as_secs_f64() * options.frequency as f64) as usize);
+
+        apply_commands(&commands, &mut history, &mut options, &mut calculator, &mut annotations);
+        let spectrum = played.spectrum();
+        if let Some(gap) = options.split_silence {
+            split_session(&sessions, spectrum.is_empty(), gap, &mut silence_start);
+        }
+        let level = if spectrum.is_empty() { f32::NEG_INFINITY } else { 0f32 };
+        reset_at_chord_change(&mut calculator, options, context.as_ref(), &mut chord_changes);
+        let mut scores = calculator.calculate(spectrum.clone(), &spectrum, context.as_ref().map(Context::spectrum));
+        // Judge the highest note held against the cantus
+        let note = played.notes().into_iter().max_by_key(|&n| n as usize);
+        judge(&mut scores, counterpoint.as_ref(), continuo.as_ref(), note);
+        scores.level = level;
+        publish(&mut scores, &mut annotations, &sessions, &mut outputs);
+        if sender.send(scores).is_err() {
+            return;
+        }
+    }
+}
+
+// Apply the commands from the displays and the control API, true when the scores were reset
+fn apply_commands(
+    commands: &Receiver<Command>,
+    history: &mut History,
+    options: &mut ScoringOptions,
+    calculator: &mut ScoreCalculator,
+    annotations: &mut Vec<String>,
+) -> bool {
+    let mut reset = false;
+    for command in commands.try_iter() {
+        history.apply(options, &command);
+        match command {
+            Command::Reset => {
+                *calculator = ScoreCalculator::new(*options);
+                reset = true;
+            }
+            Command::NextStyle => {
+                calculator.set_bass_decay(options.bass_decay);
+                status!("Style = {}", options.style.name());
+            }
+            Command::SetHalflife(halflife) => calculator.set_halflife(halflife),
+            Command::Annotate(text) => annotations.push(text),
+            Command::Undo | Command::Redo | Command::Revert => restore(calculator, *options),
+        }
+    }
+    reset
+}
+
+// Start the next chord of the chart mostly afresh, when asked to
+fn reset_at_chord_change(
+    calculator: &mut ScoreCalculator,
+    options: ScoringOptions,
+    context: Option<&Context>,
+    chord_changes: &mut usize,
+) {
+    if let (Some(kept), Some(context)) = (options.chord_reset, context) {
+        let changes = context.chord_changes();
+        if changes != *chord_changes {
+            calculator.discount(kept);
+            *chord_changes = changes;
+        }
+    }
+}
+
+// Judge the note held against the cantus, and the bass and its realization against the figures
+// Nothing is played while the input is idle
+fn judge(scores: &mut Scores, counterpoint: Option<&Counterpoint>, continuo: Option<&Continuo>, note: Option<Note>) {
+    if let Some(counterpoint) = counterpoint {
+        counterpoint.listen(note);
+        scores.counterpoint = Some(counterpoint.progress());
+    }
+    if let Some(continuo) = continuo {
+        continuo.listen(if scores.idle { None } else { scores.hands.as_ref() });
+        scores.continuo = Some(continuo.progress());
+    }
+}
+
+// Attach the annotations waiting, record the scores in the session and publish them
+fn publish(
+    scores: &mut Scores,
+    annotations: &mut Vec<String>,
+    sessions: &Mutex<Vec<Session>>,
+    outputs: &mut [Box<dyn Output>],
+) {
+    scores.annotations = std::mem::take(annotations);
+    record(scores, sessions);
+    for output in outputs.iter_mut() {
+        output.publish(scores);
+    }
+}
+
+// Stamp the scores when sent, with when their packet was taken and how long it waited
+fn stamp(scores: &mut Scores, buffer: &AudioBuffer, taken: Instant) {
+    scores.latency = Latency {
+        taken: Some(taken),
+        sent: Some(Instant::now()),
+        buffered: buffer.lag(),
+        queued: buffer.backlog(),
+    };
+}
+
+// Bring the calculator to the options undone, redone or reverted to
+fn restore(calculator: &mut ScoreCalculator, options: ScoringOptions) {
+    calculator.set_halflife(options.halflife);
+    calculator.set_bass_decay(options.bass_decay);
+    status!("Style = {}, halflife = {:.2}s", options.style.name(), options.halflife);
+}
+
+// Send the scores of the first channel with the latest of the others attached
+pub fn combine_channels(first: Receiver<Scores>, others: Vec<Receiver<Scores>>, sender: Sender<Scores>) {
+    std::thread::spawn(move || {
+        let mut latest: Vec<Option<Scores>> = vec![None; others.len()];
+        for mut scores in first.iter() {
+            for (receiver, latest) in others.iter().zip(latest.iter_mut()) {
+                if let Some(newer) = receiver.try_iter().last() {
+                    *latest = Some(newer);
+                }
+            }
+            scores.channels = latest.clone();
+            if sender.send(scores).is_err() {
+                return;
+            }
+        }
+    });
+}
+
 fn fourier_analysis(
     vec: &[f32],
-    planner: &mut FFTplanner<f32>,
+    plans: &mut Plans,
     mask: Option<&[Frequency]>,
     options: ScoringOptions,
 ) -> Vec<Frequency> {
 
-    // Setup fft parameters, possibly padding the input array
+    // Setup fft parameters, windowing then possibly padding the input array
     let len = vec.len() * options.zpadding as usize;
-    let mut fft_in = vec
-        .iter()
-        .map(|&f| Complex { re: f, im: 0f32 })
-        .collect_vec();
-    fft_in.resize(len, Complex::default());
-    let mut fft_out = vec![Complex::default(); len];
-    let fft = planner.plan_fft(len);
 
-    // Process fft
-    fft.process(&mut fft_in, &mut fft_out);
+    // Process fft, up to half the sampling rate
+    let fft_out = plans.get(vec.len(), len).process(vec);
 
-    // Discard useless data
-    fft_out.truncate(len / 2);
     // Map results to frequencies and intensity, skipping the first element (0hz)
     fft_out
         .iter()
@@ -86,7 +1091,7 @@ fn fourier_analysis(
             // FACTOR A norm_sqr vs sqr ?
             let mut intensity = c.norm_sqr();
             // Calculate frequency
-            let frequency = i as f32 / len as f32 * options.frequency as f32;
+            let frequency = i as f32 / len as f32 * options.frequency as f32 * options.calibration.unwrap_or(1f32);
             // Noise masking, currently unused
             if let Some(vec) = mask {
                 if intensity > vec[i - 1].intensity {
@@ -107,6 +1112,15 @@ fn fourier_analysis(
         .collect_vec()
 }
 
+// The level under which the input counts as silent, in decibels
+const SILENCE_LEVEL: f32 = -50f32;
+
+// The RMS level of an audio packet, in decibels
+pub fn level(vec: &[f32]) -> f32 {
+    let mean = vec.iter().map(|s| s * s).sum::<f32>() / vec.len().max(1) as f32;
+    10f32 * mean.log10()
+}
+
 // https://fr.mathworks.com/matlabcentral/fileexchange/46819-a-weighting-filter-with-matlab
 // Reduce frequency intensity based on human perception
 pub fn a_weigh_frequency(freq: f32) -> f32 {
@@ -120,3 +1134,43 @@ pub fn a_weigh_frequency(freq: f32) -> f32 {
     let den = (freq + c2) * ((freq + c3) * (freq + c4)).sqrt() * (freq + c1);
     1.2589f32 * num / den
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The first half of the bins of the samples padded to the length, computed directly
+    fn dft(samples: &[f32], len: usize) -> Vec<Complex<f32>> {
+        (0..len / 2)
+            .map(|k| {
+                samples
+                    .iter()
+                    .enumerate()
+                    .map(|(n, &s)| {
+                        let phase = -2f32 * std::f32::consts::PI * (k * n) as f32 / len as f32;
+                        Complex::new(s * phase.cos(), s * phase.sin())
+                    })
+                    .fold(Complex::default(), |a, b| a + b)
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn packed_transform_matches_the_direct_one() {
+        let mut planner = FFTplanner::new(false);
+        let samples = (0..24).map(|i| ((i * 7 % 11) as f32 - 5f32) / 5f32).collect_vec();
+        // Packed by pairs, padded or not, then an odd length which is not packed
+        for &len in [24, 32, 27].iter() {
+            let mut plan = Plan::new(&mut planner, samples.len(), len, Window::Rectangular);
+            assert_eq!(plan.twiddles.is_empty(), len % 2 == 1);
+            // Reused, as every frame
+            plan.process(&samples);
+            let spectrum = plan.process(&samples).to_vec();
+            let expected = dft(&samples, len);
+            assert_eq!(spectrum.len(), expected.len());
+            for (bin, expected) in spectrum.iter().zip(expected.iter()) {
+                assert!((bin - expected).norm() < 1e-3, "{} != {}", bin, expected);
+            }
+        }
+    }
+}
@@ -5,7 +5,6 @@ pub struct Frequency {
 }
 
 impl Frequency {
-    #[allow(dead_code)]
     pub fn amplitude(self) -> f32 {
         self.intensity.sqrt()
     }
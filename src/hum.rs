@@ -0,0 +1,97 @@
+// The hum query mode, tells which notes were hummed and their likely key
+
+// Standard
+use std::time::{Duration, Instant};
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::audio_buffer::AudioBuffer;
+use crate::display::Notation;
+use crate::error::Error;
+use crate::fourier::{Analyzer, FourierAnalyzer, ScoringOptions};
+use crate::keys;
+use crate::notes::Note;
+use crate::pitch::Cepstrum;
+
+// The number of consecutive frames a pitch must last to count as a note
+const MIN_NOTE_FRAMES: usize = 2;
+// The number of candidate keys reported
+const KEY_CANDIDATES: usize = 3;
+
+// Listen for the given duration, then print the hummed notes and key candidates
+pub fn hum(
+    buffer: AudioBuffer,
+    options: ScoringOptions,
+    notation: Notation,
+    duration: Duration,
+) -> Result<(), Error> {
+    let mut buffer = buffer;
+    let mut analyzer = FourierAnalyzer::new(options);
+    let mut cepstrum = Cepstrum::new();
+
+    status!("Listening for {} seconds, hum away", duration.as_secs());
+    let start = Instant::now();
+    // The detected note of every frame
+    let mut frames = Vec::new();
+    while start.elapsed() < duration {
+        let vec = match buffer.take() {
+            Some(vec) => vec,
+            None => break,
+        };
+        let fourier = analyzer.analyze(&vec[..], None);
+        frames.push(cepstrum.fundamental(&fourier).and_then(Note::from_freq));
+    }
+
+    // Group identical consecutive frames into notes, ignoring the shortest
+    let notes = frames
+        .into_iter()
+        .group_by(|&note| note)
+        .into_iter()
+        .filter_map(|(note, group)| Some((note?, group.count())))
+        .filter(|&(_, count)| count >= MIN_NOTE_FRAMES)
+        .collect_vec();
+
+    if notes.is_empty() {
+        return Err(Error::NotHeard("No notes were heard".to_owned()));
+    }
+
+    // Weigh each pitch class by how long it was hummed
+    let mut profile = [0f32; 12];
+    for &(note, count) in notes.iter() {
+        profile[note.get_octave_index() as usize] += count as f32;
+    }
+
+    status!(
+        "Notes: {}",
+        notes
+            .iter()
+            .map(|&(note, _)| notation.get_name(note).trim().to_owned())
+            .join(" ")
+    );
+
+    let candidates = keys::candidates(&profile);
+    let (best, _) = candidates[0];
+    let scale = best.scale();
+    status!(
+        "Implied scale: {} ({})",
+        scale
+            .iter()
+            .map(|&pitch| notation.get_names()[pitch].trim())
+            .join(" "),
+        best.name(notation)
+    );
+    status!(
+        "Outside the scale: {}",
+        (0..12)
+            .filter(|&pitch| profile[pitch] > 0f32 && !scale.contains(&pitch))
+            .map(|pitch| notation.get_names()[pitch].trim())
+            .join(" ")
+    );
+    status!("Candidate keys:");
+    for (key, correlation) in candidates.into_iter().take(KEY_CANDIDATES) {
+        status!("  {:<10} ({:.2})", key.name(notation), correlation);
+    }
+    Ok(())
+}
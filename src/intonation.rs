@@ -0,0 +1,54 @@
+// Tracks how far played pitches are from their tempered value over a session
+
+use crate::frequency::Frequency;
+use crate::notes::{Note, NOTE_COUNT};
+
+// Peaks weaker than this fraction of the strongest peak are not counted as played
+const MIN_RELATIVE_AMPLITUDE: f32 = 0.1;
+
+pub struct Intonation {
+    // The amplitude-weighted sum of the deviations of each note, in cents
+    sums: [f32; NOTE_COUNT],
+    // The sum of the amplitudes of each note
+    weights: [f32; NOTE_COUNT],
+}
+
+impl Intonation {
+    pub fn new() -> Intonation {
+        Intonation {
+            sums: [0f32; NOTE_COUNT],
+            weights: [0f32; NOTE_COUNT],
+        }
+    }
+
+    // The deviation of a frequency from its closest note, in cents
+    pub fn cents(f: f32, note: Note) -> f32 {
+        1200f32 * (f / note.freq()).log2()
+    }
+
+    // Add the deviations of the strongest peaks
+    pub fn update(&mut self, peaks: &[Frequency]) {
+        let max = peaks.iter().map(|f| f.amplitude()).fold(0f32, f32::max);
+        for f in peaks {
+            let amplitude = f.amplitude();
+            if amplitude < max * MIN_RELATIVE_AMPLITUDE {
+                continue;
+            }
+            if let Some(note) = Note::from_freq(f.value) {
+                self.sums[note as usize] += Self::cents(f.value, note) * amplitude;
+                self.weights[note as usize] += amplitude;
+            }
+        }
+    }
+
+    // The average deviation of every note played during the session, in cents
+    pub fn deviations(&self) -> [Option<f32>; NOTE_COUNT] {
+        let mut deviations = [None; NOTE_COUNT];
+        for (i, d) in deviations.iter_mut().enumerate() {
+            if self.weights[i] > 0f32 {
+                *d = Some(self.sums[i] / self.weights[i]);
+            }
+        }
+        deviations
+    }
+}
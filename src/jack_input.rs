@@ -0,0 +1,81 @@
+// Capture as a JACK client, the input port being patched from anything in the graph
+
+// Tools
+use jack::{AsyncClient, AudioIn, Client, ClientOptions, Control, Port, ProcessScope};
+
+// Crate
+use crate::error::Error;
+use crate::ring::{self, Consumer, Producer};
+use crate::timeline::Timeline;
+
+// The process callback, writing the port buffers to a ring like the SDL recorder
+pub struct JackRecorder {
+    port: Port<AudioIn>,
+    producer: Producer,
+    timeline: Timeline,
+}
+
+impl jack::ProcessHandler for JackRecorder {
+    fn process(&mut self, _: &Client, scope: &ProcessScope) -> Control {
+        let input = self.port.as_slice(scope);
+        self.timeline.advance(input.len());
+        self.producer.write(input.iter().cloned());
+        Control::Continue
+    }
+}
+
+// The active client, capturing until dropped
+pub struct JackInput {
+    _client: AsyncClient<(), JackRecorder>,
+    // The full name of the input port, ie. ImproVe:input
+    pub port: String,
+    pub rate: i32,
+    pub samples: u32,
+}
+
+fn capture_error(reason: jack::Error) -> Error {
+    Error::Capture {
+        driver: "jack".to_owned(),
+        reason: reason.to_string(),
+    }
+}
+
+// Register the client and its input port, connected to a source port if given
+// The samples are read from the ring returned
+pub fn open(
+    client_name: &str,
+    port_name: &str,
+    source: Option<&str>,
+    timeline: Timeline,
+) -> Result<(JackInput, Consumer), Error> {
+    let (client, _) =
+        Client::new(client_name, ClientOptions::NO_START_SERVER).map_err(capture_error)?;
+    let port = client
+        .register_port(port_name, AudioIn::default())
+        .map_err(capture_error)?;
+    let full_name = port.name().map_err(capture_error)?;
+    let rate = client.sample_rate() as i32;
+    let samples = client.buffer_size();
+    let (producer, consumer) = ring::for_rate(rate);
+    let recorder = JackRecorder {
+        port,
+        producer,
+        timeline,
+    };
+    let client = client.activate_async((), recorder).map_err(capture_error)?;
+    if let Some(source) = source {
+        client
+            .as_client()
+            .connect_ports_by_name(source, &full_name)
+            .map_err(capture_error)?;
+    }
+    Ok((
+        JackInput {
+            _client: client,
+            port: full_name,
+            rate,
+            samples,
+        },
+        consumer,
+    ))
+}
@@ -0,0 +1,133 @@
+// Writes every analysed frame as a line of JSON, for scripts and automated checks of the whole chain
+// The messages follow the versioned schema of the widget protocol, read by the client crate
+// {"v":1,"type":"hello","engine":"improve","engine_version":"0.1.0","first_note":"C0","notes":120}
+// {"v":1,"type":"frame","time":1.25,"chord":"Cm","confidence":0.91,"level":-18.2,"loudness":-20.5,
+//  "warmup":1,"onset":"C4","annotations":[],"events":[],"prompt":null,
+//  "metrics":{"entropy":2.8,"chord_rate":12,"tension_variance":0.04},"scores":[...]}
+// Values that are not finite, like the level of silence, are written as null
+// Fields may be added within a version, removing or changing one increments it
+
+// Standard
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// Crate
+use crate::display::Notation;
+use crate::notes::{Note, NOTE_COUNT};
+use crate::output::Output;
+use crate::scores::Scores;
+use crate::timeline::Timeline;
+
+// The version of the message schema
+pub const SCHEMA_VERSION: u32 = 1;
+
+fn number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_owned()
+    }
+}
+
+fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn list(values: Vec<String>) -> String {
+    format!("[{}]", values.join(","))
+}
+
+// The first message, describing the engine and the notes scored
+pub fn hello() -> String {
+    format!(
+        "{{\"v\":{},\"type\":\"hello\",\"engine\":\"improve\",\"engine_version\":{},\"first_note\":{},\"notes\":{}}}",
+        SCHEMA_VERSION,
+        string(env!("CARGO_PKG_VERSION")),
+        string(&Note::C0.name()),
+        NOTE_COUNT
+    )
+}
+
+// The message of an analysed frame, at the given time in seconds
+pub fn frame(scores: &Scores, time: f32, notation: Notation) -> String {
+    let chord = scores.chord.map_or("null".to_owned(), |c| string(&c.name(notation)));
+    let onset = scores.onset.map_or("null".to_owned(), |n| string(&n.name()));
+    let loudness = scores.loudness.map_or("null".to_owned(), number);
+    let prompt = scores.prompt.as_ref().map_or("null".to_owned(), |p| string(p));
+    let annotations = scores.annotations.iter().map(|a| string(a)).collect();
+    let events = scores.input_events.iter().map(|e| string(&e.describe())).collect();
+    let note_scores = scores.note_scores.iter().map(|&s| number(s)).collect();
+    let metrics = scores.metrics.map_or("null".to_owned(), |m| {
+        format!(
+            "{{\"entropy\":{},\"chord_rate\":{},\"tension_variance\":{}}}",
+            number(m.entropy),
+            number(m.chord_rate),
+            number(m.tension_variance)
+        )
+    });
+    format!(
+        "{{\"v\":{},\"type\":\"frame\",\"time\":{},\"chord\":{},\"confidence\":{},\"level\":{},\"loudness\":{},\"warmup\":{},\"onset\":{},\"annotations\":{},\"events\":{},\"prompt\":{},\"metrics\":{},\"scores\":{}}}",
+        SCHEMA_VERSION,
+        number(time),
+        chord,
+        number(scores.confidence),
+        number(scores.level),
+        loudness,
+        number(scores.warmup),
+        onset,
+        list(annotations),
+        list(events),
+        prompt,
+        metrics,
+        list(note_scores)
+    )
+}
+
+pub struct JsonWriter {
+    writer: Box<dyn Write + Send>,
+    timeline: Timeline,
+    notation: Notation,
+}
+
+impl JsonWriter {
+    // Write to a file, or to the standard output for '-'
+    pub fn create(path: &str, timeline: Timeline, notation: Notation) -> Result<JsonWriter, String> {
+        let writer: Box<dyn Write + Send> = if path == "-" {
+            Box::new(std::io::stdout())
+        } else {
+            let file = File::create(path).map_err(|e| format!("Cannot create {}: {}", path, e))?;
+            Box::new(BufWriter::new(file))
+        };
+        let mut writer = writer;
+        writeln!(writer, "{}", hello()).map_err(|e| format!("Cannot write to {}: {}", path, e))?;
+        Ok(JsonWriter {
+            writer,
+            timeline,
+            notation,
+        })
+    }
+}
+
+impl Output for JsonWriter {
+    fn publish(&mut self, scores: &Scores) {
+        let line = frame(scores, self.timeline.seconds() as f32, self.notation);
+        writeln!(self.writer, "{}", line).ok();
+    }
+}
+
+impl Drop for JsonWriter {
+    fn drop(&mut self) {
+        self.writer.flush().ok();
+    }
+}
@@ -0,0 +1,183 @@
+// The keyboard bindings, shared by the displays for dispatch and help
+// Remapped in the keys section of the config, one binding per line:
+//   h = help       the h key shows the help
+//   f1 = help      an action can have several keys
+// The actions remapped lose their default keys, and a key can only trigger one action
+
+// Crate
+use crate::config::Config;
+use crate::control::Action;
+
+// A key, named the same way by every display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    // A printable key, lowercase
+    Char(char),
+    Escape,
+    PageUp,
+    PageDown,
+    F(u8),
+}
+
+impl Key {
+    pub fn name(self) -> String {
+        match self {
+            Key::Char(' ') => "space".to_owned(),
+            Key::Char(c) => c.to_string(),
+            Key::Escape => "escape".to_owned(),
+            Key::PageUp => "pageup".to_owned(),
+            Key::PageDown => "pagedown".to_owned(),
+            Key::F(n) => format!("f{}", n),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Key> {
+        let name = name.to_lowercase();
+        match name.as_str() {
+            "space" => Some(Key::Char(' ')),
+            "escape" => Some(Key::Escape),
+            "pageup" => Some(Key::PageUp),
+            "pagedown" => Some(Key::PageDown),
+            _ if name.starts_with('f') && name.len() > 1 => match name[1..].parse::<u8>() {
+                Ok(n @ 1..=12) => Some(Key::F(n)),
+                _ => None,
+            },
+            _ => {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_graphic() => Some(Key::Char(c)),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap {
+            bindings: vec![
+                (Key::Escape, Action::Quit),
+                (Key::Char('p'), Action::PlayChord),
+                (Key::Char('i'), Action::Intonation),
+                (Key::Char('d'), Action::Detail),
+                (Key::PageDown, Action::NextSong),
+                (Key::PageUp, Action::PreviousSong),
+                (Key::Char('n'), Action::NextChord),
+                (Key::Char('r'), Action::Reset),
+                (Key::Char('f'), Action::Freeze),
+                (Key::Char('s'), Action::NextStyle),
+                (Key::Char('c'), Action::Scaling),
+                (Key::Char('h'), Action::Help),
+                (Key::Char('u'), Action::Undo),
+                (Key::Char('y'), Action::Redo),
+                (Key::Char('l'), Action::Revert),
+            ],
+        }
+    }
+}
+
+impl Keymap {
+    // The bindings of the config over the default ones
+    pub fn from_config(config: &Config) -> Result<Keymap, String> {
+        let mut configured: Vec<(usize, Key, Action)> = Vec::new();
+        for (number, line) in config.section("keys") {
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap().trim();
+            let name = parts.next().unwrap_or("").trim();
+            let key = Key::from_name(key)
+                .ok_or_else(|| config.error(number, &format!("unknown key '{}'", key)))?;
+            let action = Action::from_name(name)
+                .ok_or_else(|| config.error(number, &format!("unknown action '{}'", name)))?;
+            if let Some(&(other, _, bound)) = configured.iter().find(|(_, k, _)| *k == key) {
+                return Err(config.error(
+                    number,
+                    &format!(
+                        "'{}' is already bound to {} on line {}",
+                        key.name(),
+                        bound.name(),
+                        other + 1
+                    ),
+                ));
+            }
+            configured.push((number, key, action));
+        }
+
+        // Keep the default keys of the actions not remapped, unless they are taken
+        let mut keymap = Keymap::default();
+        keymap
+            .bindings
+            .retain(|(_, action)| !configured.iter().any(|(_, _, a)| a == action));
+        for (number, key, action) in configured {
+            if let Some(bound) = keymap.action(key) {
+                return Err(config.error(
+                    number,
+                    &format!(
+                        "'{}' is the default key of {}, which needs another key first",
+                        key.name(),
+                        bound.name()
+                    ),
+                ));
+            }
+            keymap.bindings.push((key, action));
+        }
+        Ok(keymap)
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|&(_, action)| action)
+    }
+
+    // A line per binding, for the actions a display supports
+    pub fn help(&self, supported: &[Action]) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, action)| supported.contains(action))
+            .map(|(key, action)| format!("{:<9} {}", key.name(), action.description()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(text: &str) -> Result<Keymap, String> {
+        Keymap::from_config(&Config::parse("test.conf", text)?)
+    }
+
+    #[test]
+    fn remapped_action_loses_its_default_key() {
+        let keymap = keymap("[keys]\nf1 = help\nH = freeze\n").unwrap();
+        assert_eq!(keymap.action(Key::F(1)), Some(Action::Help));
+        assert_eq!(keymap.action(Key::Char('h')), Some(Action::Freeze));
+        assert_eq!(keymap.action(Key::Char('f')), None);
+        assert_eq!(keymap.action(Key::Escape), Some(Action::Quit));
+        assert_eq!(keymap.help(&[Action::Help]), vec![format!("{:<9} {}", "f1", Action::Help.description())]);
+    }
+
+    #[test]
+    fn conflicts_are_refused() {
+        assert_eq!(
+            keymap("[keys]\nf1 = help\nf1 = quit\n").unwrap_err(),
+            "test.conf:3: 'f1' is already bound to help on line 2"
+        );
+        assert_eq!(
+            keymap("[keys]\np = help\n").unwrap_err(),
+            format!(
+                "test.conf:2: 'p' is the default key of {}, which needs another key first",
+                Action::PlayChord.name()
+            )
+        );
+        assert!(keymap("[keys]\nf13 = help\n").unwrap_err().contains("unknown key 'f13'"));
+        assert!(keymap("[keys]\nx = dance\n").unwrap_err().contains("unknown action 'dance'"));
+    }
+}
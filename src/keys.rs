@@ -0,0 +1,145 @@
+// Key detection from pitch class profiles
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::chords::{self, Chord, Quality, Symbols};
+use crate::display::Notation;
+use crate::notes::NOTE_COUNT;
+
+// Krumhansl-Kessler key profiles, starting from the tonic
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+// The degrees of the major and natural minor scales
+const MAJOR_SCALE: [usize; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MINOR_SCALE: [usize; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+// How much of the key profile is kept every frame
+const KEY_MEMORY: f32 = 0.995;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Key {
+    // The pitch class of the tonic, 0 being C
+    pub root: usize,
+    pub mode: Mode,
+}
+
+impl Key {
+    // The pitch classes of the key's scale
+    pub fn scale(self) -> [usize; 7] {
+        let degrees = match self.mode {
+            Mode::Major => MAJOR_SCALE,
+            Mode::Minor => MINOR_SCALE,
+        };
+        let mut scale = [0; 7];
+        for (pitch, degree) in scale.iter_mut().zip(degrees.iter()) {
+            *pitch = (self.root + degree) % 12;
+        }
+        scale
+    }
+
+    // Parse a key (ie. Bb, F#m, Eb minor, G major), its root written in a convention, ie. H minor in German
+    pub fn parse(name: &str, symbols: Symbols) -> Option<Key> {
+        let mut words = name.split_whitespace();
+        let chord = Chord::parse(words.next()?, symbols)?;
+        let mode = match (words.next(), chord.quality) {
+            (Some("minor"), _) | (None, Quality::Minor) => Mode::Minor,
+            (Some("major"), _) | (None, Quality::Major) => Mode::Major,
+            _ => return None,
+        };
+        Some(Key {
+            root: chord.root,
+            mode,
+        })
+    }
+
+    // The same key, moved by a number of semitones
+    pub fn transpose(self, semitones: i32) -> Key {
+        Key {
+            root: (self.root as i32 + semitones).rem_euclid(12) as usize,
+            mode: self.mode,
+        }
+    }
+
+    pub fn name(self, notation: Notation) -> String {
+        let root = notation.root_name(self.root);
+        match self.mode {
+            Mode::Major => format!("{} major", root),
+            Mode::Minor => format!("{} minor", root),
+        }
+    }
+
+    // The correlation between a pitch class profile and the key's profile
+    fn correlation(self, profile: &[f32; 12]) -> f32 {
+        let reference = match self.mode {
+            Mode::Major => MAJOR_PROFILE,
+            Mode::Minor => MINOR_PROFILE,
+        };
+        let reference = (0..12)
+            .map(|i| reference[(i + 12 - self.root) % 12])
+            .collect_vec();
+        let mean = |v: &[f32]| v.iter().sum::<f32>() / v.len() as f32;
+        let (mean_p, mean_r) = (mean(profile), mean(&reference));
+        let mut cov = 0f32;
+        let mut var_p = 0f32;
+        let mut var_r = 0f32;
+        for (p, r) in profile.iter().zip(reference.iter()) {
+            cov += (p - mean_p) * (r - mean_r);
+            var_p += (p - mean_p).powi(2);
+            var_r += (r - mean_r).powi(2);
+        }
+        if var_p > 0f32 {
+            cov / (var_p * var_r).sqrt()
+        } else {
+            0f32
+        }
+    }
+}
+
+// Every key, ordered from the most to the least likely for a pitch class profile
+pub fn candidates(profile: &[f32; 12]) -> Vec<(Key, f32)> {
+    (0..12)
+        .cartesian_product([Mode::Major, Mode::Minor].iter())
+        .map(|(root, &mode)| {
+            let key = Key { root, mode };
+            (key, key.correlation(profile))
+        })
+        .sorted_by(|a, b| b.1.partial_cmp(&a.1).unwrap())
+        .collect_vec()
+}
+
+// Follows the key of the notes heard, over a longer period than the chords
+pub struct KeyTracker {
+    // The decaying pitch class profile
+    profile: [f32; 12],
+}
+
+impl KeyTracker {
+    pub fn new() -> KeyTracker {
+        KeyTracker { profile: [0f32; 12] }
+    }
+
+    // The most likely key so far, adding the values of the current frame
+    pub fn update(&mut self, note_values: &[f32; NOTE_COUNT]) -> Option<Key> {
+        for (profile, value) in self.profile.iter_mut().zip(chords::chroma(note_values).iter()) {
+            *profile = *profile * KEY_MEMORY + value;
+        }
+        if self.profile.iter().sum::<f32>() > 0f32 {
+            candidates(&self.profile).first().map(|&(key, _)| key)
+        } else {
+            None
+        }
+    }
+}
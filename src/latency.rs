@@ -0,0 +1,222 @@
+// Measures how late the displays show the audio, from the capture to the frame drawn
+//   buffer: how long the newest sample analysed waited in the audio buffer, behind the samples before it
+//   analysis: from the packet taken from the buffer to its scores sent
+//   queue: from the scores sent to their receipt by the display, the channel backing up under load
+//   drawing: from the receipt of the scores to the frame drawn
+// The scores a frame waited behind are counted from the receipts of the display, so the analysis
+// and the display share nothing but the times sent with the scores
+// The averages and the worst frame are reported every second, and every frame can be logged
+
+// Standard
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+
+// How often the latency is reported
+const REPORT_PERIOD: Duration = Duration::from_secs(1);
+// The receipts kept to count the scores queued, far longer than the channel ever backs up
+const RECEIPTS_KEPT: Duration = Duration::from_secs(5);
+
+// The times sent with the scores, the mirrored ones and the MIDI ones having none
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Latency {
+    // When the packet analysed was taken from the buffer, and when its scores were sent
+    pub taken: Option<Instant>,
+    pub sent: Option<Instant>,
+    // How long the newest sample of the packet waited in the buffer, in seconds
+    pub buffered: f32,
+    // The samples received behind the packet, waiting for the next ones
+    pub queued: usize,
+}
+
+// The latency of a frame drawn, in seconds
+#[derive(Clone, Copy, Debug)]
+struct Frame {
+    buffer: f32,
+    analysis: f32,
+    queue: f32,
+    drawing: f32,
+    samples: usize,
+    scores: usize,
+}
+
+impl Frame {
+    fn total(&self) -> f32 {
+        self.buffer + self.analysis + self.queue + self.drawing
+    }
+}
+
+// The frames of the last report period
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Report {
+    // The average of each stage, and the worst total, in milliseconds
+    pub buffer: f32,
+    pub analysis: f32,
+    pub queue: f32,
+    pub drawing: f32,
+    pub worst: f32,
+    // The most samples and scores queued
+    pub samples: usize,
+    pub scores: usize,
+}
+
+impl Report {
+    pub fn total(&self) -> f32 {
+        self.buffer + self.analysis + self.queue + self.drawing
+    }
+
+    // ie. 42 ms, worst 60 ms (buffer 20, analysis 5, queue 2, drawing 15), queued 512 samples, 0 scores
+    pub fn describe(&self) -> String {
+        format!(
+            "{:.0} ms, worst {:.0} ms (buffer {:.0}, analysis {:.0}, queue {:.0}, drawing {:.0}), \
+             queued {} samples, {} scores",
+            self.total(),
+            self.worst,
+            self.buffer,
+            self.analysis,
+            self.queue,
+            self.drawing,
+            self.samples,
+            self.scores
+        )
+    }
+}
+
+pub struct Monitor {
+    // When the display started, the times of the log being from it
+    start: Instant,
+    // When the last scores were received
+    receipts: VecDeque<Instant>,
+    // The latency of the scores received, until their frame is drawn, and when they were received
+    pending: Option<(Latency, Instant, usize)>,
+    // The frames drawn since the last report, and when it was made
+    frames: Vec<Frame>,
+    reported: Instant,
+    report: Option<Report>,
+    log: Option<BufWriter<File>>,
+}
+
+impl Monitor {
+    // Log every frame as CSV to the file, when given
+    pub fn new(log: Option<&str>) -> Result<Monitor, String> {
+        let log = match log {
+            Some(path) => {
+                let mut log = BufWriter::new(
+                    File::create(path).map_err(|e| format!("Cannot create {}: {}", path, e))?,
+                );
+                writeln!(
+                    log,
+                    "time,buffer_ms,analysis_ms,queue_ms,drawing_ms,total_ms,queued_samples,queued_scores"
+                )
+                .map_err(|e| format!("Cannot write {}: {}", path, e))?;
+                Some(log)
+            }
+            None => None,
+        };
+        Ok(Monitor {
+            start: Instant::now(),
+            receipts: VecDeque::new(),
+            pending: None,
+            frames: Vec::new(),
+            reported: Instant::now(),
+            report: None,
+            log,
+        })
+    }
+
+    // Count the scores received, the latest one being drawn next
+    pub fn receive(&mut self, latency: Latency) {
+        let now = Instant::now();
+        while self
+            .receipts
+            .front()
+            .map_or(false, |&r| now - r > RECEIPTS_KEPT)
+        {
+            self.receipts.pop_front();
+        }
+        // The scores received while these waited in the channel were ahead of them
+        let ahead = match latency.sent {
+            Some(sent) => self
+                .receipts
+                .iter()
+                .rev()
+                .take_while(|&&r| r > sent)
+                .count(),
+            None => 0,
+        };
+        self.receipts.push_back(now);
+        self.pending = Some((latency, now, ahead));
+    }
+
+    // Measure the frame of the latest scores received, once drawn
+    pub fn drawn(&mut self) {
+        let now = Instant::now();
+        if let Some((latency, received, ahead)) = self.pending.take() {
+            if let (Some(taken), Some(sent)) = (latency.taken, latency.sent) {
+                let frame = Frame {
+                    buffer: latency.buffered,
+                    analysis: (sent - taken).as_secs_f32(),
+                    queue: (received.max(sent) - sent).as_secs_f32(),
+                    drawing: (now - received).as_secs_f32(),
+                    samples: latency.queued,
+                    scores: ahead,
+                };
+                self.log(&frame);
+                self.frames.push(frame);
+            }
+        }
+        if now - self.reported >= REPORT_PERIOD {
+            self.reported = now;
+            self.report = report(&self.frames);
+            self.frames.clear();
+        }
+    }
+
+    // The latency over the last period, None without frames measured
+    pub fn report(&self) -> Option<Report> {
+        self.report
+    }
+
+    fn log(&mut self, frame: &Frame) {
+        let time = self.start.elapsed().as_secs_f32();
+        let written = self.log.as_mut().map(|log| {
+            writeln!(
+                log,
+                "{:.3},{:.2},{:.2},{:.2},{:.2},{:.2},{},{}",
+                time,
+                frame.buffer * 1000f32,
+                frame.analysis * 1000f32,
+                frame.queue * 1000f32,
+                frame.drawing * 1000f32,
+                frame.total() * 1000f32,
+                frame.samples,
+                frame.scores
+            )
+        });
+        // A failing disk stops the log, not the display
+        if let Some(Err(e)) = written {
+            status!("Cannot write the latency log: {}, logging stopped", e);
+            self.log = None;
+        }
+    }
+}
+
+// The averages and the worst of the frames, in milliseconds
+fn report(frames: &[Frame]) -> Option<Report> {
+    if frames.is_empty() {
+        return None;
+    }
+    let count = frames.len() as f32;
+    let average =
+        |stage: fn(&Frame) -> f32| frames.iter().map(stage).sum::<f32>() / count * 1000f32;
+    Some(Report {
+        buffer: average(|f| f.buffer),
+        analysis: average(|f| f.analysis),
+        queue: average(|f| f.queue),
+        drawing: average(|f| f.drawing),
+        worst: frames.iter().map(Frame::total).fold(0f32, f32::max) * 1000f32,
+        samples: frames.iter().map(|f| f.samples).max().unwrap_or(0),
+        scores: frames.iter().map(|f| f.scores).max().unwrap_or(0),
+    })
+}
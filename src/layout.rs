@@ -0,0 +1,206 @@
+// Display layouts, every window being rows of panels placed left to right
+// Read from the layout section of the config: a row of panel names per line, windows separated by '---'
+// The separator can place the next window, ie. '--- display=1 fullscreen'
+
+// Crate
+use crate::config::Config;
+
+// The content of a part of a window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Panel {
+    // The note, intonation or detail graph, switched with the keyboard
+    Graph,
+    // The scores of every note of the guitar
+    Fretboard,
+    // The scores of the 12 pitch classes
+    Notes,
+    // The spectrum over time
+    Spectrogram,
+    // The detected chord, in large
+    Chord,
+    // The confidence, chord, song and held note
+    Stats,
+    // Large buttons for the common actions, for touchscreens
+    Controls,
+    // The lyrics and cues of the chart followed, scrolling along
+    Lyrics,
+    // The input level and its short-term loudness
+    Loudness,
+    // The pitch class scores of the other channels, when they are analysed apart
+    Channels,
+    // The pitch class scores against the band and against the player, when the band is heard apart
+    Attribution,
+    // The voicings suggested for the chord, as chord diagrams or keyboards
+    Voicings,
+    // The cantus firmus and the notes played against it, with their faults
+    Counterpoint,
+    // The figured bass and the bass and realization played over it, with their faults
+    Continuo,
+}
+
+impl Panel {
+    pub fn from_name(name: &str) -> Option<Panel> {
+        match name {
+            "graph" => Some(Panel::Graph),
+            "fretboard" => Some(Panel::Fretboard),
+            "notes" => Some(Panel::Notes),
+            "spectrogram" => Some(Panel::Spectrogram),
+            "chord" => Some(Panel::Chord),
+            "stats" => Some(Panel::Stats),
+            "controls" => Some(Panel::Controls),
+            "lyrics" => Some(Panel::Lyrics),
+            "loudness" => Some(Panel::Loudness),
+            "channels" => Some(Panel::Channels),
+            "attribution" => Some(Panel::Attribution),
+            "voicings" => Some(Panel::Voicings),
+            "counterpoint" => Some(Panel::Counterpoint),
+            "continuo" => Some(Panel::Continuo),
+            _ => None,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Panel::Graph => "Fourier",
+            Panel::Fretboard => "Fretboard",
+            Panel::Notes => "Notes",
+            Panel::Spectrogram => "Spectrogram",
+            Panel::Chord => "Chord",
+            Panel::Stats => "Stats",
+            Panel::Controls => "Controls",
+            Panel::Lyrics => "Lyrics",
+            Panel::Loudness => "Loudness",
+            Panel::Channels => "Channels",
+            Panel::Attribution => "Attribution",
+            Panel::Voicings => "Voicings",
+            Panel::Counterpoint => "Counterpoint",
+            Panel::Continuo => "Continuo",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WindowLayout {
+    pub rows: Vec<Vec<Panel>>,
+    // The monitor the window opens on, the windows without one are stacked on the first
+    pub display: Option<i32>,
+    // Fill the monitor
+    pub fullscreen: bool,
+}
+
+impl WindowLayout {
+    fn new(rows: Vec<Vec<Panel>>) -> WindowLayout {
+        WindowLayout {
+            rows,
+            display: None,
+            fullscreen: false,
+        }
+    }
+
+    // Read the options following a window separator
+    fn parse_options(&mut self, options: &str) -> Result<(), String> {
+        for option in options.split_whitespace() {
+            let mut parts = option.splitn(2, '=');
+            match (parts.next().unwrap(), parts.next()) {
+                ("fullscreen", None) => self.fullscreen = true,
+                ("display", Some(index)) => {
+                    self.display = Some(
+                        index
+                            .parse::<i32>()
+                            .ok()
+                            .filter(|&i| i >= 0)
+                            .ok_or("display is not an unsigned int")?,
+                    )
+                }
+                _ => return Err(format!("unknown window option '{}'", option)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub windows: Vec<WindowLayout>,
+}
+
+impl Default for Layout {
+    // The graph with the level meters under it, and the fretboard in their own windows
+    fn default() -> Layout {
+        Layout {
+            windows: vec![
+                WindowLayout::new(vec![vec![Panel::Graph], vec![Panel::Loudness]]),
+                WindowLayout::new(vec![vec![Panel::Fretboard]]),
+            ],
+        }
+    }
+}
+
+impl Layout {
+    // Add a window with the panel, unless a window already has it
+    pub fn show(&mut self, panel: Panel) {
+        if !self.windows.iter().any(|w| w.rows.iter().any(|row| row.contains(&panel))) {
+            self.windows.push(WindowLayout::new(vec![vec![panel]]));
+        }
+    }
+
+    // The layout of the config, or the default one if it has none
+    pub fn from_config(config: &Config) -> Result<Layout, String> {
+        if !config.has_section("layout") {
+            return Ok(Layout::default());
+        }
+        let lines = config.section("layout");
+        let mut windows = vec![WindowLayout::default()];
+        for (number, line) in lines {
+            if line.starts_with("---") {
+                // A separator before any panel sets the options of the first window
+                if !windows.last().unwrap().rows.is_empty() {
+                    windows.push(WindowLayout::default());
+                }
+                windows
+                    .last_mut()
+                    .unwrap()
+                    .parse_options(&line[3..])
+                    .map_err(|e| config.error(number, &e))?;
+                continue;
+            }
+            let row = line
+                .split_whitespace()
+                .map(|name| {
+                    Panel::from_name(name).ok_or_else(|| {
+                        config.error(
+                            number,
+                            &format!(
+                                "unknown panel '{}', expected graph, fretboard, notes, spectrogram, chord, stats, controls, lyrics, loudness, channels, attribution, voicings, counterpoint or continuo",
+                                name
+                            ),
+                        )
+                    })
+                })
+                .collect::<Result<Vec<Panel>, String>>()?;
+            windows.last_mut().unwrap().rows.push(row);
+        }
+        windows.retain(|window| !window.rows.is_empty());
+        if windows.is_empty() {
+            return Err(format!("No panels found in the layout section of {}", config.path()));
+        }
+        Ok(Layout { windows })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_layout_section_is_refused() {
+        let config = Config::parse("test.conf", "[layout]\n--- fullscreen\n[keys]\nspace pause").unwrap();
+        assert_eq!(
+            Layout::from_config(&config).err().as_deref(),
+            Some("No panels found in the layout section of test.conf")
+        );
+        // Without the section, the default layout
+        let config = Config::parse("test.conf", "[keys]\nspace pause").unwrap();
+        assert!(Layout::from_config(&config).is_ok());
+    }
+}
@@ -0,0 +1,78 @@
+// The analysis, the displays, the inputs and the outputs, run by main.rs
+// The fuzz targets in fuzz/ use them too
+
+// Print a status line, see tools::status
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        $crate::tools::status(format_args!($($arg)*))
+    };
+}
+
+pub mod articulation;
+pub mod audio_buffer;
+pub mod calibration;
+pub mod cantus;
+pub mod capture;
+pub mod chart;
+pub mod chords;
+pub mod classifier;
+pub mod compare;
+pub mod config;
+pub mod context;
+pub mod continuo;
+pub mod control;
+pub mod controller;
+pub mod counterpoint;
+#[cfg(feature = "cpal-backend")]
+pub mod cpal_input;
+pub mod devices;
+pub mod diagnose;
+pub mod display;
+pub mod display_sdl;
+pub mod display_term;
+pub mod dissonance;
+pub mod echo;
+pub mod error;
+pub mod fourier;
+pub mod frequency;
+pub mod hum;
+pub mod intonation;
+pub mod json;
+#[cfg(feature = "jack-backend")]
+pub mod jack_input;
+pub mod keymap;
+pub mod keys;
+pub mod latency;
+pub mod layout;
+pub mod loudness;
+#[cfg(feature = "midi")]
+pub mod midi;
+pub mod mirror;
+pub mod notes;
+pub mod osc;
+pub mod output;
+#[cfg(feature = "midi")]
+pub mod pads;
+pub mod pcm_input;
+pub mod pitch;
+pub mod prompts;
+pub mod reaper;
+pub mod resample;
+pub mod ring;
+pub mod saved;
+pub mod scores;
+pub mod session;
+pub mod setlist;
+pub mod sheet;
+pub mod spectrum;
+pub mod synth;
+pub mod talkback;
+pub mod temperament;
+pub mod testsignal;
+pub mod timeline;
+pub mod tools;
+pub mod udp_input;
+pub mod voicings;
+pub mod wav;
+pub mod widget;
@@ -0,0 +1,113 @@
+// EBU R128 short-term loudness, K-weighted over the last 3 seconds (ITU-R BS.1770)
+
+// Standard
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// The length of the blocks the mean square is gathered in, in seconds
+const BLOCK: f64 = 0.1;
+// The number of blocks of the short-term window
+const SHORT_TERM_BLOCKS: usize = 30;
+
+// A second order IIR filter
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    // The last two inputs and outputs
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Biquad {
+        Biquad {
+            b,
+            a,
+            x: [0f64; 2],
+            y: [0f64; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+pub struct LoudnessMeter {
+    // The K-weighting, a high shelf modelling the head then a high pass
+    shelf: Biquad,
+    high_pass: Biquad,
+    // The samples per block
+    block_length: usize,
+    // The sum of squares and length of the block being filled
+    sum: f64,
+    count: usize,
+    // The mean squares of the last blocks
+    blocks: VecDeque<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(rate: i32) -> LoudnessMeter {
+        let rate = rate as f64;
+        // The filter coefficients of the standard, recomputed for the sample rate
+        let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20f64);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1f64 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2f64 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [1f64, 2f64 * (k * k - 1f64) / a0, (1f64 - k / q + k * k) / a0],
+        );
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1f64 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1f64, -2f64, 1f64],
+            [1f64, 2f64 * (k * k - 1f64) / a0, (1f64 - k / q + k * k) / a0],
+        );
+        LoudnessMeter {
+            shelf,
+            high_pass,
+            block_length: (rate * BLOCK).round().max(1f64) as usize,
+            sum: 0f64,
+            count: 0,
+            blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+        }
+    }
+
+    // Measure the samples received from the capture device
+    pub fn feed(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let weighted = self.high_pass.process(self.shelf.process(sample as f64));
+            self.sum += weighted * weighted;
+            self.count += 1;
+            if self.count == self.block_length {
+                if self.blocks.len() == SHORT_TERM_BLOCKS {
+                    self.blocks.pop_front();
+                }
+                self.blocks.push_back(self.sum / self.count as f64);
+                self.sum = 0f64;
+                self.count = 0;
+            }
+        }
+    }
+
+    // The short-term loudness in LUFS, once 3 seconds were measured
+    pub fn short_term(&self) -> Option<f32> {
+        if self.blocks.len() < SHORT_TERM_BLOCKS {
+            return None;
+        }
+        let mean = self.blocks.iter().sum::<f64>() / self.blocks.len() as f64;
+        Some((-0.691 + 10f64 * mean.log10()) as f32)
+    }
+}
@@ -1,134 +1,192 @@
 // Standard
 use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
 
 // Parser
-use clap::{App, Arg};
+use clap::{App, Arg, ArgMatches, SubCommand};
 
 // SDL2
-use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::audio::AudioSpecDesired;
+use sdl2::AudioSubsystem;
 
 // Crate
-mod audio_buffer;
-mod display;
-mod display_sdl;
-mod display_term;
-mod dissonance;
-mod fourier;
-mod frequency;
-mod notes;
-mod scores;
-mod tools;
-
-use self::audio_buffer::{AudioBuffer, BufferOptions};
-use self::display::DisplayOptions;
-use self::scores::Scores;
-
-fn main() -> Result<(), String> {
+use improve::{
+    calibration, capture, chart, chords, compare, continuo, control, devices, diagnose, display,
+    display_sdl, display_term, echo, fourier, hum, json, mirror, osc, pcm_input, prompts, reaper,
+    ring, session, sheet, synth, talkback, testsignal, udp_input, wav, widget,
+};
+#[cfg(feature = "cpal-backend")]
+use improve::cpal_input;
+#[cfg(feature = "jack-backend")]
+use improve::jack_input;
+#[cfg(feature = "midi")]
+use improve::midi;
+#[cfg(feature = "midi")]
+use improve::pads;
+use improve::{status, tools};
+use improve::audio_buffer::{AudioBuffer, BufferOptions, ChannelMode};
+use improve::cantus::Melody;
+use improve::capture::{Recorder, Watchdog};
+use improve::classifier::Classify;
+use improve::config::Config;
+use improve::context::Context;
+use improve::control::Command;
+use improve::controller::ControllerMap;
+use improve::continuo::{Continuo, FiguredBass};
+use improve::counterpoint::{Counterpoint, Species};
+use improve::keymap::Keymap;
+use improve::latency::Monitor;
+use improve::layout::{Layout, Panel};
+use improve::output::Output;
+use improve::saved::Saved;
+use improve::display::DisplayOptions;
+use improve::error::Error;
+use improve::notes::Note;
+use improve::scores::{Aggregation, Fold, Scores, Style};
+use improve::session::Session;
+use improve::setlist::{Exercise, KeyCycle, Setlist};
+use improve::temperament::Temperament;
+use improve::voicings::Fingering;
+use improve::timeline::Timeline;
+
+// The lowest capture rate, keeping the fundamentals up to B8
+const MIN_RATE: i32 = 16000;
+// The highest rate asked of capture devices
+const MAX_RATE: i32 = 192000;
+// The rate the default resolution is meant for, other rates scaling it
+const DEFAULT_RATE: i32 = 88200;
+const DEFAULT_RESOLUTION: usize = 8192;
+// The capture buffer requested in low latency mode, about 3ms at 88200 Hz
+const LOW_LATENCY_SAMPLES: u16 = 256;
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("Error: {}", error);
+        if let Some(hint) = error.hint() {
+            eprintln!("Hint: {}", hint);
+        }
+        if error.is_capture() {
+            for line in diagnose::capture() {
+                eprintln!("Diagnostic: {}", line);
+            }
+        }
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
     // Parse args
-    let matches = App::new("ImproVe")
-        .version("0.1")
-        .author("Louis Garczynski <louis.roc@gmail.com>")
-        .about("Real-time improvisation suggestions")
-        .arg(
-            Arg::with_name("resolution")
-                .short("r")
-                .long("resolution")
-                .value_name("UINT")
-                .help(
-                    "Width of audio data analyzed every step\n\
-                     Higher values 'blur' the audio over time\n\
-                     Higher values can have a significant performance cost\n\
-                     Powers of two are significantly faster\n",
-                )
-                .next_line_help(true)
-                .default_value("8192")
-                .validator(|s| match s.parse::<u32>() {
-                    Ok(32..=1_048_576) => Ok(()),
-                    Ok(_) => Err("Argument out of range: (32 .. 1048576)".to_owned()),
-                    Err(_) => Err("Argument is not an unsigned int".to_owned()),
-                }),
-        )
-        .arg(
-            Arg::with_name("zpadding")
-                .short("z")
-                .long("zpadding")
-                .value_name("FACTOR")
-                .help(
-                    "The fourier transform can interpolate sparse data...\n\
-                     write more stuff\n",
-                )
-                .next_line_help(true)
-                .default_value("1")
-                .validator(|s| match s.parse::<u32>() {
-                    Ok(1..=32) => Ok(()),
-                    Ok(_) => Err("Argument out of range: (1 .. 32)".to_owned()),
-                    Err(_) => Err("Argument is not an unsigned int".to_owned()),
-                }),
-        )
-        .arg(
-            Arg::with_name("notation")
-                .short("n")
-                .long("notation")
-                .value_name("LANGUAGE")
-                .help("English or Romance notation\n")
-                .next_line_help(true)
-                .possible_values(&["e", "r"])
-                .default_value("e"),
-        )
-        .arg(
-            Arg::with_name("halflife")
-                .short("f")
-                .long("halflife")
-                .value_name("SECONDS")
-                .help("The time in seconds for the dissonance to drop by half\n")
-                .next_line_help(true)
-                .default_value("1.0")
-                .validator(|s| match s.parse::<f32>() {
-                    Ok(f) => {
-                        if f >= 0.0 && f <= 100.0 {
-                            Ok(())
-                        } else {
-                            Err("Argument out of range: (0 .. 100)".to_owned())
-                        }
-                    }
-                    Err(_) => Err("Argument is not a float".to_owned()),
-                }),
-        )
-        .arg(
-            Arg::with_name("discard")
-                .short("d")
-                .long("discard")
-                .help("Allows the program to discard data if latency is too high\n"),
-        )
-        .arg(
-            Arg::with_name("overlap")
-                .short("o")
-                .long("overlap")
-                .help("Allows the program to reuse data if the latency is too low\n"),
-        )
-        .arg(
-            Arg::with_name("terminal")
-                .short("t")
-                .long("terminal")
-                .help("Use the terminal instead of SDL2 windows\n"),
-        )
-        .arg(
-            Arg::with_name("noclear")
-                .short("c")
-                .long("noclear")
-                .help("Prevents the program from using termios\n"),
-        )
-        .get_matches();
+    let matches = app().get_matches();
+    // Keep the standard output to the JSON lines
+    if matches.value_of("json") == Some("-") {
+        tools::status_to_stderr();
+    }
     // Get notation convention
+    let symbols = chords::Symbols::from_name(matches.value_of("chord-symbols").unwrap()).unwrap();
     let notation = match matches.value_of("notation").unwrap() {
-        "e" => display::Notation::English,
-        _ => display::Notation::Romance,
+        "e" => display::Notation { language: display::Language::English, symbols },
+        _ => display::Notation { language: display::Language::Romance, symbols },
+    };
+    // Compare saved sessions, without capturing
+    if let Some(matches) = matches.subcommand_matches("compare") {
+        let (before, after) = (matches.value_of("before").unwrap(), matches.value_of("after").unwrap());
+        let lines = compare::compare(
+            before,
+            &session::SavedSession::load(before)?,
+            after,
+            &session::SavedSession::load(after)?,
+            notation,
+        )?;
+        for line in lines {
+            status!("{}", line);
+        }
+        return Ok(());
+    }
+    // Get the channels captured, and how they are analysed
+    let channels = matches.value_of("channels").unwrap().parse::<u8>().unwrap();
+    let channel_mode = ChannelMode::from_name(matches.value_of("channel").unwrap()).unwrap();
+    if let ChannelMode::Select(channel) = channel_mode {
+        if channel >= channels as usize {
+            return Err(Error::Option {
+                option: "channel",
+                reason: format!("channel {} is not among the {} captured", channel + 1, channels),
+            });
+        }
+    }
+    let streams = if channel_mode == ChannelMode::Split { channels as usize } else { 1 };
+    // Keyboard players scored from their notes, instead of the analysed audio
+    let midi_player = is_midi_player(&matches);
+    let other_source = midi_player
+        || matches.subcommand_matches("testsignal").is_some()
+        || matches.subcommand_matches("analyze").is_some()
+        || matches.subcommand_matches("mirror").is_some()
+        || matches.is_present("input")
+        || matches.value_of("backend") != Some("sdl");
+    if streams > 1 && other_source {
+        return Err(Error::Option {
+            option: "channel",
+            reason: "splitting the channels requires capturing with the sdl backend".to_owned(),
+        });
+    }
+    // Get the state saved when the windows were last closed
+    let saved = match matches.value_of("state") {
+        Some(path) => Some(Saved::load(path)?),
+        None => None,
     };
+    // The options not given on the command line, as saved
+    let restored = |option: &str| saved.as_ref().filter(|_| matches.occurrences_of(option) == 0);
+
     // Get display option
     let disp_opt = DisplayOptions {
         notation,
         clear_term: !matches.is_present("noclear"),
-        instrument: (),
+        remote: matches.is_present("remote-term"),
+        // Remote terminals redraw less often unless asked otherwise
+        refresh: if matches.is_present("remote-term") && matches.occurrences_of("refresh") == 0 {
+            display::REMOTE_REFRESH
+        } else {
+            matches
+                .value_of("refresh")
+                .unwrap()
+                .parse::<f32>()
+                .unwrap()
+        },
+        instrument: matches.value_of("voicings").map(|v| Fingering::from_name(v).unwrap()),
+        latency: matches.is_present("latency"),
+        confidence: matches
+            .value_of("confidence")
+            .unwrap()
+            .parse::<f32>()
+            .unwrap(),
+        cadence: display::Cadence::from_name(matches.value_of("suggest-every").unwrap()).unwrap(),
+        tempo: matches
+            .value_of("tempo")
+            .map_or(chart::DEFAULT_TEMPO, |t| t.parse::<f32>().unwrap()),
+        beats_per_bar: matches
+            .value_of("beats-per-bar")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap(),
+        smoothing: matches
+            .value_of("smoothing")
+            .unwrap()
+            .parse::<f32>()
+            .unwrap(),
+        scaling: restored("scaling")
+            .and_then(|saved| saved.scaling)
+            .unwrap_or_else(|| display::Scaling::from_name(matches.value_of("scaling").unwrap()).unwrap()),
+        labels: match matches.value_of("labels").unwrap() {
+            "all" => display::Labels::All,
+            "key" => display::Labels::Key,
+            "none" => display::Labels::None,
+            _ => display::Labels::Best(matches.value_of("best-labels").unwrap().parse::<usize>().unwrap()),
+        },
+        label_size: matches
+            .value_of("label-size")
+            .unwrap()
+            .parse::<f32>()
+            .unwrap(),
+        channels: streams,
     };
 
     // Get audio buffering options
@@ -141,8 +199,17 @@ fn main() -> Result<(), String> {
         .unwrap();
     // Check if values can be discarded if input is too fast
     buf_opt.discard = matches.is_present("discard");
-    // Check if values can be analyzed multiple times if input is too slow
-    buf_opt.overlap = matches.is_present("overlap");
+    // Get the noise gate
+    buf_opt.gate = matches.value_of("gate").map(|s| s.parse::<f32>().unwrap());
+    buf_opt.gate_attack = matches.value_of("gate-attack").unwrap_or("0.01").parse::<f32>().unwrap();
+    buf_opt.gate_release = matches.value_of("gate-release").unwrap_or("0.2").parse::<f32>().unwrap();
+    // Get the silence after which the analysis pauses
+    let idle = matches.value_of("idle-after").unwrap().parse::<f32>().unwrap();
+    buf_opt.idle = if idle > 0f32 { Some(idle) } else { None };
+    // Get the window of the automatic gain control
+    if !matches.is_present("no-agc") {
+        buf_opt.agc = Some(matches.value_of("agc-window").unwrap().parse::<f32>().unwrap());
+    }
 
     // Get the zero-padding factor
     let zpadding = matches
@@ -151,78 +218,2743 @@ fn main() -> Result<(), String> {
         .parse::<u32>()
         .unwrap();
 
+    // Get the spectral analysis backend
+    let transform = match matches.value_of("transform").unwrap() {
+        "vq" => fourier::Transform::VariableQ,
+        "cqt" => fourier::Transform::ConstantQ,
+        _ => fourier::Transform::Fourier,
+    };
+
+    // Get the input type
+    let input_type = match matches.value_of("input-type").unwrap() {
+        "voice" => fourier::InputType::Voice,
+        _ => fourier::InputType::Instrument,
+    };
+    let classify = matches.value_of("classify").map(|c| Classify::from_name(c).unwrap());
+
     // Get the dissonance half-life
-    let halflife = matches
-        .value_of("halflife")
+    let halflife = restored("halflife")
+        .and_then(|saved| saved.halflife)
+        .unwrap_or_else(|| matches.value_of("halflife").unwrap().parse::<f32>().unwrap());
+
+    // Get how the frames are combined, a bar lasting its beats at the tempo
+    let aggregation_window = matches
+        .value_of("aggregation-window")
         .unwrap()
         .parse::<f32>()
         .unwrap();
+    let aggregation_seconds = match matches.value_of("aggregation").unwrap() {
+        "bar" => {
+            let tempo = matches
+                .value_of("tempo")
+                .map_or(chart::DEFAULT_TEMPO, |t| t.parse::<f32>().unwrap());
+            let beats = matches.value_of("beats-per-bar").unwrap().parse::<f32>().unwrap();
+            60f32 / tempo * beats
+        }
+        _ => aggregation_window,
+    };
+    let aggregation =
+        Aggregation::from_name(matches.value_of("aggregation").unwrap(), aggregation_seconds).unwrap();
+
+    // Get the halflife used on harmonic changes
+    let change_halflife = matches
+        .value_of("change-halflife")
+        .unwrap()
+        .parse::<f32>()
+        .unwrap();
+
+    // Get the harmonic change detection threshold
+    let change_threshold = matches
+        .value_of("change-threshold")
+        .unwrap()
+        .parse::<f32>()
+        .unwrap();
+
+    // Get the musical style
+    let style = match matches.value_of("style").unwrap() {
+        "jazz" => Style::Jazz,
+        "rock" => Style::Rock,
+        "ambient" => Style::Ambient,
+        _ => Style::Neutral,
+    };
+    let style = restored("style").and_then(|saved| saved.style).unwrap_or(style);
+
+    // Get the bass decay, defaulting to the style's
+    let bass_decay = match matches.value_of("bass-decay") {
+        Some(s) => s.parse::<f32>().unwrap(),
+        None => style.bass_decay(),
+    };
+
+    // Get the bass register limit
+    let bass_split = Note::from_name(matches.value_of("bass-split").unwrap())
+        .unwrap()
+        .freq();
+
+    // Get the rhythm section crossover frequency
+    let crossover = matches
+        .value_of("crossover")
+        .map(|s| s.parse::<f32>().unwrap());
+
+    // Get the lowest key of the right hand
+    let split = match matches.value_of("split") {
+        Some(split) => Some(Note::from_name(split).unwrap()),
+        None if matches.is_present("figured-bass") => Some(continuo::DEFAULT_SPLIT),
+        None => None,
+    };
+
+    // Get the octave folding mode
+    let fold = match matches.value_of("fold").unwrap() {
+        "weighted" => Fold::Weighted,
+        "above" => Fold::Above(Note::from_name(matches.value_of("fold-above").unwrap()).unwrap()),
+        _ => Fold::None,
+    };
+
+    // Get the maximum number of peaks
+    let max_peaks = matches
+        .value_of("max-peaks")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
 
-    // The channel to get data from audio callback and back
+    // Get the median filter length
+    let median = matches
+        .value_of("median")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+
+    // Get the spectral flux boost threshold
+    let boost = matches
+        .value_of("boost")
+        .map(|s| s.parse::<f32>().unwrap());
+
+    // The channel to get data from the file and network sources
     let (audio_sender, audio_receiver) = channel::<Vec<f32>>();
     let (score_sender, score_receiver) = channel::<Scores>();
+    // The channel to send commands from the display to the analysis
+    let (command_sender, command_receiver) = channel::<Command>();
+
+    // Choose the audio driver, older SDL versions only reading the environment
+    if let Some(driver) = matches.value_of("audio-driver") {
+        sdl2::hint::set("SDL_AUDIODRIVER", driver);
+        std::env::set_var("SDL_AUDIODRIVER", driver);
+    }
+
+    // Capture a monitor source, which SDL only opens as the default source of PulseAudio
+    if matches.is_present("loopback") {
+        if matches.value_of("backend") != Some("sdl") {
+            return Err(Error::Option {
+                option: "loopback",
+                reason: "the loopback capture requires the sdl backend".to_owned(),
+            });
+        }
+        let monitor = match matches.value_of("loopback") {
+            Some(monitor) => devices::find_monitor(monitor)?,
+            None => devices::DEFAULT_MONITOR.to_owned(),
+        };
+        status!("Loopback = {}", monitor);
+        std::env::set_var("PULSE_SOURCE", monitor);
+        if !matches.is_present("audio-driver") {
+            sdl2::hint::set("SDL_AUDIODRIVER", "pulseaudio");
+            std::env::set_var("SDL_AUDIODRIVER", "pulseaudio");
+        }
+    }
+
+    // Ask the sound servers for short periods, unless already configured
+    let low_latency = matches.is_present("low-latency");
+    if low_latency {
+        for &(variable, value) in [("PULSE_LATENCY_MSEC", "5"), ("PIPEWIRE_LATENCY", "256/48000")].iter() {
+            if std::env::var_os(variable).is_none() {
+                std::env::set_var(variable, value);
+            }
+        }
+    }
 
     // Get the SDL objects
-    let sdl_context = sdl2::init()?;
-    let audio_subsystem = sdl_context.audio()?;
-    println!(
+    let sdl_context = sdl2::init().map_err(|reason| Error::Sdl {
+        subsystem: "library",
+        reason,
+    })?;
+    let audio_subsystem = sdl_context.audio().map_err(|reason| Error::Sdl {
+        subsystem: "audio",
+        reason,
+    })?;
+    let driver = audio_subsystem.current_audio_driver().to_owned();
+    status!(
         "Capture Driver = {}",
         audio_subsystem.current_audio_driver()
     );
-    println!(
+    status!(
         "Capture Spec = {:?}",
         audio_subsystem.audio_playback_device_name(0)
     );
 
+    // Play the reference tone instead of capturing
+    if let Some(matches) = matches.subcommand_matches("tone") {
+        let notes = matches
+            .values_of("note")
+            .unwrap()
+            .map(|s| Note::from_name(s).unwrap())
+            .collect::<Vec<Note>>();
+        let temperament = match matches.value_of("temperament").unwrap() {
+            "just" => Temperament::Just,
+            "pythagorean" => Temperament::Pythagorean,
+            _ => Temperament::Equal,
+        };
+        let tonic = match matches.value_of("tonic") {
+            Some(s) => Note::from_name(s).unwrap(),
+            None => *notes.iter().min_by_key(|&&n| n as usize).unwrap(),
+        };
+        let duration = matches
+            .value_of("duration")
+            .unwrap()
+            .parse::<f32>()
+            .unwrap();
+        let frequencies = notes
+            .iter()
+            .map(|&n| temperament.freq(n, tonic))
+            .collect();
+        return synth::play(&audio_subsystem, frequencies, duration);
+    }
+
+    // Other drivers ignore the source set for the loopback
+    if matches.is_present("loopback") && driver != "pulseaudio" {
+        return Err(Error::Option {
+            option: "loopback",
+            reason: format!(
+                "the loopback capture requires the pulseaudio driver, not {} (PipeWire provides it with pipewire-pulse)",
+                driver
+            ),
+        });
+    }
+
     // Set the desired specs
+    let samplerate = matches.value_of("samplerate").unwrap().parse::<i32>().unwrap();
     let desired_spec = AudioSpecDesired {
-        freq: Some(88200),
-        channels: Some(1),
-        samples: None,
+        freq: Some(samplerate),
+        channels: Some(channels),
+        samples: if low_latency { Some(LOW_LATENCY_SAMPLES) } else { None },
     };
+    // SDL only opens WASAPI devices in shared mode
+    if low_latency && driver == "wasapi" {
+        status!("Low latency = shared mode, exclusive mode is not available through SDL");
+    }
 
-    // Build the callback object and start recording
-    let mut received_spec = None;
+    // List the capture devices instead of capturing
+    if matches.subcommand_matches("devices").is_some() {
+        for line in devices::list(&audio_subsystem, &desired_spec) {
+            status!("{}", line);
+        }
+        let monitors = devices::monitor_sources();
+        if !monitors.is_empty() {
+            status!("Monitor sources, for --loopback:");
+            for (i, monitor) in monitors.iter().enumerate() {
+                status!("{}: {}", i, monitor);
+            }
+        }
+        return Ok(());
+    }
 
-    let capture_device = audio_subsystem.open_capture(None, &desired_spec, |spec| {
-        println!("Capture Spec = {:?}", spec);
-        received_spec = Some(spec);
-        Recorder { audio_sender }
-    })?;
-    let frequency = received_spec.unwrap().freq;
+    // The clock of the session, counting the captured samples
+    let timeline = Timeline::new();
+
+    // Feed a test signal to the analysis, or capture the input
+    let Input {
+        rate: frequency,
+        mut watchdog,
+        consumers: mut ring_consumers,
+    } = open_input(
+        &matches,
+        &audio_subsystem,
+        &desired_spec,
+        channel_mode,
+        streams,
+        &timeline,
+        audio_sender,
+    )?;
 
-    capture_device.resume();
+    // Analyse at a fixed rate, resampling the capture
+    let capture_rate = frequency;
+    let frequency = matches
+        .value_of("analysis-rate")
+        .map_or(capture_rate, |r| r.parse::<i32>().unwrap());
+    if frequency != capture_rate {
+        status!("Resampling = {} Hz to {} Hz", capture_rate, frequency);
+    }
+
+    // Keep the analysed duration of the default resolution at the rate opened
+    if matches.occurrences_of("resolution") == 0 && frequency != DEFAULT_RATE {
+        let samples = DEFAULT_RESOLUTION as f32 * frequency as f32 / DEFAULT_RATE as f32;
+        buf_opt.resolution = 1 << samples.log2().round() as u32;
+        status!("Resolution = {} at {} Hz", buf_opt.resolution, frequency);
+    }
+    // Get the hop, once the resolution is known
+    buf_opt.hop = matches.value_of("hop").map(|h| parse_hop(h, buf_opt.resolution).unwrap());
 
     // Build audio receiver and aggrgator
-    let buffer = AudioBuffer::new(audio_receiver, buf_opt);
+    let mut buffer = if ring_consumers.is_empty() {
+        AudioBuffer::new(audio_receiver, buf_opt)
+    } else {
+        AudioBuffer::new(ring_consumers.remove(0), buf_opt)
+    };
+    if frequency != capture_rate {
+        buffer.resample(capture_rate, frequency);
+    }
+    buffer.set_rate(frequency);
+    if let Some(path) = matches.value_of("record") {
+        let rotation = wav::Rotation {
+            bytes: matches.value_of("record-size").map(|s| s.parse::<u64>().unwrap() * 1_000_000),
+            seconds: matches.value_of("record-minutes").map(|s| s.parse::<f32>().unwrap() * 60f32),
+        };
+        buffer.record(wav::Recorder::create(path, frequency, rotation)?);
+    }
+    let tap = if matches.is_present("suppress-feedback") {
+        let seconds = matches.value_of("feedback-length").unwrap_or("50").parse::<f32>().unwrap() / 1000f32;
+        let (tap, canceller) = echo::canceller(frequency, seconds);
+        buffer.suppress_feedback(canceller);
+        Some(tap)
+    } else {
+        None
+    };
+
+    // Get the config, with the layout of the SDL windows, the game controller mapping, the keys
+    // and the calibration
+    let config = match matches.value_of("config") {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
 
     let scoring_options = fourier::ScoringOptions {
         frequency,
         zpadding,
+        transform,
+        window: fourier::Window::from_name(matches.value_of("window").unwrap()).unwrap(),
+        input_type,
+        mono: matches.is_present("mono"),
+        classify,
         halflife,
+        change_halflife,
+        change_threshold,
+        bass_split,
+        style,
+        bass_decay,
+        crossover,
+        split,
+        fold,
+        max_peaks,
+        median,
+        hps: matches.value_of("hps").map(|h| h.parse::<usize>().unwrap()),
+        boost,
+        reject_drums: matches.is_present("reject-drums"),
+        split_silence: matches.value_of("split-silence").map(|s| s.parse::<f32>().unwrap()),
+        calibration: calibration::from_config(&config)?,
+        aggregation,
+        chord_reset: matches.value_of("chord-reset").map(|k| k.parse::<f32>().unwrap()),
+    };
+
+    // Measure the capture clock instead of the live suggestions
+    if let Some(matches) = matches.subcommand_matches("calibrate") {
+        let reference = matches.value_of("reference").unwrap().parse::<f32>().unwrap();
+        let duration = matches.value_of("duration").unwrap().parse::<u64>().unwrap();
+        let factor = calibration::calibrate(
+            buffer,
+            scoring_options,
+            reference,
+            std::time::Duration::from_secs(duration),
+        )?;
+        if let Some(path) = matches.value_of("save") {
+            calibration::save(path, factor)?;
+        }
+        return Ok(());
+    }
+
+    // Run the hum query instead of the live suggestions
+    if let Some(matches) = matches.subcommand_matches("hum") {
+        let duration = matches
+            .value_of("duration")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap();
+        return hum::hum(
+            buffer,
+            scoring_options,
+            notation,
+            std::time::Duration::from_secs(duration),
+        );
+    }
+
+    // Get the harmonic context from MIDI or a chart, instead of the analysed audio
+    let tempo = matches.value_of("tempo").map(|t| t.parse::<f32>().unwrap());
+    let setlist = match (matches.value_of("chart"), matches.value_of("setlist")) {
+        (Some(path), _) => Some(Setlist::single(chart::Chart::load(path, notation.symbols)?, tempo, path)),
+        (_, Some(path)) => Some(Setlist::load(path, tempo, notation.symbols)?),
+        _ => None,
+    };
+    let mut layout = Layout::from_config(&config)?;
+    if streams > 1 {
+        layout.show(Panel::Channels);
+    }
+    let controller_map = ControllerMap::from_config(&config)?;
+    let keymap = Keymap::from_config(&config)?;
+
+    // Show the display of a student instead of analysing
+    if let Some(mirror_matches) = matches.subcommand_matches("mirror") {
+        let port = mirror_matches.value_of("port").unwrap().parse::<u16>().unwrap();
+        let host = mirror_matches.value_of("host").unwrap();
+        mirror::serve(host, port, score_sender, command_receiver)?;
+        if let Some(port) = matches.value_of("osc") {
+            osc::listen(
+                matches.value_of("osc-host").unwrap_or(osc::DEFAULT_HOST),
+                port.parse::<u16>().unwrap(),
+                command_sender.clone(),
+            )?;
+        }
+        // The terminal display reading the keys, only the SDL one leaves the console to type in
+        let terminal = matches.is_present("terminal") || matches.is_present("remote-term");
+        if !terminal {
+            mirror::read_console(command_sender.clone());
+        }
+        if terminal {
+            display_term::display(
+                score_receiver,
+                command_sender,
+                disp_opt,
+                scoring_options,
+                keymap,
+                None,
+                &mut Watchdog::none(),
+                Monitor::new(None).unwrap(),
+            )?;
+        } else {
+            display_sdl::display(
+                sdl_context,
+                score_receiver,
+                command_sender,
+                disp_opt,
+                scoring_options,
+                keymap,
+                None,
+                layout,
+                controller_map,
+                &mut Watchdog::none(),
+                None,
+                Monitor::new(None).unwrap(),
+                saved,
+            )?;
+        }
+        return Ok(());
+    }
+
+    // Get the key exercise
+    let setlist = match (setlist, matches.value_of("all-keys")) {
+        (Some(setlist), Some(cycle)) => Some(setlist.with_exercise(Exercise {
+            cycle: match cycle {
+                "random" => KeyCycle::Random,
+                _ => KeyCycle::Fourths,
+            },
+            passes: matches.value_of("passes").unwrap_or("1").parse::<usize>().unwrap(),
+        })),
+        (None, Some(_)) => {
+            return Err(Error::Option {
+                option: "all-keys",
+                reason: "the key exercise requires a chart or a setlist".to_owned(),
+            })
+        }
+        (setlist, None) => setlist,
+    };
+    let setlist = setlist.map(|setlist| setlist.with_timeline(timeline.clone()));
+    if setlist.is_none() && matches.is_present("chord-reset") {
+        return Err(Error::Option {
+            option: "chord-reset",
+            reason: "the chord changes come from a chart or a setlist".to_owned(),
+        });
+    }
+    // Get the counterpoint exercise, its cantus firmus being the harmony
+    let counterpoint = match matches.value_of("cantus") {
+        Some(path) => {
+            let melody = Melody::load(path)?;
+            let species = Species::from_name(matches.value_of("species").unwrap_or("first")).unwrap();
+            let tempo = tempo.or(melody.tempo).unwrap_or(chart::DEFAULT_TEMPO);
+            status!(
+                "Cantus = {} ({} species, {:.0} bpm)",
+                melody.title.as_deref().unwrap_or(path),
+                species.name(),
+                tempo
+            );
+            Some(Counterpoint::new(melody, tempo, species, timeline.clone()))
+        }
+        None => None,
+    };
+    // Get the figured bass exercise, the bass and its figures being the harmony
+    let continuo = match matches.value_of("figured-bass") {
+        Some(path) => {
+            let bass = FiguredBass::load(path, notation.symbols)?;
+            let tempo = tempo.or(bass.tempo).unwrap_or(chart::DEFAULT_TEMPO);
+            status!(
+                "Figured bass = {} (in {}, {:.0} bpm)",
+                bass.title.as_deref().unwrap_or(path),
+                bass.key.name(notation),
+                tempo
+            );
+            Some(Continuo::new(bass, tempo, timeline.clone()))
+        }
+        None => None,
+    };
+    let context = if (matches.is_present("midi-in") && !midi_player) || setlist.is_some() || counterpoint.is_some() || continuo.is_some() {
+        Some(Context::new())
+    } else {
+        None
+    };
+    if let (Some(setlist), Some(context)) = (&setlist, &context) {
+        setlist.follow(context.clone(), notation);
+    }
+    if let (Some(counterpoint), Some(context)) = (&counterpoint, &context) {
+        counterpoint.follow(context.clone());
+        layout.show(Panel::Counterpoint);
+    }
+    if let (Some(continuo), Some(context)) = (&continuo, &context) {
+        continuo.follow(context.clone());
+        layout.show(Panel::Continuo);
+    }
+    // The latency with the other statistics
+    if disp_opt.latency {
+        layout.show(Panel::Stats);
+    }
+    // The voicings suggested, in their own window unless laid out
+    if disp_opt.instrument.is_some() {
+        layout.show(Panel::Voicings);
+    }
+    // The band heard apart from the player, the dissonance can be split between them
+    if context.is_some() || crossover.is_some() || split.is_some() {
+        layout.show(Panel::Attribution);
+    }
+    // The notes held by the player, with the player role
+    let played = if midi_player { Some(Context::new()) } else { None };
+    #[cfg(feature = "midi")]
+    let _midi_input = match (matches.value_of("midi-in"), &played, &context) {
+        (Some(port), Some(played), _) => Some(midi::open_input(port, played.clone(), None)?),
+        (Some(port), None, Some(context)) => Some(midi::open_input(port, context.clone(), setlist.clone())?),
+        _ => None,
+    };
+    #[cfg(not(feature = "midi"))]
+    {
+        if let Some(option) = ["midi-in", "midi-out", "pads"].iter().find(|o| matches.is_present(o)) {
+            return Err(Error::Option {
+                option,
+                reason: "MIDI requires building with --features midi".to_owned(),
+            });
+        }
+    }
+
+    // Get the outputs publishing the analysis
+    let outputs = open_outputs(&matches, &timeline, notation, &command_sender)?;
+
+    // Listen to the external tools
+    if let Some(port) = matches.value_of("osc") {
+        osc::listen(
+            matches.value_of("osc-host").unwrap_or(osc::DEFAULT_HOST),
+            port.parse::<u16>().unwrap(),
+            command_sender.clone(),
+        )?;
+    }
+
+    // The statistics of the whole session, split at long silences
+    let prompts = if matches.is_present("prompt") || matches.is_present("prompt-file") {
+        Some(prompts::Prompts::load(matches.value_of("prompt-file"))?)
+    } else {
+        None
+    };
+    let session = Session::new(timeline.clone()).with_prompts(prompts);
+    if let Some(prompt) = session.prompt() {
+        status!("Prompt = {}", prompt);
+    }
+    let sessions = Arc::new(Mutex::new(vec![session]));
+
+    // Analyse the other channels apart, their scores joining those of the first
+    let mut channel_sessions = Vec::new();
+    let (score_sender, command_receiver) = if ring_consumers.is_empty() {
+        (score_sender, command_receiver)
+    } else {
+        let (first_sender, first_receiver) = channel::<Scores>();
+        let (first_commands, first_command_receiver) = channel::<Command>();
+        let mut command_senders = vec![first_commands];
+        let mut score_receivers = Vec::new();
+        for consumer in ring_consumers {
+            let mut buffer = AudioBuffer::new(consumer, buf_opt);
+            if frequency != capture_rate {
+                buffer.resample(capture_rate, frequency);
+            }
+            buffer.set_rate(frequency);
+            let (scores, score_receiver) = channel::<Scores>();
+            let (commands, command_receiver) = channel::<Command>();
+            let sessions = Arc::new(Mutex::new(vec![Session::new(timeline.clone())]));
+            channel_sessions.push(sessions.clone());
+            let context = context.clone();
+            std::thread::spawn(move || {
+                fourier::fourier_thread(
+                    buffer,
+                    scores,
+                    scoring_options,
+                    sessions,
+                    context,
+                    None,
+                    None,
+                    Vec::new(),
+                    command_receiver,
+                );
+            });
+            command_senders.push(commands);
+            score_receivers.push(score_receiver);
+        }
+        control::fan_out(command_receiver, command_senders);
+        fourier::combine_channels(first_receiver, score_receivers, score_sender);
+        (first_sender, first_command_receiver)
     };
 
     // Start the data analysis
-    std::thread::spawn(move || {
-        fourier::fourier_thread(buffer, score_sender, scoring_options);
+    let analysis_sessions = sessions.clone();
+    let analysis_timeline = timeline.clone();
+    let analysis_counterpoint = counterpoint.clone();
+    let analysis_continuo = continuo.clone();
+    std::thread::spawn(move || match played {
+        Some(played) => fourier::midi_thread(
+            played,
+            analysis_timeline,
+            score_sender,
+            scoring_options,
+            analysis_sessions,
+            context,
+            analysis_counterpoint,
+            analysis_continuo,
+            outputs,
+            command_receiver,
+        ),
+        None => fourier::fourier_thread(
+            buffer,
+            score_sender,
+            scoring_options,
+            analysis_sessions,
+            context,
+            analysis_counterpoint,
+            analysis_continuo,
+            outputs,
+            command_receiver,
+        ),
     });
 
-    if matches.is_present("terminal") {
-        display_term::display(score_receiver, disp_opt)
+    // Measure the latency of the scores shown, logged when asked
+    let mut monitor = Monitor::new(matches.value_of("latency-log")).map_err(|reason| Error::Option {
+        option: "latency-log",
+        reason,
+    })?;
+    if matches.is_present("headless") {
+        // The analysis ending with the recording or the signal
+        for scores in score_receiver.iter() {
+            monitor.receive(scores.latency);
+            monitor.drawn();
+            watchdog.check();
+        }
+    } else if matches.is_present("terminal") || matches.is_present("remote-term") {
+        display_term::display(
+            score_receiver,
+            command_sender,
+            disp_opt,
+            scoring_options,
+            keymap,
+            setlist,
+            &mut watchdog,
+            monitor,
+        )?;
     } else {
-        display_sdl::display(sdl_context, score_receiver, disp_opt)
+        display_sdl::display(
+            sdl_context,
+            score_receiver,
+            command_sender,
+            disp_opt,
+            scoring_options,
+            keymap,
+            setlist,
+            layout,
+            controller_map,
+            &mut watchdog,
+            tap,
+            monitor,
+            saved,
+        )?;
+    }
+
+    // Report on the sessions, split at long silences
+    let sessions = sessions.lock().unwrap();
+    if !channel_sessions.is_empty() {
+        status!("Channel 1");
+    }
+    for (i, session) in sessions.iter().enumerate() {
+        if sessions.len() > 1 {
+            let start = session.start() as u32;
+            status!("Session {}/{}, from {}:{:02}", i + 1, sessions.len(), start / 60, start % 60);
+        }
+        for line in session.report(notation) {
+            status!("{}", line);
+        }
+    }
+
+    for (i, sessions) in channel_sessions.iter().enumerate() {
+        status!("Channel {}", i + 2);
+        for session in sessions.lock().unwrap().iter() {
+            for line in session.report(notation) {
+                status!("{}", line);
+            }
+        }
+    }
+
+    // Report on the current pass of the counterpoint
+    if let Some(counterpoint) = counterpoint.as_ref() {
+        for line in counterpoint.progress().report() {
+            status!("{}", line);
+        }
     }
+    // And of the figured bass
+    if let Some(continuo) = continuo.as_ref() {
+        for line in continuo.progress().report() {
+            status!("{}", line);
+        }
+    }
+
+    // Save the sessions, to compare them later
+    if let Some(path) = matches.value_of("save-session") {
+        for (i, session) in sessions.iter().enumerate() {
+            let path = if i > 0 { sheet::numbered_path(path, i + 1) } else { path.to_owned() };
+            session.save(&path)?;
+            status!("Session saved to {}", path);
+        }
+    }
+
+    // Write the chord sheets, one per session
+    if let Some(path) = matches.value_of("chord-sheet") {
+        let beats_per_bar = matches.value_of("beats-per-bar").unwrap().parse::<usize>().unwrap();
+        let format = sheet::SheetFormat::from_path(path);
+        for (i, session) in sessions.iter().enumerate() {
+            let path = if sessions.len() > 1 {
+                sheet::numbered_path(path, i + 1)
+            } else {
+                path.to_owned()
+            };
+            let tempo = match matches.value_of("tempo") {
+                Some(tempo) => tempo.parse::<f32>().unwrap(),
+                None => match sheet::estimate_tempo(session.onset_times()) {
+                    Some(tempo) => tempo,
+                    // A split session too short for a sheet does not prevent the others
+                    None if sessions.len() > 1 => {
+                        status!("Not enough notes to estimate the tempo of {}, skipped", path);
+                        continue;
+                    }
+                    None => {
+                        return Err(Error::Option {
+                            option: "chord-sheet",
+                            reason: "not enough notes to estimate the tempo of the chord sheet, use --tempo"
+                                .to_owned(),
+                        })
+                    }
+                },
+            };
+            let text = sheet::chord_sheet(session, tempo, beats_per_bar, format, notation);
+            std::fs::write(&path, text).map_err(|e| format!("Cannot write {}: {}", path, e))?;
+            status!("Chord sheet written to {}", path);
+        }
+    }
+    Ok(())
+}
+
+// The command line arguments and subcommands
+fn app() -> App<'static, 'static> {
+    App::new("ImproVe")
+    .version("0.1")
+    .author("Louis Garczynski <louis.roc@gmail.com>")
+    .about("Real-time improvisation suggestions")
+    .arg(
+        Arg::with_name("resolution")
+            .short("r")
+            .long("resolution")
+            .value_name("UINT")
+            .help(
+                "Width of audio data analyzed every step\n\
+                 Higher values 'blur' the audio over time\n\
+                 Higher values can have a significant performance cost\n\
+                 Powers of two are significantly faster\n\
+                 Defaults to 8192 at 88200 Hz, the power of two closest to as long at other rates\n",
+            )
+            .next_line_help(true)
+            .default_value("8192")
+            .validator(|s| match s.parse::<u32>() {
+                Ok(32..=1_048_576) => Ok(()),
+                Ok(_) => Err("Argument out of range: (32 .. 1048576)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("samplerate")
+            .long("samplerate")
+            .value_name("HZ")
+            .help(
+                "The rate asked of the capture device, which may open at another one\n\
+                 The analysis follows the rate the device opens at, unless --analysis-rate is given\n",
+            )
+            .next_line_help(true)
+            .default_value("88200")
+            .validator(|s| match s.parse::<i32>() {
+                Ok(MIN_RATE..=MAX_RATE) => Ok(()),
+                Ok(_) => Err(format!("Argument out of range: ({} .. {})", MIN_RATE, MAX_RATE)),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("analysis-rate")
+            .long("analysis-rate")
+            .value_name("HZ")
+            .help(
+                "Resamples the input to this rate before the analysis, whatever the rate captured\n\
+                 The resolution then spans the same duration on every device\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<i32>() {
+                Ok(MIN_RATE..=MAX_RATE) => Ok(()),
+                Ok(_) => Err(format!("Argument out of range: ({} .. {})", MIN_RATE, MAX_RATE)),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("zpadding")
+            .short("z")
+            .long("zpadding")
+            .value_name("FACTOR")
+            .help(
+                "The fourier transform can interpolate sparse data...\n\
+                 write more stuff\n",
+            )
+            .next_line_help(true)
+            .default_value("1")
+            .validator(|s| match s.parse::<u32>() {
+                Ok(1..=32) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 32)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("transform")
+            .long("transform")
+            .value_name("TRANSFORM")
+            .help(
+                "The spectral analysis backend\n\
+                 fft: a single fourier transform over the resolution\n\
+                 vq: variable-Q, shorter transforms for faster high register response\n\
+                 cqt: constant-Q, a bin per third of a semitone, each note over as many of its periods\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["fft", "vq", "cqt"])
+            .default_value("fft"),
+    )
+    .arg(
+        Arg::with_name("window")
+            .long("window")
+            .value_name("WINDOW")
+            .help(
+                "The window applied before the transforms, against the leakage between neighbouring notes\n\
+                 rectangular: none, the narrowest peaks but the most leakage\n\
+                 hann, hamming, blackman-harris: less leakage, wider peaks\n\
+                 flat-top: the most accurate levels, the widest peaks\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["rectangular", "hann", "hamming", "blackman-harris", "flat-top"])
+            .default_value("hann"),
+    )
+    .arg(
+        Arg::with_name("input-type")
+            .short("i")
+            .long("input-type")
+            .value_name("TYPE")
+            .help(
+                "The kind of sound being analysed\n\
+                 voice: flattens formants, smooths vibrato and gates breath noise\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["instrument", "voice"])
+            .default_value("instrument"),
+    )
+    .arg(
+        Arg::with_name("classify")
+            .long("classify")
+            .value_name("MODE")
+            .help(
+                "Recognizes the instrument of each new input, at the start or after an idle input\n\
+                 suggest: shows the instrument and the input type suiting it\n\
+                 auto: also switches to that input type\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["suggest", "auto"]),
+    )
+    .arg(
+        Arg::with_name("notation")
+            .short("n")
+            .long("notation")
+            .value_name("LANGUAGE")
+            .help("English or Romance notation\n")
+            .next_line_help(true)
+            .possible_values(&["e", "r"])
+            .default_value("e"),
+    )
+    .arg(
+        Arg::with_name("chord-symbols")
+            .long("chord-symbols")
+            .value_name("CONVENTION")
+            .help(
+                "The convention the chords are written and read in\n\
+                 standard: Cmaj7, Cm7, Cdim, Caug\n\
+                 jazz: CΔ7, C−7, C°, C+\n\
+                 german: the standard symbols, B natural written H and B flat B\n\
+                 Applies to the displays, the charts, the setlists and the exports\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["standard", "jazz", "german"])
+            .default_value("standard"),
+    )
+    .arg(
+        Arg::with_name("halflife")
+            .short("f")
+            .long("halflife")
+            .value_name("SECONDS")
+            .help(
+                "The time in seconds for the dissonance to drop by half\n\
+                 Used as long as the harmony stays static\n",
+            )
+            .next_line_help(true)
+            .default_value("1.0")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.0 && f <= 100.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0 .. 100)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("aggregation")
+            .long("aggregation")
+            .value_name("MODE")
+            .help(
+                "How the dissonance of the frames is combined over time\n\
+                 exponential: decays by half every halflife, the bass more slowly\n\
+                 window: the average of the last --aggregation-window seconds\n\
+                 bar: the average since the start of the bar, at --tempo and --beats-per-bar\n\
+                 leaky: held at its peak, then drained to empty in --aggregation-window seconds\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["exponential", "window", "bar", "leaky"])
+            .default_value("exponential"),
+    )
+    .arg(
+        Arg::with_name("aggregation-window")
+            .long("aggregation-window")
+            .value_name("SECONDS")
+            .help("The seconds of the window and leaky aggregations\n")
+            .next_line_help(true)
+            .default_value("2.0")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.05 && f <= 60.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0.05 .. 60)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("change-halflife")
+            .long("change-halflife")
+            .value_name("SECONDS")
+            .help(
+                "The halflife used right after a harmonic change is detected\n\
+                 It then recovers towards the static halflife\n",
+            )
+            .next_line_help(true)
+            .default_value("0.2")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.0 && f <= 100.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0 .. 100)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("change-threshold")
+            .long("change-threshold")
+            .value_name("RATIO")
+            .help(
+                "How different the heard harmony must be to count as a change\n\
+                 0 always shortens the halflife, 1 never does\n",
+            )
+            .next_line_help(true)
+            .default_value("0.3")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.0 && f <= 1.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0 .. 1)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("style")
+            .short("s")
+            .long("style")
+            .value_name("STYLE")
+            .help(
+                "Musical style, setting how long the bass defines the harmony\n\
+                 Neutral, Jazz, Rock or Ambient\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["neutral", "jazz", "rock", "ambient"])
+            .default_value("neutral"),
+    )
+    .arg(
+        Arg::with_name("bass-decay")
+            .long("bass-decay")
+            .value_name("FACTOR")
+            .help(
+                "The decay time of bass frequencies, relative to the halflife\n\
+                 Overrides the value set by the style\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f > 0.0 && f <= 100.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0 .. 100)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("bass-split")
+            .long("bass-split")
+            .value_name("NOTE")
+            .help("The note under which heard frequencies count as bass\n")
+            .next_line_help(true)
+            .default_value("C3")
+            .validator(|s| match Note::from_name(&s) {
+                Some(_) => Ok(()),
+                None => Err("Argument is not a note (ie. C3, F#2, Bb1)".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("split")
+            .long("split")
+            .value_name("NOTE")
+            .help(
+                "Splits a piano at this key, scoring the right hand against the left one\n\
+                 The harmonics of the left hand over the split stay with it, both hands are shown\n",
+            )
+            .next_line_help(true)
+            .conflicts_with("crossover")
+            .validator(|s| match Note::from_name(&s) {
+                Some(_) => Ok(()),
+                None => Err("Argument is not a note (ie. C4, F#3, Bb3)".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("crossover")
+            .short("x")
+            .long("crossover")
+            .value_name("HERTZ")
+            .help(
+                "Only score against frequencies under this limit\n\
+                 Keeps the soloist's register out of the harmonic context\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 20.0 && f <= 20000.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (20 .. 20000)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("fold")
+            .long("fold")
+            .value_name("MODE")
+            .help(
+                "How the scores of a pitch class are shared between octaves\n\
+                 none: every note is scored on its own\n\
+                 weighted: octaves share scores, weighted by distance\n\
+                 above: octaves share scores from --fold-above upwards\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["none", "weighted", "above"])
+            .default_value("none"),
+    )
+    .arg(
+        Arg::with_name("fold-above")
+            .long("fold-above")
+            .value_name("NOTE")
+            .help("The note from which octaves are folded in 'above' mode\n")
+            .next_line_help(true)
+            .default_value("C4")
+            .validator(|s| match Note::from_name(&s) {
+                Some(_) => Ok(()),
+                None => Err("Argument is not a note (ie. C3, F#2, Bb1)".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("max-peaks")
+            .short("p")
+            .long("max-peaks")
+            .value_name("UINT")
+            .help(
+                "Maximum number of spectral peaks used for scoring\n\
+                 Higher values can have a significant performance cost\n",
+            )
+            .next_line_help(true)
+            .default_value("64")
+            .validator(|s| match s.parse::<u32>() {
+                Ok(1..=4096) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 4096)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("hps")
+            .long("hps")
+            .value_name("HARMONICS")
+            .help(
+                "Suppress the harmonics of the notes with a harmonic product spectrum over as many harmonics\n\
+                 Keeps the overtones of a single note from lighting up its octave and fifth\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<u32>() {
+                Ok(2..=8) => Ok(()),
+                Ok(_) => Err("Argument out of range: (2 .. 8)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("median")
+            .short("m")
+            .long("median")
+            .value_name("FRAMES")
+            .help(
+                "Number of spectra over which a median filter is applied\n\
+                 Rejects one-frame glitches like clicks and string squeaks\n\
+                 A value of 1 disables the filter\n",
+            )
+            .next_line_help(true)
+            .default_value("1")
+            .validator(|s| match s.parse::<u32>() {
+                Ok(1..=15) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 15)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("confidence")
+            .short("k")
+            .long("confidence")
+            .value_name("RATIO")
+            .help(
+                "Minimum stability of the scores for the suggestions to be updated\n\
+                 Avoids reshuffling during transients, 0 updates continuously\n",
+            )
+            .next_line_help(true)
+            .default_value("0")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.0 && f <= 1.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0 .. 1)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("suggest-every")
+            .long("suggest-every")
+            .value_name("CADENCE")
+            .help(
+                "When the suggestions shown are updated, the analysis running on underneath\n\
+                 Holds them still mid-phrase, the beats following the chart or --tempo and --beats-per-bar\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["continuous", "beat", "bar", "chord"])
+            .default_value("continuous"),
+    )
+    .arg(
+        Arg::with_name("smoothing")
+            .long("smoothing")
+            .value_name("SECONDS")
+            .help(
+                "The time in seconds for the displayed scores to move halfway to new ones\n\
+                 Calms the displays without slowing the analysis, 0 shows every frame as is\n",
+            )
+            .next_line_help(true)
+            .default_value("0")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.0 && f <= 10.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0 .. 10)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("scaling")
+            .long("scaling")
+            .value_name("SCALING")
+            .help(
+                "How the scores are mapped to colors, switched at runtime with 'c'\n\
+                 frame: from the best to the worst note now, to spot the best note\n\
+                 session: against the worst dissonance of the session so far\n\
+                 absolute: on a fixed dissonance scale, to judge the overall consonance\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["frame", "session", "absolute"])
+            .default_value("frame"),
+    )
+    .arg(
+        Arg::with_name("labels")
+            .long("labels")
+            .value_name("LABELS")
+            .help(
+                "The note names drawn over the scores\n\
+                 all, best: only the most consonant notes, key: only the notes of the key\n\
+                 (the song key, or the key heard so far), none\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["all", "best", "key", "none"])
+            .default_value("all"),
+    )
+    .arg(
+        Arg::with_name("best-labels")
+            .long("best-labels")
+            .value_name("COUNT")
+            .help("The number of notes named with '--labels best'\n")
+            .next_line_help(true)
+            .default_value("5")
+            .validator(|s| match s.parse::<usize>() {
+                Ok(1..=88) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 88)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("label-size")
+            .long("label-size")
+            .value_name("RATIO")
+            .help(
+                "The size of the note names, relative to the fretboard cells\n\
+                 Smaller names keep the colors readable in small windows\n",
+            )
+            .next_line_help(true)
+            .default_value("1")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.3 && f <= 1.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0.3 .. 1)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("boost")
+            .short("b")
+            .long("boost")
+            .value_name("RATIO")
+            .help(
+                "Analyse four times more often for a second after large spectral changes\n\
+                 The ratio of new spectral energy needed to trigger it (ie. 0.5)\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f > 0.0 && f <= 1.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0 .. 1)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("mono")
+            .long("mono")
+            .help(
+                "Detects the single pitch of a melodic line (voice, sax, violin) with YIN instead of the spectrum\n\
+                 More accurate and cheaper for a single line, the spectral options being ignored\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("reject-drums")
+            .long("reject-drums")
+            .help("Ignores drum-dominated frames, to play along drum loops\n"),
+    )
+    .arg(
+        Arg::with_name("midi-in")
+            .long("midi-in")
+            .value_name("PORT")
+            .help(
+                "Receive MIDI from this port, by index or part of its name\n\
+                 With a chart or setlist, program changes select the song instead\n\
+                 Requires building with the midi feature\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("midi-role")
+            .long("midi-role")
+            .value_name("ROLE")
+            .help(
+                "How the MIDI input is used\n\
+                 context: the held notes are the harmony, instead of the audio\n\
+                 player: the held notes are what is played, scored without capturing audio\n\
+                 Defaults to context\n",
+            )
+            .next_line_help(true)
+            .requires("midi-in")
+            .possible_values(&["context", "player"]),
+    )
+    .arg(
+        Arg::with_name("midi-out")
+            .long("midi-out")
+            .value_name("PORT")
+            .help(
+                "Publish the detected chords to this port, by index or part of its name\n\
+                 Requires building with the midi feature\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("midi-out-messages")
+            .long("midi-out-messages")
+            .value_name("MESSAGES")
+            .help(
+                "How the detected chords are published\n\
+                 notes: the chord notes, held until the chord changes\n\
+                 cc: the root (CC 20) and quality (CC 21), 127 meaning no chord\n\
+                 Defaults to notes\n",
+            )
+            .next_line_help(true)
+            .requires("midi-out")
+            .possible_values(&["notes", "cc"]),
+    )
+    .arg(
+        Arg::with_name("pads")
+            .long("pads")
+            .value_name("PORT")
+            .help(
+                "Light the pads of a Launchpad or a Push on this port with the scores, by index or part of its name\n\
+                 Requires building with the midi feature\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("pads-model")
+            .long("pads-model")
+            .value_name("MODEL")
+            .help(
+                "The pad controller, the Launchpads being switched to programmer mode\n\
+                 The Push 2 is lit through its user port\n\
+                 Defaults to launchpad-x\n",
+            )
+            .next_line_help(true)
+            .requires("pads")
+            .possible_values(&["launchpad-x", "launchpad-mini", "launchpad-pro", "push2"]),
+    )
+    .arg(
+        Arg::with_name("pads-note")
+            .long("pads-note")
+            .value_name("NOTE")
+            .help(
+                "The note of the bottom left pad, ie. E1\n\
+                 The pads go up a semitone to the right and a fourth to the top\n\
+                 Defaults to C2\n",
+            )
+            .next_line_help(true)
+            .requires("pads")
+            .validator(|s| match Note::from_name(&s) {
+                Some(_) => Ok(()),
+                None => Err("Argument is not a note (ie. E1)".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("reaper")
+            .long("reaper")
+            .value_name("HOST:PORT")
+            .help(
+                "Annotate the REAPER project with the detected chords and key\n\
+                 Through the REAPER web interface, ie. localhost:8080\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.rsplit(':').next().map(|p| p.parse::<u16>()) {
+                Some(Ok(_)) if s.contains(':') => Ok(()),
+                _ => Err("Argument is not an address (ie. localhost:8080)".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("reaper-action")
+            .long("reaper-action")
+            .value_name("ACTION")
+            .help(
+                "The REAPER action run on every change, once the project state is set\n\
+                 The state holds the chord and key in the ImproVe section\n\
+                 Inserts an unnamed marker by default, the ID of assets/reaper/improve_marker.lua\n\
+                 loaded as an action inserts one named after the chord or key\n",
+            )
+            .next_line_help(true)
+            .requires("reaper"),
+    )
+    .arg(
+        Arg::with_name("talkback")
+            .long("talkback")
+            .value_name("TRIGGER")
+            .help(
+                "Raise a trigger while the heard notes stay dissonant, released once they resolve\n\
+                 serial:DEVICE writes 1 then 0, gpio:PIN drives a sysfs pin high then low\n\
+                 midi:PORT sends CC 22 at 127 then 0, requiring the midi feature\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("talkback-threshold")
+            .long("talkback-threshold")
+            .value_name("SCORE")
+            .help(
+                "The dissonance of the heard notes raising the trigger, from 0 to 1\n\
+                 Defaults to 0.7\n",
+            )
+            .next_line_help(true)
+            .requires("talkback")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) if f >= 0.0 && f <= 1.0 => Ok(()),
+                Ok(_) => Err("Argument out of range: (0 .. 1)".to_owned()),
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("talkback-duration")
+            .long("talkback-duration")
+            .value_name("SECONDS")
+            .help(
+                "How long the dissonance must last to raise the trigger\n\
+                 Defaults to 2 seconds\n",
+            )
+            .next_line_help(true)
+            .requires("talkback")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) if f >= 0.0 && f <= 60.0 => Ok(()),
+                Ok(_) => Err("Argument out of range: (0 .. 60)".to_owned()),
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("mirror")
+            .long("mirror")
+            .value_name("HOST:PORT")
+            .help(
+                "Mirror the display to a teacher running the mirror subcommand\n\
+                 The teacher sees the scores read-only, and annotates the session back\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.rsplit(':').next().map(|p| p.parse::<u16>()) {
+                Some(Ok(_)) if s.contains(':') => Ok(()),
+                _ => Err("Argument is not an address (ie. 192.168.1.10:9100)".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("device")
+            .long("device")
+            .value_name("NAME|INDEX")
+            .help(
+                "The capture device, by index or part of its name, instead of the default one\n\
+                 ie. --device 1 or --device USB, the devices subcommand lists them\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("input-latency")
+            .long("input-latency")
+            .value_name("MS")
+            .help(
+                "How late the captured audio is, to follow charts at the time the player hears\n\
+                 Defaults to the capture buffer, plus 200ms for Bluetooth devices\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<usize>() {
+                Ok(0..=2000) => Ok(()),
+                Ok(_) => Err("Argument out of range: (0 .. 2000)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("audio-driver")
+            .long("audio-driver")
+            .value_name("DRIVER")
+            .help(
+                "The SDL audio driver, to work around a broken default one\n\
+                 pulseaudio, pipewire, alsa, wasapi, coreaudio... as built into SDL\n",
+            )
+            .next_line_help(true)
+            .validator(|s| {
+                let drivers = sdl2::audio::drivers().collect::<Vec<&str>>();
+                if drivers.contains(&s.as_str()) {
+                    Ok(())
+                } else {
+                    Err(format!("Driver not built into SDL, available: {}", drivers.join(", ")))
+                }
+            }),
+    )
+    .arg(
+        Arg::with_name("loopback")
+            .long("loopback")
+            .value_name("MONITOR")
+            .help(
+                "Capture what the other applications play, ie. a backing track, instead of the input\n\
+                 The monitor of the default output, or one by index or part of its name\n\
+                 Requires PulseAudio or PipeWire, the devices subcommand lists the monitors\n",
+            )
+            .next_line_help(true)
+            .min_values(0)
+            .max_values(1)
+            .conflicts_with("device"),
+    )
+    .arg(
+        Arg::with_name("prompt")
+            .long("prompt")
+            .help(
+                "Draw a practice prompt at the start of every session, ie. 'Improvise in 3/4'\n\
+                 Shown in the stats and the session report\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("prompt-file")
+            .long("prompt-file")
+            .value_name("FILE")
+            .help(
+                "Add the prompts of a file to the built-in ones, one per line, implies --prompt\n\
+                 Lines starting with '#' are comments\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("backend")
+            .long("backend")
+            .value_name("BACKEND")
+            .help(
+                "The audio library capturing the input\n\
+                 cpal requires building with --features cpal-backend, --audio-driver only applies to sdl\n\
+                 jack registers a client to patch, --device then names a port to connect to it\n\
+                 jack requires building with --features jack-backend\n\
+                 udp receives the audio sent from the network to the --listen port\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["sdl", "cpal", "jack", "udp"])
+            .default_value("sdl"),
+    )
+    .arg(
+        Arg::with_name("input")
+            .long("input")
+            .value_name("FILE")
+            .help(
+                "Read raw PCM from a named pipe, or the standard input for -, instead of capturing\n\
+                 ie. arecord -f FLOAT_LE -t raw - | improve --input -\n\
+                 At the --samplerate rate, interleaved if --channels is given, ending with the stream\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("input-format")
+            .long("input-format")
+            .value_name("FORMAT")
+            .help(
+                "The little endian samples of --input: f32 (FLOAT_LE), s16 (S16_LE) or s32 (S32_LE)\n\
+                 Defaults to f32\n",
+            )
+            .next_line_help(true)
+            .requires("input")
+            .possible_values(&["f32", "s16", "s32"]),
+    )
+    .arg(
+        Arg::with_name("listen")
+            .long("listen")
+            .value_name("PORT")
+            .help("The UDP port the udp backend receives the audio on, at the --samplerate rate\n")
+            .next_line_help(true)
+            .default_value("5004")
+            .validator(|s| match s.parse::<u16>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("Argument is not a port".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("udp-format")
+            .long("udp-format")
+            .value_name("FORMAT")
+            .help(
+                "The packets of the udp backend, interleaved if --channels is given\n\
+                 f32: raw little endian floats, ie. from arecord -f FLOAT_LE -t raw\n\
+                 rtp: RTP with 16 bits samples (L16), reordered and lost packets replaced by silence\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["f32", "rtp"])
+            .default_value("f32"),
+    )
+    .arg(
+        Arg::with_name("jack-client")
+            .long("jack-client")
+            .value_name("NAME")
+            .help("The name of the JACK client\n")
+            .next_line_help(true)
+            .default_value("ImproVe"),
+    )
+    .arg(
+        Arg::with_name("jack-port")
+            .long("jack-port")
+            .value_name("NAME")
+            .help("The name of the input port of the JACK client\n")
+            .next_line_help(true)
+            .default_value("input"),
+    )
+    .arg(
+        Arg::with_name("osc")
+            .long("osc")
+            .value_name("PORT")
+            .help(
+                "Receive OSC messages from external tools on a UDP port\n\
+                 /improve/annotate \"text\" marks the spectrogram and the session report\n\
+                 /improve/reset, /improve/style and /improve/halflife SECONDS change the analysis\n\
+                 /improve/undo, /improve/redo and /improve/revert undo the changes, back to the launch options\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<u16>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("Argument is not a port".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("osc-host")
+            .long("osc-host")
+            .value_name("ADDRESS")
+            .help(
+                "The address the OSC port is opened on, 0.0.0.0 for the whole network\n\
+                 Defaults to 127.0.0.1, only the tools of this machine\n",
+            )
+            .next_line_help(true)
+            .requires("osc"),
+    )
+    .arg(
+        Arg::with_name("voicings")
+            .long("voicings")
+            .value_name("INSTRUMENT")
+            .help(
+                "Suggests shell, drop-2 and quartal voicings of the chord, the most consonant first\n\
+                 The chord of the chart when following one, drawn as chord diagrams or keyboards\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["guitar", "keyboard"]),
+    )
+    .arg(
+        Arg::with_name("latency")
+            .long("latency")
+            .help(
+                "Shows how late the display is, from the audio captured to the frame drawn, and its stages
+\
+                 The samples and the scores queued tell an analysis or a display falling behind
+",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("latency-log")
+            .long("latency-log")
+            .value_name("FILE")
+            .help("Logs the latency of every frame drawn to a CSV file, in milliseconds\n")
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("state")
+            .long("state")
+            .value_name("FILE")
+            .help(
+                "Restore the placement of the windows, what the graph shows, the color scaling, the style\n\
+                 and the halflife from a file, saved there again once the windows are closed\n\
+                 The options given on the command line are kept over the saved ones\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("config")
+            .long("config")
+            .value_name("FILE")
+            .help(
+                "Read the display, controller and keyboard settings from a config file\n\
+                 [layout]: a row of panels per line, among graph, fretboard, notes,\n\
+                 spectrogram, chord, stats, controls, lyrics, loudness, channels, attribution, voicings,\n\
+                 counterpoint and continuo\n\
+                 A line with '---' starts a new window, optionally followed by its placement:\n\
+                 display=INDEX opens it on another monitor, fullscreen fills the monitor\n\
+                 [controller]: game controller buttons triggering actions (ie. a = next-chord),\n\
+                 and axes setting the halflife (ie. rightx = halflife 0.1 4)\n\
+                 [keys]: keys triggering actions (ie. f1 = help), replacing the default keys\n\
+                 of the actions remapped, a key being bound to one action at most\n\
+                 The keys are letters, digits, punctuation, space, escape, pageup, pagedown and f1 to f12\n\
+                 The actions are quit, play-chord, intonation, detail, next-song, previous-song,\n\
+                 next-chord, reset, freeze, style, scaling and help\n\
+                 [calibration]: rate = FACTOR, the capture clock correction the calibrate subcommand saves\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("chart")
+            .long("chart")
+            .value_name("FILE")
+            .help(
+                "Follow a chord chart at the tempo, the chords being the harmony instead of the audio\n\
+                 Plain text (| C | Am F |), ChordPro or iReal Pro (irealbook://, not irealb://) charts\n\
+                 The sixth, sus2 and added note chords are refused, the others kept to their seventh\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("cantus")
+            .long("cantus")
+            .value_name("FILE")
+            .help(
+                "Play a counterpoint against a cantus firmus followed at the tempo, its notes being the harmony\n\
+                 A MIDI or MusicXML melody, the notes played judged by the rules of the species\n",
+            )
+            .next_line_help(true)
+            .conflicts_with_all(&["chart", "setlist"]),
+    )
+    .arg(
+        Arg::with_name("species")
+            .long("species")
+            .value_name("SPECIES")
+            .help(
+                "The notes played against each note of the cantus\n\
+                 first: one, second: two, the second passing, third: four, the weak ones passing\n\
+                 Defaults to first\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["first", "second", "third"])
+            .requires("cantus"),
+    )
+    .arg(
+        Arg::with_name("figured-bass")
+            .long("figured-bass")
+            .value_name("FILE")
+            .help(
+                "Play a continuo over a figured bass followed at the tempo, the bass and its figures being the harmony\n\
+                 The left hand plays the bass, the right hand realizes the figures, each figure being judged\n\
+                 Splits the keyboard at C4 unless --split is given\n",
+            )
+            .next_line_help(true)
+            .conflicts_with_all(&["chart", "setlist", "cantus", "crossover"]),
+    )
+    .arg(
+        Arg::with_name("setlist")
+            .long("setlist")
+            .value_name("FILE")
+            .help(
+                "Follow a setlist of charts, switched with Page Up / Page Down or MIDI program changes\n\
+                 One song per line: a chart path, then tempo=BPM transpose=SEMITONES key=KEY name=NAME\n",
+            )
+            .next_line_help(true)
+            .conflicts_with("chart"),
+    )
+    .arg(
+        Arg::with_name("all-keys")
+            .long("all-keys")
+            .value_name("CYCLE")
+            .help(
+                "Take the chart or the setlist songs through all 12 keys\n\
+                 fourths: up a fourth every time, the cycle of fourths\n\
+                 random: every key once, in a random order\n",
+            )
+            .next_line_help(true)
+            .possible_values(&["fourths", "random"]),
+    )
+    .arg(
+        Arg::with_name("chord-reset")
+            .long("chord-reset")
+            .value_name("KEPT")
+            .help(
+                "Discount the dissonance accumulated at every chord change of the chart or the setlist\n\
+                 The part kept (0 .. 1), 0 starting every chord afresh, so the previous one does not linger\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.0 && f <= 1.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0 .. 1)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("passes")
+            .long("passes")
+            .value_name("COUNT")
+            .help(
+                "The number of times the chart is played in each key of the exercise\n\
+                 Defaults to 1\n",
+            )
+            .next_line_help(true)
+            .requires("all-keys")
+            .validator(|s| match s.parse::<usize>() {
+                Ok(1..=100) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 100)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("chord-sheet")
+            .long("chord-sheet")
+            .value_name("FILE")
+            .help(
+                "Write a bar aligned chord sheet of the session to this file on exit\n\
+                 ChordPro for the .cho, .chopro and .chordpro extensions, plain text otherwise\n\
+                 Split sessions get a sheet each, numbered before the extension\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("split-silence")
+            .long("split-silence")
+            .value_name("SECONDS")
+            .help(
+                "Start a new session after this many seconds of silence\n\
+                 Every tune of a long rehearsal then gets its own report and chord sheet\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<usize>() {
+                Ok(1..=3600) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 3600)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("tempo")
+            .long("tempo")
+            .value_name("BPM")
+            .help(
+                "The tempo of the chart and the chord sheet\n\
+                 Defaults to the chart tempo, or is estimated from the notes played for the sheet\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<f32>() {
+                Ok(t) => {
+                    if t >= 20f32 && t <= 400f32 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (20 .. 400)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("beats-per-bar")
+            .long("beats-per-bar")
+            .value_name("BEATS")
+            .help("The number of beats in a bar of the chord sheet and of the bar aggregation\n")
+            .next_line_help(true)
+            .default_value("4")
+            .validator(|s| match s.parse::<usize>() {
+                Ok(1..=16) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 16)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("agc-window")
+            .long("agc-window")
+            .value_name("SECONDS")
+            .help(
+                "The window over which the automatic gain control evens out the level before the FFT\n\
+                 Quiet instruments are raised and loud ones lowered, the meters still show the level captured\n",
+            )
+            .next_line_help(true)
+            .default_value("3")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) if f >= 0.1 && f <= 60f32 => Ok(()),
+                Ok(_) => Err("Argument out of range: (0.1 .. 60)".to_owned()),
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("no-agc")
+            .long("no-agc")
+            .help("Disables the automatic gain control, analysing the level captured\n"),
+    )
+    .arg(
+        Arg::with_name("gate")
+            .long("gate")
+            .value_name("DB")
+            .help(
+                "Silence the input under this RMS level, ie. -50, so room hiss is not scored\n\
+                 The gate opens over --gate-attack and closes over --gate-release\n",
+            )
+            .next_line_help(true)
+            .allow_hyphen_values(true)
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) if f >= -120f32 && f <= 0f32 => Ok(()),
+                Ok(_) => Err("Argument out of range: (-120 .. 0)".to_owned()),
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("gate-attack")
+            .long("gate-attack")
+            .value_name("SECONDS")
+            .help(
+                "How long the noise gate takes to open\n\
+                 Defaults to 10ms\n",
+            )
+            .next_line_help(true)
+            .requires("gate")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) if f >= 0f32 && f <= 5f32 => Ok(()),
+                Ok(_) => Err("Argument out of range: (0 .. 5)".to_owned()),
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("gate-release")
+            .long("gate-release")
+            .value_name("SECONDS")
+            .help(
+                "How long the noise gate takes to close\n\
+                 Defaults to 200ms\n",
+            )
+            .next_line_help(true)
+            .requires("gate")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) if f >= 0f32 && f <= 5f32 => Ok(()),
+                Ok(_) => Err("Argument out of range: (0 .. 5)".to_owned()),
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("idle-after")
+            .long("idle-after")
+            .value_name("SECONDS")
+            .help(
+                "Pause the analysis after this much silence, the displays showing idle until playing resumes\n\
+                 0 to keep analysing the silence\n",
+            )
+            .next_line_help(true)
+            .default_value("2")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) if f >= 0f32 && f <= 600f32 => Ok(()),
+                Ok(_) => Err("Argument out of range: (0 .. 600)".to_owned()),
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("discard")
+            .short("d")
+            .long("discard")
+            .help("Allows the program to discard data if latency is too high\n"),
+    )
+    .arg(
+        Arg::with_name("channels")
+            .long("channels")
+            .value_name("UINT")
+            .help("The number of channels captured, with the sdl and udp backends or --input\n")
+            .next_line_help(true)
+            .default_value("1")
+            .validator(|s| match s.parse::<u8>() {
+                Ok(1..=8) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 8)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("channel")
+            .long("channel")
+            .value_name("MODE")
+            .help(
+                "How the captured channels are analysed\n\
+                 mix: mixed down, a channel number from 1: that channel only\n\
+                 split: every channel apart, ie. guitar on 1 and keys on 2, shown side by side\n",
+            )
+            .next_line_help(true)
+            .default_value("mix")
+            .validator(|s| match ChannelMode::from_name(&s) {
+                Some(_) => Ok(()),
+                None => Err("Argument is not mix, split or a channel number".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("low-latency")
+            .long("low-latency")
+            .help(
+                "Requests small capture buffers, and small periods from the sound server\n\
+                 More responsive but more likely to drop audio, WASAPI stays in shared mode\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("hop")
+            .long("hop")
+            .value_name("SIZE")
+            .help(
+                "The samples between the starts of two analysed packets, or a percentage of the resolution\n\
+                 ie. --hop 25% analyses four times per resolution, each packet reusing the last 75%\n\
+                 Defaults to the resolution, the packets following each other\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match parse_hop(&s, 1_048_576) {
+                Some(_) => Ok(()),
+                None => Err("Argument is not a number of samples or a percentage (1% .. 100%)".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("terminal")
+            .short("t")
+            .long("terminal")
+            .help("Use the terminal instead of SDL2 windows\n"),
+    )
+    .arg(
+        Arg::with_name("save-session")
+            .long("save-session")
+            .value_name("FILE")
+            .help(
+                "Save the notes played and the chords of the session on exit, for the compare subcommand\n\
+                 The chords are written in the standard symbols, whatever the --chord-symbols\n\
+                 Sessions split at long silences are numbered, as FILE-2, FILE-3...\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("record")
+            .long("record")
+            .value_name("FILE")
+            .help(
+                "Record the audio analysed to a WAV file, to review the session against the suggestions\n\
+                 The first channel when they are analysed apart, as 32 bits float mono\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("suppress-feedback")
+            .long("suppress-feedback")
+            .help(
+                "Removes the chords played back from the capture, when the microphone hears\n\
+                 the speakers, learning the echo while they play\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("feedback-length")
+            .long("feedback-length")
+            .value_name("MS")
+            .help(
+                "The longest echo of the speakers removed, its delay included\n\
+                 Defaults to 50ms\n",
+            )
+            .next_line_help(true)
+            .requires("suppress-feedback")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 5.0 && f <= 500.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (5 .. 500)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("record-size")
+            .long("record-size")
+            .value_name("MB")
+            .help("Move on to a new recording file, numbered as FILE-2, FILE-3..., at this size\n")
+            .next_line_help(true)
+            .requires("record")
+            .validator(|s| match s.parse::<u64>() {
+                Ok(1..=4095) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 4095)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("record-minutes")
+            .long("record-minutes")
+            .value_name("MINUTES")
+            .help("Move on to a new recording file, numbered as FILE-2, FILE-3..., after this duration\n")
+            .next_line_help(true)
+            .requires("record")
+            .validator(|s| match s.parse::<f32>() {
+                Ok(f) => {
+                    if f >= 0.1 && f <= 1440.0 {
+                        Ok(())
+                    } else {
+                        Err("Argument out of range: (0.1 .. 1440)".to_owned())
+                    }
+                }
+                Err(_) => Err("Argument is not a float".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("headless")
+            .long("headless")
+            .help(
+                "Show nothing, running until the analysis ends, ie. with the analyze subcommand\n\
+                 For scripts and automated checks, along with --json\n",
+            )
+            .next_line_help(true)
+            .conflicts_with_all(&["terminal", "remote-term"]),
+    )
+    .arg(
+        Arg::with_name("json")
+            .long("json")
+            .value_name("FILE")
+            .help(
+                "Write every analysed frame as a line of JSON, to the standard output for -\n\
+                 The status lines then go to the standard error\n\
+                 With the time, chord, confidence, level, loudness, warmup, onset, annotations, input events,\n\
+                 prompt, session metrics and scores, after a hello line giving the schema version\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("widget")
+            .long("widget")
+            .value_name("PORT")
+            .help(
+                "Serve the frames to the apps embedding ImproVe, on a TCP port\n\
+                 The messages are those of --json, read with the improve-client crate\n",
+            )
+            .next_line_help(true)
+            .validator(|s| match s.parse::<u16>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("Argument is not a port".to_owned()),
+            }),
+    )
+    .arg(
+        Arg::with_name("widget-host")
+            .long("widget-host")
+            .value_name("ADDRESS")
+            .help(
+                "The address the widget port is opened on, 0.0.0.0 for the whole network\n\
+                 Defaults to 127.0.0.1, only the apps of this machine\n",
+            )
+            .next_line_help(true)
+            .requires("widget"),
+    )
+    .arg(
+        Arg::with_name("noclear")
+            .short("c")
+            .long("noclear")
+            .help("Prevents the program from using termios\n"),
+    )
+    .arg(
+        Arg::with_name("remote-term")
+            .long("remote-term")
+            .help(
+                "Use the terminal, with 16 colors and 4 redraws per second by default\n\
+                 For running on a studio machine and watching over SSH\n",
+            )
+            .next_line_help(true),
+    )
+    .arg(
+        Arg::with_name("refresh")
+            .long("refresh")
+            .value_name("HZ")
+            .help(
+                "The highest number of terminal redraws per second\n\
+                 Frames arriving faster are skipped, for SSH sessions and slow terminals\n",
+            )
+            .next_line_help(true)
+            .default_value("10")
+            .validator(|s| match s.parse::<usize>() {
+                Ok(1..=60) => Ok(()),
+                Ok(_) => Err("Argument out of range: (1 .. 60)".to_owned()),
+                Err(_) => Err("Argument is not an unsigned int".to_owned()),
+            }),
+    )
+    .subcommand(
+        SubCommand::with_name("hum")
+            .about("Reports the notes and likely keys of a hummed phrase")
+            .arg(
+                Arg::with_name("duration")
+                    .short("d")
+                    .long("duration")
+                    .value_name("SECONDS")
+                    .help("How long to listen for\n")
+                    .next_line_help(true)
+                    .default_value("5")
+                    .validator(|s| match s.parse::<u32>() {
+                        Ok(1..=600) => Ok(()),
+                        Ok(_) => Err("Argument out of range: (1 .. 600)".to_owned()),
+                        Err(_) => Err("Argument is not an unsigned int".to_owned()),
+                    }),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("compare")
+            .about("Compares two sessions saved with --save-session, the older one first")
+            .arg(
+                Arg::with_name("before")
+                    .value_name("SESSION")
+                    .help("The earlier session\n")
+                    .required(true)
+                    .index(1),
+            )
+            .arg(
+                Arg::with_name("after")
+                    .value_name("SESSION")
+                    .help("The later session\n")
+                    .required(true)
+                    .index(2),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("calibrate")
+            .about("Measures how far off the capture clock runs, from a reference tone")
+            .arg(
+                Arg::with_name("reference")
+                    .long("reference")
+                    .value_name("HZ")
+                    .help("The frequency of the reference, ie. a tuning fork or a generated tone\n")
+                    .next_line_help(true)
+                    .default_value("440")
+                    .validator(|s| match s.parse::<f32>() {
+                        Ok(f) if f >= 50.0 && f <= 5000.0 => Ok(()),
+                        Ok(_) => Err("Argument out of range: (50 .. 5000)".to_owned()),
+                        Err(_) => Err("Argument is not a float".to_owned()),
+                    }),
+            )
+            .arg(
+                Arg::with_name("duration")
+                    .short("d")
+                    .long("duration")
+                    .value_name("SECONDS")
+                    .help("How long to listen for\n")
+                    .next_line_help(true)
+                    .default_value("5")
+                    .validator(|s| match s.parse::<u32>() {
+                        Ok(1..=60) => Ok(()),
+                        Ok(_) => Err("Argument out of range: (1 .. 60)".to_owned()),
+                        Err(_) => Err("Argument is not an unsigned int".to_owned()),
+                    }),
+            )
+            .arg(
+                Arg::with_name("save")
+                    .long("save")
+                    .value_name("FILE")
+                    .help(
+                        "Save the correction to the calibration section of a config file,\n\
+                         applied to the analysis when read with --config\n",
+                    )
+                    .next_line_help(true),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("analyze")
+            .about("Analyses a WAV recording instead of the input, the analysis ending with it")
+            .arg(
+                Arg::with_name("file")
+                    .value_name("FILE")
+                    .help("The WAV file, integer or float PCM, mixed down to mono\n")
+                    .required(true)
+                    .index(1),
+            )
+            .arg(
+                Arg::with_name("speed")
+                    .long("speed")
+                    .value_name("UINT")
+                    .help("How many times faster than its own pace to read the recording\n")
+                    .next_line_help(true)
+                    .default_value("1")
+                    .validator(|s| match s.parse::<u32>() {
+                        Ok(1..=32) => Ok(()),
+                        Ok(_) => Err("Argument out of range: (1 .. 32)".to_owned()),
+                        Err(_) => Err("Argument is not an unsigned int".to_owned()),
+                    }),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("mirror")
+            .about(
+                "Shows the display of a student started with --mirror, instead of the input\n\
+                 Lines typed in the console, or OSC annotations, are sent back to the student",
+            )
+            .arg(
+                Arg::with_name("port")
+                    .long("port")
+                    .value_name("PORT")
+                    .help("The TCP port the student connects to\n")
+                    .next_line_help(true)
+                    .default_value("9100")
+                    .validator(|s| match s.parse::<u16>() {
+                        Ok(_) => Ok(()),
+                        Err(_) => Err("Argument is not a port".to_owned()),
+                    }),
+            )
+            .arg(
+                Arg::with_name("host")
+                    .long("host")
+                    .value_name("ADDRESS")
+                    .help(
+                        "The address the port is opened on, 0.0.0.0 for the students of the network\n\
+                         Only those of this machine connect to the default one\n",
+                    )
+                    .next_line_help(true)
+                    .default_value("127.0.0.1"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("devices")
+            .about("Lists the capture devices, by index, with the spec each opens with"),
+    )
+    .subcommand(
+        SubCommand::with_name("testsignal")
+            .about("Analyses a generated signal instead of the input, to check the whole chain")
+            .arg(
+                Arg::with_name("type")
+                    .long("type")
+                    .value_name("SIGNAL")
+                    .help(
+                        "white or pink noise, a sweep from 50Hz to 5kHz every 10s,\n\
+                         or a C major chord, which should score its notes as consonant\n",
+                    )
+                    .next_line_help(true)
+                    .possible_values(&["white", "pink", "sweep", "chord"])
+                    .default_value("sweep"),
+            ),
+    )
+    .subcommand(
+        SubCommand::with_name("tone")
+            .about("Plays reference pitches or chords through the output device")
+            .arg(
+                Arg::with_name("note")
+                    .short("n")
+                    .long("note")
+                    .value_name("NOTE")
+                    .help("The notes to play, repeat or separate with commas for chords\n")
+                    .next_line_help(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .default_value("A4")
+                    .validator(|s| match Note::from_name(&s) {
+                        Some(_) => Ok(()),
+                        None => Err("Argument is not a note (ie. C3, F#2, Bb1)".to_owned()),
+                    }),
+            )
+            .arg(
+                Arg::with_name("temperament")
+                    .short("t")
+                    .long("temperament")
+                    .value_name("TEMPERAMENT")
+                    .help("The tuning system\n")
+                    .next_line_help(true)
+                    .possible_values(&["equal", "just", "pythagorean"])
+                    .default_value("equal"),
+            )
+            .arg(
+                Arg::with_name("tonic")
+                    .short("k")
+                    .long("tonic")
+                    .value_name("NOTE")
+                    .help(
+                        "The tonic the temperament is built from\n\
+                         Defaults to the lowest note played\n",
+                    )
+                    .next_line_help(true)
+                    .validator(|s| match Note::from_name(&s) {
+                        Some(_) => Ok(()),
+                        None => Err("Argument is not a note (ie. C3, F#2, Bb1)".to_owned()),
+                    }),
+            )
+            .arg(
+                Arg::with_name("duration")
+                    .short("d")
+                    .long("duration")
+                    .value_name("SECONDS")
+                    .help("How long to play for\n")
+                    .next_line_help(true)
+                    .default_value("5")
+                    .validator(|s| match s.parse::<f32>() {
+                        Ok(f) => {
+                            if f > 0.0 && f <= 600.0 {
+                                Ok(())
+                            } else {
+                                Err("Argument out of range: (0 .. 600)".to_owned())
+                            }
+                        }
+                        Err(_) => Err("Argument is not a float".to_owned()),
+                    }),
+            ),
+    )
+}
+
+// Keyboard players scored from their notes, instead of the analysed audio
+fn is_midi_player(matches: &ArgMatches) -> bool {
+    matches.is_present("midi-in") && matches.value_of("midi-role") == Some("player")
 }
 
-// Audio callback object, simply allocates and transfers to a sender
-struct Recorder {
+// The capture opened, or the other source started
+struct Input {
+    rate: i32,
+    watchdog: Watchdog,
+    // The rings of the capture callbacks, one per stream, none for the sources sending packets
+    consumers: Vec<ring::Consumer>,
+}
+
+// Feed a test signal to the analysis, or capture the input with the backend chosen
+fn open_input(
+    matches: &ArgMatches,
+    audio: &AudioSubsystem,
+    spec: &AudioSpecDesired,
+    channel_mode: ChannelMode,
+    streams: usize,
+    timeline: &Timeline,
     audio_sender: Sender<Vec<f32>>,
+) -> Result<Input, Error> {
+    let samplerate = spec.freq.unwrap();
+    let channels = spec.channels.unwrap();
+    let driver = audio.current_audio_driver().to_owned();
+    let mut ring_consumers = Vec::new();
+    let test_signal = matches
+        .subcommand_matches("testsignal")
+        .map(|m| testsignal::Signal::from_name(m.value_of("type").unwrap()).unwrap());
+    let (rate, watchdog) = if let Some(signal) = test_signal {
+        testsignal::start(signal, samplerate, audio_sender, timeline.clone());
+        (samplerate, Watchdog::none())
+    } else if let Some(matches) = matches.subcommand_matches("analyze") {
+        // Analyse a recording, at its own rate
+        let path = matches.value_of("file").unwrap();
+        let recording = wav::Wav::load(path)?;
+        status!(
+            "Recording = {}, {} Hz, {:.1}s",
+            path,
+            recording.rate,
+            recording.duration()
+        );
+        if recording.rate < MIN_RATE {
+            return Err(Error::Option {
+                option: "analyze",
+                reason: format!("the recording is sampled under the {} Hz the analysis needs", MIN_RATE),
+            });
+        }
+        let frequency = recording.rate;
+        let speed = matches.value_of("speed").unwrap().parse::<u32>().unwrap();
+        wav::start(recording, audio_sender, timeline.clone(), speed);
+        (frequency, Watchdog::none())
+    } else if let Some(path) = matches.value_of("input") {
+        // The terminal display reads its keys from the standard input
+        if path == "-" && (matches.is_present("terminal") || matches.is_present("remote-term")) {
+            return Err(Error::Option {
+                option: "input",
+                reason: "the terminal display reads the standard input, use the SDL display or --headless"
+                    .to_owned(),
+            });
+        }
+        pcm_input::start(
+            path,
+            pcm_input::Format::from_name(matches.value_of("input-format").unwrap_or("f32")).unwrap(),
+            channels as usize,
+            channel_mode,
+            audio_sender,
+            timeline.clone(),
+        )?;
+        timeline.set_rate(samplerate);
+        timeline.set_latency(input_latency(matches, 0f32, None));
+        (samplerate, Watchdog::none())
+    } else if is_midi_player(matches) {
+        // The notes are scored directly, nothing is captured
+        (samplerate, Watchdog::none())
+    } else if matches.subcommand_matches("mirror").is_some() {
+        // The student analyses, nothing is captured
+        (MIN_RATE, Watchdog::none())
+    } else if matches.value_of("backend") == Some("cpal") {
+        #[cfg(feature = "cpal-backend")]
+        {
+            let (input, consumer) = cpal_input::open(
+                matches.value_of("device"),
+                samplerate as u32,
+                spec.samples,
+                timeline.clone(),
+            )?;
+            ring_consumers.push(consumer);
+            status!("Capture Device = {}", input.name);
+            status!("Capture Rate = {}", input.rate);
+            let frequency = input.rate;
+            timeline.set_rate(frequency);
+            let buffer = input.samples.map_or(0f32, |s| s as f32 / frequency as f32);
+            timeline.set_latency(input_latency(matches, buffer, Some(&input.name)));
+            if frequency < MIN_RATE {
+                return Err(Error::UnsupportedRate {
+                    driver: "cpal".to_owned(),
+                    rate: frequency,
+                    min: MIN_RATE,
+                });
+            }
+            (frequency, Watchdog::keep(Some(capture::Capture::Cpal(input))))
+        }
+        #[cfg(not(feature = "cpal-backend"))]
+        {
+            return Err(Error::Option {
+                option: "backend",
+                reason: "the cpal backend requires building with --features cpal-backend".to_owned(),
+            });
+        }
+    } else if matches.value_of("backend") == Some("udp") {
+        udp_input::listen(
+            matches.value_of("listen").unwrap().parse::<u16>().unwrap(),
+            udp_input::Format::from_name(matches.value_of("udp-format").unwrap()).unwrap(),
+            channels as usize,
+            channel_mode,
+            audio_sender,
+            timeline.clone(),
+        )?;
+        timeline.set_rate(samplerate);
+        timeline.set_latency(input_latency(matches, 0f32, None));
+        (samplerate, Watchdog::none())
+    } else if matches.value_of("backend") == Some("jack") {
+        #[cfg(feature = "jack-backend")]
+        {
+            let (input, consumer) = jack_input::open(
+                matches.value_of("jack-client").unwrap(),
+                matches.value_of("jack-port").unwrap(),
+                matches.value_of("device"),
+                timeline.clone(),
+            )?;
+            ring_consumers.push(consumer);
+            status!("Capture Port = {}", input.port);
+            status!("Capture Rate = {}", input.rate);
+            // The server runs at its own rate
+            if matches.occurrences_of("samplerate") > 0 && input.rate != samplerate {
+                status!("Sample rate = {} Hz set by the JACK server, not {} Hz", input.rate, samplerate);
+            }
+            let frequency = input.rate;
+            timeline.set_rate(frequency);
+            let buffer = input.samples as f32 / frequency as f32;
+            timeline.set_latency(input_latency(matches, buffer, None));
+            if frequency < MIN_RATE {
+                return Err(Error::UnsupportedRate {
+                    driver: "jack".to_owned(),
+                    rate: frequency,
+                    min: MIN_RATE,
+                });
+            }
+            (frequency, Watchdog::keep(Some(capture::Capture::Jack(input))))
+        }
+        #[cfg(not(feature = "jack-backend"))]
+        {
+            return Err(Error::Option {
+                option: "backend",
+                reason: "the jack backend requires building with --features jack-backend".to_owned(),
+            });
+        }
+    } else {
+        // Build the callback object and start recording
+        let mut received_spec = None;
+        let mut handles = Vec::new();
+        let recorder_timeline = timeline.clone();
+        let device = match matches.value_of("device") {
+            Some(device) => Some(devices::find_capture(device, &audio)?),
+            None => None,
+        };
+        if let Some(device) = device.as_ref() {
+            status!("Capture Device = {}", device);
+        }
+        let capture_device = audio.open_capture(device.as_deref(), spec, |spec| {
+            status!("Capture Spec = {:?}", spec);
+            received_spec = Some(spec);
+            let (producers, consumers): (Vec<ring::Producer>, _) =
+                (0..streams).map(|_| ring::for_rate(spec.freq)).unzip();
+            ring_consumers = consumers;
+            handles = producers.iter().map(ring::Producer::handle).collect();
+            Recorder {
+                producers,
+                channels: spec.channels as usize,
+                mode: channel_mode,
+                timeline: recorder_timeline,
+            }
+        })
+        .map_err(|reason| Error::Capture {
+            driver: driver.clone(),
+            reason,
+        })?;
+        let received_spec = received_spec.unwrap();
+        let frequency = received_spec.freq;
+        timeline.set_rate(frequency);
+        let buffer = received_spec.samples as f32 / frequency as f32;
+        timeline.set_latency(input_latency(matches, buffer, device.as_deref()));
+        if frequency < MIN_RATE {
+            return Err(Error::UnsupportedRate {
+                driver,
+                rate: frequency,
+                min: MIN_RATE,
+            });
+        }
+
+        capture_device.resume();
+        // Reopened at the rate the analysis runs at when lost
+        let sdl = capture::SdlCapture {
+            audio: audio.clone(),
+            driver,
+            device,
+            rate: frequency,
+            channels,
+            samples: spec.samples,
+            mode: channel_mode,
+            timeline: timeline.clone(),
+            handles,
+        };
+        (frequency, Watchdog::sdl(capture_device, sdl))
+    };
+    Ok(Input {
+        rate,
+        watchdog,
+        consumers: ring_consumers,
+    })
 }
 
-impl AudioCallback for Recorder {
-    type Channel = f32;
+// Get the outputs publishing the analysis
+fn open_outputs(
+    matches: &ArgMatches,
+    timeline: &Timeline,
+    notation: display::Notation,
+    commands: &Sender<Command>,
+) -> Result<Vec<Box<dyn Output>>, Error> {
+    let mut outputs: Vec<Box<dyn Output>> = Vec::new();
+    #[cfg(feature = "midi")]
+    {
+        if let Some(port) = matches.value_of("midi-out") {
+            let messages = match matches.value_of("midi-out-messages").unwrap_or("notes") {
+                "cc" => midi::ChordMessages::Controllers,
+                _ => midi::ChordMessages::Notes,
+            };
+            outputs.push(Box::new(midi::ChordPublisher::open(port, messages)?));
+        }
+        if let Some(port) = matches.value_of("pads") {
+            let model = pads::Model::from_name(matches.value_of("pads-model").unwrap_or("launchpad-x")).unwrap();
+            let first = Note::from_name(matches.value_of("pads-note").unwrap_or("C2")).unwrap();
+            outputs.push(Box::new(pads::PadGrid::open(port, model, first)?));
+        }
+    }
+
+    if let Some(address) = matches.value_of("reaper") {
+        let action = matches.value_of("reaper-action").unwrap_or(reaper::INSERT_MARKER);
+        outputs.push(Box::new(reaper::ReaperBridge::new(address, action, notation)?));
+    }
+
+    if let Some(path) = matches.value_of("json") {
+        outputs.push(Box::new(json::JsonWriter::create(path, timeline.clone(), notation)?));
+    }
+
+    if let Some(port) = matches.value_of("widget") {
+        let port = port.parse::<u16>().unwrap();
+        let host = matches.value_of("widget-host").unwrap_or(widget::DEFAULT_HOST);
+        outputs.push(Box::new(widget::WidgetServer::listen(host, port, timeline.clone(), notation)?));
+    }
+
+    if let Some(trigger) = matches.value_of("talkback") {
+        let threshold = matches.value_of("talkback-threshold").unwrap_or("0.7").parse::<f32>().unwrap();
+        let duration = matches.value_of("talkback-duration").unwrap_or("2.0").parse::<f32>().unwrap();
+        outputs.push(Box::new(talkback::Talkback::new(
+            talkback::Trigger::open(trigger)?,
+            threshold,
+            std::time::Duration::from_secs_f32(duration),
+        )));
+    }
+
+    if let Some(address) = matches.value_of("mirror") {
+        outputs.push(Box::new(mirror::MirrorPublisher::connect(address, commands.clone())?));
+    }
+    Ok(outputs)
+}
+
+// How late the captured audio is, in seconds
+// The audio reaches us a buffer late, and Bluetooth sources add their own delay
+fn input_latency(matches: &ArgMatches, buffer: f32, device: Option<&str>) -> f32 {
+    let latency = match matches.value_of("input-latency") {
+        Some(latency) => latency.parse::<f32>().unwrap() / 1000f32,
+        None => {
+            let bluetooth = device.map_or(false, devices::is_bluetooth);
+            if bluetooth {
+                status!("Bluetooth device, its latency is compensated");
+            }
+            buffer + if bluetooth { devices::BLUETOOTH_LATENCY } else { 0f32 }
+        }
+    };
+    status!("Input latency = {:.0}ms", latency * 1000f32);
+    latency
+}
+
+// The hop in samples, from a number of samples or a percentage of the resolution
+fn parse_hop(text: &str, resolution: usize) -> Option<usize> {
+    match text.strip_suffix('%') {
+        Some(percent) => percent
+            .parse::<f32>()
+            .ok()
+            .filter(|p| *p >= 1f32 && *p <= 100f32)
+            .map(|p| ((resolution as f32 * p / 100f32).round() as usize).max(1)),
+        None => text.parse::<usize>().ok().filter(|h| (1..=1_048_576).contains(h)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hop_from_a_percentage() {
+        assert_eq!(parse_hop("50%", 4096), Some(2048));
+        assert_eq!(parse_hop("100%", 4096), Some(4096));
+        assert_eq!(parse_hop("1%", 64), Some(1));
+        assert_eq!(parse_hop("0.5%", 4096), None);
+        assert_eq!(parse_hop("150%", 4096), None);
+    }
 
-    fn callback(&mut self, input: &mut [f32]) {
-        self.audio_sender.send(input.to_owned()).ok();
+    #[test]
+    fn hop_in_samples() {
+        assert_eq!(parse_hop("1024", 4096), Some(1024));
+        assert_eq!(parse_hop("0", 4096), None);
+        assert_eq!(parse_hop("-1", 4096), None);
+        assert_eq!(parse_hop("half", 4096), None);
     }
 }
@@ -0,0 +1,219 @@
+// MIDI input, providing the harmonic context from a DAW or keyboard
+
+// Standard
+use std::collections::BTreeSet;
+
+// Midi
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+
+// Crate
+use crate::chords::{Chord, QUALITIES};
+use crate::context::Context;
+use crate::notes::Note;
+use crate::notes::Note::C3;
+use crate::output::Output;
+use crate::scores::Scores;
+use crate::setlist::Setlist;
+
+// MIDI status bytes, without the channel
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PROGRAM_CHANGE: u8 = 0xC0;
+// The controllers encoding the chord root and quality
+const CC_ROOT: u8 = 20;
+const CC_QUALITY: u8 = 21;
+// The controller value meaning no chord
+const CC_NONE: u8 = 127;
+// The controller raised by the talkback trigger
+const CC_TALKBACK: u8 = 22;
+// The velocity of published chord notes
+const VELOCITY: u8 = 80;
+// The MIDI number of C0
+const MIDI_C0: u8 = 12;
+
+// Convert a MIDI note number
+pub fn note_from_midi(number: u8) -> Option<Note> {
+    number
+        .checked_sub(MIDI_C0)
+        .and_then(|i| Note::iter().nth(i as usize))
+}
+
+// Convert a note to its MIDI number
+pub fn midi_from_note(note: Note) -> u8 {
+    note as u8 + MIDI_C0
+}
+
+// Find a port from its index or part of its name
+fn find_port(port: &str, names: &[String], direction: &str) -> Result<usize, String> {
+    match port.parse::<usize>() {
+        Ok(i) if i < names.len() => Some(i),
+        _ => names.iter().position(|n| n.contains(port)),
+    }
+    .ok_or_else(|| {
+        format!(
+            "MIDI {} port '{}' not found, available ports: {}",
+            direction,
+            port,
+            names.join(", ")
+        )
+    })
+}
+
+// Open a MIDI input port, from its index or part of its name
+// The connection closes when dropped
+pub fn open_input(
+    port: &str,
+    context: Context,
+    setlist: Option<Setlist>,
+) -> Result<MidiInputConnection<()>, String> {
+    let input = MidiInput::new("ImproVe").map_err(|e| e.to_string())?;
+    let ports = input.ports();
+    let names = ports
+        .iter()
+        .map(|p| input.port_name(p).unwrap_or_default())
+        .collect::<Vec<String>>();
+    let index = find_port(port, &names, "input")?;
+    status!("MIDI input = {}", names[index]);
+
+    // The notes currently held down
+    let mut held = BTreeSet::new();
+    input
+        .connect(
+            &ports[index],
+            "ImproVe input",
+            move |_, message, _| {
+                // With a setlist the charts are the harmony, and program changes select the song
+                if let Some(setlist) = setlist.as_ref() {
+                    if message.len() >= 2 && message[0] & 0xF0 == PROGRAM_CHANGE {
+                        setlist.select(message[1] as usize);
+                    }
+                    return;
+                }
+                if message.len() < 3 {
+                    return;
+                }
+                match message[0] & 0xF0 {
+                    // A note on with zero velocity is a note off
+                    NOTE_ON if message[2] > 0 => {
+                        held.insert(message[1]);
+                    }
+                    NOTE_ON | NOTE_OFF => {
+                        held.remove(&message[1]);
+                    }
+                    _ => return,
+                }
+                context.set_notes(held.iter().filter_map(|&n| note_from_midi(n)).collect());
+            },
+            (),
+        )
+        .map_err(|e| e.to_string())
+}
+
+// How detected chords are published
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChordMessages {
+    // The chord notes, held until the chord changes
+    Notes,
+    // The root and quality as controller values
+    Controllers,
+}
+
+// Open an output port, from its index or part of its name
+pub fn open_output(port: &str, title: &str) -> Result<MidiOutputConnection, String> {
+    let output = MidiOutput::new("ImproVe").map_err(|e| e.to_string())?;
+    let ports = output.ports();
+    let names = ports
+        .iter()
+        .map(|p| output.port_name(p).unwrap_or_default())
+        .collect::<Vec<String>>();
+    let index = find_port(port, &names, "output")?;
+    status!("MIDI output = {}", names[index]);
+    output.connect(&ports[index], title).map_err(|e| e.to_string())
+}
+
+// Publishes the detected chord to a MIDI output port whenever it changes
+pub struct ChordPublisher {
+    connection: MidiOutputConnection,
+    messages: ChordMessages,
+    chord: Option<Chord>,
+    // The notes currently held on the output
+    sounding: Vec<u8>,
+}
+
+impl ChordPublisher {
+    // Open a MIDI output port, from its index or part of its name
+    pub fn open(port: &str, messages: ChordMessages) -> Result<ChordPublisher, String> {
+        Ok(ChordPublisher {
+            connection: open_output(port, "ImproVe chords")?,
+            messages,
+            chord: None,
+            sounding: Vec::new(),
+        })
+    }
+
+    fn send(&mut self, message: &[u8]) {
+        self.connection.send(message).ok();
+    }
+
+    fn send_chord(&mut self, chord: Option<Chord>) {
+        match self.messages {
+            ChordMessages::Notes => {
+                for note in std::mem::replace(&mut self.sounding, Vec::new()) {
+                    self.send(&[NOTE_OFF, note, 0]);
+                }
+                if let Some(chord) = chord {
+                    self.sounding = chord.voicing(C3).into_iter().map(midi_from_note).collect();
+                    for note in self.sounding.clone() {
+                        self.send(&[NOTE_ON, note, VELOCITY]);
+                    }
+                }
+            }
+            ChordMessages::Controllers => {
+                let (root, quality) = match chord {
+                    Some(chord) => (
+                        chord.root as u8,
+                        QUALITIES.iter().position(|&q| q == chord.quality).unwrap() as u8,
+                    ),
+                    None => (CC_NONE, CC_NONE),
+                };
+                self.send(&[CONTROL_CHANGE, CC_ROOT, root]);
+                self.send(&[CONTROL_CHANGE, CC_QUALITY, quality]);
+            }
+        }
+    }
+}
+
+impl Output for ChordPublisher {
+    fn publish(&mut self, scores: &Scores) {
+        if scores.chord != self.chord {
+            self.chord = scores.chord;
+            self.send_chord(scores.chord);
+        }
+    }
+}
+
+impl Drop for ChordPublisher {
+    // Release the held notes
+    fn drop(&mut self) {
+        self.send_chord(None);
+    }
+}
+
+// The talkback trigger, as a controller raised and released
+pub struct TriggerPort {
+    connection: MidiOutputConnection,
+}
+
+impl TriggerPort {
+    pub fn open(port: &str) -> Result<TriggerPort, String> {
+        Ok(TriggerPort {
+            connection: open_output(port, "ImproVe talkback")?,
+        })
+    }
+
+    pub fn set(&mut self, on: bool) {
+        let value = if on { 127 } else { 0 };
+        self.connection.send(&[CONTROL_CHANGE, CC_TALKBACK, value]).ok();
+    }
+}
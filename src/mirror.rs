@@ -0,0 +1,239 @@
+// Mirrors the display of a student to a teacher over TCP, the teacher annotating back
+// Both ways send a line per message, of fields separated by tabs:
+//   scores CONFIDENCE LEVEL WARMUP LOUDNESS CHORD INVALID PROMPT SCORES VALUES DISSONANCE ANNOTATIONS...
+//   annotate "text"
+// The notes fields are comma separated, '-' standing for a missing value
+
+// Standard
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Crate
+use crate::chords::{Chord, QUALITIES};
+use crate::control::Command;
+use crate::error::Error;
+use crate::notes::NOTE_COUNT;
+use crate::output::Output;
+use crate::scores::Scores;
+
+// The shortest time between two published frames, the displays not refreshing faster
+const MIN_INTERVAL: Duration = Duration::from_millis(50);
+// The connection timeout of the student
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+// Keep a text on its field
+fn clean(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+fn join(values: &[f32]) -> String {
+    values.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(",")
+}
+
+fn split(field: &str) -> Option<[f32; NOTE_COUNT]> {
+    let mut values = [0f32; NOTE_COUNT];
+    let mut count = 0;
+    for (value, text) in values.iter_mut().zip(field.split(',')) {
+        *value = text.parse::<f32>().ok()?;
+        count += 1;
+    }
+    if count == NOTE_COUNT {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+fn encode(scores: &Scores, annotations: &[String]) -> String {
+    let chord = match scores.chord {
+        Some(chord) => format!("{}:{}", chord.root, QUALITIES.iter().position(|&q| q == chord.quality).unwrap()),
+        None => "-".to_owned(),
+    };
+    let mut fields = vec![
+        "scores".to_owned(),
+        scores.confidence.to_string(),
+        scores.level.to_string(),
+        scores.warmup.to_string(),
+        scores.loudness.map_or("-".to_owned(), |l| l.to_string()),
+        chord,
+        scores.invalid_samples.to_string(),
+        scores.prompt.as_deref().map_or("-".to_owned(), clean),
+        join(&scores.note_scores),
+        join(&scores.note_values),
+        join(&scores.dissonance),
+    ];
+    fields.extend(annotations.iter().map(|a| clean(a)));
+    fields.join("\t")
+}
+
+fn decode(line: &str) -> Option<Scores> {
+    let fields = line.split('\t').collect::<Vec<&str>>();
+    if fields.len() < 11 || fields[0] != "scores" {
+        return None;
+    }
+    let chord = match fields[5] {
+        "-" => None,
+        chord => {
+            let mut parts = chord.split(':');
+            let root = parts.next()?.parse::<usize>().ok().filter(|&r| r < 12)?;
+            let quality = *QUALITIES.get(parts.next()?.parse::<usize>().ok()?)?;
+            Some(Chord { root, quality })
+        }
+    };
+    Some(Scores {
+        note_scores: split(fields[8])?,
+        dissonance: split(fields[10])?,
+        note_values: split(fields[9])?,
+        fourier: Vec::new(),
+        confidence: fields[1].parse().ok()?,
+        chord,
+        intonation: [None; NOTE_COUNT],
+        detail: None,
+        onset: None,
+        annotations: fields[11..].iter().map(|&a| a.to_owned()).collect(),
+        level: fields[2].parse().ok()?,
+        loudness: match fields[4] {
+            "-" => None,
+            loudness => Some(loudness.parse().ok()?),
+        },
+        // The mirrored engine does not send its meter
+        meter: Default::default(),
+        input_events: Vec::new(),
+        invalid_samples: fields[6].parse().ok()?,
+        warmup: fields[3].parse().ok()?,
+        prompt: match fields[7] {
+            "-" => None,
+            prompt => Some(prompt.to_owned()),
+        },
+        metrics: None,
+        idle: false,
+        attribution: None,
+        hands: None,
+        instrument: None,
+        counterpoint: None,
+        continuo: None,
+        latency: Default::default(),
+        channels: Vec::new(),
+    })
+}
+
+// The student side, publishing the scores to the teacher
+pub struct MirrorPublisher {
+    // The lines, written from another thread to never block the analysis
+    sender: Sender<String>,
+    last: Option<Instant>,
+    // The annotations of the frames skipped since the last one published
+    annotations: Vec<String>,
+}
+
+impl MirrorPublisher {
+    // Connect to the teacher, forwarding the annotations received to the analysis
+    pub fn connect(address: &str, commands: Sender<Command>) -> Result<MirrorPublisher, String> {
+        let socket = address
+            .to_socket_addrs()
+            .map_err(|e| format!("Cannot resolve {}: {}", address, e))?
+            .next()
+            .ok_or_else(|| format!("Cannot resolve {}", address))?;
+        let mut stream = TcpStream::connect_timeout(&socket, TIMEOUT)
+            .map_err(|e| format!("Cannot connect to the teacher at {}: {}", address, e))?;
+        let reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+        status!("Mirror = {}", address);
+
+        let (sender, receiver) = channel::<String>();
+        std::thread::spawn(move || {
+            for line in receiver.iter() {
+                if writeln!(stream, "{}", line).is_err() {
+                    status!("Mirror disconnected");
+                    return;
+                }
+            }
+        });
+        std::thread::spawn(move || {
+            for line in reader.lines().map_while(Result::ok) {
+                if let Some(text) = line.strip_prefix("annotate\t") {
+                    if commands.send(Command::Annotate(text.to_owned())).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(MirrorPublisher {
+            sender,
+            last: None,
+            annotations: Vec::new(),
+        })
+    }
+}
+
+impl Output for MirrorPublisher {
+    fn publish(&mut self, scores: &Scores) {
+        self.annotations.extend(scores.annotations.iter().cloned());
+        if self.last.is_some_and(|last| last.elapsed() < MIN_INTERVAL) {
+            return;
+        }
+        self.last = Some(Instant::now());
+        self.sender.send(encode(scores, &self.annotations)).ok();
+        self.annotations.clear();
+    }
+}
+
+// Annotate from the lines typed in the console
+pub fn read_console(commands: Sender<Command>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+            if !line.trim().is_empty() && commands.send(Command::Annotate(line)).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+// The teacher side, showing the scores of a student at a time, read-only
+// The annotations commands are sent back to the student, the others ignored
+pub fn serve(host: &str, port: u16, scores: Sender<Scores>, commands: Receiver<Command>) -> Result<(), Error> {
+    let listener = TcpListener::bind((host, port)).map_err(|e| Error::Listen {
+        service: "mirrored student",
+        address: format!("TCP port {} of {}", port, host),
+        reason: e.to_string(),
+    })?;
+    status!("Mirror = {}:{}, waiting for a student", host, port);
+    let student: Arc<Mutex<Option<TcpStream>>> = Arc::new(Mutex::new(None));
+
+    let writer = student.clone();
+    std::thread::spawn(move || {
+        for command in commands.iter() {
+            if let Command::Annotate(text) = command {
+                match writer.lock().unwrap().as_mut() {
+                    Some(stream) => {
+                        writeln!(stream, "annotate\t{}", clean(&text)).ok();
+                    }
+                    None => status!("No student to annotate"),
+                }
+            }
+        }
+    });
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().filter_map(Result::ok) {
+            let address = stream.peer_addr().map_or("unknown".to_owned(), |a| a.to_string());
+            status!("Student = {}", address);
+            *student.lock().unwrap() = stream.try_clone().ok();
+            for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                match decode(&line) {
+                    Some(frame) => {
+                        if scores.send(frame).is_err() {
+                            return;
+                        }
+                    }
+                    None => status!("Malformed mirror message ignored"),
+                }
+            }
+            status!("Student {} disconnected", address);
+            *student.lock().unwrap() = None;
+        }
+    });
+    Ok(())
+}
@@ -20,6 +20,8 @@ pub enum Note {
 pub const NOTE_COUNT: usize = Note::B9 as usize + 1;
 pub const BASE_NOTE: Note = Note::A4;
 pub const BASE_FREQUENCY: f32 = 440f32;
+// The pitch classes in scientific pitch notation
+const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
 
 impl Note {
     pub fn freq(self) -> f32 {
@@ -41,4 +43,61 @@ impl Note {
     pub fn get_octave_index(self) -> u32 {
         (self as u32) % 12
     }
+    // The name in scientific pitch notation (ie. A4, C#3), as parsed by from_name
+    pub fn name(self) -> String {
+        format!("{}{}", NAMES[self.get_octave_index() as usize], self as u32 / 12)
+    }
+    // Parse a note in scientific pitch notation (ie. A4, C#3, Eb2)
+    pub fn from_name(name: &str) -> Option<Note> {
+        let mut chars = name.trim().chars().peekable();
+        let base: i32 = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let alteration = match chars.peek() {
+            Some('#') => 1,
+            Some('b') => -1,
+            _ => 0,
+        };
+        if alteration != 0 {
+            chars.next();
+        }
+        let octave = chars.collect::<String>().parse::<i32>().ok()?;
+        let index = octave.checked_mul(12)?.checked_add(base + alteration)?;
+        if index < 0 {
+            return None;
+        }
+        Note::iter().nth(index as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_parsed() {
+        assert_eq!(Note::from_name("A4"), Some(Note::A4));
+        assert_eq!(Note::from_name("c#3"), Some(Note::CSharp3));
+        assert_eq!(Note::from_name("Eb2"), Some(Note::DSharp2));
+        assert_eq!(Note::from_name(" B9 "), Some(Note::B9));
+    }
+
+    #[test]
+    fn invalid_names_are_refused() {
+        for name in ["", "H4", "C", "Cb0", "C10", "A-1", "C#x"].iter() {
+            assert_eq!(Note::from_name(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn names_round_trip() {
+        assert!(Note::iter().all(|n| Note::from_name(&n.name()) == Some(n)));
+    }
 }
@@ -0,0 +1,201 @@
+// The control API, receiving OSC messages over UDP from external tools
+// The messages understood, with their arguments:
+//   /improve/annotate "text"   mark the display timeline, and the session
+//   /improve/reset             forget the smoothed scores
+//   /improve/style             switch to the next musical style
+//   /improve/halflife 0.5      change the halflife, in seconds
+//   /improve/undo              undo the last change of the options
+//   /improve/redo              redo the change undone
+//   /improve/revert            go back to the options at launch
+
+// Standard
+use std::net::UdpSocket;
+use std::sync::mpsc::Sender;
+
+// Crate
+use crate::control::Command;
+use crate::error::Error;
+
+// The largest datagram read
+const MAX_PACKET: usize = 65536;
+
+// An OSC argument
+#[derive(Clone, Debug, PartialEq)]
+enum Argument {
+    Int(i32),
+    Float(f32),
+    Text(String),
+}
+
+// Read a null terminated string, padded to 4 bytes
+fn read_string(data: &[u8], offset: &mut usize) -> Option<String> {
+    let rest = data.get(*offset..)?;
+    let end = rest.iter().position(|&b| b == 0)?;
+    let text = String::from_utf8(rest[..end].to_vec()).ok()?;
+    *offset += (end + 4) & !3;
+    Some(text)
+}
+
+// Read a big endian 32 bits word
+fn read_word(data: &[u8], offset: &mut usize) -> Option<[u8; 4]> {
+    let bytes = data.get(*offset..*offset + 4)?;
+    *offset += 4;
+    Some([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// Parse a packet into its messages, unpacking bundles
+fn parse(data: &[u8], messages: &mut Vec<(String, Vec<Argument>)>) -> Option<()> {
+    let mut offset = 0;
+    let address = read_string(data, &mut offset)?;
+    if address == "#bundle" {
+        // Skip the time tag, the messages are applied on arrival
+        offset += 8;
+        while offset < data.len() {
+            let size = u32::from_be_bytes(read_word(data, &mut offset)?) as usize;
+            parse(data.get(offset..offset + size)?, messages)?;
+            offset += size;
+        }
+        return Some(());
+    }
+    // Old implementations may omit the type tags
+    let tags = if offset < data.len() {
+        read_string(data, &mut offset)?
+    } else {
+        ",".to_owned()
+    };
+    let mut arguments = Vec::new();
+    for tag in tags.chars().skip(1) {
+        arguments.push(match tag {
+            'i' => Argument::Int(i32::from_be_bytes(read_word(data, &mut offset)?)),
+            'f' => Argument::Float(f32::from_bits(u32::from_be_bytes(read_word(data, &mut offset)?))),
+            's' => Argument::Text(read_string(data, &mut offset)?),
+            _ => return None,
+        });
+    }
+    messages.push((address, arguments));
+    Some(())
+}
+
+// The command of a message
+fn command(address: &str, arguments: &[Argument]) -> Option<Command> {
+    match (address, arguments) {
+        ("/improve/annotate", [Argument::Text(text)]) => Some(Command::Annotate(text.clone())),
+        ("/improve/reset", []) => Some(Command::Reset),
+        ("/improve/style", []) => Some(Command::NextStyle),
+        ("/improve/undo", []) => Some(Command::Undo),
+        ("/improve/redo", []) => Some(Command::Redo),
+        ("/improve/revert", []) => Some(Command::Revert),
+        ("/improve/halflife", [Argument::Float(halflife)]) if *halflife > 0f32 => {
+            Some(Command::SetHalflife(*halflife))
+        }
+        ("/improve/halflife", [Argument::Int(halflife)]) if *halflife > 0 => {
+            Some(Command::SetHalflife(*halflife as f32))
+        }
+        _ => None,
+    }
+}
+
+// The address listened on unless told otherwise, keeping the control to this machine
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+
+// Listen on a UDP port of the host address, forwarding the commands received to the analysis
+pub fn listen(host: &str, port: u16, commands: Sender<Command>) -> Result<(), Error> {
+    let socket = UdpSocket::bind((host, port)).map_err(|e| Error::Listen {
+        service: "OSC messages",
+        address: format!("UDP port {} of {}", port, host),
+        reason: e.to_string(),
+    })?;
+    status!("OSC input = {}:{}", host, port);
+    std::thread::spawn(move || {
+        let mut data = vec![0u8; MAX_PACKET];
+        while let Ok(size) = socket.recv(&mut data) {
+            let mut messages = Vec::new();
+            if parse(&data[..size], &mut messages).is_none() {
+                status!("Malformed OSC packet ignored");
+                continue;
+            }
+            for (address, arguments) in messages {
+                match command(&address, &arguments) {
+                    Some(command) => {
+                        if commands.send(command).is_err() {
+                            return;
+                        }
+                    }
+                    None => status!("Unknown OSC message {} {:?}", address, arguments),
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A string null terminated then padded to 4 bytes
+    fn string(text: &str) -> Vec<u8> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize((text.len() + 4) & !3, 0);
+        bytes
+    }
+
+    fn message(address: &str, tags: &str, words: &[[u8; 4]]) -> Vec<u8> {
+        let mut bytes = string(address);
+        bytes.extend(string(tags));
+        for word in words {
+            bytes.extend_from_slice(word);
+        }
+        bytes
+    }
+
+    fn commands(data: &[u8]) -> Option<Vec<Command>> {
+        let mut messages = Vec::new();
+        parse(data, &mut messages)?;
+        messages.iter().map(|(address, arguments)| command(address, arguments)).collect()
+    }
+
+    #[test]
+    fn messages_are_parsed() {
+        let mut annotate = string("/improve/annotate");
+        annotate.extend(string(",s"));
+        annotate.extend(string("chorus"));
+        assert_eq!(commands(&annotate), Some(vec![Command::Annotate("chorus".to_owned())]));
+        assert_eq!(
+            commands(&message("/improve/halflife", ",f", &[0.25f32.to_bits().to_be_bytes()])),
+            Some(vec![Command::SetHalflife(0.25)])
+        );
+        assert_eq!(
+            commands(&message("/improve/halflife", ",i", &[2i32.to_be_bytes()])),
+            Some(vec![Command::SetHalflife(2f32)])
+        );
+        // Without the type tags
+        assert_eq!(commands(&string("/improve/reset")), Some(vec![Command::Reset]));
+        // A halflife must be positive
+        assert_eq!(commands(&message("/improve/halflife", ",i", &[0i32.to_be_bytes()])), None);
+    }
+
+    #[test]
+    fn bundles_are_unpacked() {
+        let mut bundle = string("#bundle");
+        bundle.extend_from_slice(&[0u8; 8]);
+        for element in [message("/improve/style", ",", &[]), message("/improve/undo", ",", &[])].iter() {
+            bundle.extend_from_slice(&(element.len() as u32).to_be_bytes());
+            bundle.extend_from_slice(element);
+        }
+        assert_eq!(commands(&bundle), Some(vec![Command::NextStyle, Command::Undo]));
+        // An element longer than the packet
+        bundle.truncate(bundle.len() - 4);
+        assert_eq!(commands(&bundle), None);
+    }
+
+    #[test]
+    fn malformed_packets_are_refused() {
+        let mut messages = Vec::new();
+        // A missing argument, an unknown type tag and an unterminated address
+        assert!(parse(&message("/improve/halflife", ",f", &[]), &mut messages).is_none());
+        assert!(parse(&message("/improve/halflife", ",d", &[[0u8; 4], [0u8; 4]]), &mut messages).is_none());
+        assert!(parse(b"/improve", &mut messages).is_none());
+        assert!(messages.is_empty());
+    }
+}
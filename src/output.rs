@@ -0,0 +1,7 @@
+// Outputs notified of every analysis result, to publish them outside of the displays
+
+use crate::scores::Scores;
+
+pub trait Output: Send {
+    fn publish(&mut self, scores: &Scores);
+}
@@ -0,0 +1,201 @@
+// Lights the pad grid of a Launchpad or a Push with the scores, a display without a screen
+// The pads are laid out in fourths like the Push, a semitone to the right and a fourth up
+// The colors go from green for the consonant notes to red for the dissonant ones, scaled on the grid
+//   Launchpad X, Mini and Pro MK3: switched to programmer mode, each pad lit with an RGB sysex
+//   Push 2: switched to user mode, a palette gradient set by sysex, each pad lit with a note velocity from it
+
+// Midi
+use midir::MidiOutputConnection;
+
+// Crate
+use crate::midi;
+use crate::notes::{Note, NOTE_COUNT};
+use crate::output::Output;
+use crate::scores::Scores;
+use crate::tools::Normalizable;
+
+// The side of the grid
+const SIZE: usize = 8;
+// The semitones between two rows
+const ROW_INTERVAL: usize = 5;
+// The shades of the gradient, the steps of the colors sent
+const SHADES: usize = 16;
+// The manufacturer headers of the sysex messages
+const NOVATION: [u8; 5] = [0xF0, 0x00, 0x20, 0x29, 0x02];
+const ABLETON: [u8; 6] = [0xF0, 0x00, 0x21, 0x1D, 0x01, 0x01];
+const SYSEX_END: u8 = 0xF7;
+const NOTE_ON: u8 = 0x90;
+// The note of the bottom left pad of the Push
+const PUSH_FIRST_PAD: u8 = 36;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    LaunchpadX,
+    LaunchpadMini,
+    LaunchpadPro,
+    Push2,
+}
+
+impl Model {
+    pub fn from_name(name: &str) -> Option<Model> {
+        match name {
+            "launchpad-x" => Some(Model::LaunchpadX),
+            "launchpad-mini" => Some(Model::LaunchpadMini),
+            "launchpad-pro" => Some(Model::LaunchpadPro),
+            "push2" => Some(Model::Push2),
+            _ => None,
+        }
+    }
+
+    // The device byte of the Novation sysex messages
+    fn device(self) -> u8 {
+        match self {
+            Model::LaunchpadX => 0x0C,
+            Model::LaunchpadMini => 0x0D,
+            Model::LaunchpadPro => 0x0E,
+            Model::Push2 => 0,
+        }
+    }
+}
+
+// The color of a shade, from green to red, in 7 bits RGB
+fn shade_color(shade: usize) -> (u8, u8, u8) {
+    let score = shade as f32 / (SHADES - 1) as f32;
+    ((score * 127f32) as u8, ((1f32 - score) * 127f32) as u8, 0)
+}
+
+pub struct PadGrid {
+    connection: MidiOutputConnection,
+    model: Model,
+    // The note of the bottom left pad
+    first: Note,
+    // The shades lit, row by row from the bottom, None for the dark pads
+    lit: [Option<usize>; SIZE * SIZE],
+}
+
+impl PadGrid {
+    // Open the grid on an output port, from its index or part of its name
+    pub fn open(port: &str, model: Model, first: Note) -> Result<PadGrid, String> {
+        let mut grid = PadGrid {
+            connection: midi::open_output(port, "ImproVe pads")?,
+            model,
+            first,
+            lit: [None; SIZE * SIZE],
+        };
+        grid.set_user_mode(true);
+        if model == Model::Push2 {
+            // The gradient in the palette entries from 1, 0 being dark
+            for shade in 0..SHADES {
+                let (r, g, b) = shade_color(shade);
+                let mut message = ABLETON.to_vec();
+                message.extend(&[0x03, shade as u8 + 1]);
+                // 8 bits components sent as two 7 bits bytes, the white led left off
+                for &component in [r * 2, g * 2, b * 2, 0].iter() {
+                    message.extend(&[component & 0x7F, component >> 7]);
+                }
+                message.push(SYSEX_END);
+                grid.send(&message);
+            }
+            let mut reapply = ABLETON.to_vec();
+            reapply.extend(&[0x05, SYSEX_END]);
+            grid.send(&reapply);
+        }
+        grid.clear();
+        Ok(grid)
+    }
+
+    fn send(&mut self, message: &[u8]) {
+        self.connection.send(message).ok();
+    }
+
+    // The programmer mode of the Launchpads, or the user mode of the Push, leaves the pads to us
+    // The live mode gives them back to the DAW
+    fn set_user_mode(&mut self, on: bool) {
+        let mut message = match self.model {
+            Model::Push2 => [&ABLETON[..], &[0x0A]].concat(),
+            _ => [&NOVATION[..], &[self.model.device(), 0x0E]].concat(),
+        };
+        message.extend(&[on as u8, SYSEX_END]);
+        self.send(&message);
+    }
+
+    // The note of a pad, if scored
+    fn note(&self, pad: usize) -> Option<usize> {
+        let note = self.first as usize + (pad / SIZE) * ROW_INTERVAL + pad % SIZE;
+        if note < NOTE_COUNT {
+            Some(note)
+        } else {
+            None
+        }
+    }
+
+    // Light the pads whose shade changed
+    fn light(&mut self, shades: [Option<usize>; SIZE * SIZE]) {
+        let changed = (0..SIZE * SIZE)
+            .filter(|&p| shades[p] != self.lit[p])
+            .collect::<Vec<usize>>();
+        if changed.is_empty() {
+            return;
+        }
+        match self.model {
+            Model::Push2 => {
+                for &pad in changed.iter() {
+                    let velocity = shades[pad].map_or(0, |s| s as u8 + 1);
+                    self.send(&[NOTE_ON, PUSH_FIRST_PAD + pad as u8, velocity]);
+                }
+            }
+            _ => {
+                // Every changed pad in a single message, its number being row then column from 1
+                let mut message = NOVATION.to_vec();
+                message.extend(&[self.model.device(), 0x03]);
+                for &pad in changed.iter() {
+                    let (r, g, b) = shades[pad].map_or((0, 0, 0), shade_color);
+                    let number = (pad / SIZE + 1) * 10 + pad % SIZE + 1;
+                    message.extend(&[0x03, number as u8, r, g, b]);
+                }
+                message.push(SYSEX_END);
+                self.send(&message);
+            }
+        }
+        self.lit = shades;
+    }
+
+    fn clear(&mut self) {
+        // Force every pad to be sent
+        self.lit = [Some(0); SIZE * SIZE];
+        self.light([None; SIZE * SIZE]);
+    }
+}
+
+impl Output for PadGrid {
+    fn publish(&mut self, scores: &Scores) {
+        let notes = (0..SIZE * SIZE)
+            .map(|p| self.note(p))
+            .collect::<Vec<Option<usize>>>();
+        let mut values = notes
+            .iter()
+            .filter_map(|n| n.map(|n| scores.note_scores[n]))
+            .collect::<Vec<f32>>();
+        values.normalize();
+        let mut values = values.into_iter();
+        let mut shades = [None; SIZE * SIZE];
+        for (shade, note) in shades.iter_mut().zip(notes) {
+            if note.is_some() {
+                let value = values.next().unwrap();
+                if value.is_finite() {
+                    *shade =
+                        Some((value.max(0f32).min(1f32) * (SHADES - 1) as f32).round() as usize);
+                }
+            }
+        }
+        self.light(shades);
+    }
+}
+
+impl Drop for PadGrid {
+    // Leave the grid dark, in live mode
+    fn drop(&mut self) {
+        self.clear();
+        self.set_user_mode(false);
+    }
+}
@@ -0,0 +1,96 @@
+// Raw PCM read from the standard input or a named pipe, ie. arecord -f FLOAT_LE -t raw - | improve --input -
+// Nothing in the stream gives its rate nor format, they are set with the options
+// The analysis ends with the stream
+
+// Standard
+use std::fs::File;
+use std::io::Read;
+use std::sync::mpsc::Sender;
+
+// Crate
+use crate::audio_buffer::{self, ChannelMode};
+use crate::error::Error;
+use crate::timeline::Timeline;
+
+// The bytes read at once, about 12ms of mono floats at 88200 Hz
+const BLOCK: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // Little endian samples, as arecord names them
+    FloatLe,
+    S16Le,
+    S32Le,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "f32" => Some(Format::FloatLe),
+            "s16" => Some(Format::S16Le),
+            "s32" => Some(Format::S32Le),
+            _ => None,
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            Format::S16Le => 2,
+            Format::FloatLe | Format::S32Le => 4,
+        }
+    }
+
+    fn sample(self, b: &[u8]) -> f32 {
+        match self {
+            Format::FloatLe => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            Format::S16Le => i16::from_le_bytes([b[0], b[1]]) as f32 / 32768f32,
+            Format::S32Le => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648f32,
+        }
+    }
+}
+
+// Read the stream from another thread, sending the samples like a capture callback
+pub fn start(
+    path: &str,
+    format: Format,
+    channels: usize,
+    mode: ChannelMode,
+    sender: Sender<Vec<f32>>,
+    timeline: Timeline,
+) -> Result<(), Error> {
+    let mut reader: Box<dyn Read + Send> = if path == "-" {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(File::open(path).map_err(|e| Error::Capture {
+            driver: "stream".to_owned(),
+            reason: format!("cannot open {}: {}", path, e),
+        })?)
+    };
+    status!("Capture Stream = {}, {:?}, {} channel(s)", path, format, channels);
+    let frame = format.width() * channels;
+    std::thread::spawn(move || {
+        let mut block = vec![0u8; BLOCK];
+        // The bytes of a frame split between two reads
+        let mut pending = Vec::new();
+        loop {
+            let size = match reader.read(&mut block) {
+                Ok(0) | Err(_) => break,
+                Ok(size) => size,
+            };
+            pending.extend_from_slice(&block[..size]);
+            let whole = pending.len() / frame * frame;
+            let samples = pending[..whole]
+                .chunks_exact(format.width())
+                .map(|b| format.sample(b))
+                .collect::<Vec<f32>>();
+            pending.drain(..whole);
+            let mono = audio_buffer::deinterleave(&samples, channels, mode).remove(0);
+            timeline.advance(mono.len());
+            if sender.send(mono).is_err() {
+                return;
+            }
+        }
+        status!("End of the input stream");
+    });
+    Ok(())
+}
@@ -0,0 +1,286 @@
+// Fundamental frequency estimation
+
+// Standard
+use std::collections::VecDeque;
+
+// Tools
+use itertools::Itertools;
+
+// Math
+use rustfft::num_complex::Complex;
+use rustfft::FFTplanner;
+
+// Crate
+use crate::frequency::Frequency;
+
+// The range of fundamentals searched for
+const MIN_FUNDAMENTAL: f32 = 70f32;
+const MAX_FUNDAMENTAL: f32 = 1100f32;
+// How far from a harmonic a peak can be, relative to the harmonic
+const HARMONIC_TOLERANCE: f32 = 0.03;
+// The attenuation of peaks which are not harmonics of the fundamental
+const NON_HARMONIC_GAIN: f32 = 0.05;
+// How much the cepstral peak must exceed the average to be trusted
+const MIN_CEPSTRAL_RATIO: f32 = 4f32;
+// Avoid taking the log of zero
+const LOG_FLOOR: f32 = 1e-20;
+
+// Finds the fundamental of a spectrum from the periodicity of its harmonics
+// Formants shape the spectral envelope, but not its periodicity
+pub struct Cepstrum {
+    planner: FFTplanner<f32>,
+}
+
+impl Cepstrum {
+    pub fn new() -> Cepstrum {
+        Cepstrum {
+            planner: FFTplanner::new(false),
+        }
+    }
+
+    // Get the fundamental of a spectrum with evenly spaced bins, starting at the first bin
+    pub fn fundamental(&mut self, spectrum: &[Frequency]) -> Option<f32> {
+        if spectrum.len() < 4 {
+            return None;
+        }
+        let bin_width = spectrum[1].value - spectrum[0].value;
+        // The cepstrum only makes sense on evenly spaced bins
+        let last_width = spectrum[spectrum.len() - 1].value - spectrum[spectrum.len() - 2].value;
+        if (last_width - bin_width).abs() > bin_width * 0.01 {
+            return None;
+        }
+
+        // Rebuild the full, symmetric log spectrum, including the 0hz bin
+        let half = spectrum.len() + 1;
+        let len = half * 2;
+        let sample_rate = bin_width * len as f32;
+        let log = std::iter::once(spectrum[0].intensity)
+            .chain(spectrum.iter().map(|f| f.intensity))
+            .map(|i| i.max(LOG_FLOOR).ln())
+            .collect_vec();
+        let mut fft_in = log
+            .iter()
+            .chain(log.iter().rev())
+            .map(|&l| Complex { re: l, im: 0f32 })
+            .collect_vec();
+        let mut fft_out = vec![Complex::default(); len];
+        self.planner.plan_fft(len).process(&mut fft_in, &mut fft_out);
+
+        // Look for the strongest quefrency within the fundamental range
+        let first = (sample_rate / MAX_FUNDAMENTAL).ceil() as usize;
+        let last = ((sample_rate / MIN_FUNDAMENTAL).floor() as usize).min(half - 1);
+        if first >= last {
+            return None;
+        }
+        let cepstrum = fft_out[first..=last].iter().map(|c| c.re).collect_vec();
+        let (index, &max) = cepstrum
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())?;
+        let average = cepstrum.iter().map(|c| c.abs()).sum::<f32>() / cepstrum.len() as f32;
+        if max < average * MIN_CEPSTRAL_RATIO {
+            return None;
+        }
+        Some(sample_rate / (first + index) as f32)
+    }
+
+}
+
+// The range of the single lines followed in mono mode, from the low voices to the violin
+const YIN_MIN_FUNDAMENTAL: f32 = 60f32;
+const YIN_MAX_FUNDAMENTAL: f32 = 2000f32;
+// The normalized difference under which a lag is taken for the period, the lower the stricter
+const YIN_THRESHOLD: f32 = 0.15;
+// The harmonics of the pitch found, scored against like the notes of a chart
+const YIN_HARMONICS: usize = 8;
+
+// Finds the pitch of a single line from the periodicity of the samples, without any transform
+// The YIN difference function, normalized by its running mean so the octave above is not taken
+pub struct Yin {
+    sample_rate: f32,
+    difference: Vec<f32>,
+}
+
+impl Yin {
+    pub fn new(sample_rate: f32) -> Yin {
+        Yin {
+            sample_rate,
+            difference: Vec::new(),
+        }
+    }
+
+    // Get the fundamental of the most recent samples, None when they are not periodic enough
+    pub fn fundamental(&mut self, samples: &[f32]) -> Option<f32> {
+        let min_lag = ((self.sample_rate / YIN_MAX_FUNDAMENTAL) as usize).max(2);
+        // The lags are compared over a window as long as the longest of them
+        let window = ((self.sample_rate / YIN_MIN_FUNDAMENTAL).ceil() as usize).min(samples.len() / 2);
+        if window <= min_lag + 1 {
+            return None;
+        }
+        let recent = &samples[samples.len() - 2 * window..];
+
+        // The cumulative mean normalized difference, 1 at the lag 0
+        self.difference.clear();
+        self.difference.push(1f32);
+        let mut sum = 0f32;
+        for lag in 1..=window {
+            let difference = recent[..window]
+                .iter()
+                .zip(recent[lag..].iter())
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f32>();
+            sum += difference;
+            self.difference.push(if sum > 0f32 { difference * lag as f32 / sum } else { 1f32 });
+        }
+
+        // The first dip under the threshold, down to its lowest lag
+        let difference = &self.difference;
+        let mut lag = (min_lag..window).find(|&l| difference[l] < YIN_THRESHOLD)?;
+        while lag + 1 < window && difference[lag + 1] < difference[lag] {
+            lag += 1;
+        }
+        // Between the lags, on the parabola through the neighbours
+        let (before, at, after) = (difference[lag - 1], difference[lag], difference[lag + 1]);
+        let curvature = before - 2f32 * at + after;
+        let shift = if curvature > 0f32 {
+            (0.5 * (before - after) / curvature).max(-1f32).min(1f32)
+        } else {
+            0f32
+        };
+        Some(self.sample_rate / (lag as f32 + shift))
+    }
+
+    // The pitch of the samples with its harmonics, empty without any
+    pub fn spectrum(&mut self, samples: &[f32]) -> Vec<Frequency> {
+        match self.fundamental(samples) {
+            Some(fundamental) => (1..=YIN_HARMONICS)
+                .map(|h| Frequency {
+                    intensity: 1f32 / h as f32,
+                    value: fundamental * h as f32,
+                })
+                .collect_vec(),
+            None => Vec::new(),
+        }
+    }
+}
+
+// The number of fundamentals the vibrato is smoothed over
+const VIBRATO_FRAMES: usize = 5;
+// How far the pitch can wander from the smoothed pitch and still be vibrato, in semitones
+const VIBRATO_DEPTH: f32 = 1f32;
+
+// Confirms the fundamental of a sung note, smoothing out vibrato
+pub struct VoiceTracker {
+    cepstrum: Cepstrum,
+    // The recent fundamentals, in semitones relative to 1hz
+    history: VecDeque<f32>,
+}
+
+impl VoiceTracker {
+    pub fn new() -> VoiceTracker {
+        VoiceTracker {
+            cepstrum: Cepstrum::new(),
+            history: VecDeque::with_capacity(VIBRATO_FRAMES),
+        }
+    }
+
+    // Get the median of the recent fundamentals, forgetting them when a new note starts
+    fn smooth(&mut self, fundamental: f32) -> f32 {
+        let pitch = fundamental.log2() * 12f32;
+        let median = |history: &VecDeque<f32>| {
+            let sorted = history
+                .iter()
+                .cloned()
+                .sorted_by(|a, b| a.partial_cmp(b).unwrap())
+                .collect_vec();
+            sorted[sorted.len() / 2]
+        };
+        if !self.history.is_empty() && (median(&self.history) - pitch).abs() > VIBRATO_DEPTH {
+            self.history.clear();
+        }
+        if self.history.len() == VIBRATO_FRAMES {
+            self.history.pop_front();
+        }
+        self.history.push_back(pitch);
+        2f32.powf(median(&self.history) / 12f32)
+    }
+
+    // Attenuate the peaks that are not harmonics of the detected fundamental
+    // The harmonics are moved back to the smoothed pitch
+    pub fn confirm(&mut self, spectrum: &[Frequency], peaks: Vec<Frequency>) -> Vec<Frequency> {
+        let fundamental = match self.cepstrum.fundamental(spectrum) {
+            Some(fundamental) => fundamental,
+            None => return peaks,
+        };
+        let smoothed = self.smooth(fundamental);
+        harmonic_filter(peaks, fundamental)
+            .into_iter()
+            .map(|mut f| {
+                f.value *= smoothed / fundamental;
+                f
+            })
+            .collect_vec()
+    }
+}
+
+// Keep the harmonics of a fundamental, attenuating everything else
+pub fn harmonic_filter(peaks: Vec<Frequency>, fundamental: f32) -> Vec<Frequency> {
+    peaks
+        .into_iter()
+        .map(|mut f| {
+            let rank = (f.value / fundamental).round();
+            let distance = (f.value / fundamental - rank).abs();
+            if rank < 1f32 || distance > HARMONIC_TOLERANCE * rank {
+                f.intensity *= NON_HARMONIC_GAIN;
+            }
+            f
+        })
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f32 = 44100f32;
+
+    // A tone with its octave louder than the fundamental, which the difference function must not take
+    fn tone(fundamental: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let phase = 2f32 * std::f32::consts::PI * fundamental * i as f32 / RATE;
+                0.3 * phase.sin() + 0.6 * (2f32 * phase).sin() + 0.1 * (3f32 * phase).sin()
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn fundamental_is_found() {
+        let mut yin = Yin::new(RATE);
+        for &fundamental in [82.41f32, 220f32, 659.26, 1760f32].iter() {
+            let found = yin.fundamental(&tone(fundamental, 4096)).unwrap();
+            assert!((found / fundamental - 1f32).abs() < 0.005, "{} for {}", found, fundamental);
+        }
+        let spectrum = yin.spectrum(&tone(220f32, 4096));
+        assert_eq!(spectrum.len(), YIN_HARMONICS);
+        assert!((spectrum[1].value / 440f32 - 1f32).abs() < 0.005);
+    }
+
+    #[test]
+    fn aperiodic_samples_have_no_pitch() {
+        let mut yin = Yin::new(RATE);
+        assert_eq!(yin.fundamental(&[0f32; 4096]), None);
+        // Too short for the lowest fundamental to be compared
+        assert_eq!(yin.fundamental(&tone(220f32, 32)), None);
+        // Noise
+        let mut state = 1u32;
+        let noise = (0..4096)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+            })
+            .collect_vec();
+        assert_eq!(yin.fundamental(&noise), None);
+        assert!(yin.spectrum(&noise).is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+// Practice prompts, one drawn at random at the start of every session
+// A prompt file adds its lines to the built-in ones, lines starting with '#' being comments
+
+// Standard
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Crate
+use crate::tools;
+
+const BUILT_IN: [&str; 12] = [
+    "Improvise in 3/4",
+    "Start every phrase on the 9th",
+    "Play only the chord tones",
+    "Leave a bar of rest between phrases",
+    "Stay on two strings",
+    "End every phrase on the 3rd",
+    "Use only the pentatonic scale",
+    "Play one note per beat",
+    "Answer every phrase an octave higher",
+    "Approach every chord tone from a semitone below",
+    "Play as quietly as you can",
+    "Repeat a motif, changing one note each time",
+];
+
+#[derive(Clone, Debug)]
+pub struct Prompts {
+    prompts: Vec<String>,
+}
+
+impl Prompts {
+    // The built-in prompts, and those of the file if given
+    pub fn load(path: Option<&str>) -> Result<Prompts, String> {
+        let mut prompts = BUILT_IN.iter().map(|&p| p.to_owned()).collect::<Vec<String>>();
+        if let Some(path) = path {
+            let text = tools::read_text(path)?;
+            prompts.extend(
+                text.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_owned),
+            );
+        }
+        Ok(Prompts { prompts })
+    }
+
+    // A prompt drawn at random
+    pub fn draw(&self) -> String {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as usize)
+            .unwrap_or(0);
+        self.prompts[seed % self.prompts.len()].clone()
+    }
+}
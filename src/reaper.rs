@@ -0,0 +1,152 @@
+// Annotates a REAPER project with the detected chords and key, through its web interface
+// Every change sets the project extended state, then runs an action, inserting an unnamed marker by default
+// The ReaScript of assets/reaper, run as the action instead, names the marker from the state
+
+// Standard
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Sender};
+use std::time::{Duration, Instant};
+
+// Crate
+use crate::chords::Chord;
+use crate::display::Notation;
+use crate::keys::{Key, KeyTracker};
+use crate::output::Output;
+use crate::scores::Scores;
+
+// The REAPER action inserting a marker at the play position
+pub const INSERT_MARKER: &str = "40157";
+// The extended state section written to
+const SECTION: &str = "ImproVe";
+// How long a chord must last before it is published
+const MIN_CHORD_DURATION: Duration = Duration::from_millis(500);
+// The connection timeout, slower is considered down
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+// Percent encode a path component
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// The commands setting a value of the project state, and the marker name, then running the action
+fn annotation(key: &str, value: &str, action: &str) -> String {
+    format!(
+        "SET/PROJEXTSTATE/{section}/{}/{value};SET/PROJEXTSTATE/{section}/marker/{value};{}",
+        key,
+        action,
+        section = SECTION,
+        value = encode(value),
+    )
+}
+
+// Send a request to the web interface, ignoring the answer
+fn request(address: &str, commands: &str) -> Result<(), String> {
+    let socket = address
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("Cannot resolve {}", address))?;
+    let mut stream = TcpStream::connect_timeout(&socket, TIMEOUT).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok();
+    write!(
+        stream,
+        "GET /_/{} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        commands, address
+    )
+    .map_err(|e| e.to_string())?;
+    stream.read_to_end(&mut Vec::new()).ok();
+    Ok(())
+}
+
+pub struct ReaperBridge {
+    // The requests, sent from another thread to never block the analysis
+    sender: Sender<String>,
+    action: String,
+    notation: Notation,
+    // The last published chord and key
+    chord: Option<Chord>,
+    key: Option<Key>,
+    // The chord waiting to last long enough, and since when
+    pending: Option<(Option<Chord>, Instant)>,
+    key_tracker: KeyTracker,
+}
+
+impl ReaperBridge {
+    // Connect to the web interface, at host:port
+    pub fn new(address: &str, action: &str, notation: Notation) -> Result<ReaperBridge, String> {
+        // Check REAPER is listening before starting
+        request(address, "TRANSPORT")
+            .map_err(|e| format!("REAPER web interface at {} unreachable: {}", address, e))?;
+        status!("REAPER web interface = {}", address);
+
+        let (sender, receiver) = channel::<String>();
+        let address = address.to_owned();
+        std::thread::spawn(move || {
+            for commands in receiver {
+                request(&address, &commands).ok();
+            }
+        });
+        Ok(ReaperBridge {
+            sender,
+            action: action.to_owned(),
+            notation,
+            chord: None,
+            key: None,
+            pending: None,
+            key_tracker: KeyTracker::new(),
+        })
+    }
+
+    // Set a value of the project state then run the action
+    fn annotate(&self, key: &str, value: &str) {
+        self.sender.send(annotation(key, value, &self.action)).ok();
+    }
+}
+
+impl Output for ReaperBridge {
+    fn publish(&mut self, scores: &Scores) {
+        let key = self.key_tracker.update(&scores.note_values);
+        if key != self.key {
+            self.key = key;
+            if let Some(key) = key {
+                self.annotate("key", &key.name(self.notation));
+            }
+        }
+
+        // Publish the chords once they have lasted
+        if scores.chord == self.chord {
+            self.pending = None;
+            return;
+        }
+        match self.pending {
+            Some((chord, since)) if chord == scores.chord => {
+                if since.elapsed() >= MIN_CHORD_DURATION {
+                    self.chord = chord;
+                    self.pending = None;
+                    let name = chord.map_or("N.C.".to_owned(), |c| c.name(self.notation));
+                    self.annotate("chord", &name);
+                }
+            }
+            _ => self.pending = Some((scores.chord, Instant::now())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotation_names_the_marker() {
+        assert_eq!(
+            annotation("chord", "C#m7", "_RS1234"),
+            "SET/PROJEXTSTATE/ImproVe/chord/C%23m7;SET/PROJEXTSTATE/ImproVe/marker/C%23m7;_RS1234"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+// Converts the capture to the rate of the analysis, whatever the rate the device opened at
+// Each output sample is interpolated with a Blackman windowed sinc, low-passed under the lower Nyquist
+// frequency so downsampling does not fold the high register onto the notes analysed
+
+// Standard
+use std::f64::consts::PI;
+
+// The zero crossings of the sinc on each side of an output sample
+const ZERO_CROSSINGS: usize = 16;
+// The cutoff below the lower Nyquist frequency, leaving room for the transition band
+const CUTOFF: f64 = 0.95;
+
+pub struct Resampler {
+    // The input samples per output sample
+    step: f64,
+    // The cutoff relative to the input Nyquist frequency
+    cutoff: f64,
+    // The input samples on each side of an output sample
+    half: usize,
+    // The input samples still needed, and the position of the next output sample among them
+    history: Vec<f32>,
+    position: f64,
+    input_rate: i32,
+}
+
+impl Resampler {
+    pub fn new(input_rate: i32, output_rate: i32) -> Resampler {
+        let step = input_rate as f64 / output_rate as f64;
+        let cutoff = CUTOFF * (1f64 / step).min(1f64);
+        let half = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        Resampler {
+            step,
+            cutoff,
+            half,
+            // Silence before the first samples
+            history: vec![0f32; half],
+            position: half as f64,
+            input_rate,
+        }
+    }
+
+    pub fn input_rate(&self) -> i32 {
+        self.input_rate
+    }
+
+    // The windowed sinc at a distance in input samples
+    fn kernel(&self, distance: f64) -> f64 {
+        let width = self.half as f64;
+        if distance.abs() >= width {
+            return 0f64;
+        }
+        let x = PI * self.cutoff * distance;
+        let sinc = if x == 0f64 { 1f64 } else { x.sin() / x };
+        let phase = PI * (distance / width + 1f64);
+        let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2f64 * phase).cos();
+        self.cutoff * sinc * window
+    }
+
+    // The output samples the input completes
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        self.history.extend_from_slice(input);
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        while self.position.floor() as usize + self.half < self.history.len() {
+            let center = self.position.floor() as usize;
+            let fraction = self.position - center as f64;
+            let sample: f64 = (center + 1 - self.half..=center + self.half)
+                .map(|i| self.history[i] as f64 * self.kernel(i as f64 - center as f64 - fraction))
+                .sum();
+            output.push(sample as f32);
+            self.position += self.step;
+        }
+        // Forget the samples no longer in reach
+        let used = (self.position.floor() as usize).saturating_sub(self.half);
+        self.history.drain(..used.min(self.history.len()));
+        self.position -= used as f64;
+        output
+    }
+}
@@ -0,0 +1,239 @@
+// A preallocated single producer, single consumer ring of samples, from the capture callbacks
+// Writing neither allocates nor locks, so the real-time thread is never held up by the analysis
+// When the analysis falls behind and the ring is full, the samples are dropped and counted
+// A capture reopened writes to the same ring through a new producer, the previous one falling silent
+// The two can overlap while the replaced one finishes a callback: it stops at the next sample and
+// gives up the samples of that write, but one sample stored meanwhile may land among the new ones
+
+// Standard
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How often a read waiting for samples checks the ring
+const READ_POLL: Duration = Duration::from_millis(2);
+// The audio held, in seconds, far more than the analysis ever lags
+const CAPACITY_SECONDS: usize = 2;
+
+struct Shared {
+    // The bits of the samples, atomic so the ring needs no unsafe code
+    samples: Vec<AtomicU32>,
+    // The samples written and read since the start, their difference being those waiting
+    written: AtomicUsize,
+    read: AtomicUsize,
+    // The samples that did not fit, since the last read
+    dropped: AtomicUsize,
+    // Set when the producer is gone, the capture being closed
+    closed: AtomicBool,
+    // The producer writing, the previous ones being ignored
+    generation: AtomicUsize,
+}
+
+pub struct Producer {
+    shared: Arc<Shared>,
+    generation: usize,
+}
+
+// What a read waiting for samples got
+pub enum Wait {
+    // Samples, with how many were dropped since the previous read
+    Read(usize),
+    // Nothing written for the whole timeout, the capture having stalled
+    Timeout,
+    // The producer is gone and the ring empty
+    Closed,
+}
+
+// Makes the producer of a reopened capture, and tells whether the current one writes
+#[derive(Clone)]
+pub struct Handle {
+    shared: Arc<Shared>,
+}
+
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+// A ring holding the given number of samples
+pub fn ring(capacity: usize) -> (Producer, Consumer) {
+    let shared = Arc::new(Shared {
+        samples: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+        dropped: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        generation: AtomicUsize::new(0),
+    });
+    (
+        Producer {
+            shared: shared.clone(),
+            generation: 0,
+        },
+        Consumer { shared },
+    )
+}
+
+// A ring holding a few seconds of a stream at the given rate
+pub fn for_rate(rate: i32) -> (Producer, Consumer) {
+    ring(rate.max(1) as usize * CAPACITY_SECONDS)
+}
+
+impl Producer {
+    // Write the samples that fit, dropping the others
+    // Nothing is written once a newer producer started, even in the middle of the samples
+    pub fn write<I: IntoIterator<Item = f32>>(&mut self, samples: I) {
+        let shared = &self.shared;
+        if shared.generation.load(Ordering::Acquire) != self.generation {
+            return;
+        }
+        let capacity = shared.samples.len();
+        let read = shared.read.load(Ordering::Acquire);
+        let start = shared.written.load(Ordering::Acquire);
+        let mut written = start;
+        let mut samples = samples.into_iter();
+        while written - read < capacity {
+            let sample = match samples.next() {
+                Some(sample) => sample,
+                None => break,
+            };
+            if shared.generation.load(Ordering::Acquire) != self.generation {
+                return;
+            }
+            shared.samples[written % capacity].store(sample.to_bits(), Ordering::Relaxed);
+            written += 1;
+        }
+        // The newer producer published its own samples meanwhile
+        if shared
+            .written
+            .compare_exchange(start, written, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+        let dropped = samples.count();
+        if dropped > 0 {
+            shared.dropped.fetch_add(dropped, Ordering::Relaxed);
+        }
+    }
+
+    pub fn handle(&self) -> Handle {
+        Handle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Producer {
+    // Only the current producer closes the ring, the replaced ones dropping with their capture
+    fn drop(&mut self) {
+        if self.shared.generation.load(Ordering::Acquire) == self.generation {
+            self.shared.closed.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl Handle {
+    // A producer replacing the previous one, which then writes nothing
+    pub fn producer(&self) -> Producer {
+        let generation = self.shared.generation.fetch_add(1, Ordering::AcqRel) + 1;
+        Producer {
+            shared: self.shared.clone(),
+            generation,
+        }
+    }
+
+    // The samples written since the start, to tell a stalled capture
+    pub fn written(&self) -> usize {
+        self.shared.written.load(Ordering::Acquire)
+    }
+}
+
+impl Consumer {
+    // Append the samples waiting, returning how many were dropped since the previous read
+    pub fn read(&mut self, output: &mut Vec<f32>) -> usize {
+        let shared = &self.shared;
+        let capacity = shared.samples.len();
+        let written = shared.written.load(Ordering::Acquire);
+        let read = shared.read.load(Ordering::Relaxed);
+        output.extend(
+            (read..written)
+                .map(|i| f32::from_bits(shared.samples[i % capacity].load(Ordering::Relaxed))),
+        );
+        shared.read.store(written, Ordering::Release);
+        shared.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    // Wait for samples, at most for the timeout
+    pub fn read_wait(&mut self, output: &mut Vec<f32>, timeout: Duration) -> Wait {
+        let start = output.len();
+        let waited = Instant::now();
+        loop {
+            let closed = self.shared.closed.load(Ordering::Acquire);
+            let dropped = self.read(output);
+            if output.len() > start || dropped > 0 {
+                return Wait::Read(dropped);
+            } else if closed {
+                return Wait::Closed;
+            } else if waited.elapsed() >= timeout {
+                return Wait::Timeout;
+            }
+            std::thread::sleep(READ_POLL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn overfill_is_dropped_and_counted() {
+        let (mut producer, mut consumer) = ring(4);
+        producer.write((0..6).map(|i| i as f32));
+        let mut output = Vec::new();
+        assert_eq!(consumer.read(&mut output), 2);
+        assert_eq!(output, vec![0f32, 1f32, 2f32, 3f32]);
+        // The count is reset once read
+        producer.write(vec![4f32]);
+        assert_eq!(consumer.read(&mut output), 0);
+        assert_eq!(output.len(), 5);
+    }
+
+    #[test]
+    fn replaced_producer_stops_in_the_middle_of_a_write() {
+        let (mut producer, mut consumer) = ring(8);
+        let handle = producer.handle();
+        let reopened = RefCell::new(None);
+        // The capture reopened, and written to, while the first sample is being taken
+        producer.write((0..4).map(|i| {
+            if i == 0 {
+                let mut newer = handle.producer();
+                newer.write(vec![9f32; 3]);
+                *reopened.borrow_mut() = Some(newer);
+            }
+            i as f32
+        }));
+        let mut output = Vec::new();
+        assert_eq!(consumer.read(&mut output), 0);
+        assert_eq!(output, vec![9f32; 3]);
+        assert_eq!(handle.written(), 3);
+    }
+
+    #[test]
+    fn replaced_producer_keeps_the_ring_open() {
+        let (producer, mut consumer) = ring(8);
+        let reopened = producer.handle().producer();
+        drop(producer);
+        let mut output = Vec::new();
+        assert!(matches!(
+            consumer.read_wait(&mut output, Duration::from_millis(10)),
+            Wait::Timeout
+        ));
+        drop(reopened);
+        assert!(matches!(
+            consumer.read_wait(&mut output, Duration::from_millis(10)),
+            Wait::Closed
+        ));
+    }
+}
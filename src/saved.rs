@@ -0,0 +1,134 @@
+// The state of the windows when last closed, restored at the next launch, apart from the config
+// One setting per line, rewritten on exit:
+//   window = 0 120 80 1024 600   the position and size of a window, by its index in the layout
+//   graph = intonation           what the graph window shows: notes, intonation or detail
+//   scaling = session            the color scaling
+//   style = jazz                 the musical style
+//   halflife = 0.50              the halflife, in seconds
+// The options given on the command line are kept over the saved ones
+
+// Standard
+use std::fs;
+
+// Crate
+use crate::display::Scaling;
+use crate::scores::Style;
+use crate::tools;
+
+// The position and size of a window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Saved {
+    path: String,
+    // By the index of the window in the layout
+    pub windows: Vec<Option<Placement>>,
+    pub graph: Option<String>,
+    pub scaling: Option<Scaling>,
+    pub style: Option<Style>,
+    pub halflife: Option<f32>,
+}
+
+impl Saved {
+    // The state saved in a file, nothing before the first launch
+    pub fn load(path: &str) -> Result<Saved, String> {
+        let mut saved = Saved {
+            path: path.to_owned(),
+            ..Saved::default()
+        };
+        if fs::metadata(path).is_err() {
+            return Ok(saved);
+        }
+        let text = tools::read_text(path)?;
+        for (number, line) in text.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("{}:{}: {}", path, number + 1, message);
+            let mut parts = line.splitn(2, '=').map(str::trim);
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name, value),
+                _ => return Err(error("expected name = value")),
+            };
+            match name {
+                "window" => {
+                    let numbers = value
+                        .split_whitespace()
+                        .map(|n| n.parse::<i32>().ok())
+                        .collect::<Option<Vec<i32>>>()
+                        .filter(|n| n.len() == 5 && n[0] >= 0 && n[3] > 0 && n[4] > 0)
+                        .ok_or_else(|| {
+                            error("expected the window index, x, y, width and height")
+                        })?;
+                    let index = numbers[0] as usize;
+                    if saved.windows.len() <= index {
+                        saved.windows.resize(index + 1, None);
+                    }
+                    saved.windows[index] = Some(Placement {
+                        x: numbers[1],
+                        y: numbers[2],
+                        width: numbers[3] as u32,
+                        height: numbers[4] as u32,
+                    });
+                }
+                "graph" => saved.graph = Some(value.to_owned()),
+                "scaling" => {
+                    saved.scaling =
+                        Some(Scaling::from_name(value).ok_or_else(|| error("unknown scaling"))?)
+                }
+                "style" => {
+                    saved.style =
+                        Some(Style::from_name(value).ok_or_else(|| error("unknown style"))?)
+                }
+                "halflife" => {
+                    saved.halflife = Some(
+                        value
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|&h| h > 0f32)
+                            .ok_or_else(|| error("the halflife is not a positive float"))?,
+                    )
+                }
+                _ => return Err(error(&format!("unknown setting '{}'", name))),
+            }
+        }
+        Ok(saved)
+    }
+
+    // Write the state back, a failure only being reported
+    pub fn save(&self) {
+        let mut lines =
+            vec!["# The state of the windows when last closed, restored with --state".to_owned()];
+        for (index, placement) in self.windows.iter().enumerate() {
+            if let Some(p) = placement {
+                lines.push(format!(
+                    "window = {} {} {} {} {}",
+                    index, p.x, p.y, p.width, p.height
+                ));
+            }
+        }
+        if let Some(graph) = self.graph.as_ref() {
+            lines.push(format!("graph = {}", graph));
+        }
+        if let Some(scaling) = self.scaling {
+            lines.push(format!("scaling = {}", scaling.name()));
+        }
+        if let Some(style) = self.style {
+            lines.push(format!("style = {}", style.name()));
+        }
+        if let Some(halflife) = self.halflife {
+            lines.push(format!("halflife = {:.2}", halflife));
+        }
+        lines.push(String::new());
+        match fs::write(&self.path, lines.join("\n")) {
+            Ok(()) => status!("State saved to {}", self.path),
+            Err(e) => eprintln!("Cannot save the state to {}: {}", self.path, e),
+        }
+    }
+}
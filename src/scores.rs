@@ -1,72 +1,525 @@
+use crate::articulation::{ArticulationTracker, NoteDetail};
+use crate::audio_buffer::{InputEvent, Meter};
+use crate::chords::{self, Chord};
+use crate::classifier::Instrument;
+use crate::continuo;
+use crate::counterpoint::Progress;
 use crate::dissonance;
+use crate::fourier::ScoringOptions;
 use crate::frequency::Frequency;
+use crate::intonation::Intonation;
+use crate::latency::Latency;
 
 use crate::notes::{Note, NOTE_COUNT};
+use crate::session::Metrics;
 
 use itertools::Itertools;
 
+use std::collections::VecDeque;
 use std::time::Instant;
 
+// The halflives of audio heard before the scores are near their steady state, 87.5% of it
+const WARMUP_HALFLIVES: f32 = 3f32;
+
+// Musical styles, setting how long the bass defines the harmony
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    // Bass and melody decay alike
+    Neutral,
+    // Walking bass lines move often
+    Jazz,
+    // Riffs and power chords sit on a steady bass
+    Rock,
+    // Long pedal tones and drones
+    Ambient,
+}
+
+impl Style {
+    // The bass decay time, relative to the halflife
+    pub fn bass_decay(self) -> f32 {
+        match self {
+            Style::Neutral => 1.0,
+            Style::Jazz => 1.5,
+            Style::Rock => 2.5,
+            Style::Ambient => 4.0,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Style> {
+        match name {
+            "neutral" => Some(Style::Neutral),
+            "jazz" => Some(Style::Jazz),
+            "rock" => Some(Style::Rock),
+            "ambient" => Some(Style::Ambient),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Neutral => "neutral",
+            Style::Jazz => "jazz",
+            Style::Rock => "rock",
+            Style::Ambient => "ambient",
+        }
+    }
+
+    // The style switched to at runtime
+    pub fn next(self) -> Style {
+        match self {
+            Style::Neutral => Style::Jazz,
+            Style::Jazz => Style::Rock,
+            Style::Rock => Style::Ambient,
+            Style::Ambient => Style::Neutral,
+        }
+    }
+}
+
+impl Default for Style {
+    fn default() -> Style {
+        Style::Neutral
+    }
+}
+
+// How the scores of a pitch class are shared between its octaves
+#[derive(Clone, Copy, Debug)]
+pub enum Fold {
+    // Every note is scored on its own
+    None,
+    // Every note gets the average of its pitch class, weighted by octave distance
+    Weighted,
+    // Notes from this one upwards share the average of their pitch class
+    Above(Note),
+}
+
+impl Default for Fold {
+    fn default() -> Fold {
+        Fold::None
+    }
+}
+
+// The dissonance of every note, independent of the input level, split by what it clashes with
+#[derive(Clone, Copy, Debug)]
+pub struct Attribution {
+    // With the band: the chart, the MIDI context, the left hand or the register under the crossover
+    pub band: [f32; NOTE_COUNT],
+    // With what the player just played
+    pub own: [f32; NOTE_COUNT],
+}
+
+// The notes held by each hand of a pianist, lowest first, when the keyboard is split
+#[derive(Clone, Debug, Default)]
+pub struct Hands {
+    pub left: Vec<Note>,
+    pub right: Vec<Note>,
+}
+
+// The pitch deviation, in cents, within which a peak is a harmonic of a lower note
+const HARMONIC_CENTS: f32 = 30f32;
+// The highest harmonic of the left hand notes told apart from the right hand
+const HANDS_MAX_HARMONIC: f32 = 8f32;
+
+// Split the peaks between the hands, at the lowest key of the right hand
+// The harmonics of the left hand notes that cross the split stay with the left hand
+fn split_hands(peaks: &[Frequency], split: Note) -> (Vec<Frequency>, Vec<Frequency>) {
+    // Halfway to the key under the split
+    let limit = split.freq() * 2f32.powf(-0.5 / 12f32);
+    let (mut left, above): (Vec<Frequency>, Vec<Frequency>) = peaks.iter().partition(|f| f.value < limit);
+    let fundamentals = left.clone();
+    let mut right = Vec::new();
+    for peak in above {
+        let harmonic = fundamentals.iter().any(|f| {
+            let ratio = peak.value / f.value;
+            let cents = 1200f32 * (ratio / ratio.round()).log2().abs();
+            ratio.round() >= 2f32
+                && ratio.round() <= HANDS_MAX_HARMONIC
+                && cents < HARMONIC_CENTS
+                && peak.intensity < f.intensity
+        });
+        if harmonic {
+            left.push(peak);
+        } else {
+            right.push(peak);
+        }
+    }
+    (left, right)
+}
+
+// The notes of the peaks of a hand, under a key when the harmonics are left out
+fn held_notes(peaks: &[Frequency], under: Option<Note>) -> Vec<Note> {
+    peaks
+        .iter()
+        .filter_map(|f| Note::from_freq(f.value))
+        .filter(|&n| under.map_or(true, |u| (n as usize) < u as usize))
+        .sorted_by_key(|&n| n as usize)
+        .dedup()
+        .collect_vec()
+}
+
+// How the dissonance of the frames is combined over time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregation {
+    // Decaying by half every halflife, the bass more slowly
+    Exponential,
+    // The average of the frames of the last seconds
+    Window(f32),
+    // The average since the start of the bar, of the given seconds, reset on every bar
+    Bar(f32),
+    // Held at its peak, then draining at a constant rate to empty in the given seconds
+    Leaky(f32),
+}
+
+impl Default for Aggregation {
+    fn default() -> Aggregation {
+        Aggregation::Exponential
+    }
+}
+
+impl Aggregation {
+    // The mode of the given name, lasting the given seconds
+    pub fn from_name(name: &str, seconds: f32) -> Option<Aggregation> {
+        match name {
+            "exponential" => Some(Aggregation::Exponential),
+            "window" => Some(Aggregation::Window(seconds)),
+            "bar" => Some(Aggregation::Bar(seconds)),
+            "leaky" => Some(Aggregation::Leaky(seconds)),
+            _ => None,
+        }
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Aggregation::Exponential => "exponential".to_owned(),
+            Aggregation::Window(seconds) => format!("window of {:.1}s", seconds),
+            Aggregation::Bar(seconds) => format!("bar of {:.1}s", seconds),
+            Aggregation::Leaky(seconds) => format!("leaky over {:.1}s", seconds),
+        }
+    }
+}
+
+// Combines the values of the frames with the modes other than the exponential decay
+struct Aggregator {
+    aggregation: Aggregation,
+    // The frames of the window, with their durations
+    frames: VecDeque<(f32, Vec<f32>)>,
+    // The time into the bar, and the values summed since its start weighted by duration
+    bar_time: f32,
+    sums: Vec<f32>,
+    // The values held by the leaky buckets, and the peaks they drain from
+    levels: Vec<f32>,
+    peaks: Vec<f32>,
+}
+
+impl Aggregator {
+    fn new(aggregation: Aggregation) -> Aggregator {
+        Aggregator {
+            aggregation,
+            frames: VecDeque::new(),
+            bar_time: 0f32,
+            sums: Vec::new(),
+            levels: Vec::new(),
+            peaks: Vec::new(),
+        }
+    }
+
+    // The aggregated values, after a frame lasting the given seconds
+    fn aggregate(&mut self, frame: &[f32], seconds: f32) -> Vec<f32> {
+        match self.aggregation {
+            Aggregation::Exponential => frame.to_vec(),
+            Aggregation::Window(window) => {
+                self.frames.push_back((seconds, frame.to_vec()));
+                // Drop the frames entirely out of the window, keeping the last
+                let mut duration = self.frames.iter().map(|f| f.0).sum::<f32>();
+                while self.frames.len() > 1 && duration - self.frames[0].0 >= window {
+                    duration -= self.frames.pop_front().unwrap().0;
+                }
+                let duration = duration.max(1e-6);
+                (0..frame.len())
+                    .map(|i| self.frames.iter().map(|(d, v)| d * v[i]).sum::<f32>() / duration)
+                    .collect()
+            }
+            Aggregation::Bar(bar) => {
+                if self.sums.len() != frame.len() || self.bar_time + seconds > bar {
+                    self.bar_time = (self.bar_time + seconds) % bar.max(1e-3);
+                    self.sums = vec![0f32; frame.len()];
+                } else {
+                    self.bar_time += seconds;
+                }
+                for (sum, value) in self.sums.iter_mut().zip(frame.iter()) {
+                    *sum += value * seconds;
+                }
+                // The start of the bar weighs at least a frame
+                let duration = self.bar_time.max(seconds).max(1e-6);
+                self.sums.iter().map(|s| s / duration).collect()
+            }
+            Aggregation::Leaky(drain) => {
+                if self.levels.len() != frame.len() {
+                    self.levels = frame.to_vec();
+                    self.peaks = frame.to_vec();
+                }
+                for ((level, peak), &value) in self.levels.iter_mut().zip(self.peaks.iter_mut()).zip(frame) {
+                    if value >= *level {
+                        *level = value;
+                        *peak = value;
+                    } else {
+                        *level = (*level - *peak * seconds / drain.max(1e-3)).max(value);
+                    }
+                }
+                self.levels.clone()
+            }
+        }
+    }
+
+    // Keep only part of the values combined so far
+    fn discount(&mut self, kept: f32) {
+        for (_, frame) in self.frames.iter_mut() {
+            frame.iter_mut().for_each(|v| *v *= kept);
+        }
+        self.levels.iter_mut().for_each(|v| *v *= kept);
+        self.peaks.iter_mut().for_each(|v| *v *= kept);
+        self.sums.iter_mut().for_each(|v| *v *= kept);
+    }
+}
+
+impl Fold {
+    fn apply(self, notes: &mut [f32; NOTE_COUNT]) {
+        let raw = *notes;
+        match self {
+            Fold::None => {}
+            Fold::Weighted => {
+                for (i, score) in notes.iter_mut().enumerate() {
+                    let (sum, weights) = (i % 12..NOTE_COUNT)
+                        .step_by(12)
+                        .map(|j| {
+                            // Halve the contribution for every octave of distance
+                            let distance = (i as i32 - j as i32).abs() / 12;
+                            let weight = 0.5f32.powi(distance);
+                            (raw[j] * weight, weight)
+                        })
+                        .fold((0f32, 0f32), |a, b| (a.0 + b.0, a.1 + b.1));
+                    *score = sum / weights;
+                }
+            }
+            Fold::Above(note) => {
+                let first = note as usize;
+                for class in 0..12 {
+                    let octaves = (first..NOTE_COUNT).filter(|j| j % 12 == class).collect_vec();
+                    let average = octaves.iter().map(|&j| raw[j]).sum::<f32>() / octaves.len() as f32;
+                    for j in octaves {
+                        notes[j] = average;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Scores {
     // The dissonance score of each note
     pub note_scores: [f32; NOTE_COUNT],
+    // The dissonance of each note per unit of heard intensity, before the octaves are normalized
+    pub dissonance: [f32; NOTE_COUNT],
     // The intensity of each note
     pub note_values: [f32; NOTE_COUNT],
     pub fourier: Vec<Frequency>,
+    // How stable the scores are compared to the previous frame (0 .. 1)
+    pub confidence: f32,
+    // The chord best matching the heard notes
+    pub chord: Option<Chord>,
+    // The average deviation of every note played during the session, in cents
+    pub intonation: [Option<f32>; NOTE_COUNT],
+    // The pitch analysis of the note currently held
+    pub detail: Option<NoteDetail>,
+    // The note started on this frame, if any
+    pub onset: Option<Note>,
+    // The annotations received since the previous frame
+    pub annotations: Vec<String>,
+    // The RMS level of the analysed audio, in decibels
+    pub level: f32,
+    // The short-term loudness of the input, in LUFS, once measured long enough
+    pub loudness: Option<f32>,
+    // The RMS and peak levels of the input captured since the previous frame
+    pub meter: Meter,
+    // The input problems since the previous frame
+    pub input_events: Vec<InputEvent>,
+    // The NaN or infinite samples replaced since the start
+    pub invalid_samples: u64,
+    // How much audio was heard relative to the halflife (0 .. 1), the scores meaning little below 1
+    pub warmup: f32,
+    // The practice prompt of the session
+    pub prompt: Option<String>,
+    // The descriptors of the session so far, once notes were played
+    pub metrics: Option<Metrics>,
+    // Whether the input is silent and the analysis paused, these being the last scores
+    pub idle: bool,
+    // The dissonance split between the band and the player, when the band is heard apart
+    pub attribution: Option<Attribution>,
+    // The notes of each hand, when the keyboard is split
+    pub hands: Option<Hands>,
+    // The instrument recognized, when classifying the input
+    pub instrument: Option<Instrument>,
+    // How far the counterpoint exercise is, and the notes judged
+    pub counterpoint: Option<Progress>,
+    // How far the figured bass exercise is, and the figures judged
+    pub continuo: Option<continuo::Progress>,
+    // When the packet scored was taken from the buffer and sent, to measure the latency
+    pub latency: Latency,
+    // The latest scores of the other channels when they are analysed apart, None until their first
+    pub channels: Vec<Option<Scores>>,
 }
 
 pub struct ScoreCalculator {
-    dissonance_values: Vec<Vec<f32>>,
-    prev_score: [f32; NOTE_COUNT],
+    options: ScoringOptions,
+    // The scores caused by bass and treble frequencies, decaying at different rates
+    prev_bass: [f32; NOTE_COUNT],
+    prev_treble: [f32; NOTE_COUNT],
     prev_values: [f32; NOTE_COUNT],
+    // The heard intensity, decaying like the treble scores
+    prev_intensity: f32,
+    // The dissonance of the last call, before the octaves are normalized
+    dissonance: [f32; NOTE_COUNT],
+    // The scores returned by the previous call, to measure stability
+    prev_note_scores: [f32; NOTE_COUNT],
+    // The slowly moving pitch class profile, used to detect harmonic changes
+    prev_chroma: [f32; 12],
+    // The deviations of played pitches over the session
+    intonation: Intonation,
+    // The pitch trajectory of sustained notes
+    articulation: ArticulationTracker,
+    // The halflife currently in use, between options.change_halflife and options.halflife
+    halflife: f32,
+    // The seconds of audio with peaks scored since the start
+    heard: f32,
     time: Instant,
+    // Combines the frames, with the modes other than the exponential decay
+    aggregator: Aggregator,
+    // The dissonance with the notes of the player, and their intensity, decaying like the treble
+    prev_own: [f32; NOTE_COUNT],
+    prev_own_intensity: f32,
 }
 
 impl ScoreCalculator {
-    pub fn new(heard: &[Frequency]) -> ScoreCalculator {
-        let dissonance_values = dissonance::dissonance_scores(heard);
-
+    pub fn new(options: ScoringOptions) -> ScoreCalculator {
         ScoreCalculator {
-            dissonance_values,
-            prev_score: [0f32; NOTE_COUNT],
+            options,
+            prev_bass: [0f32; NOTE_COUNT],
+            prev_treble: [0f32; NOTE_COUNT],
             prev_values: [0f32; NOTE_COUNT],
+            prev_intensity: 0f32,
+            dissonance: [0f32; NOTE_COUNT],
+            prev_note_scores: [0f32; NOTE_COUNT],
+            prev_chroma: [0f32; 12],
+            intonation: Intonation::new(),
+            articulation: ArticulationTracker::new(),
+            halflife: options.halflife,
+            heard: 0f32,
             time: Instant::now(),
+            aggregator: Aggregator::new(options.aggregation),
+            prev_own: [0f32; NOTE_COUNT],
+            prev_own_intensity: 0f32,
         }
     }
 
-    fn calculate_note(&self, heard: &[(usize, Frequency)], note: Note) -> f32 {
-        let mut score = 0f32;
-        for &(u, f) in heard.iter() {
-            score += f.intensity * self.dissonance_values[note as usize][u];
+    // Sum the intensity of the heard peaks per pitch class
+    fn chroma(heard: &[Frequency]) -> [f32; 12] {
+        let mut chroma = [0f32; 12];
+        for f in heard {
+            if let Some(note) = Note::from_freq(f.value) {
+                chroma[note.get_octave_index() as usize] += f.intensity.sqrt();
+            }
+        }
+        chroma
+    }
+
+    // Shorten the halflife when the harmony changes, and let it recover during static harmony
+    fn adapt_halflife(&mut self, heard: &[Frequency], seconds: f32) -> f32 {
+        let chroma = Self::chroma(heard);
+
+        // Cosine distance between the current and the recent pitch class profiles
+        let dot: f32 = chroma.iter().zip(self.prev_chroma.iter()).map(|(a, b)| a * b).sum();
+        let norm = |c: &[f32; 12]| c.iter().map(|a| a * a).sum::<f32>().sqrt();
+        let norms = norm(&chroma) * norm(&self.prev_chroma);
+        let distance = if norms > 0f32 { 1f32 - dot / norms } else { 0f32 };
+
+        // The halflife recovers towards its static value at the static rate
+        let recovery = 0.5f32.powf(seconds / self.options.halflife);
+        self.halflife = self.options.halflife
+            + (self.halflife - self.options.halflife) * recovery;
+        if distance > self.options.change_threshold {
+            self.halflife = self.halflife.min(self.options.change_halflife);
+        }
+
+        // Update the recent profile at the static rate
+        for (prev, new) in self.prev_chroma.iter_mut().zip(chroma.iter()) {
+            *prev = *prev * recovery + new * (1f32 - recovery);
+        }
+        self.halflife
+    }
+
+    // Get the dissonance caused by the bass and treble frequencies
+    fn calculate_note(&self, dissonance_values: &[Vec<f32>], heard: &[Frequency], note: Note) -> (f32, f32) {
+        let mut bass = 0f32;
+        let mut treble = 0f32;
+        for (u, f) in heard.iter().enumerate() {
+            let score = f.intensity * dissonance_values[note as usize][u];
+            if f.value < self.options.bass_split {
+                bass += score;
+            } else {
+                treble += score;
+            }
         }
-        score
+        (bass, treble)
     }
 
-    fn calculate_scores(&mut self, heard: &Vec<Frequency>, factor:f32) -> [f32; NOTE_COUNT] {
+    fn calculate_scores(
+        &mut self,
+        heard: &[Frequency],
+        factor: f32,
+        bass_factor: f32,
+        seconds: f32,
+    ) -> [f32; NOTE_COUNT] {
         let mut notes = [0f32; NOTE_COUNT];
 
-        // Extract indices for lookup table
-        // Sort the array
-        // Possibly skip lower parts for noise reduction
-        let heard_sorted = heard
-            .iter()
-            .cloned()
-            .enumerate()
-            .sorted_by_key(|(_, f)| *f)
-            // .skip(heard.len() / 2)
-            // .skip(heard.len() / 4)
-            // .skip(heard.len() / 8)
-            .collect_vec();
+        // Build the lookup table for the heard peaks
+        let dissonance_values = dissonance::dissonance_scores(heard);
+        let intensity: f32 = heard.iter().map(|f| f.intensity).sum();
 
-        // Get each score, and average with previous value
-        for note in Note::iter() {
-            let score = self.calculate_note(heard_sorted.as_slice(), note);
-            notes[note as usize] =
-                score * (1f32 - factor) + self.prev_score[note as usize] * factor;
+        if self.options.aggregation == Aggregation::Exponential {
+            // Get each score, and average with previous value
+            for note in Note::iter() {
+                let i = note as usize;
+                let (bass, treble) = self.calculate_note(&dissonance_values, heard, note);
+                self.prev_bass[i] = bass * (1f32 - bass_factor) + self.prev_bass[i] * bass_factor;
+                self.prev_treble[i] = treble * (1f32 - factor) + self.prev_treble[i] * factor;
+            }
+            self.prev_intensity = intensity * (1f32 - factor) + self.prev_intensity * factor;
+        } else {
+            // The bass, treble and intensity aggregated alike, the bass decay not applying
+            let mut frame = Vec::with_capacity(2 * NOTE_COUNT + 1);
+            let (bass, treble): (Vec<f32>, Vec<f32>) = Note::iter()
+                .map(|note| self.calculate_note(&dissonance_values, heard, note))
+                .unzip();
+            frame.extend(bass);
+            frame.extend(treble);
+            frame.push(intensity);
+            let aggregated = self.aggregator.aggregate(&frame, seconds);
+            self.prev_bass.copy_from_slice(&aggregated[..NOTE_COUNT]);
+            self.prev_treble.copy_from_slice(&aggregated[NOTE_COUNT..2 * NOTE_COUNT]);
+            self.prev_intensity = aggregated[2 * NOTE_COUNT];
+        }
+        for (i, note) in notes.iter_mut().enumerate() {
+            *note = self.prev_bass[i] + self.prev_treble[i];
         }
 
-        self.prev_score = notes;
-        
+        // Keep the dissonance independent of the input level
+        for (dissonance, note) in self.dissonance.iter_mut().zip(notes.iter()) {
+            *dissonance = if self.prev_intensity > 0f32 { note / self.prev_intensity } else { 0f32 };
+        }
+
+        // Share the scores between octaves
+        self.options.fold.apply(&mut notes);
+
         // Normalise octaves
         for i in 0 .. notes.len() {
 
@@ -108,7 +561,7 @@ impl ScoreCalculator {
 
     // Assign each frequency to a note, and sum their value
     // Allows the dsplay of every perceived note
-    fn calculate_values(&mut self, heard: &Vec<Frequency>, factor:f32) -> [f32; NOTE_COUNT] {
+    fn calculate_values(&mut self, heard: &[Frequency], factor:f32) -> [f32; NOTE_COUNT] {
         let mut note_values = [0f32; NOTE_COUNT];
 
         for f in heard {
@@ -121,8 +574,39 @@ impl ScoreCalculator {
         note_values
     }
 
-    // Analyses a list of perceived frequencies, and returns displayable data
-    pub fn calculate(&mut self, heard: Vec<Frequency>, halflife:f32) -> Scores {
+    // One minus the average change of the normalized scores since the previous frame
+    fn calculate_confidence(&mut self, note_scores: &[f32; NOTE_COUNT]) -> f32 {
+        let diffs = note_scores
+            .iter()
+            .zip(self.prev_note_scores.iter())
+            .map(|(a, b)| (a - b).abs())
+            .filter(|d| d.is_finite())
+            .collect_vec();
+        self.prev_note_scores = *note_scores;
+        if diffs.is_empty() {
+            return 0f32;
+        }
+        let change = diffs.iter().sum::<f32>() / diffs.len() as f32;
+        (1f32 - change).max(0f32).min(1f32)
+    }
+
+    // Analyses the peaks of a spectrum, and returns displayable data
+    // The context, when given, replaces the peaks as the harmony to score against
+    pub fn set_halflife(&mut self, halflife: f32) {
+        self.options.halflife = halflife;
+        self.halflife = halflife;
+    }
+
+    pub fn set_bass_decay(&mut self, bass_decay: f32) {
+        self.options.bass_decay = bass_decay;
+    }
+
+    pub fn calculate(
+        &mut self,
+        fourier: Vec<Frequency>,
+        peaks: &[Frequency],
+        context: Option<Vec<Frequency>>,
+    ) -> Scores {
 
         // Time-aware walking average
         // An approximation of second-order beatings
@@ -135,14 +619,233 @@ impl ScoreCalculator {
             + time_since_last_call.subsec_nanos() as f32 * 1e-9;
         self.time = Instant::now();
         assert!(seconds >= 0f32);
+
+        // The left hand accompanying the right one, when the keyboard is split
+        let hands = self.options.split.map(|split| split_hands(peaks, split));
+
+        // The notes of the player, when the band is heard apart from them
+        let player = match (&context, &hands, self.options.crossover) {
+            (_, Some((_, right)), _) => Some(right.clone()),
+            (Some(_), None, _) => Some(peaks.to_vec()),
+            (None, None, Some(crossover)) => {
+                Some(peaks.iter().cloned().filter(|f| f.value >= crossover).collect_vec())
+            }
+            (None, None, None) => None,
+        };
+
+        // Only score against the rhythm section's register, ignoring the soloist
+        let context = match (context, &hands, self.options.crossover) {
+            (Some(context), _, _) => context,
+            (None, Some((left, _)), _) => left.clone(),
+            (None, None, Some(crossover)) => peaks.iter().cloned().filter(|f| f.value < crossover).collect_vec(),
+            (None, None, None) => peaks.to_vec(),
+        };
+        let hands = hands.map(|(left, right)| Hands {
+            left: held_notes(&left, self.options.split),
+            right: held_notes(&right, None),
+        });
+
+        // Get the halflife for the current harmonic situation
+        let halflife = self.adapt_halflife(&context, seconds);
         // Get how much previous score should have faded
         let factor = 0.5f32.powf(seconds / halflife);
         assert!(factor >= 0f32 && factor <= 1f32);
+        // The bass keeps defining the harmony for longer
+        let bass_factor = 0.5f32.powf(seconds / (halflife * self.options.bass_decay));
+
+        let note_scores = self.calculate_scores(&context, factor, bass_factor, seconds);
+        let attribution = player.map(|player| self.attribute(&player, factor));
+        let confidence = self.calculate_confidence(&note_scores);
+        let note_values = self.calculate_values(peaks, factor / 5.0);
+        let chord = Chord::detect(&chords::chroma(&note_values));
+        self.intonation.update(peaks);
+        let detail = self.articulation.update(peaks, seconds);
+        if !context.is_empty() {
+            self.heard += seconds;
+        }
+        let warmup = (self.heard / (WARMUP_HALFLIVES * self.options.halflife)).min(1f32);
 
         Scores {
-            note_scores: self.calculate_scores(&heard, factor),
-            note_values: self.calculate_values(&heard, factor / 5.0),
-            fourier: heard,
+            note_scores,
+            dissonance: self.dissonance,
+            note_values,
+            fourier,
+            confidence,
+            chord,
+            intonation: self.intonation.deviations(),
+            detail,
+            onset: self.articulation.onset(),
+            annotations: Vec::new(),
+            level: f32::NEG_INFINITY,
+            loudness: None,
+            meter: Meter::default(),
+            input_events: Vec::new(),
+            invalid_samples: 0,
+            warmup,
+            prompt: None,
+            metrics: None,
+            idle: false,
+            attribution,
+            hands,
+            instrument: None,
+            counterpoint: None,
+            continuo: None,
+            latency: Latency::default(),
+            channels: Vec::new(),
+        }
+    }
+
+    // Score every note against the notes just played, beside the band ones scored already
+    fn attribute(&mut self, player: &[Frequency], factor: f32) -> Attribution {
+        let dissonance_values = dissonance::dissonance_scores(player);
+        let intensity: f32 = player.iter().map(|f| f.intensity).sum();
+        self.prev_own_intensity = intensity * (1f32 - factor) + self.prev_own_intensity * factor;
+        let mut own = [0f32; NOTE_COUNT];
+        for note in Note::iter() {
+            let i = note as usize;
+            let (bass, treble) = self.calculate_note(&dissonance_values, player, note);
+            self.prev_own[i] = (bass + treble) * (1f32 - factor) + self.prev_own[i] * factor;
+            own[i] = if self.prev_own_intensity > 0f32 {
+                self.prev_own[i] / self.prev_own_intensity
+            } else {
+                0f32
+            };
+        }
+        Attribution {
+            band: self.dissonance,
+            own,
+        }
+    }
+
+    // Don't decay the scores over the time the analysis was paused
+    pub fn resume(&mut self) {
+        self.time = Instant::now();
+    }
+
+    // Keep only part of the dissonance accumulated, so the previous chord weighs little on the next
+    pub fn discount(&mut self, kept: f32) {
+        self.prev_bass.iter_mut().for_each(|v| *v *= kept);
+        self.prev_treble.iter_mut().for_each(|v| *v *= kept);
+        self.prev_intensity *= kept;
+        self.aggregator.discount(kept);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::{InputType, Transform, Window};
+
+    fn options() -> ScoringOptions {
+        ScoringOptions {
+            frequency: 44100,
+            zpadding: 1,
+            transform: Transform::Fourier,
+            window: Window::Hann,
+            input_type: InputType::default(),
+            mono: false,
+            classify: None,
+            halflife: 0.5,
+            change_halflife: 0.5,
+            change_threshold: 1f32,
+            bass_split: 200f32,
+            style: Style::Neutral,
+            // The bass and treble decaying alike, the dissonance is independent of the time elapsed
+            bass_decay: 1f32,
+            crossover: None,
+            split: None,
+            fold: Fold::None,
+            max_peaks: 10,
+            boost: None,
+            reject_drums: false,
+            median: 1,
+            hps: None,
+            split_silence: None,
+            calibration: None,
+            aggregation: Aggregation::default(),
+            chord_reset: None,
+        }
+    }
+
+    // The dissonance of every note after a frame of the peaks
+    fn score(options: ScoringOptions, peaks: &[Frequency]) -> Scores {
+        let mut calculator = ScoreCalculator::new(options);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        calculator.calculate(Vec::new(), peaks, None)
+    }
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() <= 1e-4 * a.abs().max(b.abs()).max(1f32))
+    }
+
+    #[test]
+    fn styles_cycle_through_longer_bass_decays() {
+        let mut style = Style::Neutral;
+        let mut decays = Vec::new();
+        for _ in 0..4 {
+            assert_eq!(Style::from_name(style.name()), Some(style));
+            decays.push(style.bass_decay());
+            style = style.next();
+        }
+        assert_eq!(style, Style::Neutral);
+        assert!(decays.windows(2).all(|d| d[0] < d[1]));
+        assert_eq!(Style::default().bass_decay(), 1f32);
+        assert_eq!(Style::from_name("swing"), None);
+    }
+
+    #[test]
+    fn crossover_leaves_the_soloist_out() {
+        let bass = Frequency {
+            value: 130.81,
+            intensity: 1f32,
+        };
+        let solo = Frequency {
+            value: 1108.73,
+            intensity: 1f32,
+        };
+        let band = score(options(), &[bass]);
+        let crossed = score(
+            ScoringOptions {
+                crossover: Some(500f32),
+                ..options()
+            },
+            &[bass, solo],
+        );
+        let both = score(options(), &[bass, solo]);
+        assert!(close(&band.dissonance, &crossed.dissonance));
+        assert!(!close(&band.dissonance, &both.dissonance));
+        // The soloist is still scored on its own
+        assert!(band.attribution.is_none());
+        let attribution = crossed.attribution.unwrap();
+        assert!(close(&attribution.band, &crossed.dissonance));
+        assert!(attribution.own.iter().any(|&d| d > 0f32));
+    }
+
+    #[test]
+    fn folds_share_the_octaves() {
+        // A score on a single C
+        let c = Note::C4 as usize;
+        let mut raw = [0f32; NOTE_COUNT];
+        raw[c] = 1f32;
+
+        let mut notes = raw;
+        Fold::None.apply(&mut notes);
+        assert_eq!(notes[..], raw[..]);
+
+        // Halved for every octave away, the other pitch classes untouched
+        let mut notes = raw;
+        Fold::Weighted.apply(&mut notes);
+        assert!(notes[c] < 1f32);
+        assert!(notes[c] > notes[c - 12] && notes[c - 12] > notes[c - 24] && notes[c - 24] > 0f32);
+        assert!(notes[c + 1] == 0f32 && notes[c - 1] == 0f32);
+
+        // The average of the octaves from C4 up, the lower ones untouched
+        let mut notes = raw;
+        Fold::Above(Note::C4).apply(&mut notes);
+        let octaves = (c..NOTE_COUNT).step_by(12).count() as f32;
+        for j in (c..NOTE_COUNT).step_by(12) {
+            assert!((notes[j] - 1f32 / octaves).abs() < 1e-6);
         }
+        assert_eq!(notes[c - 12], 0f32);
     }
 }
@@ -0,0 +1,429 @@
+// Gathers statistics over a whole session, and reports them on exit
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::audio_buffer::InputEvent;
+use crate::chords::{Chord, Symbols};
+use crate::display::Notation;
+use crate::notes::Note;
+use crate::prompts::Prompts;
+use crate::timeline::Timeline;
+use crate::tools;
+
+// The correlation from which the level and the note choice are reported as related
+const NOTABLE_CORRELATION: f32 = 0.3;
+// The time over which input problems of the same kind are logged as one, in seconds
+const EVENT_MERGE: f32 = 1f32;
+
+// A note started during the session
+#[derive(Clone, Copy, Debug)]
+pub struct Onset {
+    pub note: Note,
+    // The input level when the note started, in decibels
+    pub level: f32,
+    // The normalized dissonance of the note when it started (0 .. 1)
+    pub score: f32,
+}
+
+// Quantitative descriptors of a session, once notes were played
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    // The entropy of the pitch classes played, in bits (0 .. 3.58), higher for a more chromatic choice
+    pub entropy: f32,
+    // The detected chord changes per minute
+    pub chord_rate: f32,
+    // The variance of the dissonance of the notes played, higher for more contrasted tension
+    pub tension_variance: f32,
+}
+
+impl Metrics {
+    // The metrics of notes played and chords detected over a duration, in seconds
+    pub fn compute(onsets: &[Onset], chords: &[(f32, Option<Chord>)], duration: f32) -> Option<Metrics> {
+        if onsets.is_empty() {
+            return None;
+        }
+        let len = onsets.len() as f32;
+        let mut classes = [0usize; 12];
+        for o in onsets.iter() {
+            classes[o.note.get_octave_index() as usize] += 1;
+        }
+        let entropy = classes
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f32 / len;
+                -p * p.log2()
+            })
+            .sum::<f32>();
+        // The changes between chords, not to or from silence
+        let changes = chords
+            .iter()
+            .filter_map(|&(_, chord)| chord)
+            .tuple_windows()
+            .filter(|(a, b)| a != b)
+            .count();
+        let minutes = duration / 60f32;
+        let chord_rate = if minutes > 0f32 { changes as f32 / minutes } else { 0f32 };
+        let mean = onsets.iter().map(|o| o.score).sum::<f32>() / len;
+        let tension_variance = onsets.iter().map(|o| (o.score - mean).powi(2)).sum::<f32>() / len;
+        Some(Metrics {
+            entropy,
+            chord_rate,
+            tension_variance,
+        })
+    }
+
+    pub fn describe(self) -> String {
+        format!(
+            "Pitch class entropy {:.2} bits, {:.1} chord changes per minute, tension variance {:.3}",
+            self.entropy, self.chord_rate, self.tension_variance
+        )
+    }
+}
+
+pub struct Session {
+    timeline: Timeline,
+    // When the session started on the timeline, in seconds
+    start: f64,
+    onsets: Vec<Onset>,
+    // When every onset happened, in seconds from the start
+    onset_times: Vec<f32>,
+    // When the detected chord changed, in seconds from the start
+    chords: Vec<(f32, Option<Chord>)>,
+    // The annotations of external tools, in seconds from the start
+    annotations: Vec<(f32, String)>,
+    // The clipping, overruns and dropouts of the input, in seconds from the start
+    input_events: Vec<(f32, InputEvent)>,
+    // The practice prompts, and the one drawn for this session
+    prompts: Option<Prompts>,
+    prompt: Option<String>,
+}
+
+impl Session {
+    pub fn new(timeline: Timeline) -> Session {
+        Session {
+            start: timeline.seconds(),
+            timeline,
+            onsets: Vec::new(),
+            onset_times: Vec::new(),
+            chords: Vec::new(),
+            annotations: Vec::new(),
+            input_events: Vec::new(),
+            prompts: None,
+            prompt: None,
+        }
+    }
+
+    // Draw a practice prompt for this session, and for the following ones
+    pub fn with_prompts(self, prompts: Option<Prompts>) -> Session {
+        Session {
+            prompt: prompts.as_ref().map(Prompts::draw),
+            prompts,
+            ..self
+        }
+    }
+
+    // The session following this one, starting now
+    pub fn next(&self) -> Session {
+        Session::new(self.timeline.clone()).with_prompts(self.prompts.clone())
+    }
+
+    pub fn prompt(&self) -> Option<&str> {
+        self.prompt.as_deref()
+    }
+
+    // The time since the start of the session, in seconds of captured audio
+    pub fn duration(&self) -> f32 {
+        (self.timeline.seconds() - self.start) as f32
+    }
+
+    // When the session started, in seconds from the start of the capture
+    pub fn start(&self) -> f32 {
+        self.start as f32
+    }
+
+    // If nothing was played nor annotated yet
+    pub fn is_empty(&self) -> bool {
+        self.onsets.is_empty() && self.annotations.is_empty()
+    }
+
+    pub fn add_onset(&mut self, onset: Onset) {
+        if onset.level.is_finite() && onset.score.is_finite() {
+            self.onsets.push(onset);
+            let now = self.duration();
+            self.onset_times.push(now);
+        }
+    }
+
+    // Record the detected chord, keeping only the changes
+    pub fn add_chord(&mut self, chord: Option<Chord>) {
+        if self.chords.last().map(|&(_, c)| c) != Some(chord) {
+            let now = self.duration();
+            self.chords.push((now, chord));
+        }
+    }
+
+    pub fn add_annotation(&mut self, text: &str) {
+        let now = self.duration();
+        self.annotations.push((now, text.to_owned()));
+    }
+
+    // Record an input problem, merged with the previous one of the same kind when close
+    pub fn add_input_event(&mut self, event: InputEvent) {
+        let now = self.duration();
+        if let Some((time, last)) = self.input_events.last_mut() {
+            if now - *time < EVENT_MERGE && last.merge(event) {
+                return;
+            }
+        }
+        self.input_events.push((now, event));
+    }
+
+    pub fn onset_times(&self) -> &[f32] {
+        &self.onset_times
+    }
+
+    pub fn chord_changes(&self) -> &[(f32, Option<Chord>)] {
+        &self.chords
+    }
+
+    pub fn annotations(&self) -> &[(f32, String)] {
+        &self.annotations
+    }
+
+    // A line per annotation then per input problem, with its time as minutes and seconds
+    fn annotation_lines(&self) -> Vec<String> {
+        let problems = self
+            .input_events
+            .iter()
+            .map(|&(time, event)| (time, format!("input {}", event.describe())));
+        self.annotations
+            .iter()
+            .cloned()
+            .chain(problems)
+            .map(|(time, text)| format!("At {}:{:02}: {}", time as u32 / 60, time as u32 % 60, text))
+            .collect()
+    }
+
+    // The correlation between the dissonance of the notes played and their level
+    fn dynamics_correlation(&self) -> Option<f32> {
+        if self.onsets.len() < 2 {
+            return None;
+        }
+        let len = self.onsets.len() as f32;
+        let mean_l = self.onsets.iter().map(|o| o.level).sum::<f32>() / len;
+        let mean_s = self.onsets.iter().map(|o| o.score).sum::<f32>() / len;
+        let mut cov = 0f32;
+        let mut var_l = 0f32;
+        let mut var_s = 0f32;
+        for o in self.onsets.iter() {
+            cov += (o.level - mean_l) * (o.score - mean_s);
+            var_l += (o.level - mean_l).powi(2);
+            var_s += (o.score - mean_s).powi(2);
+        }
+        if var_l > 0f32 && var_s > 0f32 {
+            Some(cov / (var_l * var_s).sqrt())
+        } else {
+            None
+        }
+    }
+
+    pub fn metrics(&self) -> Option<Metrics> {
+        Metrics::compute(&self.onsets, &self.chords, self.duration())
+    }
+
+    // Write what the session recorded, for it to be compared with another later
+    // A line per record, of fields separated by spaces:
+    //   duration SECONDS
+    //   onset TIME NOTE LEVEL SCORE
+    //   chord TIME NAME, in the standard chord symbols whatever the ones displayed, N.C. for none
+    //   annotation TIME TEXT
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut lines = vec!["# ImproVe session".to_owned(), format!("duration {}", self.duration())];
+        for (o, time) in self.onsets.iter().zip(self.onset_times.iter()) {
+            lines.push(format!("onset {} {} {} {}", time, o.note.name(), o.level, o.score));
+        }
+        for &(time, chord) in self.chords.iter() {
+            let name = chord.map_or("N.C.".to_owned(), |c| c.name(Notation::default()));
+            lines.push(format!("chord {} {}", time, name));
+        }
+        for (time, text) in self.annotations.iter() {
+            lines.push(format!("annotation {} {}", time, text.replace('\n', " ")));
+        }
+        std::fs::write(path, lines.join("\n") + "\n").map_err(|e| format!("Cannot write {}: {}", path, e))
+    }
+
+    // Human readable lines summarizing the session
+    pub fn report(&self, notation: Notation) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(prompt) = self.prompt() {
+            lines.push(format!("Prompt: {}", prompt));
+        }
+        if self.onsets.is_empty() {
+            lines.push("No notes were played".to_owned());
+            lines.extend(self.annotation_lines());
+            return lines;
+        }
+        let levels = self.onsets.iter().map(|o| o.level).collect_vec();
+        let (min, max) = levels.iter().cloned().minmax().into_option().unwrap();
+        lines.push(format!(
+            "{} notes played, from {:.0}dB to {:.0}dB (average {:.0}dB)",
+            self.onsets.len(),
+            min,
+            max,
+            levels.iter().sum::<f32>() / levels.len() as f32
+        ));
+
+        // The average level of every pitch class played
+        let by_class = self
+            .onsets
+            .iter()
+            .map(|o| (o.note.get_octave_index() as usize, o.level))
+            .into_group_map();
+        let loudest = by_class
+            .iter()
+            .map(|(&class, levels)| (class, levels.iter().sum::<f32>() / levels.len() as f32))
+            .sorted_by(|a, b| b.1.partial_cmp(&a.1).unwrap())
+            .map(|(class, level)| format!("{} ({:.0}dB)", notation.get_names()[class].trim(), level))
+            .join(", ");
+        lines.push(format!("Loudest to quietest: {}", loudest));
+        if let Some(metrics) = self.metrics() {
+            lines.push(metrics.describe());
+        }
+
+        if let Some(correlation) = self.dynamics_correlation() {
+            if correlation > NOTABLE_CORRELATION {
+                lines.push("Your consonant choices were also your quietest".to_owned());
+            } else if correlation < -NOTABLE_CORRELATION {
+                lines.push("Your consonant choices were also your loudest".to_owned());
+            } else {
+                lines.push("Your dynamics did not follow your note choices".to_owned());
+            }
+        }
+        lines.extend(self.annotation_lines());
+        lines
+    }
+}
+
+// The onset of a record: onset TIME NOTE LEVEL SCORE
+fn parse_onset(fields: &[&str]) -> Option<Onset> {
+    Some(Onset {
+        note: Note::from_name(fields[2])?,
+        level: fields[3].parse().ok().filter(|l: &f32| l.is_finite())?,
+        score: fields[4].parse().ok().filter(|s: &f32| s.is_finite())?,
+    })
+}
+
+// A session read back from the file it was saved to
+pub struct SavedSession {
+    pub duration: f32,
+    pub onsets: Vec<Onset>,
+    pub chords: Vec<(f32, Option<Chord>)>,
+}
+
+impl SavedSession {
+    pub fn load(path: &str) -> Result<SavedSession, String> {
+        let text = tools::read_text(path)?;
+        let mut session = SavedSession {
+            duration: 0f32,
+            onsets: Vec::new(),
+            chords: Vec::new(),
+        };
+        for (number, line) in text.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields = line.split(' ').collect_vec();
+            let time = fields.get(1).and_then(|t| t.parse::<f32>().ok()).filter(|t| t.is_finite());
+            let parsed = match (fields[0], time) {
+                ("duration", Some(duration)) => {
+                    session.duration = duration;
+                    true
+                }
+                ("onset", Some(_)) if fields.len() == 5 => match parse_onset(&fields) {
+                    Some(onset) => {
+                        session.onsets.push(onset);
+                        true
+                    }
+                    None => false,
+                },
+                ("chord", Some(time)) if fields.len() == 3 => match fields[2] {
+                    "N.C." => {
+                        session.chords.push((time, None));
+                        true
+                    }
+                    name => match Chord::parse(name, Symbols::Standard) {
+                        Some(chord) => {
+                            session.chords.push((time, Some(chord)));
+                            true
+                        }
+                        None => false,
+                    },
+                },
+                ("annotation", Some(_)) => true,
+                _ => false,
+            };
+            if !parsed {
+                return Err(format!("{}:{}: not a session record", path, number + 1));
+            }
+        }
+        Ok(session)
+    }
+
+    pub fn metrics(&self) -> Option<Metrics> {
+        Metrics::compute(&self.onsets, &self.chords, self.duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chords::Quality;
+
+    #[test]
+    fn saved_chords_are_read_back() {
+        let timeline = Timeline::new();
+        timeline.set_rate(100);
+        let mut session = Session::new(timeline.clone());
+        let chords = [
+            Some(Chord {
+                root: 10,
+                quality: Quality::Major,
+            }),
+            None,
+            Some(Chord {
+                root: 11,
+                quality: Quality::Dominant7,
+            }),
+            Some(Chord {
+                root: 3,
+                quality: Quality::Minor7,
+            }),
+        ];
+        for &chord in chords.iter() {
+            timeline.advance(50);
+            session.add_chord(chord);
+        }
+        session.add_onset(Onset {
+            note: Note::A4,
+            level: -20f32,
+            score: 0.25,
+        });
+
+        let path = std::env::temp_dir().join(format!("improve-session-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        session.save(path).unwrap();
+        let text = std::fs::read_to_string(path).unwrap();
+        let saved = SavedSession::load(path);
+        std::fs::remove_file(path).ok();
+
+        // Spelled the same whatever the symbols displayed, B flat never being the German B
+        assert!(text.contains("chord 0.5 A#\n"));
+        let saved = saved.unwrap();
+        assert_eq!(saved.chords, session.chord_changes());
+        assert_eq!(saved.onsets.len(), 1);
+        assert_eq!(saved.duration, 2f32);
+    }
+}
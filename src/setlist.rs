@@ -0,0 +1,323 @@
+// Setlists of charts, each song keeping its own tempo, key and transposition
+// Followed in a loop until another song is selected
+
+// Standard
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Crate
+use crate::chart::{self, Chart};
+use crate::chords::{Chord, Symbols};
+use crate::context::Context;
+use crate::display::Notation;
+use crate::keys::Key;
+use crate::notes::Note::C3;
+use crate::timeline::Timeline;
+use crate::tools;
+
+pub struct Song {
+    pub name: String,
+    pub chart: Chart,
+    // In beats per minute
+    pub tempo: f32,
+    // In semitones, applied to the chart and the key
+    pub transpose: i32,
+    pub key: Option<Key>,
+}
+
+impl Song {
+    // The name, key and tempo of the song, moved by a number of semitones
+    pub fn describe(&self, shift: i32, notation: Notation) -> String {
+        let transpose = self.transpose + shift;
+        match self.key {
+            Some(key) => format!(
+                "{} ({}, {:.0} bpm)",
+                self.name,
+                key.transpose(transpose).name(notation),
+                self.tempo
+            ),
+            None if transpose != 0 => format!("{} ({:+} semitones, {:.0} bpm)", self.name, transpose, self.tempo),
+            None => format!("{} ({:.0} bpm)", self.name, self.tempo),
+        }
+    }
+}
+
+// The order the exercise goes through the keys
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyCycle {
+    // Up a fourth every time, through all the keys
+    Fourths,
+    // Every key once, in a random order
+    Random,
+}
+
+// Takes the songs through all 12 keys, moving after a number of passes
+#[derive(Clone, Copy, Debug)]
+pub struct Exercise {
+    pub cycle: KeyCycle,
+    // The number of times the chart is played in each key
+    pub passes: usize,
+}
+
+impl Exercise {
+    // The transpositions of a round through the keys, starting from the original one
+    fn round(self) -> Vec<i32> {
+        match self.cycle {
+            KeyCycle::Fourths => (0..12).map(|i| i * 5 % 12).collect(),
+            KeyCycle::Random => {
+                // A xorshift generator, seeded by the clock
+                let mut seed = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.subsec_nanos() as u64)
+                    .unwrap_or(0)
+                    | 1;
+                let mut random = move || {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    seed
+                };
+                // Shuffle every key but the original one
+                let mut round = (0..12).collect::<Vec<i32>>();
+                for i in (2..12).rev() {
+                    let j = 1 + (random() % i as u64) as usize;
+                    round.swap(i, j);
+                }
+                round
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Setlist {
+    songs: Arc<Vec<Song>>,
+    // The index of the current song
+    current: Arc<Mutex<usize>>,
+    exercise: Option<Exercise>,
+    // The semitones the exercise currently moves the song by
+    shift: Arc<Mutex<i32>>,
+    // If the follower should move on to the next chord
+    skip: Arc<Mutex<bool>>,
+    // The bar of the current song being played, and when it started on the timeline
+    position: Arc<Mutex<(usize, f64)>>,
+    // The chord being played, None on the rests
+    chord: Arc<Mutex<Option<Chord>>>,
+    // The clock of the follower, the captured audio ahead by its latency
+    timeline: Timeline,
+}
+
+impl Setlist {
+    // A setlist of a single chart
+    pub fn single(chart: Chart, tempo: Option<f32>, name: &str) -> Setlist {
+        let song = Song {
+            name: chart.title.clone().unwrap_or_else(|| name.to_owned()),
+            tempo: tempo.or(chart.tempo).unwrap_or(chart::DEFAULT_TEMPO),
+            chart,
+            transpose: 0,
+            key: None,
+        };
+        Setlist {
+            songs: Arc::new(vec![song]),
+            current: Arc::new(Mutex::new(0)),
+            exercise: None,
+            shift: Arc::new(Mutex::new(0)),
+            skip: Arc::new(Mutex::new(false)),
+            position: Arc::new(Mutex::new((0, 0f64))),
+            chord: Arc::new(Mutex::new(None)),
+            timeline: Timeline::new(),
+        }
+    }
+
+    // Load a setlist, one song per line: a chart path then its settings
+    // ie. blues.cho tempo=96 transpose=-2 key=Bb
+    // Chart paths are relative to the setlist, lines starting with '#' are comments
+    pub fn load(path: &str, tempo: Option<f32>, symbols: Symbols) -> Result<Setlist, String> {
+        let text = tools::read_text(path)?;
+        let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let mut songs = Vec::new();
+        for (number, line) in text.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("{}:{}: {}", path, number + 1, message);
+            let mut words = line.split_whitespace();
+            let chart_path = directory.join(words.next().unwrap());
+            let chart_path = chart_path.to_string_lossy();
+            let chart = Chart::load(&chart_path, symbols)?;
+            let mut song = Song {
+                name: chart.title.clone().unwrap_or_else(|| chart_path.clone().into_owned()),
+                tempo: tempo.or(chart.tempo).unwrap_or(chart::DEFAULT_TEMPO),
+                chart,
+                transpose: 0,
+                key: None,
+            };
+            for setting in words {
+                let mut parts = setting.splitn(2, '=');
+                let (name, value) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+                match name {
+                    "name" => song.name = value.replace('_', " "),
+                    "tempo" => {
+                        song.tempo = value
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|&t| t.is_finite() && t > 0f32)
+                            .ok_or_else(|| error("tempo is not a positive float"))?
+                    }
+                    "transpose" => {
+                        song.transpose = value
+                            .parse::<i32>()
+                            .ok()
+                            .filter(|t| t.abs() <= 24)
+                            .ok_or_else(|| error("transpose is not an int (-24 .. 24)"))?
+                    }
+                    "key" => {
+                        song.key = Some(Key::parse(value, symbols).ok_or_else(|| error("key is not a key (ie. Bb, F#m)"))?)
+                    }
+                    _ => return Err(error(&format!("unknown setting '{}'", name))),
+                }
+            }
+            songs.push(song);
+        }
+        if songs.is_empty() {
+            return Err(format!("No songs found in {}", path));
+        }
+        Ok(Setlist {
+            songs: Arc::new(songs),
+            current: Arc::new(Mutex::new(0)),
+            exercise: None,
+            shift: Arc::new(Mutex::new(0)),
+            skip: Arc::new(Mutex::new(false)),
+            position: Arc::new(Mutex::new((0, 0f64))),
+            chord: Arc::new(Mutex::new(None)),
+            timeline: Timeline::new(),
+        })
+    }
+
+    // Take every song through all the keys
+    pub fn with_exercise(self, exercise: Exercise) -> Setlist {
+        Setlist {
+            exercise: Some(exercise),
+            ..self
+        }
+    }
+
+    // Follow the chart on the clock of the captured audio
+    pub fn with_timeline(self, timeline: Timeline) -> Setlist {
+        Setlist { timeline, ..self }
+    }
+
+    pub fn current(&self) -> usize {
+        *self.current.lock().unwrap()
+    }
+
+    pub fn song(&self, index: usize) -> &Song {
+        &self.songs[index]
+    }
+
+    pub fn shift(&self) -> i32 {
+        *self.shift.lock().unwrap()
+    }
+
+    // The key of the current song, as currently played
+    pub fn key(&self) -> Option<Key> {
+        let song = self.song(self.current());
+        song.key.map(|key| key.transpose(song.transpose + self.shift()))
+    }
+
+    // The description of a song, in the current key of the exercise
+    pub fn describe(&self, index: usize, notation: Notation) -> String {
+        self.songs[index].describe(self.shift(), notation)
+    }
+
+    // Select a song by index, ignoring songs past the end
+    pub fn select(&self, index: usize) {
+        if index < self.songs.len() {
+            *self.current.lock().unwrap() = index;
+        }
+    }
+
+    pub fn next(&self) {
+        self.select(self.current() + 1);
+    }
+
+    pub fn previous(&self) {
+        self.select(self.current().saturating_sub(1));
+    }
+
+    // The bar of the current song being played, and how far into it (0 .. 1)
+    pub fn position(&self) -> (usize, f32) {
+        let (bar, start) = *self.position.lock().unwrap();
+        let song = self.song(self.current());
+        let duration = 60f64 / song.tempo as f64 * song.chart.beats_per_bar as f64;
+        let progress = (self.timeline.now() - start) / duration;
+        (bar, progress.max(0f64).min(1f64) as f32)
+    }
+
+    // The bar and beat being played, counted from 1
+    pub fn beat(&self) -> (usize, usize) {
+        let (bar, progress) = self.position();
+        let beats = self.song(self.current()).chart.beats_per_bar;
+        (bar + 1, ((progress * beats as f32) as usize).min(beats.max(1) - 1) + 1)
+    }
+
+    // The chord of the chart being played, transposed
+    pub fn chord(&self) -> Option<Chord> {
+        *self.chord.lock().unwrap()
+    }
+
+    // Move on to the next chord of the chart, without waiting for it
+    pub fn next_chord(&self) {
+        *self.skip.lock().unwrap() = true;
+    }
+
+    // Follow the current song in a loop, setting its chords as the harmonic context
+    pub fn follow(&self, context: Context, notation: Notation) {
+        let setlist = self.clone();
+        std::thread::spawn(move || loop {
+            let index = setlist.current();
+            let song = setlist.song(index);
+            // Every song starts the exercise over from its original key
+            let round = setlist.exercise.map_or(vec![0], Exercise::round);
+            *setlist.shift.lock().unwrap() = 0;
+            status!("Song {}/{}: {}", index + 1, setlist.songs.len(), setlist.describe(index, notation));
+            let bar = 60f64 / song.tempo as f64 * song.chart.beats_per_bar as f64;
+            // The chords are timed from the start of the song, not to drift from the audio
+            let mut time = setlist.timeline.now();
+            'song: for pass in 0.. {
+                // Move to the next key after enough passes
+                if let Some(exercise) = setlist.exercise {
+                    if pass > 0 && pass % exercise.passes == 0 {
+                        let shift = round[pass / exercise.passes % round.len()];
+                        *setlist.shift.lock().unwrap() = shift;
+                        status!("Next key: {}", setlist.describe(index, notation));
+                    }
+                }
+                let transpose = song.transpose + setlist.shift();
+                for (number, chords) in song.chart.bars.iter().enumerate() {
+                    *setlist.position.lock().unwrap() = (number, time);
+                    let duration = bar / chords.len() as f64;
+                    for chord in chords {
+                        let chord = chord.map(|c| c.transpose(transpose));
+                        *setlist.chord.lock().unwrap() = chord;
+                        context.set_chord(chord.map_or(Vec::new(), |c| c.voicing(C3)));
+                        // Wait for the next chord, or another song
+                        *setlist.skip.lock().unwrap() = false;
+                        time += duration;
+                        let reached = setlist.timeline.wait_until(time, || {
+                            setlist.current() == index && !*setlist.skip.lock().unwrap()
+                        });
+                        if setlist.current() != index {
+                            break 'song;
+                        }
+                        // A skipped chord moves the rest of the song earlier
+                        if !reached {
+                            time = setlist.timeline.now();
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
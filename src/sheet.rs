@@ -0,0 +1,169 @@
+// Bar aligned chord sheets of a session, as plain text or ChordPro
+
+// Standard
+use std::f32::consts::PI;
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::chords::Chord;
+use crate::display::Notation;
+use crate::session::Session;
+
+// The range of tempos considered when estimating it, in beats per minute
+const MIN_TEMPO: u32 = 60;
+const MAX_TEMPO: u32 = 180;
+// The range of intervals between onsets used to estimate the tempo, in seconds
+const MIN_INTERVAL: f32 = 0.15;
+const MAX_INTERVAL: f32 = 2f32;
+// The number of bars per line
+const BARS_PER_LINE: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SheetFormat {
+    Text,
+    ChordPro,
+}
+
+impl SheetFormat {
+    // Guess the format from the file extension
+    pub fn from_path(path: &str) -> SheetFormat {
+        let path = path.to_lowercase();
+        if path.ends_with(".cho") || path.ends_with(".chopro") || path.ends_with(".chordpro") {
+            SheetFormat::ChordPro
+        } else {
+            SheetFormat::Text
+        }
+    }
+}
+
+// The path of the sheet of a split session, numbered before the extension
+// ie. rehearsal.cho becomes rehearsal-2.cho
+pub fn numbered_path(path: &str, number: usize) -> String {
+    let name_start = path.rfind('/').map_or(0, |i| i + 1);
+    match path[name_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = name_start + dot;
+            format!("{}-{}{}", &path[..dot], number, &path[dot..])
+        }
+        _ => format!("{}-{}", path, number),
+    }
+}
+
+// Estimate the tempo from the intervals between onsets
+// Every tempo is scored by how many intervals fall on a whole number of its beats
+pub fn estimate_tempo(onset_times: &[f32]) -> Option<f32> {
+    let intervals = onset_times
+        .iter()
+        .tuple_windows()
+        .map(|(a, b)| b - a)
+        .filter(|&i| i >= MIN_INTERVAL && i <= MAX_INTERVAL)
+        .collect_vec();
+    if intervals.len() < 4 {
+        return None;
+    }
+    (MIN_TEMPO..=MAX_TEMPO)
+        .map(|tempo| {
+            let beat = 60f32 / tempo as f32;
+            let score: f32 = intervals.iter().map(|i| (2f32 * PI * i / beat).cos()).sum();
+            (tempo, score)
+        })
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(tempo, _)| tempo as f32)
+}
+
+// The chord heard the longest during every beat, from the first chord on, and when they start
+fn beats(session: &Session, tempo: f32) -> (f32, Vec<Option<Chord>>) {
+    let changes = session.chord_changes();
+    let start = match changes.iter().find(|&&(_, chord)| chord.is_some()) {
+        Some(&(time, _)) => time,
+        None => return (0f32, Vec::new()),
+    };
+    let end = session.duration();
+    let beat = 60f32 / tempo;
+    // The time during which each chord lasted
+    let spans = changes
+        .iter()
+        .map(|&(time, chord)| (time, chord))
+        .chain(std::iter::once((end, None)))
+        .tuple_windows()
+        .map(|((from, chord), (to, _))| (from, to, chord))
+        .collect_vec();
+    let count = ((end - start) / beat).ceil() as usize;
+    let beats = (0..count)
+        .map(|i| {
+            let (beat_start, beat_end) = (start + i as f32 * beat, start + (i + 1) as f32 * beat);
+            spans
+                .iter()
+                .map(|&(from, to, chord)| (chord, to.min(beat_end) - from.max(beat_start)))
+                .filter(|&(_, overlap)| overlap > 0f32)
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .and_then(|(chord, _)| chord)
+        })
+        .collect_vec();
+    (start, beats)
+}
+
+// The chords of a bar, naming every chord change once
+fn bar_chords(bar: &[Option<Chord>]) -> Vec<Option<Chord>> {
+    bar.iter().cloned().dedup().collect_vec()
+}
+
+// Write the chord sheet of a session
+pub fn chord_sheet(
+    session: &Session,
+    tempo: f32,
+    beats_per_bar: usize,
+    format: SheetFormat,
+    notation: Notation,
+) -> String {
+    let name = |chord: Option<Chord>| chord.map_or("N.C.".to_owned(), |c| c.name(notation));
+    let (start, beats) = beats(session, tempo);
+    let bars = beats.chunks(beats_per_bar).collect_vec();
+    // The line of every annotation, from the bar it was made in
+    let line_of = |time: f32| {
+        let bar = ((time - start).max(0f32) * tempo / 60f32) as usize / beats_per_bar;
+        (bar / BARS_PER_LINE).min(bars.len().saturating_sub(1) / BARS_PER_LINE)
+    };
+    let annotations = session.annotations();
+    let mut lines = Vec::new();
+    if format == SheetFormat::ChordPro {
+        lines.push("{title: ImproVe session}".to_owned());
+        lines.push(format!("{{tempo: {:.0}}}", tempo));
+        lines.push(format!("{{time: {}/4}}", beats_per_bar));
+        if let Some(metrics) = session.metrics() {
+            lines.push(format!("{{comment: {}}}", metrics.describe()));
+        }
+        lines.push(String::new());
+    } else {
+        lines.push(format!("Tempo: {:.0} bpm, {}/4", tempo, beats_per_bar));
+        if let Some(metrics) = session.metrics() {
+            lines.push(format!("# {}", metrics.describe()));
+        }
+        lines.push(String::new());
+    }
+    for (i, line) in bars.chunks(BARS_PER_LINE).enumerate() {
+        for (_, text) in annotations.iter().filter(|(time, _)| line_of(*time) == i) {
+            lines.push(match format {
+                SheetFormat::ChordPro => format!("{{comment: {}}}", text),
+                SheetFormat::Text => format!("# {}", text),
+            });
+        }
+        let text = line
+            .iter()
+            .map(|bar| {
+                let chords = bar_chords(bar).into_iter();
+                match format {
+                    SheetFormat::ChordPro => chords.map(|c| format!("[{}]", name(c))).join(" "),
+                    SheetFormat::Text => chords.map(name).join(" "),
+                }
+            })
+            .join(" | ");
+        lines.push(match format {
+            SheetFormat::ChordPro => text,
+            SheetFormat::Text => format!("| {} |", text),
+        });
+    }
+    lines.join("\n") + "\n"
+}
@@ -0,0 +1,314 @@
+// Spectral processing applied between the fourier transform and the scoring
+
+use crate::frequency::Frequency;
+
+use itertools::Itertools;
+
+use std::collections::VecDeque;
+
+// Peaks weaker than this fraction of the strongest peak are ignored
+const MIN_PROMINENCE: f32 = 1e-4;
+// Avoid taking the log of zero during interpolation
+const LOG_FLOOR: f32 = 1e-20;
+
+// Extract the spectral peaks, keeping at most max_peaks of the most prominent ones
+// Each peak is interpolated between bins for sub-bin frequency precision
+pub fn pick_peaks(spectrum: &[Frequency], max_peaks: usize) -> Vec<Frequency> {
+    if spectrum.len() < 3 {
+        return Vec::new();
+    }
+
+    // Find every local maximum, along with its prominence
+    let mut peaks = (1..spectrum.len() - 1)
+        .filter(|&i| {
+            let f = spectrum[i].intensity;
+            f > spectrum[i - 1].intensity && f >= spectrum[i + 1].intensity
+        })
+        .map(|i| (prominence(spectrum, i), interpolate(spectrum, i)))
+        .collect_vec();
+
+    // Discard the peaks that barely stand out of their surroundings
+    let max = peaks.iter().map(|&(p, _)| p).fold(0f32, f32::max);
+    peaks.retain(|&(p, _)| p > max * MIN_PROMINENCE);
+
+    // Keep the most prominent peaks, ordered by frequency
+    peaks.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    peaks.truncate(max_peaks);
+    peaks
+        .into_iter()
+        .map(|(_, f)| f)
+        .sorted_by(|a, b| a.value.partial_cmp(&b.value).unwrap())
+        .collect_vec()
+}
+
+// The height of a peak above the highest valley separating it from a higher peak
+fn prominence(spectrum: &[Frequency], index: usize) -> f32 {
+    let peak = spectrum[index].intensity;
+    // Walk down each side until a higher bin or the spectrum edge is reached
+    let valley = |range: &mut dyn Iterator<Item = usize>| {
+        let mut min = peak;
+        for i in range {
+            let f = spectrum[i].intensity;
+            if f > peak {
+                break;
+            }
+            min = min.min(f);
+        }
+        min
+    };
+    let left = valley(&mut (0..index).rev());
+    let right = valley(&mut (index + 1..spectrum.len()));
+    peak - left.max(right)
+}
+
+// Fit a parabola on the log intensities of the peak and its neighbours
+fn interpolate(spectrum: &[Frequency], index: usize) -> Frequency {
+    // Bins are not evenly spaced with every analyzer
+    let bin_width = (spectrum[index + 1].value - spectrum[index - 1].value) / 2f32;
+
+    let a = spectrum[index - 1].intensity.max(LOG_FLOOR).ln();
+    let b = spectrum[index].intensity.max(LOG_FLOOR).ln();
+    let c = spectrum[index + 1].intensity.max(LOG_FLOOR).ln();
+
+    let den = a - 2f32 * b + c;
+    // Offset of the vertex from the central bin, within -0.5 .. 0.5
+    let offset = if den.abs() > std::f32::EPSILON {
+        (0.5 * (a - c) / den).max(-0.5).min(0.5)
+    } else {
+        0f32
+    };
+    Frequency {
+        intensity: (b - 0.25 * (a - c) * offset).exp(),
+        value: spectrum[index].value + offset * bin_width,
+    }
+}
+
+// Divide the spectrum by its smoothed envelope, flattening the formants
+// The envelope is the geometric average of the intensities within width hertz
+pub fn whiten(spectrum: &[Frequency], width: f32) -> Vec<Frequency> {
+    let logs = spectrum
+        .iter()
+        .map(|f| f.intensity.max(LOG_FLOOR).ln())
+        .collect_vec();
+    // Prefix sums of the logs, for fast moving averages
+    let mut sums = Vec::with_capacity(logs.len() + 1);
+    sums.push(0f32);
+    for l in logs.iter() {
+        let last = *sums.last().unwrap();
+        sums.push(last + l);
+    }
+    let mut first = 0;
+    let mut last = 0;
+    spectrum
+        .iter()
+        .map(|f| {
+            // Move the window to the frequencies around the current one
+            while spectrum[first].value < f.value - width / 2f32 {
+                first += 1;
+            }
+            while last < spectrum.len() && spectrum[last].value <= f.value + width / 2f32 {
+                last += 1;
+            }
+            let envelope = ((sums[last] - sums[first]) / (last - first) as f32).exp();
+            Frequency {
+                intensity: f.intensity / envelope,
+                value: f.value,
+            }
+        })
+        .collect_vec()
+}
+
+// The ratio of the geometric and arithmetic means of the spectrum (0 .. 1)
+// Close to 1 for noise like breath, close to 0 for tonal sounds
+pub fn flatness(spectrum: &[Frequency]) -> f32 {
+    if spectrum.is_empty() {
+        return 0f32;
+    }
+    let len = spectrum.len() as f32;
+    let geometric = (spectrum
+        .iter()
+        .map(|f| f.intensity.max(LOG_FLOOR).ln())
+        .sum::<f32>()
+        / len)
+        .exp();
+    let arithmetic = spectrum.iter().map(|f| f.intensity).sum::<f32>() / len;
+    if arithmetic > 0f32 {
+        geometric / arithmetic
+    } else {
+        0f32
+    }
+}
+
+// The intensity around a frequency, the strongest of the two bins surrounding it, 0 out of the spectrum
+fn intensity_at(spectrum: &[Frequency], value: f32) -> f32 {
+    let index = match spectrum.binary_search_by(|f| f.value.partial_cmp(&value).unwrap()) {
+        Ok(index) => return spectrum[index].intensity,
+        Err(index) => index,
+    };
+    if index == 0 || index == spectrum.len() {
+        return 0f32;
+    }
+    spectrum[index - 1].intensity.max(spectrum[index].intensity)
+}
+
+// Keep the fundamentals, suppressing their harmonics, with a harmonic product spectrum
+// The salience of a frequency is the geometric mean of the intensities of its first harmonics, those in
+// the spectrum, and every bin keeps the share of its intensity that its salience has among the saliences
+// of the fundamentals it could be a harmonic of
+// The octave and the fifth above a note then stay dark unless played, at the cost of dimming the octaves
+// played together
+pub fn harmonic_product(spectrum: &[Frequency], harmonics: usize) -> Vec<Frequency> {
+    let last = match spectrum.last() {
+        Some(last) => last.value,
+        None => return Vec::new(),
+    };
+    let salience = |value: f32| {
+        let count = (1..=harmonics)
+            .take_while(|&h| value * h as f32 <= last)
+            .count()
+            .max(1);
+        ((1..=count)
+            .map(|h| intensity_at(spectrum, value * h as f32).max(LOG_FLOOR).ln())
+            .sum::<f32>()
+            / count as f32)
+            .exp()
+    };
+    let saliences = spectrum.iter().map(|f| salience(f.value)).collect_vec();
+    spectrum
+        .iter()
+        .zip(saliences.iter())
+        .map(|(f, &own)| {
+            // The saliences of the fundamentals below, looked up in those of the bins
+            let below = (2..=harmonics)
+                .map(|h| f.value / h as f32)
+                .take_while(|&value| value >= spectrum[0].value)
+                .map(|value| match spectrum.binary_search_by(|f| f.value.partial_cmp(&value).unwrap()) {
+                    Ok(index) => saliences[index],
+                    Err(index) => saliences[index - 1].max(saliences[index]),
+                })
+                .sum::<f32>();
+            Frequency {
+                intensity: f.intensity * own / (own + below),
+                value: f.value,
+            }
+        })
+        .collect_vec()
+}
+
+// Percussive frames are noisy, and louder than the preceding frame
+const PERCUSSIVE_FLATNESS: f32 = 0.25;
+const PERCUSSIVE_ENERGY_RISE: f32 = 2f32;
+
+// Classifies drum-dominated frames from their noisiness and sudden energy
+pub struct PercussionDetector {
+    prev_energy: f32,
+}
+
+impl PercussionDetector {
+    pub fn new() -> PercussionDetector {
+        PercussionDetector { prev_energy: 0f32 }
+    }
+
+    pub fn is_percussive(&mut self, spectrum: &[Frequency]) -> bool {
+        let energy = spectrum.iter().map(|f| f.intensity).sum::<f32>();
+        let rise = if self.prev_energy > 0f32 {
+            energy / self.prev_energy
+        } else {
+            0f32
+        };
+        self.prev_energy = energy;
+        rise > PERCUSSIVE_ENERGY_RISE && flatness(spectrum) > PERCUSSIVE_FLATNESS
+    }
+}
+
+// Replaces each bin by its median over the last few spectra
+// Rejects one-frame glitches without blurring sustained changes
+pub struct MedianFilter {
+    history: VecDeque<Vec<Frequency>>,
+    length: usize,
+}
+
+impl MedianFilter {
+    pub fn new(length: usize) -> MedianFilter {
+        MedianFilter {
+            history: VecDeque::with_capacity(length),
+            length,
+        }
+    }
+
+    pub fn filter(&mut self, spectrum: Vec<Frequency>) -> Vec<Frequency> {
+        // A single spectrum is its own median
+        if self.length <= 1 {
+            return spectrum;
+        }
+        // Forget the history if the spectrum size changed
+        if self.history.front().map(Vec::len) != Some(spectrum.len()) {
+            self.history.clear();
+        }
+        if self.history.len() == self.length {
+            self.history.pop_front();
+        }
+        self.history.push_back(spectrum);
+
+        let mut window = Vec::with_capacity(self.history.len());
+        let latest = self.history.back().unwrap();
+        latest
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                window.clear();
+                window.extend(self.history.iter().map(|s| s[i].intensity));
+                window.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                Frequency {
+                    intensity: window[window.len() / 2],
+                    value: f.value,
+                }
+            })
+            .collect_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bins 10 Hz apart
+    fn bins(intensities: &[f32]) -> Vec<Frequency> {
+        intensities
+            .iter()
+            .enumerate()
+            .map(|(i, &intensity)| Frequency {
+                intensity,
+                value: i as f32 * 10f32,
+            })
+            .collect_vec()
+    }
+
+    #[test]
+    fn peaks_are_ordered_by_frequency() {
+        let spectrum = bins(&[0f32, 1f32, 0f32, 0f32, 4f32, 0f32, 0f32, 2f32, 0f32]);
+        let values = pick_peaks(&spectrum, 8).iter().map(|f| f.value).collect_vec();
+        assert_eq!(values, vec![10f32, 40f32, 70f32]);
+    }
+
+    #[test]
+    fn most_prominent_peaks_are_kept() {
+        let spectrum = bins(&[0f32, 1f32, 0f32, 0f32, 4f32, 0f32, 0f32, 2f32, 0f32]);
+        let values = pick_peaks(&spectrum, 2).iter().map(|f| f.value).collect_vec();
+        assert_eq!(values, vec![40f32, 70f32]);
+    }
+
+    #[test]
+    fn short_spectrum_has_no_peaks() {
+        assert!(pick_peaks(&bins(&[1f32, 2f32]), 8).is_empty());
+    }
+
+    #[test]
+    fn gaussian_peak_is_interpolated_to_its_vertex() {
+        // A parabola of the log intensities, its vertex a quarter of a bin above the fourth bin
+        let spectrum = bins(&(0..7).map(|i| (-(i as f32 - 3.25).powi(2)).exp()).collect_vec());
+        let peak = interpolate(&spectrum, 3);
+        assert!((peak.value - 32.5).abs() < 1e-3);
+        assert!((peak.intensity - 1f32).abs() < 1e-3);
+    }
+}
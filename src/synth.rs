@@ -0,0 +1,132 @@
+// Simple additive synthesis, to play notes through the output device
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+use std::f64::consts::PI;
+
+use crate::echo::Tap;
+use crate::error::Error;
+use crate::ring::Producer;
+
+// The relative intensity of the first few harmonics of every voice
+const HARMONICS: [f32; 4] = [1.0, 0.4, 0.2, 0.1];
+// The fade in and out time, avoiding clicks
+const FADE_SECONDS: f32 = 0.05;
+// The slower fade of pads
+const PAD_FADE_SECONDS: f32 = 0.4;
+// The overall output gain, kept low to avoid clipping chords
+const GAIN: f32 = 0.2;
+
+// Playback callback object, plays a set of frequencies for a number of samples
+pub struct Synth {
+    frequencies: Vec<f32>,
+    sample_rate: f32,
+    // The current sample index
+    position: usize,
+    // The total number of samples to play
+    length: usize,
+    // The fade in and out time
+    fade: f32,
+    // Copies the samples played, for the analysis to remove them from the capture
+    tap: Option<Producer>,
+}
+
+impl Synth {
+    pub fn new(frequencies: Vec<f32>, sample_rate: i32, seconds: f32) -> Synth {
+        Synth {
+            frequencies,
+            sample_rate: sample_rate as f32,
+            position: 0,
+            length: (seconds * sample_rate as f32) as usize,
+            fade: FADE_SECONDS,
+            tap: None,
+        }
+    }
+
+    // A soft sound, fading slowly in and out
+    pub fn pad(frequencies: Vec<f32>, sample_rate: i32, seconds: f32) -> Synth {
+        Synth {
+            fade: PAD_FADE_SECONDS.min(seconds / 2f32),
+            ..Synth::new(frequencies, sample_rate, seconds)
+        }
+    }
+
+    fn sample(&self) -> f32 {
+        // Keep the phase precise over long durations
+        let time = self.position as f64 / f64::from(self.sample_rate);
+        // Fade in and out
+        let remaining = (self.length - self.position) as f32 / self.sample_rate;
+        let envelope = (time as f32 / self.fade).min(remaining / self.fade).min(1f32);
+        let voices = self.frequencies.len().max(1) as f32;
+        let sum: f32 = self
+            .frequencies
+            .iter()
+            .map(|f| {
+                HARMONICS
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| a * (2f64 * PI * f64::from(*f) * (i + 1) as f64 * time).sin() as f32)
+                    .sum::<f32>()
+            })
+            .sum();
+        sum * envelope * GAIN / voices
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.position >= self.length
+    }
+}
+
+impl AudioCallback for Synth {
+    type Channel = f32;
+
+    fn callback(&mut self, output: &mut [f32]) {
+        for out in output.iter_mut() {
+            *out = if self.is_done() {
+                0f32
+            } else {
+                let sample = self.sample();
+                self.position += 1;
+                sample
+            };
+        }
+        if let Some(tap) = self.tap.as_mut() {
+            tap.write(output.iter().cloned());
+        }
+    }
+}
+
+// Start playing through the default output device, the sound stops with the device
+// A tapped playback runs at the capture rate, SDL converting it to the rate of the device
+pub fn start<F>(
+    audio: &AudioSubsystem,
+    tap: Option<&Tap>,
+    build: F,
+) -> Result<AudioDevice<Synth>, Error>
+where
+    F: FnOnce(i32) -> Synth,
+{
+    let desired_spec = AudioSpecDesired {
+        freq: Some(tap.map_or(44100, |t| t.rate)),
+        channels: Some(1),
+        samples: None,
+    };
+    let device = audio
+        .open_playback(None, &desired_spec, |spec| Synth {
+            tap: tap.map(Tap::producer),
+            ..build(spec.freq)
+        })
+        .map_err(|reason| Error::Playback { reason })?;
+    device.resume();
+    Ok(device)
+}
+
+// Play frequencies through the default output device, and wait until done
+pub fn play(audio: &AudioSubsystem, frequencies: Vec<f32>, seconds: f32) -> Result<(), Error> {
+    let mut device = start(audio, None, |rate| Synth::new(frequencies, rate, seconds))?;
+    while !device.lock().is_done() {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+// A trigger raised while the heard notes stay dissonant, to light a studio talkback or automate anything
+// The trigger is held as long as the dissonance stays over the threshold, then released
+//   serial:/dev/ttyUSB0   writes '1' then '0', each on its line
+//   gpio:17               drives a sysfs GPIO high then low
+//   midi:PORT             sends CC 22 at 127 then 0, with the midi feature
+
+// Standard
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Crate
+use crate::output::Output;
+use crate::scores::Scores;
+
+// The sysfs GPIO interface
+const GPIO: &str = "/sys/class/gpio";
+
+pub enum Trigger {
+    Serial(File),
+    // The path of the value of the pin
+    Gpio(String),
+    #[cfg(feature = "midi")]
+    Midi(crate::midi::TriggerPort),
+}
+
+impl Trigger {
+    pub fn open(spec: &str) -> Result<Trigger, String> {
+        let (kind, target) = match spec.find(':') {
+            Some(i) => (&spec[..i], &spec[i + 1..]),
+            None => (spec, ""),
+        };
+        match kind {
+            "serial" => {
+                let file = OpenOptions::new()
+                    .write(true)
+                    .open(target)
+                    .map_err(|e| format!("Cannot open {}: {}", target, e))?;
+                Ok(Trigger::Serial(file))
+            }
+            "gpio" => {
+                let pin = target
+                    .parse::<u32>()
+                    .map_err(|_| format!("GPIO pin {} is not a number", target))?;
+                let write = |path: &str, value: &str| {
+                    std::fs::write(path, value).map_err(|e| format!("Cannot write {}: {}", path, e))
+                };
+                let pin_path = format!("{}/gpio{}", GPIO, pin);
+                if !Path::new(&pin_path).exists() {
+                    write(&format!("{}/export", GPIO), &pin.to_string())?;
+                }
+                write(&format!("{}/direction", pin_path), "out")?;
+                let value = format!("{}/value", pin_path);
+                write(&value, "0")?;
+                Ok(Trigger::Gpio(value))
+            }
+            #[cfg(feature = "midi")]
+            "midi" => Ok(Trigger::Midi(crate::midi::TriggerPort::open(target)?)),
+            #[cfg(not(feature = "midi"))]
+            "midi" => Err("The MIDI trigger requires building with --features midi".to_owned()),
+            _ => Err(format!(
+                "Unknown trigger {}, expected serial:DEVICE, gpio:PIN or midi:PORT",
+                spec
+            )),
+        }
+    }
+
+    fn set(&mut self, on: bool) {
+        match self {
+            Trigger::Serial(file) => {
+                file.write_all(if on { b"1\n" } else { b"0\n" }).ok();
+                file.flush().ok();
+            }
+            Trigger::Gpio(value) => {
+                std::fs::write(value, if on { "1" } else { "0" }).ok();
+            }
+            #[cfg(feature = "midi")]
+            Trigger::Midi(port) => port.set(on),
+        }
+    }
+}
+
+// The dissonance of the heard notes, weighted by their intensity (0 .. 1)
+fn heard_dissonance(scores: &Scores) -> f32 {
+    let total = scores.note_values.iter().sum::<f32>();
+    if total > 0f32 {
+        scores
+            .note_scores
+            .iter()
+            .zip(scores.note_values.iter())
+            .map(|(s, v)| s * v)
+            .sum::<f32>()
+            / total
+    } else {
+        0f32
+    }
+}
+
+pub struct Talkback {
+    trigger: Trigger,
+    threshold: f32,
+    duration: Duration,
+    // Since when the dissonance is over the threshold
+    since: Option<Instant>,
+    on: bool,
+}
+
+impl Talkback {
+    pub fn new(trigger: Trigger, threshold: f32, duration: Duration) -> Talkback {
+        Talkback {
+            trigger,
+            threshold,
+            duration,
+            since: None,
+            on: false,
+        }
+    }
+}
+
+impl Output for Talkback {
+    fn publish(&mut self, scores: &Scores) {
+        if heard_dissonance(scores) >= self.threshold {
+            let since = *self.since.get_or_insert_with(Instant::now);
+            if !self.on && since.elapsed() >= self.duration {
+                self.on = true;
+                self.trigger.set(true);
+            }
+        } else {
+            self.since = None;
+            if self.on {
+                self.on = false;
+                self.trigger.set(false);
+            }
+        }
+    }
+}
+
+impl Drop for Talkback {
+    // Never leave the trigger raised
+    fn drop(&mut self) {
+        if self.on {
+            self.trigger.set(false);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// Tuning systems, mapping notes to frequencies
+
+use crate::notes::Note;
+
+// Ratios of each semitone above the tonic
+const JUST_RATIOS: [f32; 12] = [
+    1.0, 16.0 / 15.0, 9.0 / 8.0, 6.0 / 5.0, 5.0 / 4.0, 4.0 / 3.0,
+    45.0 / 32.0, 3.0 / 2.0, 8.0 / 5.0, 5.0 / 3.0, 9.0 / 5.0, 15.0 / 8.0,
+];
+const PYTHAGOREAN_RATIOS: [f32; 12] = [
+    1.0, 256.0 / 243.0, 9.0 / 8.0, 32.0 / 27.0, 81.0 / 64.0, 4.0 / 3.0,
+    729.0 / 512.0, 3.0 / 2.0, 128.0 / 81.0, 27.0 / 16.0, 16.0 / 9.0, 243.0 / 128.0,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Temperament {
+    Equal,
+    // Pure intervals relative to the tonic
+    Just,
+    // Stacked pure fifths from the tonic
+    Pythagorean,
+}
+
+impl Temperament {
+    // The frequency of a note, in a key starting from the tonic
+    // The tonic itself is always tuned in equal temperament
+    pub fn freq(self, note: Note, tonic: Note) -> f32 {
+        let ratios = match self {
+            Temperament::Equal => return note.freq(),
+            Temperament::Just => JUST_RATIOS,
+            Temperament::Pythagorean => PYTHAGOREAN_RATIOS,
+        };
+        let interval = note as i32 - tonic as i32;
+        let octaves = interval.div_euclid(12);
+        let degree = interval.rem_euclid(12) as usize;
+        tonic.freq() * ratios[degree] * 2f32.powi(octaves)
+    }
+}
@@ -0,0 +1,150 @@
+// Synthetic input signals, fed to the analysis instead of the capture to check the whole chain
+
+// Standard
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// SDL2
+use sdl2::audio::AudioCallback;
+
+// Crate
+use crate::notes::Note::{C3, E3, G3};
+use crate::synth::Synth;
+use crate::timeline::Timeline;
+
+// The samples sent at once, like a capture callback
+const PACKET: usize = 1024;
+// The sweep range and how long it takes, repeating
+const SWEEP_LOW: f64 = 50.0;
+const SWEEP_HIGH: f64 = 5000.0;
+const SWEEP_SECONDS: f64 = 10.0;
+// The generated level, under full scale
+const AMPLITUDE: f32 = 0.25;
+// How long the chord plays for, longer than any session
+const CHORD_SECONDS: f32 = 24f32 * 3600f32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Signal {
+    // Noise of equal power across frequencies
+    White,
+    // Noise of equal power across octaves
+    Pink,
+    // A sine gliding up from 50Hz to 5kHz
+    Sweep,
+    // A C major triad
+    Chord,
+}
+
+impl Signal {
+    pub fn from_name(name: &str) -> Option<Signal> {
+        match name {
+            "white" => Some(Signal::White),
+            "pink" => Some(Signal::Pink),
+            "sweep" => Some(Signal::Sweep),
+            "chord" => Some(Signal::Chord),
+            _ => None,
+        }
+    }
+}
+
+struct Generator {
+    signal: Signal,
+    // The sample index
+    position: u64,
+    // The state of the xorshift noise
+    seed: u64,
+    // The filters turning white noise into pink noise
+    pink: [f32; 7],
+    // The phase of the sweep, in turns
+    phase: f64,
+    chord: Synth,
+    rate: i32,
+}
+
+impl Generator {
+    fn new(signal: Signal, rate: i32) -> Generator {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(1)
+            | 1;
+        let frequencies = [C3, E3, G3].iter().map(|n| n.freq()).collect();
+        Generator {
+            signal,
+            position: 0,
+            seed,
+            pink: [0f32; 7],
+            phase: 0f64,
+            chord: Synth::new(frequencies, rate, CHORD_SECONDS),
+            rate,
+        }
+    }
+
+    // A uniform sample (-1 .. 1)
+    fn noise(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 40) as f32 / (1u64 << 23) as f32 - 1f32
+    }
+
+    fn fill(&mut self, packet: &mut [f32]) {
+        if self.signal == Signal::Chord {
+            self.chord.callback(packet);
+            return;
+        }
+        for sample in packet.iter_mut() {
+            *sample = match self.signal {
+                Signal::White => self.noise() * AMPLITUDE,
+                // Paul Kellet's filter
+                Signal::Pink => {
+                    let white = self.noise();
+                    let p = &mut self.pink;
+                    p[0] = 0.99886 * p[0] + white * 0.0555179;
+                    p[1] = 0.99332 * p[1] + white * 0.0750759;
+                    p[2] = 0.96900 * p[2] + white * 0.1538520;
+                    p[3] = 0.86650 * p[3] + white * 0.3104856;
+                    p[4] = 0.55000 * p[4] + white * 0.5329522;
+                    p[5] = -0.7616 * p[5] - white * 0.0168980;
+                    let pink = p[0] + p[1] + p[2] + p[3] + p[4] + p[5] + p[6] + white * 0.5362;
+                    p[6] = white * 0.115926;
+                    pink * AMPLITUDE * 0.2
+                }
+                // An exponential sweep, every octave taking as long
+                Signal::Sweep => {
+                    let time = (self.position as f64 / self.rate as f64) % SWEEP_SECONDS;
+                    let frequency = SWEEP_LOW * (SWEEP_HIGH / SWEEP_LOW).powf(time / SWEEP_SECONDS);
+                    self.phase = (self.phase + frequency / self.rate as f64).fract();
+                    (self.phase * 2f64 * std::f64::consts::PI).sin() as f32 * AMPLITUDE
+                }
+                Signal::Chord => unreachable!(),
+            };
+            self.position += 1;
+        }
+    }
+}
+
+// Send the signal to the analysis at the pace of a capture device, at the rate asked of them
+pub fn start(signal: Signal, rate: i32, sender: Sender<Vec<f32>>, timeline: Timeline) {
+    status!("Test signal = {:?}", signal);
+    timeline.set_rate(rate);
+    std::thread::spawn(move || {
+        let mut generator = Generator::new(signal, rate);
+        let start = Instant::now();
+        let mut sent = 0u64;
+        loop {
+            let mut packet = vec![0f32; PACKET];
+            generator.fill(&mut packet);
+            timeline.advance(PACKET);
+            if sender.send(packet).is_err() {
+                return;
+            }
+            sent += PACKET as u64;
+            // Stay on the clock, without drifting
+            let due = Duration::from_secs_f64(sent as f64 / rate as f64);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+    });
+}
@@ -0,0 +1,79 @@
+// The time of the session, counted in captured samples rather than with the wall clock
+// Shared by the analysis, the chart follower, the displays and the session, so they agree on the bar
+
+// Standard
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// How often the waits check the sample count
+const WAIT_POLL: Duration = Duration::from_millis(2);
+
+#[derive(Clone)]
+pub struct Timeline {
+    // The samples captured so far
+    samples: Arc<AtomicU64>,
+    // The capture rate, 0 until the device is open
+    rate: Arc<AtomicU32>,
+    // How late the captured audio reaches us, in microseconds
+    latency: Arc<AtomicU32>,
+}
+
+impl Timeline {
+    pub fn new() -> Timeline {
+        Timeline {
+            samples: Arc::new(AtomicU64::new(0)),
+            rate: Arc::new(AtomicU32::new(0)),
+            latency: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    pub fn set_rate(&self, rate: i32) {
+        self.rate.store(rate.max(0) as u32, Ordering::Relaxed);
+    }
+
+    // Set how late the captured audio is, in seconds
+    pub fn set_latency(&self, latency: f32) {
+        self.latency.store((latency.max(0f32) * 1e6) as u32, Ordering::Relaxed);
+    }
+
+    // Count samples received from the capture device
+    pub fn advance(&self, samples: usize) {
+        self.samples.fetch_add(samples as u64, Ordering::Relaxed);
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples.load(Ordering::Relaxed)
+    }
+
+    // The time since the capture started, in seconds
+    pub fn seconds(&self) -> f64 {
+        match self.rate.load(Ordering::Relaxed) {
+            0 => 0f64,
+            rate => self.samples() as f64 / rate as f64,
+        }
+    }
+
+    // The time the player is at, ahead of the captured audio by the input latency
+    pub fn now(&self) -> f64 {
+        match self.rate.load(Ordering::Relaxed) {
+            0 => 0f64,
+            _ => self.seconds() + self.latency.load(Ordering::Relaxed) as f64 * 1e-6,
+        }
+    }
+
+    // Wait until the player reaches a time, or the condition stops holding
+    // Returns whether the time was reached
+    pub fn wait_until<F>(&self, seconds: f64, mut condition: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        while self.now() < seconds {
+            if !condition() {
+                return false;
+            }
+            std::thread::sleep(WAIT_POLL);
+        }
+        true
+    }
+}
@@ -1,6 +1,7 @@
 use num_traits::cast::{NumCast, ToPrimitive};
 use std::fmt::Debug;
 use std::ops::RangeInclusive as RanInc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub trait Mappable<F, T>
 {
@@ -99,4 +100,47 @@ impl Normalizable for [f64] {
             *i = (*i - min) / (max - min);
         });
     }
-}
\ No newline at end of file
+}
+// Whether the status lines go to the standard error, the JSON lines taking the standard output
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+// Send the status lines to the standard error from now on
+pub fn status_to_stderr() {
+    STATUS_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+// Print a status line, to the standard output unless the JSON lines are written there
+pub fn status(line: std::fmt::Arguments) {
+    if STATUS_TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+// Decode a text whatever its encoding, invalid UTF-8 being replaced and a byte order mark dropped
+pub fn decode_text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_start_matches('\u{feff}').to_owned()
+}
+
+// Read a text file whatever its encoding
+pub fn read_text(path: &str) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+    Ok(decode_text(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_order_mark_is_dropped() {
+        assert_eq!(decode_text(b"\xEF\xBB\xBFC | G"), "C | G");
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        assert_eq!(decode_text(b"C \xFF| G"), "C \u{fffd}| G");
+        assert_eq!(decode_text(b"\xEF\xBB"), "\u{fffd}");
+    }
+}
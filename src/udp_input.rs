@@ -0,0 +1,207 @@
+// Capture from the network, audio sent to a UDP port from a machine near the instrument
+//   f32: raw little endian floats, ie. arecord -f FLOAT_LE -t raw | nc -u host 5004
+//   rtp: RTP packets of big endian 16 bits samples (L16), reordered and lost packets replaced by silence
+// The interleaved channels are mixed down or selected, as with the capture
+
+// Standard
+use std::collections::BTreeMap;
+use std::net::UdpSocket;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+// Crate
+use crate::audio_buffer::{self, ChannelMode};
+use crate::error::Error;
+use crate::timeline::Timeline;
+
+// The largest datagram read
+const MAX_PACKET: usize = 65536;
+// The size of the fixed RTP header
+const RTP_HEADER: usize = 12;
+// The packets held waiting for a missing one, before it is given up as lost
+const JITTER_PACKETS: usize = 4;
+// The sequence jump taken as a restarted sender
+const MAX_GAP: i64 = 100;
+// How often the lost packets are reported
+const REPORT_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Float,
+    Rtp,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "f32" => Some(Format::Float),
+            "rtp" => Some(Format::Rtp),
+            _ => None,
+        }
+    }
+}
+
+// Reorders the RTP packets, releasing them in sequence
+struct JitterBuffer {
+    // The sequence number released next, unwrapped
+    expected: Option<i64>,
+    pending: BTreeMap<i64, Vec<f32>>,
+    packet_len: usize,
+    lost: u64,
+}
+
+impl JitterBuffer {
+    fn new() -> JitterBuffer {
+        JitterBuffer {
+            expected: None,
+            pending: BTreeMap::new(),
+            packet_len: 0,
+            lost: 0,
+        }
+    }
+
+    fn push(&mut self, sequence: u16, samples: Vec<f32>, released: &mut Vec<Vec<f32>>) {
+        let expected = *self.expected.get_or_insert(sequence as i64);
+        // Unwrap the sequence number around the expected one
+        let mut delta = sequence.wrapping_sub(expected as u16) as i16 as i64;
+        if delta.abs() > MAX_GAP {
+            self.expected = Some(sequence as i64);
+            self.pending.clear();
+            delta = 0;
+        } else if delta < 0 {
+            // Late or duplicated, its place already taken
+            return;
+        }
+        let index = self.expected.unwrap() + delta;
+        self.packet_len = samples.len();
+        self.pending.insert(index, samples);
+        loop {
+            let next = self.expected.unwrap();
+            if let Some(samples) = self.pending.remove(&next) {
+                released.push(samples);
+            } else if self.pending.len() > JITTER_PACKETS {
+                released.push(vec![0f32; self.packet_len]);
+                self.lost += 1;
+            } else {
+                break;
+            }
+            self.expected = Some(next + 1);
+        }
+    }
+}
+
+// The samples of an RTP packet with its sequence number
+fn parse_rtp(data: &[u8]) -> Option<(u16, Vec<f32>)> {
+    if data.len() < RTP_HEADER || data[0] >> 6 != 2 {
+        return None;
+    }
+    let sequence = u16::from_be_bytes([data[2], data[3]]);
+    let mut start = RTP_HEADER + 4 * (data[0] & 0x0f) as usize;
+    // Skip the header extension
+    if data[0] & 0x10 != 0 {
+        let words = u16::from_be_bytes([*data.get(start + 2)?, *data.get(start + 3)?]) as usize;
+        start += 4 + 4 * words;
+    }
+    let mut end = data.len();
+    // Remove the padding
+    if data[0] & 0x20 != 0 {
+        end = end.checked_sub(*data.last()? as usize)?;
+    }
+    let payload = data.get(start..end)?;
+    let samples = payload
+        .chunks_exact(2)
+        .map(|b| i16::from_be_bytes([b[0], b[1]]) as f32 / 32768f32)
+        .collect();
+    Some((sequence, samples))
+}
+
+fn parse_float(data: &[u8]) -> Vec<f32> {
+    data.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+// Listen on a UDP port, sending the audio received like a capture callback
+pub fn listen(
+    port: u16,
+    format: Format,
+    channels: usize,
+    mode: ChannelMode,
+    sender: Sender<Vec<f32>>,
+    timeline: Timeline,
+) -> Result<(), Error> {
+    let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(|e| Error::Capture {
+        driver: "udp".to_owned(),
+        reason: format!("cannot listen on port {}: {}", port, e),
+    })?;
+    status!("Capture Port = UDP {}, {:?}", port, format);
+    std::thread::spawn(move || {
+        let mut data = vec![0u8; MAX_PACKET];
+        let mut jitter = JitterBuffer::new();
+        let mut reported = (Instant::now(), 0u64);
+        while let Ok(size) = socket.recv(&mut data) {
+            let mut released = Vec::new();
+            match format {
+                Format::Float => released.push(parse_float(&data[..size])),
+                Format::Rtp => match parse_rtp(&data[..size]) {
+                    Some((sequence, samples)) => jitter.push(sequence, samples, &mut released),
+                    None => continue,
+                },
+            }
+            for samples in released {
+                let mono = audio_buffer::deinterleave(&samples, channels, mode).remove(0);
+                timeline.advance(mono.len());
+                if sender.send(mono).is_err() {
+                    return;
+                }
+            }
+            if jitter.lost > reported.1 && reported.0.elapsed() >= REPORT_PERIOD {
+                status!("Network input = {} packets lost", jitter.lost - reported.1);
+                reported = (Instant::now(), jitter.lost);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The packets released after pushing these sequence numbers, each packet holding its own
+    fn release(jitter: &mut JitterBuffer, sequences: &[u16]) -> Vec<f32> {
+        let mut released = Vec::new();
+        for &sequence in sequences {
+            jitter.push(sequence, vec![sequence as f32], &mut released);
+        }
+        released.into_iter().map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn packets_are_put_back_in_sequence() {
+        let mut jitter = JitterBuffer::new();
+        assert_eq!(release(&mut jitter, &[10, 12, 11, 13]), vec![10f32, 11f32, 12f32, 13f32]);
+        // Late and duplicated ones are dropped
+        assert_eq!(release(&mut jitter, &[12, 14, 14]), vec![14f32]);
+        assert_eq!(jitter.lost, 0);
+    }
+
+    #[test]
+    fn missing_packet_is_silence() {
+        let mut jitter = JitterBuffer::new();
+        // Given up once more packets than held wait behind it
+        let sequences = (0..JITTER_PACKETS as u16 + 3).filter(|&s| s != 1).collect::<Vec<u16>>();
+        let released = release(&mut jitter, &sequences);
+        assert_eq!(released.len(), sequences.len() + 1);
+        assert_eq!(released[1], 0f32);
+        assert_eq!(jitter.lost, 1);
+    }
+
+    #[test]
+    fn sequence_wraps_around() {
+        let mut jitter = JitterBuffer::new();
+        assert_eq!(release(&mut jitter, &[65534, 0, 65535, 1]), vec![65534f32, 65535f32, 0f32, 1f32]);
+        // A jump is a restarted sender
+        assert_eq!(release(&mut jitter, &[30000, 30001]), vec![30000f32, 30001f32]);
+    }
+}
@@ -0,0 +1,259 @@
+// Suggests voicings of the chord, laid out for a guitar or a keyboard
+//   shell: the root, third and seventh, or an open triad
+//   drop-2: a close four note voicing, its second highest note dropped an octave
+//   quartal: four notes stacked in fourths, the chord tones and their usual tensions
+// Each style keeps its voicing whose notes are the most consonant with the scores, in a comfortable
+// register, and which the hand reaches: four frets on the guitar, a tenth on the keyboard
+
+// Tools
+use itertools::Itertools;
+
+// Crate
+use crate::chords::{Chord, Quality};
+use crate::notes::{Note, NOTE_COUNT};
+
+// The strings of the guitar, from E2 to E4
+const STRINGS: [usize; 6] = [
+    Note::E2 as usize,
+    Note::A2 as usize,
+    Note::D3 as usize,
+    Note::G3 as usize,
+    Note::B3 as usize,
+    Note::E4 as usize,
+];
+// The highest fret of the voicings, and the frets the hand spans besides the open strings
+const MAX_FRET: usize = 15;
+const FRET_SPAN: usize = 3;
+// The lowest and highest notes of the keyboard voicings, and the widest they get
+const KEYBOARD_LOW: usize = Note::C2 as usize;
+const KEYBOARD_HIGH: usize = Note::C6 as usize;
+const KEYBOARD_SPAN: usize = 16;
+// The note the keyboard voicings are kept around
+const KEYBOARD_CENTER: usize = Note::C4 as usize;
+// The cost of every fret up the neck, or semitone away from the center of the keyboard
+const REGISTER_COST: f32 = 0.02;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fingering {
+    Guitar,
+    Keyboard,
+}
+
+impl Fingering {
+    pub fn from_name(name: &str) -> Option<Fingering> {
+        match name {
+            "guitar" => Some(Fingering::Guitar),
+            "keyboard" => Some(Fingering::Keyboard),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Shell,
+    Drop2,
+    Quartal,
+}
+
+const STYLES: [Style; 3] = [Style::Shell, Style::Drop2, Style::Quartal];
+
+impl Style {
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Shell => "shell",
+            Style::Drop2 => "drop-2",
+            Style::Quartal => "quartal",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Voicing {
+    pub style: Style,
+    // The notes, lowest first
+    pub notes: Vec<Note>,
+    // The fret of every string from the lowest, None for the strings not played, empty on a keyboard
+    pub frets: Vec<Option<usize>>,
+}
+
+impl Voicing {
+    // The strings from the lowest, ie. x 3 2 0 1 0, or the notes on a keyboard
+    pub fn describe(&self, names: [&str; 12]) -> String {
+        if self.frets.is_empty() {
+            self.notes
+                .iter()
+                .map(|&n| {
+                    format!(
+                        "{}{}",
+                        names[n.get_octave_index() as usize],
+                        n as usize / 12
+                    )
+                })
+                .join(" ")
+        } else {
+            self.frets
+                .iter()
+                .map(|f| f.map_or("x".to_owned(), |f| f.to_string()))
+                .join(" ")
+        }
+    }
+}
+
+// The shapes of a style, in semitones from the root of the chord
+fn shapes(chord: Chord, style: Style) -> Vec<Vec<usize>> {
+    let tones = chord.quality.intervals();
+    let third = tones[1];
+    match style {
+        Style::Shell => match tones.get(3) {
+            Some(&seventh) => vec![vec![0, third, seventh], vec![0, seventh, third + 12]],
+            None => vec![vec![0, tones[2], third + 12]],
+        },
+        Style::Drop2 => {
+            // Every inversion of the close voicing, the triads doubling their lowest note an octave up
+            (0..4)
+                .map(|inversion| {
+                    let mut notes = (0..4)
+                        .map(|i| match tones.len() {
+                            3 if i == 3 => tones[inversion % 3] + 12 * ((inversion / 3) + 1),
+                            count => {
+                                tones[(inversion + i) % count] + 12 * ((inversion + i) / count)
+                            }
+                        })
+                        .map(|n| n + 12)
+                        .collect_vec();
+                    notes[2] -= 12;
+                    notes.sort_unstable();
+                    notes
+                })
+                .collect_vec()
+        }
+        Style::Quartal => {
+            // The ninth and the thirteenth go with every chord, the eleventh without a major third
+            let mut allowed = tones.iter().map(|t| t % 12).collect_vec();
+            allowed.extend(&[2, 9]);
+            if third != 4 {
+                allowed.push(5);
+            }
+            let color = match chord.quality {
+                Quality::Suspended4 => 5,
+                _ => third,
+            };
+            (0..12)
+                .map(|start| (0..4).map(|i| start + 5 * i).collect_vec())
+                .filter(|stack| stack.iter().all(|n| allowed.contains(&(n % 12))))
+                .filter(|stack| stack.iter().any(|n| n % 12 == color))
+                .collect_vec()
+        }
+    }
+}
+
+// The frets of the notes, each on its own string, lowest first, if the hand reaches them
+// The strings skipped between the notes, then the frets up the neck, are avoided
+fn fret(notes: &[usize]) -> Option<(Vec<Option<usize>>, usize)> {
+    // Every string for the next notes, keeping the playable frets
+    fn search(
+        notes: &[usize],
+        string: usize,
+        frets: &mut Vec<Option<usize>>,
+        found: &mut Vec<Vec<Option<usize>>>,
+    ) {
+        let (&note, rest) = match notes.split_first() {
+            Some(split) => split,
+            None => {
+                let pressed = frets.iter().flatten().filter(|&&f| f > 0).collect_vec();
+                let span = match (pressed.iter().min(), pressed.iter().max()) {
+                    (Some(&&low), Some(&&high)) => high - low,
+                    _ => 0,
+                };
+                if span <= FRET_SPAN {
+                    found.push(frets.clone());
+                }
+                return;
+            }
+        };
+        for s in string..STRINGS.len() {
+            if note >= STRINGS[s] && note - STRINGS[s] <= MAX_FRET {
+                frets[s] = Some(note - STRINGS[s]);
+                search(rest, s + 1, frets, found);
+                frets[s] = None;
+            }
+        }
+    }
+    let mut found = Vec::new();
+    search(notes, 0, &mut vec![None; STRINGS.len()], &mut found);
+    found
+        .into_iter()
+        .map(|frets| {
+            let played = frets.iter().positions(Option::is_some).collect_vec();
+            let skipped = played.last().unwrap() - played[0] + 1 - played.len();
+            let highest = frets.iter().flatten().max().cloned().unwrap_or(0);
+            (frets, skipped, highest)
+        })
+        .min_by_key(|&(_, skipped, highest)| (skipped, highest))
+        .map(|(frets, _, highest)| (frets, highest))
+}
+
+// The most consonant voicing of each style that the instrument plays, the best first
+pub fn suggest(
+    chord: Chord,
+    note_scores: &[f32; NOTE_COUNT],
+    fingering: Fingering,
+) -> Vec<Voicing> {
+    let score = |n: usize| {
+        let score = note_scores[n];
+        if score.is_finite() {
+            score
+        } else {
+            0f32
+        }
+    };
+    let mut voicings = Vec::new();
+    for &style in STYLES.iter() {
+        let mut best: Option<(f32, Voicing)> = None;
+        for shape in shapes(chord, style) {
+            // Every octave of the chord, the shapes being from its root
+            for octave in 0..7 {
+                let notes = shape
+                    .iter()
+                    .map(|i| octave * 12 + chord.root + i)
+                    .collect_vec();
+                if *notes.last().unwrap() >= NOTE_COUNT {
+                    continue;
+                }
+                let (frets, register) = match fingering {
+                    Fingering::Guitar => match fret(&notes) {
+                        Some((frets, highest)) => (frets, highest as f32),
+                        None => continue,
+                    },
+                    Fingering::Keyboard => {
+                        if notes[0] < KEYBOARD_LOW
+                            || *notes.last().unwrap() > KEYBOARD_HIGH
+                            || notes.last().unwrap() - notes[0] > KEYBOARD_SPAN
+                        {
+                            continue;
+                        }
+                        let middle = notes.iter().sum::<usize>() as f32 / notes.len() as f32;
+                        (Vec::new(), (middle - KEYBOARD_CENTER as f32).abs())
+                    }
+                };
+                let cost = notes.iter().map(|&n| score(n)).sum::<f32>() / notes.len() as f32
+                    + register * REGISTER_COST;
+                if best.as_ref().map_or(true, |(c, _)| cost < *c) {
+                    let voicing = Voicing {
+                        style,
+                        notes: notes
+                            .iter()
+                            .filter_map(|&n| Note::iter().nth(n))
+                            .collect_vec(),
+                        frets,
+                    };
+                    best = Some((cost, voicing));
+                }
+            }
+        }
+        voicings.extend(best);
+    }
+    voicings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    voicings.into_iter().map(|(_, v)| v).collect_vec()
+}
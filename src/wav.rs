@@ -0,0 +1,276 @@
+// Recordings read from WAV files, fed to the analysis instead of the capture
+// Reads integer PCM of 8 to 32 bits and float PCM, the channels being mixed down
+// The capture can also be recorded while analysed, as float PCM
+
+// Standard
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+// Crate
+use crate::error::Error;
+use crate::sheet;
+use crate::timeline::Timeline;
+
+// The samples sent at once, like a capture callback
+const PACKET: usize = 1024;
+// The WAVE format tags
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+// The size of the header written, before the samples
+const HEADER_SIZE: u64 = 44;
+// The bytes of a recorded sample
+const RECORDED_WIDTH: u64 = 4;
+
+pub struct Wav {
+    pub rate: i32,
+    // The samples, mixed down to mono
+    pub samples: Vec<f32>,
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    let b = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+// Decode a sample of the given format and width
+fn sample(bytes: &[u8], format: u16, bits: u16) -> f32 {
+    match (format, bits) {
+        (FORMAT_PCM, 8) => (bytes[0] as f32 - 128f32) / 128f32,
+        (FORMAT_PCM, 16) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768f32,
+        (FORMAT_PCM, 24) => i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 2147483648f32,
+        (FORMAT_PCM, _) => {
+            i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 2147483648f32
+        }
+        (_, 32) => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        _ => {
+            let mut b = [0u8; 8];
+            b.copy_from_slice(&bytes[..8]);
+            f64::from_le_bytes(b) as f32
+        }
+    }
+}
+
+impl Wav {
+    pub fn load(path: &str) -> Result<Wav, Error> {
+        let error = |reason: String| Error::Recording {
+            path: path.to_owned(),
+            reason,
+        };
+        let bytes = std::fs::read(path).map_err(|e| error(e.to_string()))?;
+        if bytes.starts_with(b"fLaC") {
+            return Err(error("a FLAC file, only WAV is supported".to_owned()));
+        }
+        if bytes.get(0..4) != Some(b"RIFF") || bytes.get(8..12) != Some(b"WAVE") {
+            return Err(error("not a WAV file".to_owned()));
+        }
+        let malformed = || error("malformed WAV file".to_owned());
+
+        // Find the format and the data among the chunks
+        let mut format = None;
+        let mut data = None;
+        let mut offset = 12;
+        while let (Some(id), Some(size)) = (bytes.get(offset..offset + 4), u32_at(&bytes, offset + 4)) {
+            let start = offset + 8;
+            // The data chunk of a file cut short, or streamed, runs to the end
+            let end = (start + size as usize).min(bytes.len());
+            match id {
+                b"fmt " => format = Some(&bytes[start..end]),
+                b"data" => data = Some(&bytes[start..end]),
+                _ => (),
+            }
+            // Chunks are padded to an even size
+            offset = start + size as usize + (size as usize & 1);
+        }
+        let (format, data) = (format.ok_or_else(malformed)?, data.ok_or_else(malformed)?);
+
+        let mut tag = u16_at(format, 0).ok_or_else(malformed)?;
+        let channels = u16_at(format, 2).ok_or_else(malformed)? as usize;
+        let rate = u32_at(format, 4).ok_or_else(malformed)?;
+        let bits = u16_at(format, 14).ok_or_else(malformed)?;
+        // The actual format is at the start of the sub format GUID
+        if tag == FORMAT_EXTENSIBLE {
+            tag = u16_at(format, 24).ok_or_else(malformed)?;
+        }
+        let supported = match tag {
+            FORMAT_PCM => [8, 16, 24, 32].contains(&bits),
+            FORMAT_FLOAT => [32, 64].contains(&bits),
+            _ => false,
+        };
+        if !supported || channels == 0 || rate == 0 || rate > i32::MAX as u32 {
+            return Err(error(format!(
+                "unsupported WAV format ({} channels of {} bits, format {})",
+                channels, bits, tag
+            )));
+        }
+
+        let width = bits as usize / 8;
+        let samples = data
+            .chunks_exact(width * channels)
+            .map(|frame| {
+                frame
+                    .chunks_exact(width)
+                    .map(|s| sample(s, tag, bits))
+                    .sum::<f32>()
+                    / channels as f32
+            })
+            .collect();
+        Ok(Wav {
+            rate: rate as i32,
+            samples,
+        })
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.rate as f32
+    }
+}
+
+// When a recording moves on to its next file, never when both are None
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rotation {
+    // The size of a file, in bytes
+    pub bytes: Option<u64>,
+    // The duration of a file, in seconds
+    pub seconds: Option<f32>,
+}
+
+// Records the capture to a WAV file, moving on to the numbered ones after it on rotation
+// ie. practice.wav, then practice-2.wav, practice-3.wav
+pub struct Recorder {
+    path: String,
+    rate: i32,
+    rotation: Rotation,
+    file: BufWriter<File>,
+    // The number of the current file, from 1, and the samples written to it
+    number: usize,
+    samples: u64,
+}
+
+// Write the header of a mono float file, its sizes being those of a file running to the end
+// So a file left unfinished by a crash is still read whole
+fn write_header(file: &mut impl Write, rate: i32, samples: Option<u64>) -> std::io::Result<()> {
+    let data_size = samples.map_or(u32::MAX - HEADER_SIZE as u32, |s| (s * RECORDED_WIDTH) as u32);
+    file.write_all(b"RIFF")?;
+    file.write_all(&(data_size.saturating_add(HEADER_SIZE as u32 - 8)).to_le_bytes())?;
+    file.write_all(b"WAVEfmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    file.write_all(&FORMAT_FLOAT.to_le_bytes())?;
+    file.write_all(&1u16.to_le_bytes())?;
+    file.write_all(&(rate as u32).to_le_bytes())?;
+    file.write_all(&(rate as u32 * RECORDED_WIDTH as u32).to_le_bytes())?;
+    file.write_all(&(RECORDED_WIDTH as u16).to_le_bytes())?;
+    file.write_all(&(RECORDED_WIDTH as u16 * 8).to_le_bytes())?;
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())
+}
+
+fn create(path: &str, rate: i32) -> Result<BufWriter<File>, Error> {
+    let error = |e: std::io::Error| Error::Recorder {
+        path: path.to_owned(),
+        reason: e.to_string(),
+    };
+    let mut file = BufWriter::new(File::create(path).map_err(error)?);
+    write_header(&mut file, rate, None).map_err(error)?;
+    Ok(file)
+}
+
+impl Recorder {
+    pub fn create(path: &str, rate: i32, rotation: Rotation) -> Result<Recorder, Error> {
+        status!("Recording = {}", path);
+        Ok(Recorder {
+            path: path.to_owned(),
+            rate,
+            rotation,
+            file: create(path, rate)?,
+            number: 1,
+            samples: 0,
+        })
+    }
+
+    fn current_path(&self) -> String {
+        match self.number {
+            1 => self.path.clone(),
+            number => sheet::numbered_path(&self.path, number),
+        }
+    }
+
+    fn error(&self, e: std::io::Error) -> Error {
+        Error::Recorder {
+            path: self.current_path(),
+            reason: e.to_string(),
+        }
+    }
+
+    // Whether the current file reached the size or the duration of the rotation
+    fn full(&self) -> bool {
+        let bytes = HEADER_SIZE + self.samples * RECORDED_WIDTH;
+        self.rotation.bytes.map_or(false, |b| bytes >= b)
+            || self.rotation.seconds.map_or(false, |s| self.samples as f32 >= s * self.rate as f32)
+            // The sizes of the header cannot count more
+            || bytes >= u32::MAX as u64 - RECORDED_WIDTH
+    }
+
+    // Write the actual sizes in the header
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.file, self.rate, Some(self.samples))?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()
+    }
+
+    // Append the samples, moving on to the next file when the current one is full
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), Error> {
+        for &sample in samples {
+            if self.full() {
+                self.finish().map_err(|e| self.error(e))?;
+                self.number += 1;
+                self.samples = 0;
+                let path = self.current_path();
+                self.file = create(&path, self.rate)?;
+                status!("Recording = {}", path);
+            }
+            self.file.write_all(&sample.to_le_bytes()).map_err(|e| self.error(e))?;
+            self.samples += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            status!("Cannot write {}: {}", self.current_path(), e);
+        }
+    }
+}
+
+// Send the recording to the analysis, at a multiple of its own pace
+// The analysis ends with the recording
+pub fn start(wav: Wav, sender: Sender<Vec<f32>>, timeline: Timeline, speed: u32) {
+    timeline.set_rate(wav.rate);
+    std::thread::spawn(move || {
+        let start = Instant::now();
+        let mut sent = 0u64;
+        for packet in wav.samples.chunks(PACKET) {
+            timeline.advance(packet.len());
+            if sender.send(packet.to_owned()).is_err() {
+                return;
+            }
+            sent += packet.len() as u64;
+            // Stay on the clock of the recording, sped up
+            let due = Duration::from_secs_f64(sent as f64 / wav.rate as f64 / speed as f64);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        status!("End of the recording");
+    });
+}
@@ -0,0 +1,72 @@
+// Serves the analysis to the music education apps embedding ImproVe, over TCP
+// Every client gets the hello message on connection, then a frame message per analysed frame
+// The messages are the lines of JSON of the versioned schema, also written by --json
+// The client crate reads them without depending on this one
+
+// Standard
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Crate
+use crate::display::Notation;
+use crate::error::Error;
+use crate::json;
+use crate::output::Output;
+use crate::scores::Scores;
+use crate::timeline::Timeline;
+
+// How long a slow client may hold up the analysis before it is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// The address listened on unless told otherwise, keeping the frames to this machine
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+
+pub struct WidgetServer {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    timeline: Timeline,
+    notation: Notation,
+}
+
+impl WidgetServer {
+    // Accept the clients on the given port of the host address, from another thread
+    pub fn listen(host: &str, port: u16, timeline: Timeline, notation: Notation) -> Result<WidgetServer, Error> {
+        let listener = TcpListener::bind((host, port)).map_err(|e| Error::Listen {
+            service: "widget clients",
+            address: format!("TCP port {} of {}", port, host),
+            reason: e.to_string(),
+        })?;
+        status!("Widget = {}:{}, schema version {}", host, port, json::SCHEMA_VERSION);
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = clients.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().filter_map(Result::ok) {
+                stream.set_write_timeout(Some(WRITE_TIMEOUT)).ok();
+                stream.set_nodelay(true).ok();
+                if writeln!(stream, "{}", json::hello()).is_ok() {
+                    let address = stream.peer_addr().map_or("unknown".to_owned(), |a| a.to_string());
+                    status!("Widget client = {}", address);
+                    accepted.lock().unwrap().push(stream);
+                }
+            }
+        });
+        Ok(WidgetServer {
+            clients,
+            timeline,
+            notation,
+        })
+    }
+}
+
+impl Output for WidgetServer {
+    fn publish(&mut self, scores: &Scores) {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.is_empty() {
+            return;
+        }
+        let line = json::frame(scores, self.timeline.seconds() as f32, self.notation) + "\n";
+        // Drop the clients gone, or too slow to keep up
+        clients.retain(|mut stream| stream.write_all(line.as_bytes()).is_ok());
+    }
+}
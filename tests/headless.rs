@@ -0,0 +1,131 @@
+// Runs the built binary on generated recordings, checking the JSON lines of the whole chain
+
+// Standard
+use std::f32::consts::PI;
+use std::path::PathBuf;
+use std::process::Command;
+
+const RATE: u32 = 44100;
+
+// A mono 16 bits WAV file of the frequencies summed, in the temporary directory
+fn write_wav(name: &str, frequencies: &[f32], seconds: f32) -> PathBuf {
+    let samples = (0..(seconds * RATE as f32) as usize)
+        .map(|i| {
+            let t = i as f32 / RATE as f32;
+            let sum = frequencies
+                .iter()
+                .map(|f| (2f32 * PI * f * t).sin())
+                .sum::<f32>();
+            (sum / frequencies.len().max(1) as f32 * 0.5 * i16::MAX as f32) as i16
+        })
+        .collect::<Vec<i16>>();
+    let size = samples.len() as u32 * 2;
+    let mut bytes = Vec::with_capacity(44 + size as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + size).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&RATE.to_le_bytes());
+    bytes.extend_from_slice(&(RATE * 2).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&size.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    let path = std::env::temp_dir().join(format!("improve-{}-{}.wav", name, std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+// The lines written while analysing the file, the status lines going to the standard error
+fn analyze(path: &PathBuf) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_improve"))
+        .args(["--headless", "--json", "-", "analyze", "--speed", "8"])
+        .arg(path)
+        // Nothing is captured nor shown, whatever the machine has
+        .env("SDL_AUDIODRIVER", "dummy")
+        .env("SDL_VIDEODRIVER", "dummy")
+        .output()
+        .unwrap();
+    std::fs::remove_file(path).ok();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let lines = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect::<Vec<String>>();
+    for line in lines.iter() {
+        assert!(
+            line.starts_with('{') && line.ends_with('}'),
+            "not JSON: {}",
+            line
+        );
+    }
+    lines
+}
+
+// The raw value of a field, up to the next one
+fn field<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("\"{}\":", name);
+    let start = line.find(&key)? + key.len();
+    let rest = &line[start..];
+    let end = if rest.starts_with('[') {
+        rest.find(']')? + 1
+    } else if rest.starts_with('{') {
+        rest.find('}')? + 1
+    } else {
+        rest.find([',', '}']).unwrap_or(rest.len())
+    };
+    Some(&rest[..end])
+}
+
+#[test]
+fn c_major_triad_is_named() {
+    // C4, E4 and G4
+    let path = write_wav("triad", &[261.63, 329.63, 392.00], 4f32);
+    let lines = analyze(&path);
+
+    let hello = lines.first().expect("no JSON written");
+    assert_eq!(field(hello, "v"), Some("1"));
+    assert_eq!(field(hello, "type"), Some("\"hello\""));
+    let notes = field(hello, "notes").unwrap().parse::<usize>().unwrap();
+
+    let frames = &lines[1..];
+    assert!(!frames.is_empty());
+    for frame in frames {
+        assert_eq!(field(frame, "v"), Some("1"));
+        assert_eq!(field(frame, "type"), Some("\"frame\""));
+        let scores = field(frame, "scores").unwrap();
+        assert_eq!(
+            scores
+                .trim_matches(|c| c == '[' || c == ']')
+                .split(',')
+                .count(),
+            notes
+        );
+        assert!(field(frame, "time").unwrap().parse::<f32>().is_ok());
+        assert!(field(frame, "confidence").is_some());
+        assert!(field(frame, "warmup").is_some());
+    }
+    assert!(
+        frames.iter().any(|f| field(f, "chord") == Some("\"C\"")),
+        "no frame named the chord C"
+    );
+}
+
+#[test]
+fn silence_names_no_chord() {
+    let path = write_wav("silence", &[], 2f32);
+    let lines = analyze(&path);
+    assert!(lines.len() > 1);
+    assert!(lines[1..].iter().all(|f| field(f, "chord") == Some("null")));
+}